@@ -0,0 +1,128 @@
+//! The coordination stream: a replicated record of the operator's signals,
+//! startup requests and completions, heartbeats and drains of a cluster, kept
+//! apart from the events stream so it can be retained for auditing however
+//! busy the gateway is.
+
+use anyhow::{bail, Context as _, Result};
+use async_nats::jetstream::stream;
+use std::time::Duration;
+
+/// Name of the coordination stream, scoped to the subject prefix with
+/// [`crate::scoped_name`].
+pub const COORDINATION_STREAM: &str = "bedrock-coordination";
+
+/// Subjects the stream records, relative to the subject prefix. The
+/// operator's signals are captured by the signal stream and sourced from it,
+/// since two streams cannot capture the same subject.
+pub const SUBJECTS: [&str; 8] = [
+    "startup.request",
+    "startup.grant",
+    "startup.complete",
+    "gateway.startup",
+    "workers.heartbeat",
+    "workers.*.drain",
+    "workers.*.drained",
+    "operator.reshard.status",
+];
+
+/// Subjects the stream also records when the events stream is partitioned,
+/// whose partition streams capture nothing outside `partitions.>`. An
+/// unpartitioned events stream captures them with the rest of `shards.>`.
+pub const SHARD_SUBJECTS: [&str; 2] = ["shards.*.status", "shards.*.startup"];
+
+/// Name of the stream holding the latest operator signal of each subject
+/// under `subject_prefix`.
+pub fn signal_stream_name(subject_prefix: &str) -> String {
+    format!("{}-operator", subject_prefix.replace('.', "-"))
+}
+
+/// How long and on how many servers the coordination stream keeps its record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoordinationStreamSettings {
+    /// Age after which messages are removed
+    pub max_age: Duration,
+    /// Size after which the oldest messages are removed, unlimited when -1
+    pub max_bytes: i64,
+    pub replicas: usize,
+}
+
+impl Default for CoordinationStreamSettings {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(7 * 24 * 60 * 60),
+            max_bytes: -1,
+            replicas: 3,
+        }
+    }
+}
+
+impl CoordinationStreamSettings {
+    /// These settings with COORDINATION_STREAM_MAX_AGE_SECS,
+    /// COORDINATION_STREAM_MAX_BYTES and COORDINATION_STREAM_REPLICAS from
+    /// the environment in place of the ones that are set.
+    pub fn from_env(self) -> Result<Self> {
+        let var = |key: &str| std::env::var(key).ok();
+
+        let max_age = match var("COORDINATION_STREAM_MAX_AGE_SECS") {
+            Some(secs) => Duration::from_secs(
+                secs.parse()
+                    .context("COORDINATION_STREAM_MAX_AGE_SECS must be a non-negative integer")?,
+            ),
+            None => self.max_age,
+        };
+        let max_bytes = match var("COORDINATION_STREAM_MAX_BYTES") {
+            Some(bytes) => bytes.parse().context("COORDINATION_STREAM_MAX_BYTES must be an integer")?,
+            None => self.max_bytes,
+        };
+        let replicas = match var("COORDINATION_STREAM_REPLICAS") {
+            Some(replicas) => replicas
+                .parse()
+                .context("COORDINATION_STREAM_REPLICAS must be a positive integer")?,
+            None => self.replicas,
+        };
+
+        if !(1..=5).contains(&replicas) {
+            bail!("COORDINATION_STREAM_REPLICAS must be between 1 and 5");
+        }
+        Ok(Self {
+            max_age,
+            max_bytes,
+            replicas,
+        })
+    }
+}
+
+/// The coordination stream of the cluster publishing under `subject_prefix`,
+/// also recording shard status and startup messages when its events stream
+/// is `partitioned`. It does not acknowledge what it records, so requests on
+/// its subjects only get their responders' replies.
+pub fn stream_config(subject_prefix: &str, partitioned: bool, settings: &CoordinationStreamSettings) -> stream::Config {
+    let shard_subjects = if partitioned { &SHARD_SUBJECTS[..] } else { &[] };
+
+    stream::Config {
+        name: crate::scoped_name(COORDINATION_STREAM, subject_prefix),
+        description: Some("Coordination and audit record".to_string()),
+        subjects: SUBJECTS
+            .iter()
+            .chain(shard_subjects)
+            .map(|subject| format!("{}.{}", subject_prefix, subject))
+            .collect(),
+        sources: Some(vec![stream::Source {
+            name: signal_stream_name(subject_prefix),
+            ..Default::default()
+        }]),
+        retention: stream::RetentionPolicy::Limits,
+        discard: stream::DiscardPolicy::Old,
+        storage: stream::StorageType::File,
+        max_age: settings.max_age,
+        max_bytes: settings.max_bytes,
+        num_replicas: settings.replicas,
+        // NATS rejects a duplicate window longer than the stream's max age.
+        duplicate_window: match settings.max_age {
+            Duration::ZERO => Duration::from_secs(120),
+            max_age => max_age.min(Duration::from_secs(120)),
+        },
+        no_ack: true,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,36 @@
+use async_nats::header::{HeaderMap, NATS_MESSAGE_ID};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+/// Nats-Msg-Id of a message identified by nothing but its subject and
+/// payload, so publishing it again, on a retry or after a restart, yields
+/// the same id and the stream stores it once within its duplicate window.
+pub fn message_id(subject: &str, payload: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(subject.as_bytes());
+    hasher.update([0]);
+    hasher.update(payload);
+    hex::encode(hasher.finalize())
+}
+
+/// Nats-Msg-Id of its own for a message that may repeat an earlier one word
+/// for word, such as a status or a coordination message, so the stream stores
+/// every one sent. Retries of a message reuse the id it was given.
+pub fn unique_id() -> String {
+    static PROCESS: OnceLock<u64> = OnceLock::new();
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+    let process = PROCESS.get_or_init(|| RandomState::new().hash_one(SystemTime::now()));
+    format!("{:016x}-{}", process, SEQUENCE.fetch_add(1, Ordering::Relaxed))
+}
+
+/// `headers`, or new ones, with `id` set as the Nats-Msg-Id.
+pub fn with_message_id(headers: Option<HeaderMap>, id: &str) -> HeaderMap {
+    let mut headers = headers.unwrap_or_default();
+    headers.insert(NATS_MESSAGE_ID, id);
+    headers
+}
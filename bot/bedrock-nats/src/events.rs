@@ -0,0 +1,91 @@
+//! Lifecycle events of a connection: logged as they happen, counted for the
+//! components' metrics, and announced to listeners that make core-NATS
+//! subscriptions again after a reconnect.
+
+use async_nats::Event;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+/// Counts of what happened to a connection since it was made.
+#[derive(Debug)]
+pub struct ConnectionEvents {
+    disconnects: AtomicU64,
+    reconnects: AtomicU64,
+    slow_consumers: AtomicU64,
+    errors: AtomicU64,
+    disconnected: AtomicBool,
+    reconnected: watch::Sender<u64>,
+}
+
+impl Default for ConnectionEvents {
+    fn default() -> Self {
+        Self {
+            disconnects: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
+            slow_consumers: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            disconnected: AtomicBool::new(false),
+            reconnected: watch::Sender::new(0),
+        }
+    }
+}
+
+impl ConnectionEvents {
+    /// Logs and counts an event the client reported.
+    pub fn record(&self, url: &str, event: Event) {
+        match event {
+            Event::Connected => {
+                if self.disconnected.swap(false, Ordering::Relaxed) {
+                    let reconnects = self.reconnects.fetch_add(1, Ordering::Relaxed) + 1;
+                    info!(url = %url, reconnects, "Reconnected to NATS");
+                    self.reconnected.send_modify(|generation| *generation += 1);
+                }
+            }
+            Event::Disconnected => {
+                self.disconnected.store(true, Ordering::Relaxed);
+                let disconnects = self.disconnects.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!(url = %url, disconnects, "Disconnected from NATS, reconnecting...");
+            }
+            Event::SlowConsumer(sid) => {
+                self.slow_consumers.fetch_add(1, Ordering::Relaxed);
+                warn!(url = %url, sid, "NATS subscription is not keeping up, dropping messages");
+            }
+            Event::LameDuckMode => warn!(url = %url, "NATS server entered lame duck mode"),
+            Event::Draining => info!(url = %url, "Draining NATS connection"),
+            Event::Closed => info!(url = %url, "NATS connection closed"),
+            Event::ServerError(e) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                error!(url = %url, error = %e, "NATS server error");
+            }
+            Event::ClientError(e) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                error!(url = %url, error = %e, "NATS client error");
+            }
+        }
+    }
+
+    pub fn disconnects(&self) -> u64 {
+        self.disconnects.load(Ordering::Relaxed)
+    }
+
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+
+    /// Messages dropped for subscriptions that fell behind.
+    pub fn slow_consumers(&self) -> u64 {
+        self.slow_consumers.load(Ordering::Relaxed)
+    }
+
+    /// Server and client errors.
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// The number of reconnects so far, changing on each one, so a listener
+    /// can make its core subscriptions again in case the server lost them.
+    pub fn reconnected(&self) -> watch::Receiver<u64> {
+        self.reconnected.subscribe()
+    }
+}
@@ -0,0 +1,96 @@
+//! KV buckets the workers keep their state in. Each is scoped to the subject
+//! prefix of its cluster with [`crate::scoped_name`].
+
+use crate::{streams, Connection};
+use anyhow::{bail, Context as _, Result};
+use async_nats::jetstream::kv;
+use std::time::Duration;
+use tracing::info;
+
+/// Resumable gateway sessions, keyed by shard.
+pub const SESSIONS_BUCKET: &str = "stratum-sessions";
+/// Latest connection state of each shard.
+pub const SHARD_STATUS_BUCKET: &str = "stratum-shard-status";
+/// Per-shard runtime overrides set by operators.
+pub const OVERRIDES_BUCKET: &str = "stratum-shard-overrides";
+/// Active replica of each shard group.
+pub const LEASES_BUCKET: &str = "stratum-group-leases";
+
+/// Every bucket a worker opens, for granting access to them.
+pub const BUCKETS: [&str; 4] = [SESSIONS_BUCKET, SHARD_STATUS_BUCKET, OVERRIDES_BUCKET, LEASES_BUCKET];
+
+/// How much a bucket keeps of each key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketSettings {
+    /// Values kept per key, between 1 and 64
+    pub history: i64,
+    /// Age after which values expire, never when zero
+    pub ttl: Duration,
+}
+
+impl BucketSettings {
+    pub const fn new(history: i64, ttl: Duration) -> Self {
+        Self { history, ttl }
+    }
+
+    /// These settings with `<prefix>_HISTORY` and `<prefix>_TTL_SECS` from the
+    /// environment in place of the ones that are set.
+    pub fn from_env(self, prefix: &str) -> Result<Self> {
+        let history_var = format!("{}_HISTORY", prefix);
+        let ttl_var = format!("{}_TTL_SECS", prefix);
+
+        let history = match std::env::var(&history_var) {
+            Ok(history) => history
+                .parse()
+                .with_context(|| format!("{} must be an integer", history_var))?,
+            Err(_) => self.history,
+        };
+        let ttl = match std::env::var(&ttl_var) {
+            Ok(ttl) => Duration::from_secs(
+                ttl.parse()
+                    .with_context(|| format!("{} must be a non-negative integer", ttl_var))?,
+            ),
+            Err(_) => self.ttl,
+        };
+
+        if !(1..=64).contains(&history) {
+            bail!("{} must be between 1 and 64", history_var);
+        }
+        Ok(Self { history, ttl })
+    }
+}
+
+impl Connection {
+    /// Creates the bucket `name`, scoped to the connection's subject prefix,
+    /// or updates an existing one to `settings`, retrying according to the
+    /// connection's retry policy.
+    pub async fn open_bucket(&self, name: &str, description: &str, settings: BucketSettings) -> Result<kv::Store> {
+        let bucket = crate::scoped_name(name, self.subject_prefix());
+
+        let store = streams::open_kv(
+            self.jetstream(),
+            kv::Config {
+                bucket: bucket.clone(),
+                description: description.to_string(),
+                history: settings.history,
+                max_age: settings.ttl,
+                ..Default::default()
+            },
+            self.retry_policy(),
+        )
+        .await?;
+
+        info!(bucket = %bucket, history = settings.history, ttl_secs = settings.ttl.as_secs(), "Opened KV bucket");
+        Ok(store)
+    }
+
+    /// The existing bucket `name`, scoped to the connection's subject prefix,
+    /// for readers that should not create it.
+    pub async fn get_bucket(&self, name: &str) -> Result<kv::Store> {
+        let bucket = crate::scoped_name(name, self.subject_prefix());
+        self.jetstream()
+            .get_key_value(&bucket)
+            .await
+            .with_context(|| format!("KV bucket {} is not available", bucket))
+    }
+}
@@ -0,0 +1,190 @@
+//! Backlog of JetStream consumers, polled on an interval and reported as
+//! metrics and on a NATS subject, so a growing backlog shows before the
+//! latency it causes does.
+
+use anyhow::{bail, Context as _, Result};
+use async_nats::jetstream::{self, consumer};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// Time between two polls unless LAG_REPORT_INTERVAL_SECS says otherwise.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Where a consumer stood at the last poll.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsumerLag {
+    pub stream: String,
+    pub consumer: String,
+    /// Messages not delivered yet
+    pub num_pending: u64,
+    /// Messages delivered but not acknowledged yet
+    pub num_ack_pending: usize,
+    /// Messages delivered more than once
+    pub num_redelivered: usize,
+    pub timestamp: u64,
+}
+
+/// Reads one metric's value out of a consumer's lag.
+type MetricValue = fn(&ConsumerLag) -> u64;
+
+/// Polls the info of named consumers, keeping the latest values for
+/// [`LagMonitor::render_metrics`] and publishing each on the status subject.
+#[derive(Clone)]
+pub struct LagMonitor {
+    jetstream: jetstream::Context,
+    consumers: Vec<(String, String)>,
+    interval: Duration,
+    status: Option<(async_nats::Client, String)>,
+    latest: Arc<RwLock<BTreeMap<(String, String), ConsumerLag>>>,
+}
+
+impl LagMonitor {
+    pub fn new(jetstream: jetstream::Context) -> Self {
+        Self {
+            jetstream,
+            consumers: Vec::new(),
+            interval: DEFAULT_INTERVAL,
+            status: None,
+            latest: Arc::default(),
+        }
+    }
+
+    /// A monitor for the consumers in LAG_CONSUMERS, a comma-separated list
+    /// of `stream:consumer` pairs, polled every LAG_REPORT_INTERVAL_SECS.
+    pub fn from_env(jetstream: jetstream::Context) -> Result<Self> {
+        let mut monitor = Self::new(jetstream);
+
+        if let Ok(interval) = std::env::var("LAG_REPORT_INTERVAL_SECS") {
+            let interval: u64 = interval
+                .parse()
+                .context("LAG_REPORT_INTERVAL_SECS must be a positive integer")?;
+            if interval == 0 {
+                bail!("LAG_REPORT_INTERVAL_SECS must be a positive integer");
+            }
+            monitor = monitor.interval(Duration::from_secs(interval));
+        }
+
+        let consumers = std::env::var("LAG_CONSUMERS").unwrap_or_default();
+        for pair in consumers.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            match pair.split_once(':') {
+                Some((stream, consumer)) if !stream.is_empty() && !consumer.is_empty() => {
+                    monitor = monitor.consumer(stream, consumer);
+                }
+                _ => bail!("LAG_CONSUMERS entries must be stream:consumer, got '{}'", pair),
+            }
+        }
+
+        Ok(monitor)
+    }
+
+    /// Also watches `consumer` of `stream`.
+    pub fn consumer(mut self, stream: impl Into<String>, consumer: impl Into<String>) -> Self {
+        let entry = (stream.into(), consumer.into());
+        if !self.consumers.contains(&entry) {
+            self.consumers.push(entry);
+        }
+        self
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Publishes every poll of a consumer as JSON on `subject`.
+    pub fn publish_to(mut self, client: async_nats::Client, subject: impl Into<String>) -> Self {
+        self.status = Some((client, subject.into()));
+        self
+    }
+
+    /// The latest poll of every consumer that answered one.
+    pub fn latest(&self) -> Vec<ConsumerLag> {
+        self.latest.read().expect("lag monitor poisoned").values().cloned().collect()
+    }
+
+    /// The latest polls in the Prometheus text format.
+    pub fn render_metrics(&self) -> String {
+        let latest = self.latest();
+        let metrics: [(&str, MetricValue); 3] = [
+            ("bedrock_consumer_pending", |lag| lag.num_pending),
+            ("bedrock_consumer_ack_pending", |lag| lag.num_ack_pending as u64),
+            ("bedrock_consumer_redelivered", |lag| lag.num_redelivered as u64),
+        ];
+
+        let mut body = String::new();
+        for (name, value) in metrics {
+            let _ = writeln!(body, "# TYPE {} gauge", name);
+            for lag in &latest {
+                let _ = writeln!(
+                    body,
+                    "{}{{stream=\"{}\",consumer=\"{}\"}} {}",
+                    name,
+                    lag.stream,
+                    lag.consumer,
+                    value(lag)
+                );
+            }
+        }
+        body
+    }
+
+    /// Polls every consumer on the interval until the task is dropped.
+    pub async fn run(self) {
+        let mut ticker = tokio::time::interval(self.interval);
+
+        loop {
+            ticker.tick().await;
+
+            for (stream, consumer) in &self.consumers {
+                let lag = match self.poll(stream, consumer).await {
+                    Ok(lag) => lag,
+                    Err(e) => {
+                        warn!(stream = %stream, consumer = %consumer, error = %e, "Failed to read consumer lag");
+                        continue;
+                    }
+                };
+                debug!(
+                    stream = %stream,
+                    consumer = %consumer,
+                    pending = lag.num_pending,
+                    ack_pending = lag.num_ack_pending,
+                    redelivered = lag.num_redelivered,
+                    "Read consumer lag"
+                );
+
+                if let Some((client, subject)) = &self.status {
+                    let payload = serde_json::json!({ "event": "consumer_lag", "lag": lag });
+                    if let Err(e) = client.publish(subject.clone(), payload.to_string().into()).await {
+                        warn!(subject = %subject, error = %e, "Failed to publish consumer lag");
+                    }
+                }
+
+                self.latest
+                    .write()
+                    .expect("lag monitor poisoned")
+                    .insert((stream.clone(), consumer.clone()), lag);
+            }
+        }
+    }
+
+    async fn poll(&self, stream: &str, consumer: &str) -> Result<ConsumerLag> {
+        let handle = self
+            .jetstream
+            .get_consumer_from_stream::<consumer::Config, _, _>(consumer, stream)
+            .await?;
+        let info = handle.cached_info();
+
+        Ok(ConsumerLag {
+            stream: stream.to_string(),
+            consumer: consumer.to_string(),
+            num_pending: info.num_pending,
+            num_ack_pending: info.num_ack_pending,
+            num_redelivered: info.num_redelivered,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        })
+    }
+}
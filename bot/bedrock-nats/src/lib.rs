@@ -0,0 +1,453 @@
+//! NATS connection and JetStream setup shared by stratum, crust and mantle,
+//! so every component reads the same environment variables and retries the
+//! same way.
+
+pub mod coordination;
+pub mod dedup;
+pub mod events;
+pub mod kv;
+pub mod lag;
+pub mod partitions;
+pub mod rpc;
+pub mod signing;
+pub mod streams;
+
+use anyhow::{bail, Result};
+use async_nats::jetstream;
+use backon::{ExponentialBuilder, Retryable};
+use events::ConnectionEvents;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Subject prefix used when SUBJECT_PREFIX is not set.
+pub const DEFAULT_SUBJECT_PREFIX: &str = "discord";
+
+/// Schemes a server URL can use: NATS over TCP, with or without TLS, and NATS
+/// over WebSocket (`ws://`, or `wss://` with TLS) where only HTTP(S) traffic
+/// gets through.
+pub const URL_SCHEMES: [&str; 4] = ["nats", "tls", "ws", "wss"];
+
+/// Checks that every server of the comma-separated `url` uses one of
+/// [`URL_SCHEMES`], and none plain `ws://` when `tls_required`, which the
+/// WebSocket transport would connect to without TLS.
+pub fn check_url(url: &str, tls_required: bool) -> Result<()> {
+    for server in url.split(',').map(str::trim) {
+        let scheme = server.split_once("://").map_or("nats", |(scheme, _)| scheme);
+        if !URL_SCHEMES.contains(&scheme) {
+            bail!("{} must be a nats://, tls://, ws:// or wss:// URL", server);
+        }
+        if scheme == "ws" && tls_required {
+            bail!("{} is a plain WebSocket URL, TLS settings need wss://", server);
+        }
+    }
+    Ok(())
+}
+
+/// Name of a KV bucket or other shared resource for the cluster publishing
+/// under `subject_prefix`. The default prefix keeps the unscoped name.
+pub fn scoped_name(name: &str, subject_prefix: &str) -> String {
+    if subject_prefix == DEFAULT_SUBJECT_PREFIX {
+        name.to_string()
+    } else {
+        format!("{}-{}", name, subject_prefix.replace('.', "-"))
+    }
+}
+
+/// How a connection authenticates to NATS. Not Debug, so secrets stay out of
+/// the logs; `method` names the variant instead.
+#[derive(Clone, Default)]
+pub enum Auth {
+    #[default]
+    None,
+    /// Path of a `.creds` file
+    CredentialsFile(String),
+    /// Contents of a `.creds` file
+    Credentials(String),
+    /// NKey seed
+    NKey(String),
+    Token(String),
+    UserPassword { user: String, password: String },
+}
+
+impl Auth {
+    /// Reads NATS_CREDENTIALS_FILE, NATS_NKEY, NATS_TOKEN or NATS_USER and
+    /// NATS_PASSWORD, at most one method of which may be set.
+    pub fn from_env() -> Result<Self> {
+        let var = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+        match var("NATS_CREDENTIALS_FILE") {
+            Some(path) if [var("NATS_NKEY"), var("NATS_TOKEN"), var("NATS_USER")].iter().any(Option::is_some) => {
+                bail!("NATS_CREDENTIALS_FILE ({}) cannot be combined with NATS_NKEY, NATS_TOKEN or NATS_USER", path)
+            }
+            Some(path) => Ok(Self::CredentialsFile(path)),
+            None => Self::select(var("NATS_NKEY"), var("NATS_TOKEN"), var("NATS_USER"), var("NATS_PASSWORD")),
+        }
+    }
+
+    /// Picks the one of an NKey seed, a token or a user and password that is
+    /// set, failing when several are.
+    pub fn select(
+        nkey: Option<String>,
+        token: Option<String>,
+        user: Option<String>,
+        password: Option<String>,
+    ) -> Result<Self> {
+        match (nkey, token, user, password) {
+            (None, None, None, None) => Ok(Self::None),
+            (Some(seed), None, None, None) => Ok(Self::NKey(seed)),
+            (None, Some(token), None, None) => Ok(Self::Token(token)),
+            (None, None, Some(user), password) => Ok(Self::UserPassword {
+                user,
+                password: password.unwrap_or_default(),
+            }),
+            (None, None, None, Some(_)) => bail!("a NATS password needs a user"),
+            _ => bail!("only one of a NATS nkey, token or user and password may be set"),
+        }
+    }
+
+    pub fn method(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::CredentialsFile(_) | Self::Credentials(_) => "credentials",
+            Self::NKey(_) => "nkey",
+            Self::Token(_) => "token",
+            Self::UserPassword { .. } => "user",
+        }
+    }
+
+    async fn options(&self) -> Result<async_nats::ConnectOptions> {
+        Ok(match self {
+            Self::None => async_nats::ConnectOptions::new(),
+            Self::CredentialsFile(path) => async_nats::ConnectOptions::with_credentials_file(path).await?,
+            Self::Credentials(credentials) => async_nats::ConnectOptions::with_credentials(credentials)?,
+            Self::NKey(seed) => async_nats::ConnectOptions::with_nkey(seed.clone()),
+            Self::Token(token) => async_nats::ConnectOptions::with_token(token.clone()),
+            Self::UserPassword { user, password } => {
+                async_nats::ConnectOptions::with_user_and_password(user.clone(), password.clone())
+            }
+        })
+    }
+}
+
+/// TLS settings of a connection. Setting any of them requires TLS, as does a
+/// `tls://` or `wss://` URL.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tls {
+    /// CA bundle the server certificate is verified against, on top of the
+    /// system roots
+    pub ca_file: Option<String>,
+    /// Certificate and key files presented to the server for mutual TLS
+    pub client_certificate: Option<(String, String)>,
+}
+
+impl Tls {
+    /// Reads NATS_TLS_CA_FILE, NATS_TLS_CERT_FILE and NATS_TLS_KEY_FILE.
+    pub fn from_env() -> Result<Self> {
+        let var = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+        let client_certificate = match (var("NATS_TLS_CERT_FILE"), var("NATS_TLS_KEY_FILE")) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (None, None) => None,
+            _ => bail!("NATS_TLS_CERT_FILE and NATS_TLS_KEY_FILE must be set together"),
+        };
+
+        Ok(Self {
+            ca_file: var("NATS_TLS_CA_FILE"),
+            client_certificate,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.ca_file.is_some() || self.client_certificate.is_some()
+    }
+
+    fn apply(&self, mut options: async_nats::ConnectOptions) -> async_nats::ConnectOptions {
+        if let Some(ca_file) = &self.ca_file {
+            options = options.add_root_certificates(ca_file.into());
+        }
+        if let Some((cert_file, key_file)) = &self.client_certificate {
+            options = options.add_client_certificate(cert_file.into(), key_file.into());
+        }
+        options.require_tls(self.enabled())
+    }
+}
+
+/// JetStream API streams and KV buckets are reached through.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum JetStreamApi {
+    /// The local account's `$JS.API`.
+    #[default]
+    Default,
+    /// A JetStream domain, such as the hub's behind a leaf node.
+    Domain(String),
+    /// An API prefix imported from another account.
+    Prefix(String),
+}
+
+impl JetStreamApi {
+    /// Reads JETSTREAM_DOMAIN or JETSTREAM_API_PREFIX.
+    pub fn from_env() -> Result<Self> {
+        let var = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+        match (var("JETSTREAM_DOMAIN"), var("JETSTREAM_API_PREFIX")) {
+            (None, None) => Ok(Self::Default),
+            (Some(domain), None) => Ok(Self::Domain(domain)),
+            (None, Some(prefix)) => Ok(Self::Prefix(prefix)),
+            (Some(_), Some(_)) => bail!("JETSTREAM_DOMAIN and JETSTREAM_API_PREFIX are mutually exclusive"),
+        }
+    }
+
+    /// Subject prefix of this API, for reaching it from another domain or
+    /// account. None for the local `$JS.API`.
+    pub fn api_prefix(&self) -> Option<String> {
+        match self {
+            Self::Default => None,
+            Self::Domain(domain) => Some(format!("$JS.{}.API", domain)),
+            Self::Prefix(prefix) => Some(prefix.clone()),
+        }
+    }
+
+    /// JetStream context for the streams and KV buckets behind this API.
+    pub fn context(&self, client: &async_nats::Client) -> jetstream::Context {
+        match self {
+            Self::Default => jetstream::new(client.clone()),
+            Self::Domain(domain) => jetstream::with_domain(client.clone(), domain),
+            Self::Prefix(prefix) => jetstream::with_prefix(client.clone(), prefix),
+        }
+    }
+}
+
+/// How often connecting, stream setup and requests are attempted before
+/// giving up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, unlimited when None
+    pub max_retries: Option<usize>,
+    /// Longest wait between two attempts
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub const fn retries(max_retries: usize) -> Self {
+        Self {
+            max_retries: Some(max_retries),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    pub const fn forever() -> Self {
+        Self {
+            max_retries: None,
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    pub const fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub(crate) fn backoff(&self) -> ExponentialBuilder {
+        let backoff = ExponentialBuilder::default().with_max_delay(self.max_delay);
+        match self.max_retries {
+            Some(max_retries) => backoff.with_max_times(max_retries),
+            None => backoff.without_max_times(),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::retries(10)
+    }
+}
+
+/// Settings of a NATS connection, read from the environment with `from_env`
+/// or put together from code.
+#[derive(Clone)]
+pub struct ConnectionBuilder {
+    url: String,
+    auth: Auth,
+    tls: Tls,
+    jetstream_api: JetStreamApi,
+    retry: RetryPolicy,
+    subject_prefix: String,
+}
+
+impl ConnectionBuilder {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            auth: Auth::None,
+            tls: Tls::default(),
+            jetstream_api: JetStreamApi::Default,
+            retry: RetryPolicy::default(),
+            subject_prefix: DEFAULT_SUBJECT_PREFIX.to_string(),
+        }
+    }
+
+    /// NATS_URL with the authentication, TLS, JetStream API and subject
+    /// prefix (SUBJECT_PREFIX) from the environment.
+    pub fn from_env() -> Result<Self> {
+        let url = std::env::var("NATS_URL").unwrap_or_else(|_| "nats://localhost:4222".to_string());
+        let subject_prefix = std::env::var("SUBJECT_PREFIX").unwrap_or_else(|_| DEFAULT_SUBJECT_PREFIX.to_string());
+
+        Ok(Self::new(url)
+            .auth(Auth::from_env()?)
+            .tls(Tls::from_env()?)
+            .jetstream_api(JetStreamApi::from_env()?)
+            .subject_prefix(subject_prefix))
+    }
+
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn tls(mut self, tls: Tls) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn jetstream_api(mut self, jetstream_api: JetStreamApi) -> Self {
+        self.jetstream_api = jetstream_api;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn subject_prefix(mut self, subject_prefix: impl Into<String>) -> Self {
+        self.subject_prefix = subject_prefix.into();
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Connects, retrying according to the retry policy. The connection's
+    /// lifecycle events are logged and counted in [`Connection::events`].
+    pub async fn connect(self) -> Result<Connection> {
+        check_url(&self.url, self.tls.enabled())?;
+        let events = Arc::new(ConnectionEvents::default());
+        let operation = || async {
+            info!(url = %self.url, auth = self.auth.method(), tls = self.tls.enabled(), "Connecting to NATS");
+            let recorder = events.clone();
+            let url = self.url.clone();
+            let options = self
+                .tls
+                .apply(self.auth.options().await?)
+                .event_callback(move |event| {
+                    let recorder = recorder.clone();
+                    let url = url.clone();
+                    async move { recorder.record(&url, event) }
+                });
+            options.connect(self.url.as_str()).await.map_err(|e| {
+                error!(url = %self.url, error = %e, "Failed to connect to NATS, retrying...");
+                anyhow::Error::from(e)
+            })
+        };
+
+        let client = operation.retry(self.retry.backoff()).await.map_err(|e| {
+            error!(url = %self.url, error = %e, "Failed to connect to NATS after all retries");
+            e
+        })?;
+
+        info!(url = %self.url, "Connected to NATS successfully");
+        Ok(Connection {
+            jetstream: self.jetstream_api.context(&client),
+            client,
+            subject_prefix: self.subject_prefix,
+            retry: self.retry,
+            events,
+        })
+    }
+}
+
+/// A connected NATS client with the JetStream context and subject prefix it
+/// was built with.
+#[derive(Clone)]
+pub struct Connection {
+    client: async_nats::Client,
+    jetstream: jetstream::Context,
+    subject_prefix: String,
+    retry: RetryPolicy,
+    events: Arc<ConnectionEvents>,
+}
+
+impl Connection {
+    pub fn client(&self) -> &async_nats::Client {
+        &self.client
+    }
+
+    pub fn jetstream(&self) -> &jetstream::Context {
+        &self.jetstream
+    }
+
+    pub fn subject_prefix(&self) -> &str {
+        &self.subject_prefix
+    }
+
+    /// `suffix` under the subject prefix.
+    pub fn subject(&self, suffix: &str) -> String {
+        format!("{}.{}", self.subject_prefix, suffix)
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// Disconnects, reconnects and errors seen on this connection.
+    pub fn events(&self) -> &Arc<ConnectionEvents> {
+        &self.events
+    }
+
+    pub fn into_client(self) -> async_nats::Client {
+        self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn select_picks_the_one_method_set() {
+        assert!(matches!(Auth::select(None, None, None, None), Ok(Auth::None)));
+        assert!(matches!(Auth::select(set("SUAseed"), None, None, None), Ok(Auth::NKey(seed)) if seed == "SUAseed"));
+        assert!(matches!(Auth::select(None, set("s3cret"), None, None), Ok(Auth::Token(token)) if token == "s3cret"));
+        assert!(matches!(
+            Auth::select(None, None, set("bot"), set("hunter2")),
+            Ok(Auth::UserPassword { user, password }) if user == "bot" && password == "hunter2"
+        ));
+        assert!(matches!(
+            Auth::select(None, None, set("bot"), None),
+            Ok(Auth::UserPassword { password, .. }) if password.is_empty()
+        ));
+    }
+
+    #[test]
+    fn select_rejects_several_methods_or_a_lone_password() {
+        assert!(Auth::select(set("SUAseed"), set("s3cret"), None, None).is_err());
+        assert!(Auth::select(None, set("s3cret"), set("bot"), None).is_err());
+        assert!(Auth::select(None, None, None, set("hunter2")).is_err());
+    }
+
+    #[test]
+    fn check_url_accepts_every_scheme_and_bare_hosts() {
+        assert!(check_url("nats://a:4222, tls://b:4222,ws://c:80,wss://d:443", false).is_ok());
+        assert!(check_url("localhost:4222", false).is_ok());
+        assert!(check_url("tls://a:4222,wss://b:443", true).is_ok());
+    }
+
+    #[test]
+    fn check_url_rejects_unknown_schemes_and_plain_websockets_under_tls() {
+        assert!(check_url("nats://a:4222,http://b:80", false).is_err());
+        assert!(check_url("wss://a:443,ws://b:80", true).is_err());
+    }
+}
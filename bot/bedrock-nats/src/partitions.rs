@@ -0,0 +1,43 @@
+//! Events streams split by shard. With more than one partition, shard `id`
+//! publishes its events under `<prefix>.partitions.<id % partitions>`, which
+//! no unpartitioned `<prefix>.shards.>` stream overlaps, and partition `p`
+//! is the stream `<name>-<p>`.
+
+/// Partition the events of shard `shard_id` go to.
+pub fn partition_of(shard_id: u32, partitions: u32) -> u32 {
+    shard_id % partitions.max(1)
+}
+
+/// Subject shard `shard_id` publishes its gateway events on.
+pub fn event_subject(subject_prefix: &str, shard_id: u32, partitions: u32) -> String {
+    if partitions <= 1 {
+        format!("{}.shards.{}.events", subject_prefix, shard_id)
+    } else {
+        format!(
+            "{}.partitions.{}.shards.{}.events",
+            subject_prefix,
+            partition_of(shard_id, partitions),
+            shard_id
+        )
+    }
+}
+
+/// Names of the streams making up the events stream `name`, which is a
+/// single stream of that name when it is not partitioned.
+pub fn stream_names(name: &str, partitions: u32) -> Vec<String> {
+    if partitions <= 1 {
+        vec![name.to_string()]
+    } else {
+        (0..partitions).map(|partition| stream_name(name, partition)).collect()
+    }
+}
+
+/// Name of the stream of `partition` of the events stream `name`.
+pub fn stream_name(name: &str, partition: u32) -> String {
+    format!("{}-{}", name, partition)
+}
+
+/// Subject the stream of `partition` captures.
+pub fn stream_subject(subject_prefix: &str, partition: u32) -> String {
+    format!("{}.partitions.{}.>", subject_prefix, partition)
+}
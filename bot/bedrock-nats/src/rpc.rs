@@ -0,0 +1,150 @@
+//! Request-reply over core NATS with JSON bodies, for coordination calls such
+//! as startup grants and drain requests.
+
+use crate::RetryPolicy;
+use async_nats::client::RequestErrorKind;
+use async_nats::HeaderMap;
+use backon::Retryable;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Retries of a request nobody was listening for, unless set otherwise.
+pub const DEFAULT_RETRIES: usize = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RpcError {
+    /// Nobody listens on the subject, even after retrying
+    #[error("no responders on {subject}")]
+    NoResponders { subject: String },
+    /// The request went out but no reply came in time
+    #[error("no reply on {subject} within {timeout:?}")]
+    TimedOut { subject: String, timeout: Duration },
+    #[error("request on {subject} failed: {source}")]
+    Request {
+        subject: String,
+        source: async_nats::RequestError,
+    },
+    #[error("failed to encode request for {subject}: {source}")]
+    Encode { subject: String, source: serde_json::Error },
+    #[error("invalid reply on {subject}: {source}")]
+    Decode { subject: String, source: serde_json::Error },
+    /// The reply failed the request's verification, such as its signature
+    #[error("rejected reply on {subject}: {reason}")]
+    Rejected { subject: String, reason: String },
+}
+
+impl RpcError {
+    /// Whether the responder was absent or too slow, rather than the request
+    /// or its reply being broken.
+    pub fn is_unanswered(&self) -> bool {
+        matches!(self, Self::NoResponders { .. } | Self::TimedOut { .. })
+    }
+}
+
+type HeaderFn = Box<dyn Fn(&[u8]) -> HeaderMap + Send + Sync>;
+type VerifyFn = Box<dyn Fn(&async_nats::Message) -> Result<(), String> + Send + Sync>;
+
+/// How a request is sent. Requests nobody listens for are retried according
+/// to the retry policy; ones that time out are not, since the responder may
+/// have acted on them.
+pub struct Request {
+    timeout: Duration,
+    retry: RetryPolicy,
+    headers: Option<HeaderFn>,
+    verify: Option<VerifyFn>,
+}
+
+impl Request {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            retry: RetryPolicy::retries(DEFAULT_RETRIES),
+            headers: None,
+            verify: None,
+        }
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sends the headers `headers` builds from the encoded payload, such as
+    /// its signature.
+    pub fn headers(mut self, headers: impl Fn(&[u8]) -> HeaderMap + Send + Sync + 'static) -> Self {
+        self.headers = Some(Box::new(headers));
+        self
+    }
+
+    /// Only accepts replies `verify` passes, such as ones signed by the
+    /// responder. It gives the reason a reply is refused.
+    pub fn verify(mut self, verify: impl Fn(&async_nats::Message) -> Result<(), String> + Send + Sync + 'static) -> Self {
+        self.verify = Some(Box::new(verify));
+        self
+    }
+
+    /// Sends `message` on `subject` and decodes the reply.
+    pub async fn send<T: Serialize + ?Sized, R: DeserializeOwned>(
+        &self,
+        client: &async_nats::Client,
+        subject: impl Into<String>,
+        message: &T,
+    ) -> Result<R, RpcError> {
+        let subject = subject.into();
+        let payload = serde_json::to_vec(message).map_err(|source| RpcError::Encode {
+            subject: subject.clone(),
+            source,
+        })?;
+        let headers = self.headers.as_ref().map(|headers| headers(&payload));
+
+        let operation = || async {
+            let mut request = async_nats::Request::new()
+                .payload(payload.clone().into())
+                .timeout(Some(self.timeout));
+            if let Some(headers) = &headers {
+                request = request.headers(headers.clone());
+            }
+            client.send_request(subject.clone(), request).await
+        };
+
+        debug!(subject = %subject, timeout = ?self.timeout, "Sending request");
+        let reply = operation
+            .retry(self.retry.backoff())
+            .when(|e| e.kind() == RequestErrorKind::NoResponders)
+            .notify(|_, after| warn!(subject = %subject, retry_in = ?after, "No responders for request, retrying..."))
+            .await
+            .map_err(|source| match source.kind() {
+                RequestErrorKind::NoResponders => RpcError::NoResponders { subject: subject.clone() },
+                RequestErrorKind::TimedOut => RpcError::TimedOut {
+                    subject: subject.clone(),
+                    timeout: self.timeout,
+                },
+                _ => RpcError::Request {
+                    subject: subject.clone(),
+                    source,
+                },
+            })?;
+
+        debug!(subject = %subject, "Received reply");
+        if let Some(verify) = &self.verify {
+            verify(&reply).map_err(|reason| RpcError::Rejected {
+                subject: subject.clone(),
+                reason,
+            })?;
+        }
+        serde_json::from_slice(&reply.payload).map_err(|source| RpcError::Decode { subject, source })
+    }
+}
+
+/// Sends `message` on `subject` and decodes the reply, waiting up to
+/// `timeout` and retrying while nobody listens.
+pub async fn request<T: Serialize + ?Sized, R: DeserializeOwned>(
+    client: &async_nats::Client,
+    subject: impl Into<String>,
+    message: &T,
+    timeout: Duration,
+) -> Result<R, RpcError> {
+    Request::new(timeout).send(client, subject, message).await
+}
@@ -0,0 +1,135 @@
+//! HMAC signatures of coordination messages, which the operator signs with a
+//! cluster's coordination signing key and stratum verifies.
+
+use async_nats::HeaderMap;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub const SIGNATURE_HEADER: &str = "Bedrock-Signature";
+pub const TIMESTAMP_HEADER: &str = "Bedrock-Signature-Timestamp";
+const MAX_CLOCK_SKEW_SECS: u64 = 300;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum SignatureError {
+    #[error("message is not signed")]
+    Missing,
+    #[error("signature headers are malformed")]
+    Malformed,
+    #[error("signature timestamp is outside the allowed window")]
+    Expired,
+    #[error("signature does not match")]
+    Invalid,
+}
+
+/// Signs `payload` for `subject`. The subject is part of the signed material
+/// so a message cannot be replayed on another subject, such as another
+/// worker's.
+pub fn sign(key: &[u8], subject: &str, payload: &[u8]) -> HeaderMap {
+    let timestamp = unix_now().to_string();
+    let signature = mac(key, &timestamp, subject, payload).finalize().into_bytes();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(TIMESTAMP_HEADER, timestamp.as_str());
+    headers.insert(SIGNATURE_HEADER, hex::encode(signature).as_str());
+    headers
+}
+
+pub fn verify(key: &[u8], subject: &str, headers: Option<&HeaderMap>, payload: &[u8]) -> Result<(), SignatureError> {
+    verify_at(key, subject, headers, payload, unix_now())
+}
+
+/// Verifies a signature against the time the message was first received,
+/// which for replayed JetStream messages is the stream timestamp rather than now.
+pub fn verify_at(
+    key: &[u8],
+    subject: &str,
+    headers: Option<&HeaderMap>,
+    payload: &[u8],
+    received_at: u64,
+) -> Result<(), SignatureError> {
+    let headers = headers.ok_or(SignatureError::Missing)?;
+    let signature = headers.get(SIGNATURE_HEADER).ok_or(SignatureError::Missing)?;
+    let timestamp = headers.get(TIMESTAMP_HEADER).ok_or(SignatureError::Missing)?;
+
+    let signed_at: u64 = timestamp.as_str().parse().map_err(|_| SignatureError::Malformed)?;
+    if received_at.abs_diff(signed_at) > MAX_CLOCK_SKEW_SECS {
+        return Err(SignatureError::Expired);
+    }
+
+    let signature = hex::decode(signature.as_str()).map_err(|_| SignatureError::Malformed)?;
+    mac(key, timestamp.as_str(), subject, payload)
+        .verify_slice(&signature)
+        .map_err(|_| SignatureError::Invalid)
+}
+
+/// HMAC of `timestamp.subject`, a newline and the payload. Subjects cannot
+/// contain whitespace, so the newline keeps the subject and payload apart.
+fn mac(key: &[u8], timestamp: &str, subject: &str, payload: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(subject.as_bytes());
+    mac.update(b"\n");
+    mac.update(payload);
+    mac
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"secret";
+    const SUBJECT: &str = "discord.default.bot.workers.w-1.drain";
+    const PAYLOAD: &[u8] = br#"{"worker_id":"w-1"}"#;
+    /// HMAC-SHA256 of `1700000000.<SUBJECT>\n<PAYLOAD>` under KEY.
+    const SIGNATURE: &str = "ef1566c4a1015d28983b7d562cf2101c71725b99801f1f53d7ae0ed45bb74b00";
+
+    fn signed_at(timestamp: &str, signature: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMESTAMP_HEADER, timestamp);
+        headers.insert(SIGNATURE_HEADER, signature);
+        headers
+    }
+
+    #[test]
+    fn accepts_what_it_signs() {
+        let headers = sign(KEY, SUBJECT, PAYLOAD);
+        assert_eq!(verify(KEY, SUBJECT, Some(&headers), PAYLOAD), Ok(()));
+    }
+
+    #[test]
+    fn accepts_a_known_signature() {
+        let headers = signed_at("1700000000", SIGNATURE);
+        assert_eq!(verify_at(KEY, SUBJECT, Some(&headers), PAYLOAD, 1_700_000_060), Ok(()));
+    }
+
+    #[test]
+    fn rejects_a_signature_for_another_subject_or_payload() {
+        let headers = sign(KEY, SUBJECT, PAYLOAD);
+        let other_subject = "discord.default.bot.workers.w-2.drain";
+        assert_eq!(verify(KEY, other_subject, Some(&headers), PAYLOAD), Err(SignatureError::Invalid));
+        assert_eq!(verify(KEY, SUBJECT, Some(&headers), b"{}"), Err(SignatureError::Invalid));
+        assert_eq!(verify(b"other", SUBJECT, Some(&headers), PAYLOAD), Err(SignatureError::Invalid));
+    }
+
+    #[test]
+    fn rejects_missing_malformed_and_expired_signatures() {
+        assert_eq!(verify(KEY, SUBJECT, None, PAYLOAD), Err(SignatureError::Missing));
+        let headers = signed_at("yesterday", SIGNATURE);
+        assert_eq!(verify_at(KEY, SUBJECT, Some(&headers), PAYLOAD, 1_700_000_000), Err(SignatureError::Malformed));
+        let headers = signed_at("1700000000", SIGNATURE);
+        assert_eq!(
+            verify_at(KEY, SUBJECT, Some(&headers), PAYLOAD, 1_700_000_000 + MAX_CLOCK_SKEW_SECS + 1),
+            Err(SignatureError::Expired)
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{JetStreamApi, RetryPolicy};
+use anyhow::Result;
+use async_nats::jetstream::{self, kv, stream};
+use backon::Retryable;
+use tracing::{error, info};
+
+/// Creates the stream `config` describes unless it exists, retrying according
+/// to `retry`. An existing stream keeps its configuration, which may differ
+/// from `config`.
+pub async fn ensure_stream(
+    jetstream: &jetstream::Context,
+    config: &stream::Config,
+    retry: RetryPolicy,
+) -> Result<stream::Stream> {
+    let operation = || async {
+        jetstream.get_or_create_stream(config.clone()).await.map_err(|e| {
+            error!(stream.name = %config.name, error = %e, "Failed to get or create stream, retrying...");
+            anyhow::Error::from(e)
+        })
+    };
+
+    operation.retry(retry.backoff()).await.map_err(|e| {
+        error!(stream.name = %config.name, error = %e, "Failed to get or create stream after all retries");
+        e
+    })
+}
+
+/// Waits for a stream someone else provisions, retrying according to `retry`.
+pub async fn wait_for_stream(jetstream: &jetstream::Context, name: &str, retry: RetryPolicy) -> Result<()> {
+    let operation = || async {
+        jetstream.get_stream(name).await.map(drop).map_err(|e| {
+            error!(stream.name = %name, error = %e, "Stream not available yet, retrying...");
+            anyhow::Error::from(e)
+        })
+    };
+
+    operation.retry(retry.backoff()).await
+}
+
+/// Whether an existing stream already has the subjects, retention, limits,
+/// duplicate window and origins of `desired`. NATS reports unlimited as -1
+/// where 0 may have been asked for.
+pub fn same_settings(existing: &stream::Config, desired: &stream::Config) -> bool {
+    let limit = |value: i64| if value <= 0 { -1 } else { value };
+    let mirror = |config: &stream::Config| config.mirror.as_ref().map(|mirror| mirror.name.clone());
+    let sources = |config: &stream::Config| -> Vec<String> {
+        config.sources.iter().flatten().map(|source| source.name.clone()).collect()
+    };
+    existing.subjects == desired.subjects
+        && mirror(existing) == mirror(desired)
+        && sources(existing) == sources(desired)
+        && existing.retention == desired.retention
+        && existing.max_age == desired.max_age
+        && limit(existing.max_bytes) == limit(desired.max_bytes)
+        && limit(existing.max_messages) == limit(desired.max_messages)
+        && existing.max_messages_per_subject == desired.max_messages_per_subject
+        && existing.discard == desired.discard
+        && existing.storage == desired.storage
+        && existing.num_replicas == desired.num_replicas
+        && existing.duplicate_window == desired.duplicate_window
+}
+
+/// Updates the stream to `desired` unless `existing`, its current
+/// configuration, already matches. Returns whether it was updated. NATS
+/// rejects some changes, such as the storage type, on an existing stream.
+pub async fn update_stream(
+    jetstream: &jetstream::Context,
+    existing: &stream::Config,
+    desired: &stream::Config,
+) -> Result<bool> {
+    if same_settings(existing, desired) {
+        return Ok(false);
+    }
+
+    jetstream.update_stream(desired).await?;
+    info!(
+        stream.name = %desired.name,
+        max_messages = desired.max_messages,
+        previous_max_messages = existing.max_messages,
+        "Updated stream settings"
+    );
+    Ok(true)
+}
+
+/// The stream `name` behind `api` as the origin of a mirror or of a stream
+/// sourcing from it, reached through the API prefix when it lives in another
+/// domain or account.
+pub fn source(name: &str, api: &JetStreamApi) -> stream::Source {
+    stream::Source {
+        name: name.to_string(),
+        external: api.api_prefix().map(|api_prefix| stream::External {
+            api_prefix,
+            delivery_prefix: None,
+        }),
+        ..Default::default()
+    }
+}
+
+/// Creates the KV bucket `config` describes or updates an existing one to
+/// it, retrying according to `retry`.
+pub async fn open_kv(jetstream: &jetstream::Context, config: kv::Config, retry: RetryPolicy) -> Result<kv::Store> {
+    let operation = || async {
+        jetstream.create_or_update_key_value(config.clone()).await.map_err(|e| {
+            error!(bucket = %config.bucket, error = %e, "Failed to open KV bucket, retrying...");
+            anyhow::Error::from(e)
+        })
+    };
+
+    operation.retry(retry.backoff()).await
+}
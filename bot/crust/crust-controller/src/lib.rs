@@ -1,108 +1,967 @@
-use crust_types::{Context, CrustError, Result, ShardCluster, ShardClusterStatus};
-use chrono::Utc;
+use crust_types::{
+    push_reshard_record, set_condition, token_key, Condition, Context, CrustError, DryRunPlan, GatewayInfo, ReshardRecord,
+    ReshardStatus, ReshardStrategy, Result,
+    RolloutStatus, SessionStartLimit, ShardCluster, ShardClusterStatus, CONDITION_DEGRADED, CONDITION_PROGRESSING,
+    CONDITION_READY, CONDITION_RESHARDING, DRY_RUN_PLAN_ANNOTATION, RESHARD_TRIGGER_ANNOTATION, ROLLOUT_CANARY,
+    ROLLOUT_COMPLETED,
+};
+use chrono::{DateTime, Utc};
 use kube::{
-    api::{Api, Patch, PatchParams},
-    runtime::controller::Action,
+    api::{Api, ListParams, Patch, PatchParams},
+    runtime::{
+        controller::Action,
+        events::EventType,
+        finalizer::{finalizer, Error as FinalizerError, Event as FinalizerEvent},
+    },
     ResourceExt,
 };
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::sync::Arc;
 use std::time::Duration;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+const FINALIZER: &str = "crust.bedrock.dev/cleanup";
 
 pub async fn reconcile(cluster: Arc<ShardCluster>, ctx: Arc<Context>) -> Result<Action> {
+    let namespace = cluster.namespace().unwrap_or_else(|| "default".to_string());
+    let shard_clusters: Api<ShardCluster> = Api::namespaced(ctx.client.clone(), &namespace);
+
+    // Not even the finalizer is added in dry-run mode.
+    if ctx.config().dry_run {
+        return dry_run(&shard_clusters, &cluster, &ctx).await;
+    }
+
+    let result = finalizer(&shard_clusters, FINALIZER, cluster.clone(), |event| async {
+        match event {
+            FinalizerEvent::Apply(cluster) => apply(cluster, ctx.clone()).await,
+            FinalizerEvent::Cleanup(cluster) => cleanup(cluster, ctx.clone()).await,
+        }
+    })
+    .await
+    .map_err(|e| match e {
+        FinalizerError::ApplyFailed(e) | FinalizerError::CleanupFailed(e) => e,
+        other => CrustError::Other(format!("Finalizer error: {}", other)),
+    });
+
+    if result.is_ok() {
+        ctx.failures
+            .write()
+            .expect("failure registry poisoned")
+            .remove(&failure_key(&cluster));
+    }
+
+    if let Err(e) = &result {
+        crust_kubernetes::record_event(
+            &ctx.recorder,
+            &cluster,
+            EventType::Warning,
+            "ReconcileFailed",
+            "Reconcile",
+            e.to_string(),
+        ).await;
+    }
+
+    result
+}
+
+/// Works out the shard groups, workload changes and NATS signals apply would
+/// make for the cluster, logs them and writes them to the
+/// DRY_RUN_PLAN_ANNOTATION, changing nothing else.
+async fn dry_run(shard_clusters: &Api<ShardCluster>, cluster: &ShardCluster, ctx: &Context) -> Result<Action> {
+    let config = ctx.config();
+    let cluster = config.resolve(cluster);
+    let name = cluster.name_any();
+
+    let (workloads, workload_namespace) =
+        crust_kubernetes::remote::workload_target(&ctx.client, &ctx.remote_clients, &cluster).await?;
+    let namespace = cluster.namespace().unwrap_or_else(|| "default".to_string());
+    let token = crust_kubernetes::get_discord_token(&ctx.client, &namespace, &cluster.spec.discord_token_secret).await?;
+    let GatewayInfo { recommended_shards, max_concurrency, .. } =
+        crust_discord::get_cached_gateway_info(&ctx.gateway, &token).await?;
+
+    let current_shards = cluster.status.as_ref().and_then(|s| s.current_shards);
+    let shards = target_shards(&cluster, recommended_shards, max_concurrency)?;
+    let strategy = cluster.spec.reshard_strategy.unwrap_or_default();
+    let blue_green = strategy == ReshardStrategy::BlueGreen && current_shards.is_some_and(|current| current != shards);
+    let prefix = match strategy {
+        ReshardStrategy::InPlace => "stratum".to_string(),
+        ReshardStrategy::BlueGreen => format!("stratum-s{}", shards),
+    };
+    let shard_groups = crust_kubernetes::calculate_shard_groups(
+        &prefix,
+        shards,
+        cluster.spec.shards_per_replica,
+        cluster.spec.replicas_per_shard_group,
+    );
+
+    let mut actions =
+        crust_kubernetes::plan_deployments(&workloads, &workload_namespace, &cluster, &config.subject_root, &shard_groups, shards, max_concurrency)
+            .await?;
+    // The old set of a blue/green reshard is only deleted once the new one
+    // is up.
+    if blue_green {
+        actions.retain(|action| !action.starts_with("delete "));
+        actions.push("delete the current shard groups once the new ones are ready".to_string());
+    }
+    if current_shards != Some(shards) {
+        if current_shards.is_some() && !blue_green {
+            actions.push(format!("send reshard signal for {} shards", shards));
+        }
+        actions.push(format!("publish startup coordination for {} shard groups", shard_groups.len()));
+    }
+
+    for action in &actions {
+        info!(cluster = %name, "Dry run: would {}", action);
+    }
+    let plan = serde_json::to_string(&DryRunPlan { shards, shard_groups, actions })?;
+    // Annotating the cluster makes it reconcile again, so only write a plan
+    // that changed.
+    if cluster.annotations().get(DRY_RUN_PLAN_ANNOTATION) != Some(&plan) {
+        let patch = serde_json::json!({ "metadata": { "annotations": { DRY_RUN_PLAN_ANNOTATION: plan } } });
+        shard_clusters
+            .patch(&name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await?;
+    }
+
+    Ok(Action::requeue(config.resync_interval))
+}
+
+/// The shard count the cluster should run: `spec.shard_count_override` when
+/// set, otherwise Discord's recommendation unless it is within the reshard
+/// threshold of the current count.
+fn target_shards(cluster: &ShardCluster, recommended_shards: u32, max_concurrency: u32) -> Result<u32> {
+    if let Some(shards) = cluster.spec.shard_count_override {
+        // Discord rejects shard counts that do not split evenly into identify
+        // buckets.
+        if shards == 0 || shards % max_concurrency.max(1) != 0 {
+            return Err(CrustError::Validation(format!(
+                "shard_count_override {} is not a multiple of the bot's max_concurrency {}",
+                shards, max_concurrency
+            )));
+        }
+        if shards != recommended_shards {
+            info!(cluster = %cluster.name_any(), shards, recommended_shards, "Using the shard count override instead of the recommended count");
+        }
+        return Ok(shards);
+    }
+
+    // Small swings in Discord's recommendation are not worth a reshard.
+    let current_shards = cluster.status.as_ref().and_then(|s| s.current_shards);
+    match (current_shards, cluster.spec.reshard_threshold_percent) {
+        (Some(current), Some(threshold))
+            if current > 0 && recommended_shards.abs_diff(current) * 100 <= current * threshold =>
+        {
+            if recommended_shards != current {
+                info!(
+                    cluster = %cluster.name_any(),
+                    current_shards = current,
+                    recommended_shards,
+                    threshold_percent = threshold,
+                    "Recommended shard count within the reshard threshold, keeping the current count"
+                );
+            }
+            Ok(current)
+        }
+        _ => Ok(recommended_shards),
+    }
+}
+
+/// When the next periodic reshard is due: `reshard_interval_hours` after the
+/// last one plus the cluster's jitter, or right away once the trigger
+/// annotation was set after the last reshard.
+fn next_reshard_at(cluster: &ShardCluster, last_reshard: DateTime<Utc>, jitter_percent: u64) -> DateTime<Utc> {
+    let triggered = cluster
+        .annotations()
+        .get(RESHARD_TRIGGER_ANNOTATION)
+        .and_then(|trigger| DateTime::parse_from_rfc3339(trigger).ok())
+        .is_some_and(|trigger| trigger > last_reshard);
+    if triggered {
+        return last_reshard;
+    }
+
+    let interval_secs = cluster.spec.reshard_interval_hours * 3600;
+    let jitter_secs = reshard_jitter(cluster, interval_secs * jitter_percent / 100);
+    last_reshard + chrono::Duration::seconds((interval_secs + jitter_secs) as i64)
+}
+
+/// A stable per-cluster offset below `span`, so clusters created together
+/// do not all reshard at the same moment.
+fn reshard_jitter(cluster: &ShardCluster, span: u64) -> u64 {
+    if span == 0 {
+        return 0;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    cluster.namespace().hash(&mut hasher);
+    cluster.name_any().hash(&mut hasher);
+    hasher.finish() % span
+}
+
+/// Whether `shards_per_replica` or `shard_count_override` no longer matches
+/// the recorded shard groups, which takes a reshard to apply.
+fn layout_changed(cluster: &ShardCluster, status: &ShardClusterStatus) -> bool {
+    let Some(current_shards) = status.current_shards else {
+        return false;
+    };
+    if cluster.spec.shard_count_override.is_some_and(|shards| shards != current_shards) {
+        return true;
+    }
+    let desired = crust_kubernetes::calculate_shard_groups(
+        "",
+        current_shards,
+        cluster.spec.shards_per_replica,
+        cluster.spec.replicas_per_shard_group,
+    );
+
+    desired.len() != status.shard_groups.len()
+        || desired
+            .iter()
+            .zip(&status.shard_groups)
+            .any(|(desired, group)| desired.shard_start != group.shard_start || desired.shard_end != group.shard_end)
+}
+
+/// Issues the stratum pods NATS credentials with the account signing key of
+/// `spec.nats_account_signing_secret`. Credentials issued from the same key
+/// and permissions are kept, so the pods are not handed new ones every resync.
+async fn reconcile_nats_credentials(ctx: &Context, cluster: &ShardCluster) -> Result<()> {
+    let Some(signing_secret) = &cluster.spec.nats_account_signing_secret else {
+        return Ok(());
+    };
+    let namespace = cluster.namespace().unwrap_or_else(|| "default".to_string());
+
+    let seed = crust_kubernetes::get_secret_value(&ctx.client, &namespace, signing_secret, "seed").await?;
+    let seed = String::from_utf8_lossy(&seed).trim().to_string();
+    let account = crust_kubernetes::get_secret_value(&ctx.client, &namespace, signing_secret, "account")
+        .await
+        .ok()
+        .map(|account| String::from_utf8_lossy(&account).trim().to_string());
+
+    let subject_root = ctx.config().subject_root;
+    let mut hasher = DefaultHasher::new();
+    (&seed, &account, crust_nats::accounts::worker_permissions(cluster, &subject_root)).hash(&mut hasher);
+    let issued_from = format!("{:016x}", hasher.finish());
+
+    let issued = crust_kubernetes::apply_issued_credentials(&ctx.client, &namespace, cluster, &issued_from, || {
+        crust_nats::accounts::issue_user_credentials(&seed, account.as_deref(), cluster, &subject_root)
+    })
+    .await?;
+    if issued {
+        info!(cluster = %cluster.name_any(), subject_prefix = %cluster.subject_prefix(&subject_root), "Issued NATS credentials");
+        crust_kubernetes::record_event(
+            &ctx.recorder,
+            cluster,
+            EventType::Normal,
+            "IssuedNatsCredentials",
+            "IssueCredentials",
+            format!("Issued NATS credentials limited to {}.>", cluster.subject_prefix(&subject_root)),
+        ).await;
+    }
+    Ok(())
+}
+
+/// Drains the workers of deployments that do not belong to `shard_groups`, so
+/// they stop their shards before the deployments are deleted. Runs before the
+/// remaining groups are updated, so no shard is held by two workers at once.
+async fn drain_removed_groups(
+    ctx: &Context,
+    cluster: &ShardCluster,
+    workloads: &kube::Client,
+    workload_namespace: &str,
+    shard_groups: &[crust_types::ShardGroup],
+) -> Result<()> {
+    let removed = crust_kubernetes::removed_deployments(workloads, workload_namespace, cluster, shard_groups).await?;
+    if removed.is_empty() {
+        return Ok(());
+    }
+    let namespace = cluster.namespace().unwrap_or_else(|| "default".to_string());
+    let signing_key = match &cluster.spec.coordination_signing_secret {
+        Some(secret) => Some(crust_kubernetes::get_signing_key(&ctx.client, &namespace, secret).await?),
+        None => None,
+    };
+
+    let timed_out =
+        crust_nats::drain_workers(&ctx.nats_client, cluster, &ctx.config().subject_root, &removed, signing_key.as_deref())
+            .await?;
+    if !timed_out.is_empty() {
+        crust_kubernetes::record_event(
+            &ctx.recorder,
+            cluster,
+            EventType::Warning,
+            "DrainTimedOut",
+            "Reconcile",
+            format!("Deleting {} without a drain report", timed_out.join(", ")),
+        ).await;
+    }
+    Ok(())
+}
+
+/// Hands the limits Discord reported to the identify broker.
+fn update_identify_budget(
+    ctx: &Context,
+    cluster: &ShardCluster,
+    token: &str,
+    max_concurrency: u32,
+    session_start_limit: Option<SessionStartLimit>,
+) {
+    ctx.identify
+        .write()
+        .expect("identify registry poisoned")
+        .update_limits(
+            &cluster.subject_prefix(&ctx.config().subject_root),
+            &token_key(token),
+            max_concurrency,
+            session_start_limit,
+            Utc::now(),
+        );
+}
+
+/// Hands the cluster's coordination signing key to the identify broker, which
+/// signs its grants with it.
+fn update_signing_key(ctx: &Context, cluster: &ShardCluster, signing_key: Option<Vec<u8>>) {
+    let subject_prefix = cluster.subject_prefix(&ctx.config().subject_root);
+    let mut signing_keys = ctx.signing_keys.write().expect("signing key registry poisoned");
+    match signing_key {
+        Some(key) => signing_keys.insert(subject_prefix, key),
+        None => signing_keys.remove(&subject_prefix),
+    };
+}
+
+async fn cleanup(cluster: Arc<ShardCluster>, ctx: Arc<Context>) -> Result<Action> {
+    let name = cluster.name_any();
+    let namespace = cluster.namespace().unwrap_or_else(|| "default".to_string());
+
+    info!(cluster = %name, namespace = %namespace, "ShardCluster deleted, removing managed deployments");
+
+    match crust_kubernetes::remote::workload_target(&ctx.client, &ctx.remote_clients, &cluster).await {
+        Ok((workloads, workload_namespace)) => {
+            crust_kubernetes::delete_deployments(&workloads, &workload_namespace, &name).await?;
+            if cluster.spec.remote.is_some() {
+                crust_kubernetes::remote::delete_remote_secrets(&workloads, &workload_namespace, &name).await?;
+            }
+        }
+        // Without its kubeconfig the remote cluster is out of reach, so let
+        // the ShardCluster go rather than block its deletion forever.
+        Err(CrustError::Kube(kube::Error::Api(response))) if response.code == 404 => {
+            warn!(cluster = %name, "Kubeconfig secret is gone, leaving the remote workloads behind");
+        }
+        Err(e) => return Err(e),
+    }
+
+    if let Some(status) = &cluster.status {
+        let mut workers = ctx.workers.write().expect("worker registry poisoned");
+        let mut reshards = ctx.reshards.write().expect("reshard registry poisoned");
+        let mut startups = ctx.startups.write().expect("startup registry poisoned");
+        let pending = status.pending_shard_groups.iter().flatten();
+        for group in status.shard_groups.iter().chain(pending) {
+            workers.remove(&group.deployment_name);
+            reshards.remove(&group.deployment_name);
+            startups.remove(&group.deployment_name);
+        }
+    }
+    let subject_prefix = cluster.subject_prefix(&ctx.config().subject_root);
+    ctx.identify
+        .write()
+        .expect("identify registry poisoned")
+        .remove(&subject_prefix);
+    ctx.shard_statuses
+        .write()
+        .expect("shard status registry poisoned")
+        .remove(&subject_prefix);
+    ctx.processor_statuses
+        .write()
+        .expect("processor status registry poisoned")
+        .remove(&subject_prefix);
+    ctx.signing_keys
+        .write()
+        .expect("signing key registry poisoned")
+        .remove(&subject_prefix);
+
+    Ok(Action::await_change())
+}
+
+async fn apply(cluster: Arc<ShardCluster>, ctx: Arc<Context>) -> Result<Action> {
     let name = cluster.name_any();
     let namespace = cluster.namespace().unwrap_or_else(|| "default".to_string());
     
     info!(cluster = %name, namespace = %namespace, "Reconciling ShardCluster");
 
+    let shard_clusters: Api<ShardCluster> = Api::namespaced(ctx.client.clone(), &namespace);
+
+    if cluster.spec.suspend.unwrap_or(false) {
+        info!(cluster = %name, "ShardCluster is suspended, leaving its deployments alone");
+
+        let mut conditions = cluster
+            .status
+            .as_ref()
+            .map(|status| status.conditions.clone())
+            .unwrap_or_default();
+        if set_condition(&mut conditions, CONDITION_PROGRESSING, false, "Suspended", "Reconciliation is suspended".to_string()) {
+            crust_kubernetes::record_event(
+                &ctx.recorder,
+                &cluster,
+                EventType::Normal,
+                "Suspended",
+                "Reconcile",
+                "Reconciliation suspended".to_string(),
+            ).await;
+            patch_conditions(&shard_clusters, &name, &conditions).await;
+        }
+
+        return Ok(Action::await_change());
+    }
+
+    let config = ctx.config();
+    let cluster = start_rollout(&ctx, &shard_clusters, Arc::new(config.resolve(&cluster))).await?;
+
+    let (workloads, workload_namespace) =
+        crust_kubernetes::remote::workload_target(&ctx.client, &ctx.remote_clients, &cluster).await?;
+    reconcile_nats_credentials(&ctx, &cluster).await?;
+    crust_kubernetes::remote::sync_remote_secrets(&ctx.client, &workloads, &workload_namespace, &cluster).await?;
+    let token = crust_kubernetes::get_discord_token(&ctx.client, &namespace, &cluster.spec.discord_token_secret).await?;
+    let signing_key = match &cluster.spec.coordination_signing_secret {
+        Some(secret) => Some(crust_kubernetes::get_signing_key(&ctx.client, &namespace, secret).await?),
+        None => None,
+    };
+    update_signing_key(&ctx, &cluster, signing_key.clone());
+
     if let Some(status) = &cluster.status {
         if let Some(last_reshard) = status.last_reshard {
-            let time_since_last_update = Utc::now().signed_duration_since(last_reshard);
-            if time_since_last_update.num_minutes() < 10 {
+            let next_reshard = next_reshard_at(&cluster, last_reshard, config.reshard_jitter_percent);
+            let until_next_reshard = (next_reshard - Utc::now())
+                .to_std()
+                .ok()
+                .filter(|until| !until.is_zero() && !layout_changed(&cluster, status));
+            if let Some(until_next_reshard) = until_next_reshard {
                 info!(
                     cluster = %name,
-                    minutes_since_update = time_since_last_update.num_minutes(),
-                    "Recent update detected, skipping Discord API call"
+                    next_reshard = %next_reshard.to_rfc3339(),
+                    "Reshard not due yet, skipping Discord API call"
                 );
-                return Ok(Action::requeue(Duration::from_secs(600)));
+
+                // Owned deployments may have been edited or deleted since the
+                // last reshard, so put them back to the recorded layout.
+                if let (Some(total_shards), Some(max_concurrency)) = (status.current_shards, status.max_concurrency) {
+                    update_identify_budget(&ctx, &cluster, &token, max_concurrency, None);
+                    crust_nats::reconcile_event_stream(&ctx.nats_client, &cluster, &config.subject_root).await?;
+                    // The coordination stream only keeps a record, so the workers do not wait for it.
+                    if let Err(e) = crust_nats::reconcile_coordination_stream(&ctx.nats_client, &cluster, &config.subject_root, config.coordination_stream).await {
+                        warn!(cluster = %cluster.name_any(), error = %e, "Failed to reconcile coordination stream");
+                    }
+                    match (&status.pending_shard_groups, &status.reshard) {
+                        (Some(pending), Some(reshard)) => {
+                            crust_kubernetes::update_deployments(
+                                &workloads,
+                                &ctx.recorder,
+                                &workload_namespace,
+                                &cluster,
+                                &config.subject_root,
+                                &status.shard_groups,
+                                total_shards,
+                                max_concurrency,
+                            ).await?;
+                            crust_kubernetes::update_deployments(
+                                &workloads,
+                                &ctx.recorder,
+                                &workload_namespace,
+                                &cluster,
+                                &config.subject_root,
+                                pending,
+                                reshard.target_shards,
+                                max_concurrency,
+                            ).await?;
+                        }
+                        _ => {
+                            drain_removed_groups(&ctx, &cluster, &workloads, &workload_namespace, &status.shard_groups).await?;
+                            crust_kubernetes::create_or_update_deployments(
+                                &workloads,
+                                &ctx.recorder,
+                                &workload_namespace,
+                                &cluster,
+                                &config.subject_root,
+                                &status.shard_groups,
+                                total_shards,
+                                max_concurrency,
+                            ).await?;
+                        }
+                    }
+                    crust_kubernetes::create_or_update_pdb(&workloads, &workload_namespace, &cluster).await?;
+                    crust_kubernetes::reconcile_network_policy(&workloads, &workload_namespace, &cluster).await?;
+                    crust_kubernetes::reconcile_service_monitor(&workloads, &workload_namespace, &cluster).await?;
+                    crust_kubernetes::reconcile_scaled_object(&workloads, &workload_namespace, &cluster, &config.subject_root).await?;
+
+                    let mut shard_groups = status.shard_groups.clone();
+                    if crust_kubernetes::observe_readiness(&workloads, &workload_namespace, &cluster, &mut shard_groups).await? {
+                        let patch = serde_json::json!({ "status": { "shard_groups": shard_groups } });
+                        crust_kubernetes::apply_status(&shard_clusters, &name, &patch).await?;
+                    }
+                }
+
+                return Ok(Action::requeue(until_next_reshard.min(config.resync_interval)));
             }
         }
     }
 
-    let (recommended_shards, max_concurrency) = crust_discord::get_gateway_info(&util::CLIENT).await?;
+    let mut conditions = cluster
+        .status
+        .as_ref()
+        .map(|status| status.conditions.clone())
+        .unwrap_or_default();
+
+    let gateway_info = crust_discord::get_cached_gateway_info(&ctx.gateway, &token).await;
+    let GatewayInfo { recommended_shards, max_concurrency, session_start_limit } = match gateway_info {
+        Ok(info) => info,
+        Err(e) => {
+            if set_condition(&mut conditions, CONDITION_DEGRADED, true, "GatewayInfoUnavailable", e.to_string()) {
+                patch_conditions(&shard_clusters, &name, &conditions).await;
+            }
+            return Err(e);
+        }
+    };
     info!(
         cluster = %name, 
         recommended_shards, 
         max_concurrency,
         "Got Discord gateway info"
     );
+    update_identify_budget(&ctx, &cluster, &token, max_concurrency, Some(session_start_limit));
 
-    let shard_clusters: Api<ShardCluster> = Api::namespaced(ctx.client.clone(), &namespace);
-    
+    let current_shards = cluster.status.as_ref().and_then(|s| s.current_shards);
+    let recommended_shards = match target_shards(&cluster, recommended_shards, max_concurrency) {
+        Ok(shards) => shards,
+        Err(e) => {
+            if set_condition(&mut conditions, CONDITION_DEGRADED, true, "InvalidShardCountOverride", e.to_string()) {
+                patch_conditions(&shard_clusters, &name, &conditions).await;
+            }
+            return Err(e);
+        }
+    };
+
+    // Changing the shard count of a running cluster disconnects every shard,
+    // so keep it to the configured window. The first deployment is not held back.
+    if let (Some(current), Some(window)) = (current_shards, &cluster.spec.reshard_window) {
+        let in_window = window.contains(Utc::now()).map_err(CrustError::Validation)?;
+        if current != recommended_shards && !in_window {
+            let message = format!(
+                "Resharding from {} to {} shards waits for the reshard window {}-{}",
+                current, recommended_shards, window.start, window.end
+            );
+            info!(cluster = %name, current_shards = current, recommended_shards, "Deferring reshard until the reshard window opens");
+
+            defer_reshard(&ctx, &shard_clusters, &cluster, recommended_shards, message).await?;
+            return Ok(Action::requeue(config.reshard_window_recheck));
+        }
+    }
+
+    // Every shard identifies again after a reshard, so wait for the session
+    // start budget to reset rather than running out halfway through.
+    if current_shards != Some(recommended_shards) && session_start_limit.remaining < recommended_shards {
+        let retry_after = Duration::from_millis(session_start_limit.reset_after_ms.max(60_000));
+        let message = format!(
+            "Resharding to {} shards needs {} identifies but only {} of {} session starts remain, retrying in {}s",
+            recommended_shards,
+            recommended_shards,
+            session_start_limit.remaining,
+            session_start_limit.total,
+            retry_after.as_secs()
+        );
+        warn!(cluster = %name, remaining = session_start_limit.remaining, needed = recommended_shards, "Deferring reshard until session starts reset");
+
+        defer_reshard(&ctx, &shard_clusters, &cluster, recommended_shards, message).await?;
+        return Ok(Action::requeue(retry_after));
+    }
+
+    // Every reshard reconnects all of a cluster's shards, so spread them out
+    // when many clusters come due together.
+    if current_shards.is_some_and(|current| current != recommended_shards) && config.max_concurrent_reshards > 0 {
+        let resharding = count_resharding_clusters(&ctx, &cluster).await?;
+        if resharding >= config.max_concurrent_reshards {
+            let message = format!(
+                "Resharding to {} shards waits for {} other clusters to finish resharding",
+                recommended_shards, resharding
+            );
+            info!(cluster = %name, resharding, limit = config.max_concurrent_reshards, "Deferring reshard until other clusters finish");
+
+            defer_reshard(&ctx, &shard_clusters, &cluster, recommended_shards, message).await?;
+            return Ok(Action::requeue(config.resync_interval));
+        }
+    }
+
+    if current_shards != Some(recommended_shards) {
+        let note = match current_shards {
+            Some(current_shards) => format!("Resharding from {} to {} shards", current_shards, recommended_shards),
+            None => format!("Starting with {} shards", recommended_shards),
+        };
+        crust_kubernetes::record_event(
+            &ctx.recorder,
+            &cluster,
+            EventType::Normal,
+            "ReshardTriggered",
+            "Reshard",
+            note,
+        ).await;
+    }
+
+    let strategy = cluster.spec.reshard_strategy.unwrap_or_default();
+    // A blue/green reshard only applies when there is a running set to keep
+    // serving while the new one starts.
+    let blue_green = strategy == ReshardStrategy::BlueGreen
+        && current_shards.is_some_and(|current_shards| current_shards != recommended_shards);
+
+    if blue_green && cluster.status.as_ref().is_some_and(|s| s.pending_shard_groups.is_some()) {
+        info!(cluster = %name, "Blue/green reshard still in progress, waiting for it to finish");
+        return Ok(Action::requeue(Duration::from_secs(60)));
+    }
+
+    let prefix = match strategy {
+        ReshardStrategy::InPlace => "stratum".to_string(),
+        ReshardStrategy::BlueGreen => format!("stratum-s{}", recommended_shards),
+    };
     let new_shard_groups = crust_kubernetes::calculate_shard_groups(
+        &prefix,
         recommended_shards,
         cluster.spec.shards_per_replica,
+        cluster.spec.replicas_per_shard_group,
     );
+
+    if blue_green {
+        // Names are tied to the shard count, so drop anything an earlier
+        // reshard to the same count left behind.
+        let mut startups = ctx.startups.write().expect("startup registry poisoned");
+        for group in &new_shard_groups {
+            startups.remove(&group.deployment_name);
+        }
+    }
     
     let current_shard_groups = cluster.status.as_ref()
         .map(|s| s.shard_groups.len())
         .unwrap_or(0);
     
-    let needs_deployment_update = current_shard_groups != new_shard_groups.len();
-    
-    if needs_deployment_update {
+    if current_shard_groups != new_shard_groups.len() {
         info!(
             cluster = %name,
             current_groups = current_shard_groups,
             new_groups = new_shard_groups.len(),
             "Shard group count changed, updating deployments"
         );
-        
+    }
+
+    // Workers may answer as soon as the deployments or signals below reach
+    // them, and replies from before the start are dropped as stale.
+    let started_at = Utc::now();
+
+    crust_nats::reconcile_event_stream(&ctx.nats_client, &cluster, &config.subject_root).await?;
+    // The coordination stream only keeps a record, so the workers do not wait for it.
+    if let Err(e) = crust_nats::reconcile_coordination_stream(&ctx.nats_client, &cluster, &config.subject_root, config.coordination_stream).await {
+        warn!(cluster = %cluster.name_any(), error = %e, "Failed to reconcile coordination stream");
+    }
+
+    if blue_green {
+        crust_kubernetes::update_deployments(
+            &workloads,
+            &ctx.recorder,
+            &workload_namespace,
+            &cluster,
+            &config.subject_root,
+            &new_shard_groups,
+            recommended_shards,
+            max_concurrency,
+        ).await?;
+    } else {
+        drain_removed_groups(&ctx, &cluster, &workloads, &workload_namespace, &new_shard_groups).await?;
         crust_kubernetes::create_or_update_deployments(
-            &ctx.client,
-            &namespace,
+            &workloads,
+            &ctx.recorder,
+            &workload_namespace,
             &cluster,
+            &config.subject_root,
             &new_shard_groups,
             recommended_shards,
             max_concurrency,
         ).await?;
     }
-    
-    crust_nats::send_reshard_signal(&ctx.nats_client, recommended_shards).await?;
+    crust_kubernetes::create_or_update_pdb(&workloads, &workload_namespace, &cluster).await?;
+    crust_kubernetes::reconcile_network_policy(&workloads, &workload_namespace, &cluster).await?;
+    crust_kubernetes::reconcile_service_monitor(&workloads, &workload_namespace, &cluster).await?;
+    crust_kubernetes::reconcile_scaled_object(&workloads, &workload_namespace, &cluster, &config.subject_root).await?;
+
+    // The old set keeps its shard count during a blue/green reshard and is
+    // deleted once the new one is up, so only in-place reshards signal it.
+    if !blue_green {
+        crust_nats::send_reshard_signal(&ctx.nats_client, &cluster, &config.subject_root, recommended_shards, signing_key.as_deref()).await?;
+    }
     
     crust_nats::publish_startup_coordination(
         &ctx.nats_client,
-        &name,
+        &cluster,
+        &config.subject_root,
         max_concurrency,
         recommended_shards,
-        &new_shard_groups
+        &new_shard_groups,
+        &session_start_limit,
+        signing_key.as_deref(),
     ).await?;
 
+    let message = format!(
+        "Resharding to {} shards across {} shard groups",
+        recommended_shards,
+        new_shard_groups.len()
+    );
+    set_condition(&mut conditions, CONDITION_RESHARDING, true, "ReshardStarted", message.clone());
+    set_condition(&mut conditions, CONDITION_PROGRESSING, true, "DeploymentsUpdated", message);
+    if !conditions.iter().any(|condition| condition.type_ == CONDITION_READY) {
+        set_condition(&mut conditions, CONDITION_READY, false, "Provisioning", "Waiting for workers to report in");
+    }
+    if !conditions.iter().any(|condition| condition.type_ == CONDITION_DEGRADED) {
+        set_condition(&mut conditions, CONDITION_DEGRADED, false, "Provisioning", "");
+    }
+
+    let (live_shards, mut live_groups, pending_shard_groups) = if blue_green {
+        let status = cluster.status.as_ref().expect("blue/green reshards start from an existing status");
+        (current_shards, status.shard_groups.clone(), Some(new_shard_groups.clone()))
+    } else {
+        (Some(recommended_shards), new_shard_groups.clone(), None)
+    };
+
+    crust_kubernetes::observe_readiness(&workloads, &workload_namespace, &cluster, &mut live_groups).await?;
+
+    let rollout = cluster.status.as_ref().and_then(|s| s.rollout.clone());
+    let rolled_out_image = match rollout.as_ref().filter(|rollout| rollout.image == cluster.spec.image) {
+        Some(rollout) if rollout.phase != ROLLOUT_COMPLETED => rollout.previous_image.clone(),
+        _ => cluster.spec.image.clone(),
+    };
+
+    let mut reshard_history = cluster.status.as_ref().map(|s| s.reshard_history.clone()).unwrap_or_default();
+    let reshard_reason = match current_shards {
+        None => Some("Initial deployment".to_string()),
+        Some(current) if current != recommended_shards && cluster.spec.shard_count_override.is_some() => {
+            Some("Pinned by shard_count_override".to_string())
+        }
+        Some(current) if current != recommended_shards => {
+            Some(format!("Discord recommended {} shards", recommended_shards))
+        }
+        Some(_) if cluster.status.as_ref().is_some_and(|status| layout_changed(&cluster, status)) => {
+            Some(format!("shards_per_replica changed to {}", cluster.spec.shards_per_replica))
+        }
+        Some(_) => None,
+    };
+    if let Some(reason) = reshard_reason {
+        push_reshard_record(
+            &mut reshard_history,
+            ReshardRecord {
+                started_at,
+                from_shards: current_shards,
+                to_shards: recommended_shards,
+                reason,
+                outcome: "InProgress".to_string(),
+                finished_at: None,
+            },
+        );
+    }
+
     let status = ShardClusterStatus {
-        current_shards: Some(recommended_shards),
-        last_reshard: Some(Utc::now()),
-        shard_groups: new_shard_groups,
-        phase: "Active".to_string(),
+        current_shards: live_shards,
+        last_reshard: Some(started_at),
+        reshard: Some(ReshardStatus {
+            target_shards: recommended_shards,
+            phase: "InProgress".to_string(),
+            workers_total: new_shard_groups.len() as u32,
+            workers_completed: 0,
+            workers_failed: 0,
+            shards_reidentified: 0,
+            started_at: Some(started_at),
+            completed_at: None,
+            message: None,
+        }),
+        shard_groups: live_groups,
+        conditions,
+        max_concurrency: Some(max_concurrency),
+        pending_shard_groups,
+        image: Some(rolled_out_image),
+        rollout,
+        sizing: cluster.status.as_ref().and_then(|s| s.sizing.clone()),
+        shard_health: cluster.status.as_ref().and_then(|s| s.shard_health),
+        processor_health: cluster.status.as_ref().and_then(|s| s.processor_health),
+        reshard_history,
     };
 
     let status_patch = serde_json::json!({
         "status": status
     });
 
-    shard_clusters
-        .patch_status(&name, &PatchParams::default(), &Patch::Merge(&status_patch))
-        .await?;
+    crust_kubernetes::apply_status(&shard_clusters, &name, &status_patch).await?;
 
-    Ok(Action::requeue(Duration::from_secs(1800)))
+    Ok(Action::requeue(config.reshard_check_interval))
 }
 
-pub fn error_policy(_object: Arc<ShardCluster>, error: &CrustError, _ctx: Arc<Context>) -> Action {
-    error!(error = %error, "Reconciliation error");
-    
-    if error.to_string().contains("429") || error.to_string().contains("rate limit") {
-        error!("Rate limit detected, backing off for 5 minutes");
-        Action::requeue(Duration::from_secs(300))
-    } else {
-        Action::requeue(Duration::from_secs(120))
+/// Counts the other clusters with a reshard in progress.
+async fn count_resharding_clusters(ctx: &Context, cluster: &ShardCluster) -> Result<usize> {
+    let shard_clusters: Api<ShardCluster> = Api::all(ctx.client.clone());
+    let clusters = shard_clusters.list(&ListParams::default()).await?;
+
+    Ok(clusters
+        .items
+        .iter()
+        .filter(|other| other.uid() != cluster.uid())
+        .filter(|other| {
+            other
+                .status
+                .as_ref()
+                .and_then(|status| status.reshard.as_ref())
+                .is_some_and(|reshard| reshard.phase == "InProgress")
+        })
+        .count())
+}
+
+/// Records a reshard that has to wait, writing the status and event only the
+/// first time for a given target.
+async fn defer_reshard(
+    ctx: &Context,
+    shard_clusters: &Api<ShardCluster>,
+    cluster: &ShardCluster,
+    target_shards: u32,
+    message: String,
+) -> Result<()> {
+    let deferred = ReshardStatus {
+        target_shards,
+        phase: "Deferred".to_string(),
+        workers_total: 0,
+        workers_completed: 0,
+        workers_failed: 0,
+        shards_reidentified: 0,
+        started_at: None,
+        completed_at: None,
+        message: Some(message.clone()),
+    };
+    let already_deferred = cluster
+        .status
+        .as_ref()
+        .and_then(|s| s.reshard.as_ref())
+        .is_some_and(|reshard| reshard.phase == "Deferred" && reshard.target_shards == target_shards);
+
+    // Only write the deferral once, a status change per reconcile would
+    // trigger the next reconcile right away.
+    if !already_deferred {
+        crust_kubernetes::record_event(
+            &ctx.recorder,
+            cluster,
+            EventType::Warning,
+            "ReshardDeferred",
+            "Reshard",
+            message,
+        ).await;
+
+        let patch = serde_json::json!({ "status": { "reshard": deferred } });
+        crust_kubernetes::apply_status(shard_clusters, &cluster.name_any(), &patch).await?;
+    }
+
+    Ok(())
+}
+
+/// Starts a canary rollout when the spec image changed and the update
+/// strategy asks for canaries, returning the cluster with the new rollout.
+async fn start_rollout(
+    ctx: &Context,
+    shard_clusters: &Api<ShardCluster>,
+    cluster: Arc<ShardCluster>,
+) -> Result<Arc<ShardCluster>> {
+    let canary_groups = cluster
+        .spec
+        .update_strategy
+        .as_ref()
+        .map(|strategy| strategy.canary_groups)
+        .unwrap_or(0);
+
+    let Some(status) = &cluster.status else {
+        return Ok(cluster);
+    };
+    let Some(rolled_out_image) = &status.image else {
+        return Ok(cluster);
+    };
+    if canary_groups == 0 || *rolled_out_image == cluster.spec.image {
+        return Ok(cluster);
+    }
+    if status.rollout.as_ref().is_some_and(|rollout| rollout.image == cluster.spec.image) {
+        return Ok(cluster);
+    }
+
+    let rollout = RolloutStatus {
+        image: cluster.spec.image.clone(),
+        previous_image: rolled_out_image.clone(),
+        phase: ROLLOUT_CANARY.to_string(),
+        canary_groups: status
+            .shard_groups
+            .iter()
+            .take(canary_groups as usize)
+            .map(|group| group.deployment_name.clone())
+            .collect(),
+        started_at: Some(Utc::now()),
+        message: None,
+    };
+
+    let name = cluster.name_any();
+    let patch = serde_json::json!({ "status": { "rollout": rollout } });
+    crust_kubernetes::apply_status(shard_clusters, &name, &patch).await?;
+
+    info!(cluster = %name, image = %rollout.image, canaries = ?rollout.canary_groups, "Started canary rollout");
+    crust_kubernetes::record_event(
+        &ctx.recorder,
+        &cluster,
+        EventType::Normal,
+        "CanaryStarted",
+        "Rollout",
+        format!("Rolling {} out to {} first", rollout.image, rollout.canary_groups.join(", ")),
+    ).await;
+
+    let mut cluster = (*cluster).clone();
+    if let Some(status) = cluster.status.as_mut() {
+        status.rollout = Some(rollout);
+    }
+    Ok(Arc::new(cluster))
+}
+
+async fn patch_conditions(shard_clusters: &Api<ShardCluster>, name: &str, conditions: &[Condition]) {
+    let patch = serde_json::json!({ "status": { "conditions": conditions } });
+    if let Err(e) = crust_kubernetes::apply_status(shard_clusters, name, &patch).await {
+        error!(cluster = %name, error = %e, "Failed to update cluster conditions");
+    }
+}
+
+/// Key of the cluster in the failure registry.
+fn failure_key(cluster: &ShardCluster) -> String {
+    format!("{}/{}", cluster.namespace().unwrap_or_default(), cluster.name_any())
+}
+
+/// `base` doubled for every earlier failure, capped at `max`.
+fn backoff(base: Duration, max: Duration, failures: u32) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(failures.saturating_sub(1))).min(max)
+}
+
+pub fn error_policy(object: Arc<ShardCluster>, error: &CrustError, ctx: Arc<Context>) -> Action {
+    let failures = {
+        let mut registry = ctx.failures.write().expect("failure registry poisoned");
+        let failures = registry.entry(failure_key(&object)).or_insert(0);
+        *failures += 1;
+        *failures
+    };
+    error!(cluster = %object.name_any(), failures, error = %error, "Reconciliation error");
+
+    match error {
+        CrustError::RateLimited { retry_after } => {
+            warn!(cluster = %object.name_any(), retry_after = ?retry_after, "Rate limited by Discord, backing off");
+            Action::requeue((*retry_after).max(backoff(Duration::from_secs(5), Duration::from_secs(600), failures)))
+        }
+        CrustError::DiscordUnavailable(_) => {
+            Action::requeue(backoff(Duration::from_secs(30), Duration::from_secs(900), failures))
+        }
+        // Someone else changed the object in between, retrying soon is
+        // usually enough.
+        CrustError::Conflict(_) => Action::requeue(backoff(Duration::from_secs(1), Duration::from_secs(60), failures)),
+        // Retrying cannot fix the spec, the edit that does will trigger a
+        // reconcile on its own.
+        CrustError::Validation(_) => Action::await_change(),
+        _ => {
+            let config = ctx.config();
+            Action::requeue(backoff(config.error_requeue, config.max_error_requeue, failures))
+        }
     }
 }
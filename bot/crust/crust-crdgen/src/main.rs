@@ -0,0 +1,36 @@
+use anyhow::Result;
+use crust_types::{v2, ShardCluster};
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+    CustomResourceConversion, ServiceReference, WebhookClientConfig, WebhookConversion,
+};
+use kube::core::crd::merge_crds;
+use kube::CustomResourceExt;
+
+/// Prints the ShardCluster CRD generated from the Rust types, so
+/// `crd/shardcluster-crd.yaml` can be regenerated with
+/// `cargo run -p crust-crdgen > crd/shardcluster-crd.yaml`.
+///
+/// v1 stays the storage version. Objects are converted between v1 and v2 by
+/// the operator's webhook, whose caBundle has to be set once installed, see
+/// `k8s/webhook.yaml`.
+fn main() -> Result<()> {
+    let mut crd = merge_crds(vec![ShardCluster::crd(), v2::ShardCluster::crd()], "v1")?;
+    crd.spec.conversion = Some(CustomResourceConversion {
+        strategy: "Webhook".to_string(),
+        webhook: Some(WebhookConversion {
+            conversion_review_versions: vec!["v1".to_string()],
+            client_config: Some(WebhookClientConfig {
+                service: Some(ServiceReference {
+                    name: "crust-webhook".to_string(),
+                    namespace: "bedrock".to_string(),
+                    path: Some("/convert".to_string()),
+                    port: Some(443),
+                }),
+                ..Default::default()
+            }),
+        }),
+    });
+
+    print!("{}", serde_yaml::to_string(&crd)?);
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use bedrock_nats::{Auth, ConnectionBuilder, RetryPolicy, Tls};
+use crust_types::{ShardCluster, WorkerHeartbeat, RESHARD_TRIGGER_ANNOTATION};
+use futures::StreamExt;
+use kube::{
+    api::{Api, Patch, PatchParams},
+    Client,
+};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Subjects under the cluster's prefix printed by `crustctl tail`,
+/// everything except the gateway events.
+const COORDINATION_TRAFFIC: [&str; 6] = [
+    "operator.>",
+    "workers.>",
+    "startup.>",
+    "gateway.>",
+    "shards.*.startup",
+    "shards.*.status",
+];
+
+/// Operate ShardClusters managed by the crust operator.
+#[derive(Parser)]
+#[command(name = "crustctl", version)]
+struct Cli {
+    /// Namespace of the ShardCluster
+    #[arg(short, long, global = true, default_value = "default")]
+    namespace: String,
+    /// NATS server to use instead of the one in the ShardCluster spec,
+    /// authenticated through NATS_CREDENTIALS_FILE, NATS_NKEY, NATS_TOKEN or
+    /// NATS_USER and NATS_PASSWORD
+    #[arg(long, global = true, env = "NATS_URL")]
+    nats_url: Option<String>,
+    /// SUBJECT_ROOT the operator is configured with
+    #[arg(long, global = true, env = "SUBJECT_ROOT", default_value = "discord")]
+    subject_root: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Make the operator check Discord's recommended shard count now
+    Reshard { name: String },
+    /// Show shard groups, conditions and worker heartbeats of a cluster
+    Status {
+        name: String,
+        /// Seconds to collect worker heartbeats for
+        #[arg(long, default_value_t = 15)]
+        wait: u64,
+    },
+    /// Stop the operator from touching the cluster
+    Pause { name: String },
+    /// Let the operator manage the cluster again
+    Resume { name: String },
+    /// Print coordination messages as they are published
+    Tail {
+        name: String,
+        /// Subjects to subscribe to instead of all coordination traffic
+        #[arg(long)]
+        subject: Vec<String>,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let client = Client::try_default().await.context("Failed to connect to Kubernetes")?;
+    let shard_clusters: Api<ShardCluster> = Api::namespaced(client.clone(), &cli.namespace);
+
+    match &cli.command {
+        Command::Reshard { name } => {
+            let patch = serde_json::json!({
+                "metadata": { "annotations": { RESHARD_TRIGGER_ANNOTATION: Utc::now().to_rfc3339() } }
+            });
+            shard_clusters
+                .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+                .await
+                .with_context(|| format!("Failed to trigger a reshard of {}", name))?;
+            println!("Triggered a reshard check of {}", name);
+        }
+        Command::Status { name, wait } => {
+            let cluster = shard_clusters.get(name).await.with_context(|| format!("Failed to get {}", name))?;
+            let nats_client = connect_nats(&client, &cli, &cluster).await?;
+            let heartbeats = collect_heartbeats(&nats_client, &cluster, &cli.subject_root, Duration::from_secs(*wait)).await?;
+            print_status(&cluster, &heartbeats);
+        }
+        Command::Pause { name } => {
+            set_suspend(&shard_clusters, name, true).await?;
+            println!("Paused {}", name);
+        }
+        Command::Resume { name } => {
+            set_suspend(&shard_clusters, name, false).await?;
+            println!("Resumed {}", name);
+        }
+        Command::Tail { name, subject } => {
+            let cluster = shard_clusters.get(name).await.with_context(|| format!("Failed to get {}", name))?;
+            let nats_client = connect_nats(&client, &cli, &cluster).await?;
+            let subjects = if subject.is_empty() {
+                COORDINATION_TRAFFIC
+                    .iter()
+                    .map(|subject| format!("{}.{}", cluster.subject_prefix(&cli.subject_root), subject))
+                    .collect()
+            } else {
+                subject.clone()
+            };
+            tail(&nats_client, subjects).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn set_suspend(shard_clusters: &Api<ShardCluster>, name: &str, suspend: bool) -> Result<()> {
+    let patch = serde_json::json!({ "spec": { "suspend": suspend } });
+    shard_clusters
+        .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+        .with_context(|| format!("Failed to update {}", name))?;
+    Ok(())
+}
+
+/// Connects to the cluster's NATS server with its workers' authentication,
+/// unless `--nats-url` points somewhere else, which is authenticated through
+/// the NATS_* environment variables instead. TLS always comes from the
+/// NATS_TLS_* variables, since the secrets' files are not on this machine.
+async fn connect_nats(client: &Client, cli: &Cli, cluster: &ShardCluster) -> Result<async_nats::Client> {
+    let nats_url = cli.nats_url.clone().unwrap_or_else(|| cluster.spec.nats_url.clone());
+    let auth = match cli.nats_url {
+        Some(_) => Auth::from_env()?,
+        None => crust_kubernetes::get_nats_auth(client, &cli.namespace, cluster).await?,
+    };
+
+    let connection = ConnectionBuilder::new(nats_url.as_str())
+        .auth(auth)
+        .tls(Tls::from_env()?)
+        .retry(RetryPolicy::retries(3))
+        .connect()
+        .await
+        .with_context(|| format!("Failed to connect to NATS at {}", nats_url))?;
+    Ok(connection.into_client())
+}
+
+async fn collect_heartbeats(
+    nats_client: &async_nats::Client,
+    cluster: &ShardCluster,
+    subject_root: &str,
+    wait: Duration,
+) -> Result<BTreeMap<String, WorkerHeartbeat>> {
+    let subject = format!("{}.workers.heartbeat", cluster.subject_prefix(subject_root));
+    let mut subscriber = nats_client.subscribe(subject).await?;
+    let mut heartbeats = BTreeMap::new();
+
+    let _ = tokio::time::timeout(wait, async {
+        while let Some(message) = subscriber.next().await {
+            if let Ok(heartbeat) = serde_json::from_slice::<WorkerHeartbeat>(&message.payload) {
+                heartbeats.insert(heartbeat.worker_id.clone(), heartbeat);
+            }
+        }
+    })
+    .await;
+
+    Ok(heartbeats)
+}
+
+fn print_status(cluster: &ShardCluster, heartbeats: &BTreeMap<String, WorkerHeartbeat>) {
+    let spec = &cluster.spec;
+
+    println!("Suspended:      {}", spec.suspend.unwrap_or(false));
+    let Some(status) = &cluster.status else {
+        println!("The operator has not reconciled this cluster yet");
+        return;
+    };
+    println!("Shards:         {}", status.current_shards.map_or("-".to_string(), |s| s.to_string()));
+    let spec_image = if spec.image.is_empty() { "operator default" } else { &spec.image };
+    println!("Image:          {}", status.image.as_deref().unwrap_or(spec_image));
+    println!("Last reshard:   {}", status.last_reshard.map_or("-".to_string(), format_time));
+    if let Some(reshard) = &status.reshard {
+        println!(
+            "Reshard:        {} to {} shards, {}/{} workers done{}",
+            reshard.phase,
+            reshard.target_shards,
+            reshard.workers_completed,
+            reshard.workers_total,
+            reshard.message.as_ref().map_or(String::new(), |m| format!(" ({})", m))
+        );
+    }
+    if let Some(rollout) = &status.rollout {
+        println!("Rollout:        {} of {}", rollout.phase, rollout.image);
+    }
+    if let Some(health) = &status.shard_health {
+        println!(
+            "Shard health:   {} connected, {} disconnected, {} stale",
+            health.connected, health.disconnected, health.stale
+        );
+    }
+    if let Some(health) = &status.processor_health {
+        println!(
+            "Processors:     {} healthy, {} degraded, {} events pending",
+            health.healthy,
+            health.degraded,
+            health.lag.map_or("-".to_string(), |lag| lag.to_string())
+        );
+    }
+    if let Some(sizing) = &status.sizing {
+        println!(
+            "Sizing:         {} shards per replica{}, {:.0} events/s ({})",
+            sizing.shards_per_replica,
+            if sizing.applied { " (applied)" } else { "" },
+            sizing.events_per_sec,
+            sizing.reason
+        );
+    }
+
+    println!();
+    println!("CONDITION      STATUS  REASON");
+    for condition in &status.conditions {
+        println!("{:<14} {:<7} {}", condition.type_, condition.status, condition.reason);
+    }
+
+    println!();
+    println!("DEPLOYMENT                     SHARDS      READY  CONNECTED  WORKER");
+    let pending = status.pending_shard_groups.iter().flatten();
+    for group in status.shard_groups.iter().chain(pending) {
+        let worker = match heartbeats.get(&group.deployment_name) {
+            Some(heartbeat) => format!("{} shards, up {}s", heartbeat.shards.len(), heartbeat.uptime_secs),
+            None => "no heartbeat".to_string(),
+        };
+        let connected = match &group.health {
+            Some(health) => format!("{}/{}", health.connected, group.shard_end - group.shard_start + 1),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<30} {:<11} {:<6} {:<10} {}",
+            group.deployment_name,
+            format!("{}-{}", group.shard_start, group.shard_end),
+            format!("{}/{}", group.ready_replicas.unwrap_or(0), group.replicas),
+            connected,
+            worker
+        );
+    }
+
+    if !status.reshard_history.is_empty() {
+        println!();
+        println!("STARTED                    SHARDS      OUTCOME     REASON");
+        for record in status.reshard_history.iter().rev() {
+            println!(
+                "{:<26} {:<11} {:<11} {}",
+                record.started_at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                format!("{}->{}", record.from_shards.map_or("-".to_string(), |s| s.to_string()), record.to_shards),
+                record.outcome,
+                record.reason
+            );
+        }
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    let age = Utc::now() - time;
+    format!("{} ({}m ago)", time.to_rfc3339(), age.num_minutes())
+}
+
+async fn tail(nats_client: &async_nats::Client, subjects: Vec<String>) -> Result<()> {
+    let mut subscribers = Vec::new();
+    for subject in subjects {
+        subscribers.push(nats_client.subscribe(subject).await?);
+    }
+    let mut messages = futures::stream::select_all(subscribers);
+
+    while let Some(message) = messages.next().await {
+        println!(
+            "{} {} {}",
+            Utc::now().format("%H:%M:%S%.3f"),
+            message.subject,
+            String::from_utf8_lossy(&message.payload)
+        );
+    }
+
+    Ok(())
+}
@@ -1,13 +1,43 @@
-use crust_types::{CrustError, Result};
-use twilight_http::Client as DiscordClient;
-use tracing::info;
+use crust_types::{token_key, CrustError, GatewayCache, GatewayInfo, Result, SessionStartLimit};
+use std::time::{Duration, Instant};
+use twilight_http::{error::ErrorType, Client as DiscordClient};
+use tracing::{debug, info, warn};
 
-pub async fn get_gateway_info(client: &DiscordClient) -> Result<(u32, u32)> {
+/// Retry delay when Discord or the call budget gives no better one.
+const BUDGET_RETRY: Duration = Duration::from_secs(60);
+
+/// The gateway info of the bot `token`, answered from `cache` while the
+/// cached info is fresh, or while the token's call budget is spent and any
+/// info is cached. The cache is keyed by [`token_key`].
+pub async fn get_cached_gateway_info(cache: &GatewayCache, token: &str) -> Result<GatewayInfo> {
+    let key = token_key(token);
+    {
+        let mut cache = cache.write().expect("gateway cache poisoned");
+        let now = Instant::now();
+        if let Some(info) = cache.fresh(&key, now) {
+            debug!("Using cached Discord gateway information");
+            return Ok(info);
+        }
+        if !cache.try_spend(&key, now) {
+            warn!("Discord gateway call budget spent, using stale gateway information");
+            return cache.stale(&key, now).ok_or(CrustError::RateLimited { retry_after: BUDGET_RETRY });
+        }
+    }
+
+    let info = get_gateway_info(&util::client(token.to_string())).await?;
+    cache
+        .write()
+        .expect("gateway cache poisoned")
+        .insert(&key, info, Instant::now());
+    Ok(info)
+}
+
+pub async fn get_gateway_info(client: &DiscordClient) -> Result<GatewayInfo> {
     let info = client
         .gateway()
         .authed()
         .await
-        .map_err(|e| CrustError::Other(format!("Failed to get gateway info: {}", e)))?
+        .map_err(request_error)?
         .model()
         .await
         .map_err(|e| CrustError::Other(format!("Failed to deserialize gateway info: {}", e)))?;
@@ -15,8 +45,35 @@ pub async fn get_gateway_info(client: &DiscordClient) -> Result<(u32, u32)> {
     info!(
         shards = info.shards,
         max_concurrency = info.session_start_limit.max_concurrency,
+        sessions_remaining = info.session_start_limit.remaining,
         "Retrieved Discord gateway information"
     );
     
-    Ok((info.shards, info.session_start_limit.max_concurrency as u32))
+    Ok(GatewayInfo {
+        recommended_shards: info.shards,
+        max_concurrency: info.session_start_limit.max_concurrency as u32,
+        session_start_limit: SessionStartLimit {
+            total: info.session_start_limit.total,
+            remaining: info.session_start_limit.remaining,
+            reset_after_ms: info.session_start_limit.reset_after,
+        },
+    })
+}
+
+/// Sorts a failed request into a rate limit, an outage on Discord's side, or
+/// anything else.
+fn request_error(e: twilight_http::Error) -> CrustError {
+    match e.kind() {
+        ErrorType::Response { status, body, .. } if status.get() == 429 => {
+            let retry_after = serde_json::from_slice::<serde_json::Value>(body)
+                .ok()
+                .and_then(|body| body.get("retry_after").and_then(|v| v.as_f64()))
+                .map_or(BUDGET_RETRY, Duration::from_secs_f64);
+            CrustError::RateLimited { retry_after }
+        }
+        ErrorType::Response { status, .. } if status.get() < 500 => {
+            CrustError::Other(format!("Failed to get gateway info: {}", e))
+        }
+        _ => CrustError::DiscordUnavailable(format!("Failed to get gateway info: {}", e)),
+    }
 }
@@ -0,0 +1,146 @@
+use chrono::Utc;
+use crust_types::Result;
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{MicroTime, ObjectMeta};
+use kube::{
+    api::{Api, PostParams},
+    Client,
+};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Lease based leader election, so several operator replicas can run while
+/// only the lease holder reconciles.
+pub struct LeaderElector {
+    leases: Api<Lease>,
+    lease_name: String,
+    identity: String,
+    lease_duration: Duration,
+}
+
+impl LeaderElector {
+    pub fn new(client: &Client, namespace: &str, lease_name: &str, identity: &str, lease_duration: Duration) -> Self {
+        Self {
+            leases: Api::namespaced(client.clone(), namespace),
+            lease_name: lease_name.to_string(),
+            identity: identity.to_string(),
+            lease_duration,
+        }
+    }
+
+    /// Waits until this replica holds the lease.
+    pub async fn acquire(&self, retry_period: Duration) {
+        loop {
+            match self.try_acquire_or_renew().await {
+                Ok(true) => {
+                    info!(lease = %self.lease_name, identity = %self.identity, "Acquired leadership");
+                    return;
+                }
+                Ok(false) => debug!(lease = %self.lease_name, "Lease held by another replica"),
+                Err(e) => warn!(lease = %self.lease_name, error = %e, "Failed to acquire lease"),
+            }
+            tokio::time::sleep(retry_period).await;
+        }
+    }
+
+    /// Renews the lease every `renew_period` and returns once it could not be
+    /// renewed before it expired, meaning another replica may have taken over.
+    pub async fn hold(&self, renew_period: Duration) {
+        let mut last_renewal = tokio::time::Instant::now();
+
+        loop {
+            tokio::time::sleep(renew_period).await;
+
+            match self.try_acquire_or_renew().await {
+                Ok(true) => last_renewal = tokio::time::Instant::now(),
+                Ok(false) => {
+                    warn!(lease = %self.lease_name, "Lease taken over by another replica");
+                    return;
+                }
+                Err(e) => warn!(lease = %self.lease_name, error = %e, "Failed to renew lease"),
+            }
+
+            if last_renewal.elapsed() >= self.lease_duration {
+                warn!(lease = %self.lease_name, "Lease expired before it could be renewed");
+                return;
+            }
+        }
+    }
+
+    /// Gives the lease up so another replica can take over without waiting
+    /// for it to expire.
+    pub async fn release(&self) -> Result<()> {
+        let mut lease = self.leases.get(&self.lease_name).await?;
+        let Some(spec) = lease.spec.as_mut() else {
+            return Ok(());
+        };
+        if spec.holder_identity.as_deref() != Some(self.identity.as_str()) {
+            return Ok(());
+        }
+
+        spec.holder_identity = None;
+        spec.renew_time = None;
+        self.leases.replace(&self.lease_name, &PostParams::default(), &lease).await?;
+
+        info!(lease = %self.lease_name, identity = %self.identity, "Released leadership");
+        Ok(())
+    }
+
+    async fn try_acquire_or_renew(&self) -> Result<bool> {
+        let now = MicroTime(Utc::now());
+
+        let Some(mut lease) = self.leases.get_opt(&self.lease_name).await? else {
+            let lease = Lease {
+                metadata: ObjectMeta {
+                    name: Some(self.lease_name.clone()),
+                    ..Default::default()
+                },
+                spec: Some(LeaseSpec {
+                    holder_identity: Some(self.identity.clone()),
+                    lease_duration_seconds: Some(self.lease_duration.as_secs() as i32),
+                    acquire_time: Some(now.clone()),
+                    renew_time: Some(now),
+                    lease_transitions: Some(0),
+                    ..Default::default()
+                }),
+            };
+            return match self.leases.create(&PostParams::default(), &lease).await {
+                Ok(_) => Ok(true),
+                Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+                Err(e) => Err(e.into()),
+            };
+        };
+
+        let spec = lease.spec.get_or_insert_with(Default::default);
+        let held_by_us = spec.holder_identity.as_deref() == Some(self.identity.as_str());
+
+        if !held_by_us {
+            let duration = spec
+                .lease_duration_seconds
+                .map(|secs| chrono::Duration::seconds(secs.into()))
+                .unwrap_or_else(|| chrono::Duration::seconds(self.lease_duration.as_secs() as i64));
+            let expired = match (&spec.holder_identity, &spec.renew_time) {
+                (Some(_), Some(renew_time)) => renew_time.0 + duration < now.0,
+                _ => true,
+            };
+            if !expired {
+                return Ok(false);
+            }
+
+            spec.holder_identity = Some(self.identity.clone());
+            spec.acquire_time = Some(now.clone());
+            spec.lease_transitions = Some(spec.lease_transitions.unwrap_or(0) + 1);
+        }
+
+        spec.lease_duration_seconds = Some(self.lease_duration.as_secs() as i32);
+        spec.renew_time = Some(now);
+
+        // The replace carries the resource version we read, so a concurrent
+        // takeover by another replica makes this fail with a conflict.
+        match self.leases.replace(&self.lease_name, &PostParams::default(), &lease).await {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(e)) if e.code == 409 => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
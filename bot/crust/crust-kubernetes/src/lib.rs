@@ -1,34 +1,311 @@
-use crust_types::{CrustError, Result, ShardCluster, ShardGroup};
-use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
-use k8s_openapi::api::core::v1::{Container, ContainerPort, EnvVar, PodSpec, PodTemplateSpec, Secret};
-use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta};
+pub mod leader;
+pub mod remote;
+
+use bedrock_nats::Auth;
+use crust_types::{CrustError, Result, ShardCluster, ShardGroup, WorkloadKind};
+use k8s_openapi::api::apps::v1::{
+    Deployment, DeploymentSpec, DeploymentStrategy, RollingUpdateDeployment, StatefulSet, StatefulSetSpec,
+};
+use k8s_openapi::api::core::v1::{
+    Container, ContainerPort, EnvVar, KeyToPath, Pod, PodSpec, PodTemplateSpec, Secret, SecretVolumeSource, Service,
+    ServicePort, ServiceSpec, Volume, VolumeMount,
+};
+use k8s_openapi::api::networking::v1::{
+    IPBlock, NetworkPolicy, NetworkPolicyEgressRule, NetworkPolicyPeer, NetworkPolicyPort, NetworkPolicySpec,
+};
+use k8s_openapi::api::policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec};
+use k8s_openapi::ByteString;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta, OwnerReference};
 use kube::{
-    api::{Api, ListParams, Patch, PatchParams, PostParams},
-    Client, ResourceExt,
+    api::{Api, ApiResource, DynamicObject, GroupVersionKind, ListParams, Patch, PatchParams},
+    runtime::events::{Event, EventType, Recorder},
+    Client, Resource, ResourceExt,
 };
+use sha2::{Digest, Sha256};
 use std::collections::BTreeMap;
-use tracing::info;
+use tracing::{debug, info, warn};
+
+/// Pod template annotation holding a hash of the Discord token secret, so a
+/// rotated token rolls the shard group deployments.
+const TOKEN_HASH_ANNOTATION: &str = "crust.bedrock.dev/token-hash";
+
+/// Deployment annotation holding a hash of the spec the operator last
+/// applied, so only actual changes are reported as updates.
+const SPEC_HASH_ANNOTATION: &str = "crust.bedrock.dev/spec-hash";
+
+/// Annotation on issued NATS credentials holding a hash of the signing key
+/// and permissions they were issued from.
+const ISSUED_FROM_ANNOTATION: &str = "crust.bedrock.dev/issued-from";
+
+/// Field manager for server-side applies. Fields the operator stops setting
+/// are removed from the object, which merge patches never did.
+const FIELD_MANAGER: &str = "crust-operator";
+
+/// Field manager owning the restart annotation of `restart_deployment`.
+const RESTART_FIELD_MANAGER: &str = "crust-operator-restart";
+
+/// Attempts of a status apply that keeps conflicting with other writers.
+const STATUS_CONFLICT_ATTEMPTS: u32 = 5;
+
+/// Where the NATS credentials secret is mounted in the stratum pods.
+const NATS_CREDENTIALS_PATH: &str = "/etc/nats-credentials";
+const NATS_TLS_CA_PATH: &str = "/etc/nats-tls/ca";
+const NATS_TLS_CLIENT_PATH: &str = "/etc/nats-tls/client";
+
+/// Records a Kubernetes Event on `cluster` so the activity shows up in
+/// `kubectl describe`. Failures are only logged, events are best effort.
+pub async fn record_event(
+    recorder: &Recorder,
+    cluster: &ShardCluster,
+    type_: EventType,
+    reason: &str,
+    action: &str,
+    note: impl Into<String>,
+) {
+    let event = Event {
+        type_,
+        reason: reason.to_string(),
+        note: Some(note.into()),
+        action: action.to_string(),
+        secondary: None,
+    };
+
+    if let Err(e) = recorder.publish(&event, &cluster.object_ref(&())).await {
+        warn!(cluster = %cluster.name_any(), reason, error = %e, "Failed to record event");
+    }
+}
 
 pub async fn get_discord_token(
     client: &Client,
     namespace: &str,
     secret_name: &str,
 ) -> Result<String> {
+    let token_bytes = get_secret_value(client, namespace, secret_name, "token").await?;
+    
+    String::from_utf8(token_bytes)
+        .map_err(|e| CrustError::Other(format!("Invalid UTF-8 in token: {}", e)))
+}
+
+pub async fn get_signing_key(
+    client: &Client,
+    namespace: &str,
+    secret_name: &str,
+) -> Result<Vec<u8>> {
+    get_secret_value(client, namespace, secret_name, "key").await
+}
+
+pub async fn get_nats_credentials(
+    client: &Client,
+    namespace: &str,
+    secret_name: &str,
+) -> Result<String> {
+    let credentials = get_secret_value(client, namespace, secret_name, "creds").await?;
+
+    String::from_utf8(credentials)
+        .map_err(|e| CrustError::Other(format!("Invalid UTF-8 in NATS credentials: {}", e)))
+}
+
+/// How the cluster's workers authenticate to NATS, read from
+/// `spec.nats_credentials_secret` or `spec.nats_auth_secret`.
+pub async fn get_nats_auth(client: &Client, namespace: &str, cluster: &ShardCluster) -> Result<Auth> {
+    if let Some(secret_name) = &cluster.spec.nats_credentials_secret {
+        return Ok(Auth::Credentials(get_nats_credentials(client, namespace, secret_name).await?));
+    }
+    let Some(secret_name) = &cluster.spec.nats_auth_secret else {
+        return Ok(Auth::None);
+    };
+
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let data = secrets.get(secret_name).await?.data.unwrap_or_default();
+    let entry = |key: &str| -> Result<Option<String>> {
+        data.get(key)
+            .map(|value| {
+                String::from_utf8(value.0.clone())
+                    .map_err(|e| CrustError::Other(format!("Invalid UTF-8 in '{}' of {}: {}", key, secret_name, e)))
+            })
+            .transpose()
+    };
+
+    Auth::select(entry("nkey")?, entry("token")?, entry("user")?, entry("password")?)
+        .map_err(|e| CrustError::Validation(format!("Invalid {}: {}", secret_name, e)))
+}
+
+pub async fn get_secret_value(
+    client: &Client,
+    namespace: &str,
+    secret_name: &str,
+    key: &str,
+) -> Result<Vec<u8>> {
     let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
     let secret = secrets.get(secret_name).await?;
     
     let data = secret
         .data
         .ok_or_else(|| CrustError::Other("Secret has no data".to_string()))?;
-    let token_bytes = data
-        .get("token")
-        .ok_or_else(|| CrustError::Other("Secret missing 'token' key".to_string()))?;
+    let value = data
+        .get(key)
+        .ok_or_else(|| CrustError::Other(format!("Secret missing '{}' key", key)))?;
     
-    String::from_utf8(token_bytes.0.clone())
-        .map_err(|e| CrustError::Other(format!("Invalid UTF-8 in token: {}", e)))
+    Ok(value.0.clone())
+}
+
+/// Stores NATS credentials issued for the cluster's workers in the secret
+/// `spec.nats_credentials_secret` names. `issue` is only called when the
+/// secret holds none issued from `issued_from` yet. Returns whether new
+/// credentials were stored.
+pub async fn apply_issued_credentials(
+    client: &Client,
+    namespace: &str,
+    cluster: &ShardCluster,
+    issued_from: &str,
+    issue: impl FnOnce() -> Result<String>,
+) -> Result<bool> {
+    let Some(name) = &cluster.spec.nats_credentials_secret else {
+        return Ok(false);
+    };
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
+
+    let current = secrets
+        .get_opt(name)
+        .await?
+        .and_then(|secret| secret.metadata.annotations)
+        .and_then(|annotations| annotations.get(ISSUED_FROM_ANNOTATION).cloned());
+    if current.as_deref() == Some(issued_from) {
+        return Ok(false);
+    }
+
+    let mut labels = BTreeMap::new();
+    labels.insert("managed-by".to_string(), "crust-operator".to_string());
+    labels.insert("cluster".to_string(), cluster.name_any());
+
+    let secret = Secret {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(labels),
+            annotations: Some(BTreeMap::from([(ISSUED_FROM_ANNOTATION.to_string(), issued_from.to_string())])),
+            // The secret stays next to the ShardCluster even for remote clusters.
+            owner_references: cluster.controller_owner_ref(&()).map(|owner| vec![owner]),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([("creds".to_string(), ByteString(issue()?.into_bytes()))])),
+        type_: Some("Opaque".to_string()),
+        ..Default::default()
+    };
+
+    secrets
+        .patch(name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&secret))
+        .await?;
+    Ok(true)
+}
+
+/// Owner references of the objects created for `cluster`. Objects in a
+/// remote cluster get none, since its garbage collector would delete them
+/// for having an owner it cannot find.
+fn owner_references(cluster: &ShardCluster) -> Option<Vec<OwnerReference>> {
+    if cluster.spec.remote.is_some() {
+        return None;
+    }
+    cluster.controller_owner_ref(&()).map(|owner| vec![owner])
+}
+
+/// Hashes the contents of a secret, changing whenever any of its keys do.
+pub async fn get_secret_hash(client: &Client, namespace: &str, secret_name: &str) -> Result<String> {
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let secret = secrets.get(secret_name).await?;
+
+    let mut hasher = Sha256::new();
+    for (key, value) in secret.data.unwrap_or_default() {
+        hasher.update(key.as_bytes());
+        hasher.update([0]);
+        hasher.update(&value.0);
+        hasher.update([0]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Updates the cluster's status from `patch`, a JSON merge patch of the
+/// form `{"status": {..}}`. The patch is merged onto the current status and
+/// the whole status applied, since applying only the changed fields would
+/// remove all the others. The apply carries the resourceVersion it merged
+/// onto, so a status another writer changed in between is read again
+/// instead of overwritten.
+pub async fn apply_status(shard_clusters: &Api<ShardCluster>, name: &str, patch: &serde_json::Value) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        let current = shard_clusters.get_status(name).await?;
+        let mut status = serde_json::to_value(&current.status)
+            .map_err(|e| CrustError::Other(format!("Failed to serialize status: {}", e)))?;
+        merge_json(&mut status, &patch["status"]);
+        remove_nulls(&mut status);
+
+        let object = serde_json::json!({
+            "apiVersion": ShardCluster::api_version(&()),
+            "kind": ShardCluster::kind(&()),
+            "metadata": {
+                "name": name,
+                "resourceVersion": current.resource_version(),
+            },
+            "status": status,
+        });
+        match shard_clusters
+            .patch_status(name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&object))
+            .await
+        {
+            Ok(_) => return Ok(()),
+            Err(kube::Error::Api(e)) if e.code == 409 && attempt < STATUS_CONFLICT_ATTEMPTS => {
+                debug!(cluster = %name, attempt, "Status changed while applying it, retrying");
+                attempt += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Applies a JSON merge patch (RFC 7386) to `target`.
+fn merge_json(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(Default::default());
+    }
+    let Some(target) = target.as_object_mut() else {
+        return;
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_json(target.entry(key.clone()).or_insert(serde_json::Value::Null), value);
+        }
+    }
+}
+
+/// Drops null fields, which an apply would otherwise try to set.
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            fields.retain(|_, field| !field.is_null());
+            fields.values_mut().for_each(remove_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+/// SHA-256 hex of a workload serialized as the operator would apply it.
+fn hash_workload(serialized: &[u8]) -> String {
+    hex::encode(Sha256::digest(serialized))
 }
 
-pub fn calculate_shard_groups(total_shards: u32, shards_per_replica: u32) -> Vec<ShardGroup> {
+/// Splits the shards into groups of `shards_per_replica`, naming the
+/// deployments `<prefix>-group-<n>`.
+pub fn calculate_shard_groups(prefix: &str, total_shards: u32, shards_per_replica: u32, replicas: i32) -> Vec<ShardGroup> {
     let mut groups = Vec::new();
     let mut current_shard = 0;
     let mut group_index = 0;
@@ -37,10 +314,12 @@ pub fn calculate_shard_groups(total_shards: u32, shards_per_replica: u32) -> Vec
         let shard_end = std::cmp::min(current_shard + shards_per_replica - 1, total_shards - 1);
         
         groups.push(ShardGroup {
-            deployment_name: format!("stratum-group-{}", group_index),
+            deployment_name: format!("{}-group-{}", prefix, group_index),
             shard_start: current_shard,
             shard_end,
-            replicas: 1,
+            replicas,
+            ready_replicas: None,
+            health: None,
         });
 
         current_shard = shard_end + 1;
@@ -50,85 +329,833 @@ pub fn calculate_shard_groups(total_shards: u32, shards_per_replica: u32) -> Vec
     groups
 }
 
+/// Creates or updates the deployments of `shard_groups` and deletes every
+/// other deployment of the cluster.
+#[allow(clippy::too_many_arguments)]
 pub async fn create_or_update_deployments(
     client: &Client,
+    recorder: &Recorder,
+    namespace: &str,
+    cluster: &ShardCluster,
+    subject_root: &str,
+    shard_groups: &[ShardGroup],
+    total_shards: u32,
+    max_concurrency: u32,
+) -> Result<()> {
+    update_deployments(client, recorder, namespace, cluster, subject_root, shard_groups, total_shards, max_concurrency).await?;
+    prune_deployments(client, recorder, namespace, cluster, shard_groups).await
+}
+
+/// Creates or updates the deployments of `shard_groups`, leaving any other
+/// deployments of the cluster alone.
+#[allow(clippy::too_many_arguments)]
+pub async fn update_deployments(
+    client: &Client,
+    recorder: &Recorder,
     namespace: &str,
     cluster: &ShardCluster,
+    subject_root: &str,
     shard_groups: &[ShardGroup],
     total_shards: u32,
     max_concurrency: u32,
 ) -> Result<()> {
+    let token_hash = get_secret_hash(client, namespace, &cluster.spec.discord_token_secret).await?;
+    if cluster.spec.workload_kind.unwrap_or_default() == WorkloadKind::StatefulSet {
+        return apply_statefulset(client, recorder, namespace, cluster, subject_root, shard_groups, total_shards, max_concurrency, &token_hash).await;
+    }
+
     let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
-    
+    for group in shard_groups {
+        let mut deployment = create_deployment_spec(cluster, subject_root, group, namespace, total_shards, max_concurrency, &token_hash)?;
+        let spec_hash = hash_workload(&serde_json::to_vec(&deployment)?);
+        deployment
+            .annotations_mut()
+            .insert(SPEC_HASH_ANNOTATION.to_string(), spec_hash.clone());
+
+        // Applying is a no-op for an unchanged spec but still undoes edits
+        // made to the deployment by hand.
+        let existing = deployments.get_opt(&group.deployment_name).await?;
+        deployments
+            .patch(
+                &group.deployment_name,
+                &PatchParams::apply(FIELD_MANAGER).force(),
+                &Patch::Apply(&deployment),
+            )
+            .await?;
+
+        match existing.as_ref().map(|existing| existing.annotations().get(SPEC_HASH_ANNOTATION)) {
+            Some(applied) if applied == Some(&spec_hash) => {}
+            Some(_) => info!(deployment = %group.deployment_name, spec_hash = %spec_hash, "Updated deployment"),
+            None => {
+                info!(deployment = %group.deployment_name, spec_hash = %spec_hash, "Created deployment");
+                record_event(
+                    recorder,
+                    cluster,
+                    EventType::Normal,
+                    "CreatedDeployment",
+                    "Reconcile",
+                    format!(
+                        "Created deployment {} for shards {}-{}",
+                        group.deployment_name, group.shard_start, group.shard_end
+                    ),
+                ).await;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// What update_deployments and prune_deployments would do for `shard_groups`,
+/// one line per workload they would create, update or delete.
+pub async fn plan_deployments(
+    client: &Client,
+    namespace: &str,
+    cluster: &ShardCluster,
+    subject_root: &str,
+    shard_groups: &[ShardGroup],
+    total_shards: u32,
+    max_concurrency: u32,
+) -> Result<Vec<String>> {
+    let token_hash = get_secret_hash(client, namespace, &cluster.spec.discord_token_secret).await?;
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let statefulsets: Api<StatefulSet> = Api::namespaced(client.clone(), namespace);
+    let mut actions = Vec::new();
+
+    if cluster.spec.workload_kind.unwrap_or_default() == WorkloadKind::StatefulSet {
+        if let Some(statefulset) = statefulset_spec(cluster, subject_root, shard_groups, namespace, total_shards, max_concurrency, &token_hash)? {
+            let name = statefulset.name_any();
+            match statefulsets.get_opt(&name).await? {
+                None => actions.push(format!("create StatefulSet {} with {} pods", name, shard_groups.len())),
+                Some(existing) if existing.annotations().get(SPEC_HASH_ANNOTATION) != statefulset.annotations().get(SPEC_HASH_ANNOTATION) => {
+                    actions.push(format!("update StatefulSet {} to {} pods", name, shard_groups.len()))
+                }
+                Some(_) => {}
+            }
+        }
+    } else {
+        for group in shard_groups {
+            let deployment = create_deployment_spec(cluster, subject_root, group, namespace, total_shards, max_concurrency, &token_hash)?;
+            let spec_hash = hash_workload(&serde_json::to_vec(&deployment)?);
+            match deployments.get_opt(&group.deployment_name).await? {
+                None => actions.push(format!(
+                    "create deployment {} for shards {}-{}",
+                    group.deployment_name, group.shard_start, group.shard_end
+                )),
+                Some(existing) if existing.annotations().get(SPEC_HASH_ANNOTATION) != Some(&spec_hash) => actions.push(format!(
+                    "update deployment {} for shards {}-{}",
+                    group.deployment_name, group.shard_start, group.shard_end
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    for name in removed_deployments(client, namespace, cluster, shard_groups).await? {
+        actions.push(format!("delete deployment {}", name));
+    }
+    let statefulset = cluster.spec.workload_kind.unwrap_or_default() == WorkloadKind::StatefulSet;
+    let new_statefulset = statefulset_name(shard_groups).filter(|_| statefulset);
     let list_params = ListParams::default().labels(&format!(
         "managed-by=crust-operator,app=stratum,cluster={}",
         cluster.name_any()
     ));
-    
-    let existing_deployments = deployments.list(&list_params).await?;
-    let existing_names: std::collections::HashSet<String> = existing_deployments
-        .items
-        .iter()
-        .filter_map(|d| d.metadata.name.clone())
-        .collect();
-    
-    let new_names: std::collections::HashSet<String> = shard_groups
-        .iter()
-        .map(|g| g.deployment_name.clone())
-        .collect();
-    
+    for name in statefulsets.list(&list_params).await?.items.iter().map(|s| s.name_any()) {
+        if new_statefulset.as_ref() != Some(&name) {
+            actions.push(format!("delete StatefulSet {}", name));
+        }
+    }
+
+    Ok(actions)
+}
+
+/// Records the ready replicas of each group's deployment, or whether its pod
+/// is ready for StatefulSets, along with the replicas the spec asks for.
+/// Returns whether any group changed.
+pub async fn observe_readiness(
+    client: &Client,
+    namespace: &str,
+    cluster: &ShardCluster,
+    shard_groups: &mut [ShardGroup],
+) -> Result<bool> {
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let pods: Api<Pod> = Api::namespaced(client.clone(), namespace);
+    let workload_kind = cluster.spec.workload_kind.unwrap_or_default();
+    let mut changed = false;
+
     for group in shard_groups {
-        let deployment = create_deployment_spec(cluster, group, namespace, total_shards, max_concurrency)?;
-        
-        match deployments.get(&group.deployment_name).await {
-            Ok(_) => {
-                deployments
-                    .patch(
-                        &group.deployment_name,
-                        &PatchParams::default(),
-                        &Patch::Merge(&deployment),
-                    )
-                    .await?;
-                info!(deployment = %group.deployment_name, "Updated deployment");
-            }
-            Err(_) => {
-                deployments
-                    .create(&PostParams::default(), &deployment)
-                    .await?;
-                info!(deployment = %group.deployment_name, "Created deployment");
+        let ready_replicas = match workload_kind {
+            WorkloadKind::Deployment => deployments
+                .get_opt(&group.deployment_name)
+                .await?
+                .and_then(|deployment| deployment.status)
+                .and_then(|status| status.ready_replicas)
+                .unwrap_or(0),
+            WorkloadKind::StatefulSet => {
+                let ready = pods
+                    .get_opt(&group.deployment_name)
+                    .await?
+                    .and_then(|pod| pod.status)
+                    .and_then(|status| status.conditions)
+                    .is_some_and(|conditions| {
+                        conditions
+                            .iter()
+                            .any(|condition| condition.type_ == "Ready" && condition.status == "True")
+                    });
+                ready as i32
             }
+        };
+
+        if group.ready_replicas != Some(ready_replicas) {
+            group.ready_replicas = Some(ready_replicas);
+            changed = true;
+        }
+        if workload_kind == WorkloadKind::Deployment && group.replicas != cluster.spec.replicas_per_shard_group {
+            group.replicas = cluster.spec.replicas_per_shard_group;
+            changed = true;
         }
     }
+
+    Ok(changed)
+}
+
+/// Names of the deployments of the cluster that do not belong to
+/// `shard_groups`, which prune_deployments would delete.
+pub async fn removed_deployments(
+    client: &Client,
+    namespace: &str,
+    cluster: &ShardCluster,
+    shard_groups: &[ShardGroup],
+) -> Result<Vec<String>> {
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let list_params = ListParams::default().labels(&format!(
+        "managed-by=crust-operator,app=stratum,cluster={}",
+        cluster.name_any()
+    ));
+
+    let statefulset = cluster.spec.workload_kind.unwrap_or_default() == WorkloadKind::StatefulSet;
+    let new_names: std::collections::HashSet<&str> = shard_groups
+        .iter()
+        .filter(|_| !statefulset)
+        .map(|g| g.deployment_name.as_str())
+        .collect();
+
+    Ok(deployments
+        .list(&list_params)
+        .await?
+        .items
+        .into_iter()
+        .filter_map(|d| d.metadata.name)
+        .filter(|name| !new_names.contains(name.as_str()))
+        .collect())
+}
+
+/// Deletes the deployments and StatefulSets of the cluster that do not belong
+/// to `shard_groups`.
+pub async fn prune_deployments(
+    client: &Client,
+    recorder: &Recorder,
+    namespace: &str,
+    cluster: &ShardCluster,
+    shard_groups: &[ShardGroup],
+) -> Result<()> {
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
     
-    for old_deployment in existing_names.difference(&new_names) {
+    let list_params = ListParams::default().labels(&format!(
+        "managed-by=crust-operator,app=stratum,cluster={}",
+        cluster.name_any()
+    ));
+    
+    let statefulset = cluster.spec.workload_kind.unwrap_or_default() == WorkloadKind::StatefulSet;
+
+    for old_deployment in removed_deployments(client, namespace, cluster, shard_groups).await? {
         deployments
-            .delete(old_deployment, &Default::default())
+            .delete(&old_deployment, &Default::default())
             .await?;
         info!(deployment = %old_deployment, "Deleted unnecessary deployment");
+        record_event(
+            recorder,
+            cluster,
+            EventType::Normal,
+            "DeletedDeployment",
+            "Reconcile",
+            format!("Deleted deployment {} that is no longer needed", old_deployment),
+        ).await;
+    }
+
+    let statefulsets: Api<StatefulSet> = Api::namespaced(client.clone(), namespace);
+    let new_statefulset = statefulset_name(shard_groups).filter(|_| statefulset);
+    for old_statefulset in statefulsets.list(&list_params).await?.items {
+        let name = old_statefulset.name_any();
+        if new_statefulset.as_ref() == Some(&name) {
+            continue;
+        }
+        statefulsets.delete(&name, &Default::default()).await?;
+        info!(statefulset = %name, "Deleted unnecessary StatefulSet");
+        record_event(
+            recorder,
+            cluster,
+            EventType::Normal,
+            "DeletedStatefulSet",
+            "Reconcile",
+            format!("Deleted StatefulSet {} that is no longer needed", name),
+        ).await;
     }
     
     Ok(())
 }
 
+/// Keeps a PodDisruptionBudget over all stratum pods of the cluster, so a node
+/// drain only ever evicts one shard group pod at a time.
+pub async fn create_or_update_pdb(client: &Client, namespace: &str, cluster: &ShardCluster) -> Result<()> {
+    let pdbs: Api<PodDisruptionBudget> = Api::namespaced(client.clone(), namespace);
+    let name = format!("{}-stratum", cluster.name_any());
+
+    let mut selector = BTreeMap::new();
+    selector.insert("app".to_string(), "stratum".to_string());
+    selector.insert("managed-by".to_string(), "crust-operator".to_string());
+    selector.insert("cluster".to_string(), cluster.name_any());
+
+    let pdb = PodDisruptionBudget {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(selector.clone()),
+            owner_references: owner_references(cluster),
+            ..Default::default()
+        },
+        spec: Some(PodDisruptionBudgetSpec {
+            max_unavailable: Some(IntOrString::Int(1)),
+            selector: Some(LabelSelector {
+                match_labels: Some(selector),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let existing = pdbs.get_opt(&name).await?;
+    pdbs.patch(&name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&pdb)).await?;
+    if existing.is_none() {
+        info!(pdb = %name, "Created pod disruption budget");
+    }
+
+    Ok(())
+}
+
+/// Creates or removes the egress NetworkPolicy of the cluster's stratum pods,
+/// depending on `spec.network_policy`.
+pub async fn reconcile_network_policy(client: &Client, namespace: &str, cluster: &ShardCluster) -> Result<()> {
+    let policies: Api<NetworkPolicy> = Api::namespaced(client.clone(), namespace);
+    let name = format!("{}-stratum-egress", cluster.name_any());
+
+    if !cluster.spec.network_policy.unwrap_or(false) {
+        if policies.get_opt(&name).await?.is_some() {
+            policies.delete(&name, &Default::default()).await?;
+            info!(network_policy = %name, "Deleted network policy");
+        }
+        return Ok(());
+    }
+
+    let mut selector = BTreeMap::new();
+    selector.insert("app".to_string(), "stratum".to_string());
+    selector.insert("managed-by".to_string(), "crust-operator".to_string());
+    selector.insert("cluster".to_string(), cluster.name_any());
+
+    let port = |protocol: &str, port: i32| NetworkPolicyPort {
+        protocol: Some(protocol.to_string()),
+        port: Some(IntOrString::Int(port)),
+        ..Default::default()
+    };
+    let namespace_peer = |namespace: &str, pod_labels: Option<(&str, &str)>| NetworkPolicyPeer {
+        namespace_selector: Some(LabelSelector {
+            match_labels: Some(BTreeMap::from([(
+                "kubernetes.io/metadata.name".to_string(),
+                namespace.to_string(),
+            )])),
+            ..Default::default()
+        }),
+        pod_selector: pod_labels.map(|(key, value)| LabelSelector {
+            match_labels: Some(BTreeMap::from([(key.to_string(), value.to_string())])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    // Discord has no fixed address range, so allow HTTPS to anything outside
+    // the private ranges the rest of the cluster lives in.
+    let discord = NetworkPolicyEgressRule {
+        to: Some(vec![NetworkPolicyPeer {
+            ip_block: Some(IPBlock {
+                cidr: "0.0.0.0/0".to_string(),
+                except: Some(vec![
+                    "10.0.0.0/8".to_string(),
+                    "172.16.0.0/12".to_string(),
+                    "192.168.0.0/16".to_string(),
+                ]),
+            }),
+            ..Default::default()
+        }]),
+        ports: Some(vec![port("TCP", 443)]),
+    };
+
+    let dns = NetworkPolicyEgressRule {
+        to: Some(vec![namespace_peer("kube-system", Some(("k8s-app", "kube-dns")))]),
+        ports: Some(vec![port("UDP", 53), port("TCP", 53)]),
+    };
+
+    let default_proxy = NetworkPolicyEgressRule {
+        to: Some(vec![namespace_peer("bedrock", Some(("app", "twilight-gateway-proxy")))]),
+        ports: Some(vec![port("TCP", 80)]),
+    };
+
+    let to_service = |(service_namespace, service_port): (Option<String>, i32)| NetworkPolicyEgressRule {
+        to: Some(vec![match service_namespace {
+            Some(service_namespace) => namespace_peer(&service_namespace, None),
+            None => NetworkPolicyPeer {
+                ip_block: Some(IPBlock {
+                    cidr: "0.0.0.0/0".to_string(),
+                    except: None,
+                }),
+                ..Default::default()
+            },
+        }]),
+        ports: Some(vec![port("TCP", service_port)]),
+    };
+
+    let nats_url = &cluster.spec.nats_url;
+    let nats_port = if nats_url.starts_with("wss://") {
+        443
+    } else if nats_url.starts_with("ws://") {
+        80
+    } else {
+        4222
+    };
+    let mut egress = vec![discord, dns, to_service(service_destination(nats_url, namespace, nats_port))];
+    if cluster.spec.http_proxy_url.is_none() {
+        egress.push(default_proxy);
+    }
+    for proxy_url in cluster.spec.gateway_proxy_url.iter().chain(&cluster.spec.http_proxy_url) {
+        let default_port = if proxy_url.starts_with("https://") || proxy_url.starts_with("wss://") { 443 } else { 80 };
+        egress.push(to_service(service_destination(proxy_url, namespace, default_port)));
+    }
+
+    let policy = NetworkPolicy {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(selector.clone()),
+            owner_references: owner_references(cluster),
+            ..Default::default()
+        },
+        spec: Some(NetworkPolicySpec {
+            pod_selector: LabelSelector {
+                match_labels: Some(selector),
+                ..Default::default()
+            },
+            policy_types: Some(vec!["Egress".to_string()]),
+            egress: Some(egress),
+            ..Default::default()
+        }),
+    };
+
+    let existing = policies.get_opt(&name).await?;
+    policies.patch(&name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&policy)).await?;
+    if existing.is_none() {
+        info!(network_policy = %name, "Created network policy");
+    }
+
+    Ok(())
+}
+
+/// Creates or removes the metrics Service and ServiceMonitor of the cluster,
+/// depending on `spec.service_monitor`.
+pub async fn reconcile_service_monitor(client: &Client, namespace: &str, cluster: &ShardCluster) -> Result<()> {
+    let services: Api<Service> = Api::namespaced(client.clone(), namespace);
+    let resource = ApiResource::from_gvk(&GroupVersionKind::gvk("monitoring.coreos.com", "v1", "ServiceMonitor"));
+    let monitors: Api<DynamicObject> = Api::namespaced_with(client.clone(), namespace, &resource);
+    let name = format!("{}-stratum-metrics", cluster.name_any());
+
+    if !cluster.spec.service_monitor.unwrap_or(false) {
+        if monitors.get_opt(&name).await?.is_some() {
+            monitors.delete(&name, &Default::default()).await?;
+            info!(service_monitor = %name, "Deleted service monitor");
+        }
+        if services.get_opt(&name).await?.is_some() {
+            services.delete(&name, &Default::default()).await?;
+            info!(service = %name, "Deleted metrics service");
+        }
+        return Ok(());
+    }
+
+    let mut selector = BTreeMap::new();
+    selector.insert("app".to_string(), "stratum".to_string());
+    selector.insert("managed-by".to_string(), "crust-operator".to_string());
+    selector.insert("cluster".to_string(), cluster.name_any());
+
+    let metadata = ObjectMeta {
+        name: Some(name.clone()),
+        namespace: Some(namespace.to_string()),
+        labels: Some(selector.clone()),
+        owner_references: owner_references(cluster),
+        ..Default::default()
+    };
+
+    let service = Service {
+        metadata: metadata.clone(),
+        spec: Some(ServiceSpec {
+            selector: Some(selector.clone()),
+            ports: Some(vec![ServicePort {
+                name: Some("metrics".to_string()),
+                port: 8080,
+                target_port: Some(IntOrString::String("metrics".to_string())),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let existing = services.get_opt(&name).await?;
+    services.patch(&name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&service)).await?;
+    if existing.is_none() {
+        info!(service = %name, "Created metrics service");
+    }
+
+    let mut monitor = DynamicObject::new(&name, &resource).data(serde_json::json!({
+        "spec": {
+            "selector": { "matchLabels": selector },
+            "endpoints": [{ "port": "metrics", "path": "/metrics", "interval": "30s" }],
+        }
+    }));
+    monitor.metadata = metadata;
+
+    let existing = monitors.get_opt(&name).await?;
+    monitors.patch(&name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&monitor)).await?;
+    if existing.is_none() {
+        info!(service_monitor = %name, "Created service monitor");
+    }
+
+    Ok(())
+}
+
+/// Creates or removes the KEDA ScaledObject of the cluster's event
+/// processors, depending on `spec.event_processor_scaling`, and points the
+/// processors' health reports at the cluster.
+pub async fn reconcile_scaled_object(client: &Client, namespace: &str, cluster: &ShardCluster, subject_root: &str) -> Result<()> {
+    let resource = ApiResource::from_gvk(&GroupVersionKind::gvk("keda.sh", "v1alpha1", "ScaledObject"));
+    let scaled_objects: Api<DynamicObject> = Api::namespaced_with(client.clone(), namespace, &resource);
+    let name = format!("{}-event-processors", cluster.name_any());
+
+    let Some(scaling) = &cluster.spec.event_processor_scaling else {
+        if scaled_objects.get_opt(&name).await?.is_some() {
+            scaled_objects.delete(&name, &Default::default()).await?;
+            info!(scaled_object = %name, "Deleted event processor scaled object");
+        }
+        return Ok(());
+    };
+
+    let mut labels = BTreeMap::new();
+    labels.insert("managed-by".to_string(), "crust-operator".to_string());
+    labels.insert("cluster".to_string(), cluster.name_any());
+
+    // Each partition is its own stream with its own consumer lag, and KEDA
+    // scales on the largest of them.
+    let streams = match &scaling.stream {
+        Some(stream) => vec![stream.clone()],
+        None => cluster.event_stream_names(subject_root),
+    };
+    let triggers: Vec<_> = streams
+        .iter()
+        .map(|stream| {
+            serde_json::json!({
+                "type": "nats-jetstream",
+                "metadata": {
+                    "natsServerMonitoringEndpoint": scaling.monitoring_endpoint,
+                    "account": scaling.account.as_deref().unwrap_or("$G"),
+                    "stream": stream,
+                    "consumer": scaling.consumer.as_deref().unwrap_or("mantle-processors"),
+                    "lagThreshold": scaling.lag_threshold.unwrap_or(1000).to_string(),
+                },
+            })
+        })
+        .collect();
+
+    let mut scaled_object = DynamicObject::new(&name, &resource).data(serde_json::json!({
+        "spec": {
+            "scaleTargetRef": { "name": scaling.deployment },
+            "minReplicaCount": scaling.min_replicas.unwrap_or(1),
+            "maxReplicaCount": scaling.max_replicas.unwrap_or(10),
+            "triggers": triggers,
+        }
+    }));
+    scaled_object.metadata = ObjectMeta {
+        name: Some(name.clone()),
+        namespace: Some(namespace.to_string()),
+        labels: Some(labels),
+        owner_references: owner_references(cluster),
+        ..Default::default()
+    };
+
+    let existing = scaled_objects.get_opt(&name).await?;
+    scaled_objects
+        .patch(&name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&scaled_object))
+        .await?;
+    if existing.is_none() {
+        info!(scaled_object = %name, deployment = %scaling.deployment, "Created event processor scaled object");
+    }
+
+    set_processor_cluster(client, namespace, &scaling.deployment, &cluster.subject_prefix(subject_root)).await
+}
+
+/// Sets BEDROCK_CLUSTER on every container of the processors' deployment to
+/// the cluster's subject prefix, which their health reports are published
+/// under and the operator tracks them by.
+async fn set_processor_cluster(client: &Client, namespace: &str, deployment: &str, subject_prefix: &str) -> Result<()> {
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+    let Some(existing) = deployments.get_opt(deployment).await? else {
+        warn!(deployment = %deployment, "Event processor deployment not found, not setting BEDROCK_CLUSTER");
+        return Ok(());
+    };
+
+    let containers: Vec<_> = existing
+        .spec
+        .and_then(|spec| spec.template.spec)
+        .map(|pod| pod.containers)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|container| {
+            !container.env.iter().flatten().any(|env| {
+                env.name == "BEDROCK_CLUSTER" && env.value.as_deref() == Some(subject_prefix)
+            })
+        })
+        .map(|container| {
+            serde_json::json!({
+                "name": container.name,
+                "env": [{ "name": "BEDROCK_CLUSTER", "value": subject_prefix }],
+            })
+        })
+        .collect();
+    if containers.is_empty() {
+        return Ok(());
+    }
+
+    let patch = serde_json::json!({ "spec": { "template": { "spec": { "containers": containers } } } });
+    deployments.patch(deployment, &PatchParams::default(), &Patch::Strategic(&patch)).await?;
+    info!(deployment = %deployment, subject_prefix = %subject_prefix, "Set BEDROCK_CLUSTER on event processors");
+
+    Ok(())
+}
+
+/// Works out the namespace and port a service such as NATS is reached on from
+/// its URL. The namespace is `None` when the host is not a service name.
+fn service_destination(url: &str, namespace: &str, default_port: i32) -> (Option<String>, i32) {
+    let address = url.split("://").last().unwrap_or(url);
+    let address = address.rsplit('@').next().unwrap_or(address);
+    let address = address.split(['/', ',']).next().unwrap_or(address);
+
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().unwrap_or(default_port)),
+        None => (address, default_port),
+    };
+
+    let labels: Vec<&str> = host.split('.').collect();
+    let service_namespace = match labels.as_slice() {
+        [_service] => Some(namespace.to_string()),
+        [_service, service_namespace] | [_service, service_namespace, "svc", ..] => Some(service_namespace.to_string()),
+        _ => None,
+    };
+
+    (service_namespace, port)
+}
+
+pub async fn delete_deployments(client: &Client, namespace: &str, cluster_name: &str) -> Result<()> {
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+
+    let list_params = ListParams::default().labels(&format!(
+        "managed-by=crust-operator,app=stratum,cluster={}",
+        cluster_name
+    ));
+
+    for deployment in deployments.list(&list_params).await?.items {
+        let Some(name) = deployment.metadata.name else {
+            continue;
+        };
+        deployments.delete(&name, &Default::default()).await?;
+        info!(deployment = %name, cluster = %cluster_name, "Deleted deployment of removed cluster");
+    }
+
+    let statefulsets: Api<StatefulSet> = Api::namespaced(client.clone(), namespace);
+    for statefulset in statefulsets.list(&list_params).await?.items {
+        let name = statefulset.name_any();
+        statefulsets.delete(&name, &Default::default()).await?;
+        info!(statefulset = %name, cluster = %cluster_name, "Deleted StatefulSet of removed cluster");
+    }
+
+    Ok(())
+}
+
+/// Name of the StatefulSet running `shard_groups`, the group names without
+/// their index so that the pod names are the group names.
+fn statefulset_name(shard_groups: &[ShardGroup]) -> Option<String> {
+    shard_groups
+        .first()
+        .and_then(|group| group.deployment_name.rsplit_once('-'))
+        .map(|(name, _)| name.to_string())
+}
+
+/// The StatefulSet running `shard_groups`, one pod per group in group order,
+/// with its spec hash annotated.
+fn statefulset_spec(
+    cluster: &ShardCluster,
+    subject_root: &str,
+    shard_groups: &[ShardGroup],
+    namespace: &str,
+    total_shards: u32,
+    max_concurrency: u32,
+    token_hash: &str,
+) -> Result<Option<StatefulSet>> {
+    let (Some(name), Some(last_group)) = (statefulset_name(shard_groups), shard_groups.last()) else {
+        return Ok(None);
+    };
+
+    // Every pod shares one template. Canary groups come first, so the last
+    // group's image only changes once a rollout reaches every group.
+    let deployment = create_deployment_spec(cluster, subject_root, last_group, namespace, total_shards, max_concurrency, token_hash)?;
+    let Some(DeploymentSpec { selector, mut template, .. }) = deployment.spec else {
+        return Ok(None);
+    };
+
+    // Pods take their shards and worker id from their ordinal and hostname.
+    if let Some(container) = template.spec.as_mut().and_then(|spec| spec.containers.first_mut()) {
+        let env = container.env.get_or_insert_with(Vec::new);
+        env.retain(|var| !["SHARD_ID_START", "SHARD_ID_END", "WORKER_ID"].contains(&var.name.as_str()));
+        env.push(EnvVar {
+            name: "SHARD_ASSIGNMENT".to_string(),
+            value: Some("ordinal".to_string()),
+            value_from: None,
+        });
+        env.push(EnvVar {
+            name: "SHARDS_PER_REPLICA".to_string(),
+            value: Some(cluster.spec.shards_per_replica.to_string()),
+            value_from: None,
+        });
+    }
+
+    let mut labels = deployment.metadata.labels.unwrap_or_default();
+    labels.insert("shard-group".to_string(), name.clone());
+    let mut selector = selector;
+    selector.match_labels = Some(labels.clone());
+    if let Some(metadata) = template.metadata.as_mut() {
+        metadata.labels = Some(labels.clone());
+    }
+
+    let rolling_update = cluster.spec.rolling_update.clone().unwrap_or_default();
+    let mut statefulset = StatefulSet {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(labels),
+            owner_references: owner_references(cluster),
+            ..Default::default()
+        },
+        spec: Some(StatefulSetSpec {
+            replicas: Some(shard_groups.len() as i32),
+            selector,
+            service_name: Some(name.clone()),
+            // Identifies are paced by the coordination protocol, not by
+            // starting pods one after another.
+            pod_management_policy: Some("Parallel".to_string()),
+            template,
+            min_ready_seconds: rolling_update.min_ready_seconds,
+            revision_history_limit: rolling_update.revision_history_limit,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let spec_hash = hash_workload(&serde_json::to_vec(&statefulset)?);
+    statefulset
+        .annotations_mut()
+        .insert(SPEC_HASH_ANNOTATION.to_string(), spec_hash);
+
+    Ok(Some(statefulset))
+}
+
+/// Applies the StatefulSet running `shard_groups`, one pod per group in
+/// group order.
+#[allow(clippy::too_many_arguments)]
+async fn apply_statefulset(
+    client: &Client,
+    recorder: &Recorder,
+    namespace: &str,
+    cluster: &ShardCluster,
+    subject_root: &str,
+    shard_groups: &[ShardGroup],
+    total_shards: u32,
+    max_concurrency: u32,
+    token_hash: &str,
+) -> Result<()> {
+    let Some(statefulset) = statefulset_spec(cluster, subject_root, shard_groups, namespace, total_shards, max_concurrency, token_hash)? else {
+        return Ok(());
+    };
+    let name = statefulset.name_any();
+    let spec_hash = statefulset.annotations().get(SPEC_HASH_ANNOTATION).cloned().unwrap_or_default();
+    let statefulsets: Api<StatefulSet> = Api::namespaced(client.clone(), namespace);
+
+    let existing = statefulsets.get_opt(&name).await?;
+    statefulsets
+        .patch(&name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&statefulset))
+        .await?;
+
+    match existing.as_ref().map(|existing| existing.annotations().get(SPEC_HASH_ANNOTATION)) {
+        Some(applied) if applied == Some(&spec_hash) => {}
+        Some(_) => info!(statefulset = %name, spec_hash = %spec_hash, "Updated StatefulSet"),
+        None => {
+            info!(statefulset = %name, spec_hash = %spec_hash, "Created StatefulSet");
+            record_event(
+                recorder,
+                cluster,
+                EventType::Normal,
+                "CreatedStatefulSet",
+                "Reconcile",
+                format!("Created StatefulSet {} for {} shard groups", name, shard_groups.len()),
+            ).await;
+        }
+    }
+
+    Ok(())
+}
+
 fn create_deployment_spec(
     cluster: &ShardCluster,
+    subject_root: &str,
     group: &ShardGroup,
     namespace: &str,
     total_shards: u32,
     max_concurrency: u32,
+    token_hash: &str,
 ) -> Result<Deployment> {
+    let drain_timeout = cluster.spec.drain_timeout_seconds.unwrap_or(25);
+    let rolling_update = cluster.spec.rolling_update.clone().unwrap_or_default();
+
     let mut labels = BTreeMap::new();
     labels.insert("app".to_string(), "stratum".to_string());
     labels.insert("shard-group".to_string(), group.deployment_name.clone());
     labels.insert("managed-by".to_string(), "crust-operator".to_string());
     labels.insert("cluster".to_string(), cluster.name_any());
 
-    let env_vars = vec![
+    let mut env_vars = vec![
         EnvVar {
             name: "NATS_URL".to_string(),
             value: Some(cluster.spec.nats_url.clone()),
             value_from: None,
         },
+        EnvVar {
+            name: "SUBJECT_PREFIX".to_string(),
+            value: Some(cluster.subject_prefix(subject_root)),
+            value_from: None,
+        },
         EnvVar {
             name: "SHARD_ID_START".to_string(),
             value: Some(group.shard_start.to_string()),
@@ -154,6 +1181,11 @@ fn create_deployment_spec(
             value: Some(max_concurrency.to_string()),
             value_from: None,
         },
+        EnvVar {
+            name: "DRAIN_TIMEOUT_SECS".to_string(),
+            value: Some(drain_timeout.to_string()),
+            value_from: None,
+        },
         EnvVar {
             name: "DISCORD_TOKEN".to_string(),
             value: None,
@@ -168,15 +1200,222 @@ fn create_deployment_spec(
         },
     ];
 
+    if let Some(signing_secret) = &cluster.spec.coordination_signing_secret {
+        env_vars.push(EnvVar {
+            name: "COORDINATION_SIGNING_KEY".to_string(),
+            value: None,
+            value_from: Some(k8s_openapi::api::core::v1::EnvVarSource {
+                secret_key_ref: Some(k8s_openapi::api::core::v1::SecretKeySelector {
+                    name: signing_secret.clone(),
+                    key: "key".to_string(),
+                    optional: None,
+                }),
+                ..Default::default()
+            }),
+        });
+    }
+
+    if let Some(intents) = &cluster.spec.intents {
+        env_vars.push(EnvVar {
+            name: "INTENTS".to_string(),
+            value: Some(intents.join(",")),
+            value_from: None,
+        });
+    }
+
+    if let Some(allow_privileged_intents) = cluster.spec.allow_privileged_intents {
+        env_vars.push(EnvVar {
+            name: "ALLOW_PRIVILEGED_INTENTS".to_string(),
+            value: Some(allow_privileged_intents.to_string()),
+            value_from: None,
+        });
+    }
+
+    if let Some(event_filter) = &cluster.spec.event_filter {
+        env_vars.push(EnvVar {
+            name: "EVENT_FILTER".to_string(),
+            value: Some(event_filter.join(",")),
+            value_from: None,
+        });
+    }
+
+    if cluster.spec.event_stream.is_some() {
+        env_vars.push(EnvVar {
+            name: "EVENT_STREAM".to_string(),
+            value: Some(cluster.event_stream_name(subject_root)),
+            value_from: None,
+        });
+        env_vars.push(EnvVar {
+            name: "STREAM_PARTITIONS".to_string(),
+            value: Some(cluster.event_stream_partitions().to_string()),
+            value_from: None,
+        });
+    }
+
+    if let Some(gateway_proxy_url) = &cluster.spec.gateway_proxy_url {
+        env_vars.push(EnvVar {
+            name: "GATEWAY_URL".to_string(),
+            value: Some(gateway_proxy_url.clone()),
+            value_from: None,
+        });
+    }
+
+    if let Some(http_proxy_url) = &cluster.spec.http_proxy_url {
+        env_vars.push(EnvVar {
+            name: "TWILIGHT_PROXY_URL".to_string(),
+            value: Some(http_proxy_url.clone()),
+            value_from: None,
+        });
+    }
+
+    if cluster.spec.replicas_per_shard_group > 1 {
+        env_vars.push(EnvVar {
+            name: "ACTIVE_STANDBY".to_string(),
+            value: Some("true".to_string()),
+            value_from: None,
+        });
+    }
+
+    if cluster.spec.service_monitor.unwrap_or(false) {
+        env_vars.push(EnvVar {
+            name: "ADMIN_ADDR".to_string(),
+            value: Some("0.0.0.0:8080".to_string()),
+            value_from: None,
+        });
+    }
+
+    let mut overlay = cluster.spec.pod_template.clone().unwrap_or_default();
+
+    if let Some(credentials_secret) = &cluster.spec.nats_credentials_secret {
+        env_vars.push(EnvVar {
+            name: "NATS_CREDENTIALS_FILE".to_string(),
+            value: Some(format!("{}/creds", NATS_CREDENTIALS_PATH)),
+            value_from: None,
+        });
+        overlay.volumes.get_or_insert_with(Vec::new).push(Volume {
+            name: "nats-credentials".to_string(),
+            secret: Some(SecretVolumeSource {
+                secret_name: Some(credentials_secret.clone()),
+                items: Some(vec![KeyToPath {
+                    key: "creds".to_string(),
+                    path: "creds".to_string(),
+                    mode: None,
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        overlay.volume_mounts.get_or_insert_with(Vec::new).push(VolumeMount {
+            name: "nats-credentials".to_string(),
+            mount_path: NATS_CREDENTIALS_PATH.to_string(),
+            read_only: Some(true),
+            ..Default::default()
+        });
+    }
+
+    let nats_tls = cluster.spec.nats_tls.clone().unwrap_or_default();
+    let tls_mounts = nats_tls
+        .ca_secret
+        .iter()
+        .map(|secret| (secret, "nats-tls-ca", NATS_TLS_CA_PATH, vec![("NATS_TLS_CA_FILE", "ca.crt")]))
+        .chain(nats_tls.client_certificate_secret.iter().map(|secret| {
+            (
+                secret,
+                "nats-tls-client",
+                NATS_TLS_CLIENT_PATH,
+                vec![("NATS_TLS_CERT_FILE", "tls.crt"), ("NATS_TLS_KEY_FILE", "tls.key")],
+            )
+        }));
+    for (secret, volume, path, files) in tls_mounts {
+        for (name, key) in &files {
+            env_vars.push(EnvVar {
+                name: name.to_string(),
+                value: Some(format!("{}/{}", path, key)),
+                value_from: None,
+            });
+        }
+        overlay.volumes.get_or_insert_with(Vec::new).push(Volume {
+            name: volume.to_string(),
+            secret: Some(SecretVolumeSource {
+                secret_name: Some(secret.clone()),
+                items: Some(
+                    files
+                        .iter()
+                        .map(|(_, key)| KeyToPath { key: key.to_string(), path: key.to_string(), mode: None })
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        overlay.volume_mounts.get_or_insert_with(Vec::new).push(VolumeMount {
+            name: volume.to_string(),
+            mount_path: path.to_string(),
+            read_only: Some(true),
+            ..Default::default()
+        });
+    }
+
+    if let Some(domain) = &cluster.spec.nats_jetstream_domain {
+        env_vars.push(EnvVar {
+            name: "JETSTREAM_DOMAIN".to_string(),
+            value: Some(domain.clone()),
+            value_from: None,
+        });
+    }
+
+    if let Some(auth_secret) = &cluster.spec.nats_auth_secret {
+        // Optional keys, so the secret only needs the entries of the method it uses.
+        for (name, key) in [("NATS_NKEY", "nkey"), ("NATS_TOKEN", "token"), ("NATS_USER", "user"), ("NATS_PASSWORD", "password")] {
+            env_vars.push(EnvVar {
+                name: name.to_string(),
+                value: None,
+                value_from: Some(k8s_openapi::api::core::v1::EnvVarSource {
+                    secret_key_ref: Some(k8s_openapi::api::core::v1::SecretKeySelector {
+                        name: auth_secret.clone(),
+                        key: key.to_string(),
+                        optional: Some(true),
+                    }),
+                    ..Default::default()
+                }),
+            });
+        }
+    }
+
+    for var in overlay.env.unwrap_or_default() {
+        env_vars.retain(|existing| existing.name != var.name);
+        env_vars.push(var);
+    }
+
+    let mut containers = vec![Container {
+        name: "stratum".to_string(),
+        image: Some(cluster.image_for(group).to_string()),
+        image_pull_policy: Some("Never".to_string()),
+        env: Some(env_vars),
+        resources: cluster.spec.resources.clone(),
+        volume_mounts: overlay.volume_mounts,
+        ports: Some(vec![ContainerPort {
+            container_port: 8080,
+            name: Some("metrics".to_string()),
+            ..Default::default()
+        }]),
+        ..Default::default()
+    }];
+    containers.extend(overlay.sidecars.unwrap_or_default());
+
+    let mut annotations = overlay.annotations.unwrap_or_default();
+    annotations.insert(TOKEN_HASH_ANNOTATION.to_string(), token_hash.to_string());
+
     let deployment = Deployment {
         metadata: ObjectMeta {
             name: Some(group.deployment_name.clone()),
             namespace: Some(namespace.to_string()),
             labels: Some(labels.clone()),
+            owner_references: owner_references(cluster),
             ..Default::default()
         },
         spec: Some(DeploymentSpec {
-            replicas: Some(group.replicas),
+            replicas: Some(cluster.spec.replicas_per_shard_group),
             selector: LabelSelector {
                 match_labels: Some(labels.clone()),
                 ..Default::default()
@@ -184,24 +1423,33 @@ fn create_deployment_spec(
             template: PodTemplateSpec {
                 metadata: Some(ObjectMeta {
                     labels: Some(labels),
+                    annotations: Some(annotations),
                     ..Default::default()
                 }),
                 spec: Some(PodSpec {
-                    containers: vec![Container {
-                        name: "stratum".to_string(),
-                        image: Some(cluster.spec.image.clone()),
-                        image_pull_policy: Some("Never".to_string()),
-                        env: Some(env_vars),
-                        ports: Some(vec![ContainerPort {
-                            container_port: 8080,
-                            name: Some("metrics".to_string()),
-                            ..Default::default()
-                        }]),
-                        ..Default::default()
-                    }],
+                    containers,
+                    volumes: overlay.volumes,
+                    service_account_name: overlay.service_account_name,
+                    termination_grace_period_seconds: Some(drain_timeout as i64 + 10),
+                    node_selector: cluster.spec.node_selector.clone(),
+                    tolerations: cluster.spec.tolerations.clone(),
+                    affinity: cluster.spec.affinity.clone(),
+                    topology_spread_constraints: cluster.spec.topology_spread_constraints.clone(),
+                    priority_class_name: cluster.spec.priority_class_name.clone(),
                     ..Default::default()
                 }),
             },
+            strategy: (rolling_update.max_unavailable.is_some() || rolling_update.max_surge.is_some()).then(|| {
+                DeploymentStrategy {
+                    type_: Some("RollingUpdate".to_string()),
+                    rolling_update: Some(RollingUpdateDeployment {
+                        max_unavailable: rolling_update.max_unavailable.clone(),
+                        max_surge: rolling_update.max_surge.clone(),
+                    }),
+                }
+            }),
+            min_ready_seconds: rolling_update.min_ready_seconds,
+            revision_history_limit: rolling_update.revision_history_limit,
             ..Default::default()
         }),
         ..Default::default()
@@ -209,3 +1457,44 @@ fn create_deployment_spec(
 
     Ok(deployment)
 }
+
+/// Restarts the worker `name` of the cluster, rolling its deployment or
+/// deleting its pod for the StatefulSet to recreate.
+pub async fn restart_worker(client: &Client, namespace: &str, cluster: &ShardCluster, name: &str) -> Result<()> {
+    match cluster.spec.workload_kind.unwrap_or_default() {
+        WorkloadKind::Deployment => restart_deployment(client, namespace, name).await,
+        WorkloadKind::StatefulSet => {
+            let pods: Api<Pod> = Api::namespaced(client.clone(), namespace);
+            pods.delete(name, &Default::default()).await?;
+            info!(pod = %name, "Deleted pod to restart it");
+            Ok(())
+        }
+    }
+}
+
+pub async fn restart_deployment(client: &Client, namespace: &str, name: &str) -> Result<()> {
+    let deployments: Api<Deployment> = Api::namespaced(client.clone(), namespace);
+
+    let patch = serde_json::json!({
+        "apiVersion": "apps/v1",
+        "kind": "Deployment",
+        "spec": {
+            "template": {
+                "metadata": {
+                    "annotations": {
+                        "crust.bedrock.dev/restarted-at": chrono::Utc::now().to_rfc3339()
+                    }
+                }
+            }
+        }
+    });
+
+    // Applied under its own field manager, applying only the annotation as
+    // FIELD_MANAGER would drop every other field of the deployment.
+    deployments
+        .patch(name, &PatchParams::apply(RESTART_FIELD_MANAGER).force(), &Patch::Apply(&patch))
+        .await?;
+
+    info!(deployment = %name, "Triggered rolling restart");
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use crate::{get_secret_value, hash_workload, FIELD_MANAGER};
+use crust_types::{CrustError, RemoteClientRegistry, Result, ShardCluster};
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::{
+    api::{Api, ListParams, Patch, PatchParams},
+    config::{KubeConfigOptions, Kubeconfig},
+    Client, ResourceExt,
+};
+use std::collections::BTreeMap;
+use tracing::{debug, info};
+
+/// Client and namespace the workloads of `cluster` live in: those of its
+/// remote cluster, or `client` and the ShardCluster's own namespace.
+pub async fn workload_target(
+    client: &Client,
+    remote_clients: &RemoteClientRegistry,
+    cluster: &ShardCluster,
+) -> Result<(Client, String)> {
+    let namespace = cluster.namespace().unwrap_or_else(|| "default".to_string());
+    let Some(remote) = &cluster.spec.remote else {
+        return Ok((client.clone(), namespace));
+    };
+
+    let remote_namespace = remote.namespace.clone().unwrap_or_else(|| namespace.clone());
+    let kubeconfig = get_secret_value(client, &namespace, &remote.kubeconfig_secret, "kubeconfig").await?;
+    let kubeconfig_hash = hash_workload(&kubeconfig);
+    let key = format!("{}/{}", namespace, remote.kubeconfig_secret);
+
+    let cached = remote_clients
+        .read()
+        .expect("remote client registry poisoned")
+        .get(&key)
+        .filter(|(hash, _)| *hash == kubeconfig_hash)
+        .map(|(_, client)| client.clone());
+    if let Some(remote_client) = cached {
+        return Ok((remote_client, remote_namespace));
+    }
+
+    let invalid = |e: &dyn std::fmt::Display| {
+        CrustError::Validation(format!("Invalid kubeconfig in secret {}: {}", remote.kubeconfig_secret, e))
+    };
+    let kubeconfig = std::str::from_utf8(&kubeconfig).map_err(|e| invalid(&e))?;
+    let kubeconfig = Kubeconfig::from_yaml(kubeconfig).map_err(|e| invalid(&e))?;
+    let config = kube::Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default())
+        .await
+        .map_err(|e| invalid(&e))?;
+    let remote_client = Client::try_from(config)?;
+
+    info!(cluster = %cluster.name_any(), secret = %remote.kubeconfig_secret, "Connected to remote cluster");
+    remote_clients
+        .write()
+        .expect("remote client registry poisoned")
+        .insert(key, (kubeconfig_hash, remote_client.clone()));
+
+    Ok((remote_client, remote_namespace))
+}
+
+/// Copies the secrets the stratum pods reference into the remote namespace,
+/// since pods can only read secrets of their own cluster.
+pub async fn sync_remote_secrets(
+    client: &Client,
+    remote_client: &Client,
+    remote_namespace: &str,
+    cluster: &ShardCluster,
+) -> Result<()> {
+    if cluster.spec.remote.is_none() {
+        return Ok(());
+    }
+
+    let namespace = cluster.namespace().unwrap_or_else(|| "default".to_string());
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), &namespace);
+    let remote_secrets: Api<Secret> = Api::namespaced(remote_client.clone(), remote_namespace);
+
+    let referenced_secrets = std::iter::once(&cluster.spec.discord_token_secret)
+        .chain(cluster.spec.coordination_signing_secret.as_ref())
+        .chain(cluster.spec.nats_credentials_secret.as_ref())
+        .chain(cluster.spec.nats_auth_secret.as_ref())
+        .chain(cluster.spec.nats_tls.iter().flat_map(|tls| tls.secrets()));
+
+    for name in referenced_secrets {
+        let secret = secrets.get(name).await?;
+
+        let mut labels = BTreeMap::new();
+        labels.insert("managed-by".to_string(), "crust-operator".to_string());
+        labels.insert("cluster".to_string(), cluster.name_any());
+
+        let copy = Secret {
+            metadata: ObjectMeta {
+                name: Some(name.clone()),
+                namespace: Some(remote_namespace.to_string()),
+                labels: Some(labels),
+                ..Default::default()
+            },
+            data: secret.data,
+            type_: secret.type_,
+            ..Default::default()
+        };
+
+        remote_secrets
+            .patch(name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&copy))
+            .await?;
+        debug!(secret = %name, namespace = %remote_namespace, "Copied secret to remote cluster");
+    }
+
+    Ok(())
+}
+
+/// Deletes the secrets `sync_remote_secrets` copied for the cluster.
+pub async fn delete_remote_secrets(remote_client: &Client, remote_namespace: &str, cluster_name: &str) -> Result<()> {
+    let remote_secrets: Api<Secret> = Api::namespaced(remote_client.clone(), remote_namespace);
+    let list_params = ListParams::default().labels(&format!("managed-by=crust-operator,cluster={}", cluster_name));
+
+    for secret in remote_secrets.list(&list_params).await?.items {
+        let name = secret.name_any();
+        remote_secrets.delete(&name, &Default::default()).await?;
+        info!(secret = %name, cluster = %cluster_name, "Deleted copied secret of removed cluster");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use crust_types::{Context, OperatorConfig};
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::Api,
+    runtime::watcher::{watcher, Config, Event},
+};
+use tracing::{info, warn};
+
+/// Keeps the operator config in line with the `name` ConfigMap. Keys it does
+/// not set fall back to the environment, and a ConfigMap that fails to parse
+/// is logged and leaves the current config in place.
+pub async fn watch(context: Context, namespace: String, name: String) {
+    let config_maps: Api<ConfigMap> = Api::namespaced(context.client.clone(), &namespace);
+    let watch_config = Config::default().fields(&format!("metadata.name={}", name));
+    let mut events = watcher(config_maps, watch_config).boxed();
+
+    info!(config_map = %name, namespace = %namespace, "Watching operator config");
+
+    // Set again on every relist, so a ConfigMap deleted while the watch was
+    // down still reverts to the environment.
+    let mut seen = false;
+    while let Some(event) = events.next().await {
+        let config = match event {
+            Ok(Event::Init) => {
+                seen = false;
+                continue;
+            }
+            Ok(Event::InitApply(config_map)) | Ok(Event::Apply(config_map)) => {
+                seen = true;
+                OperatorConfig::from_config_map(&config_map.data.unwrap_or_default())
+            }
+            Ok(Event::InitDone) if seen => continue,
+            Ok(Event::InitDone) | Ok(Event::Delete(_)) => OperatorConfig::from_env(),
+            Err(e) => {
+                warn!(error = %e, "Operator config watch failed");
+                continue;
+            }
+        };
+
+        match config {
+            Ok(config) => apply(&context, config),
+            Err(e) => warn!(config_map = %name, error = %e, "Ignoring invalid operator config"),
+        }
+    }
+}
+
+fn apply(context: &Context, config: OperatorConfig) {
+    let mut current = context.config.write().expect("operator config poisoned");
+    if *current == config {
+        return;
+    }
+
+    context
+        .gateway
+        .write()
+        .expect("gateway cache poisoned")
+        .set_limits(config.gateway_info_ttl, config.gateway_info_calls_per_minute);
+    info!(config = ?config, "Applied operator config");
+    *current = config;
+}
@@ -1,15 +1,32 @@
-use anyhow::Result;
-use crust_types::{Context, ShardCluster};
+use anyhow::{Context as _, Result};
+use bedrock_nats::{Auth, ConnectionBuilder, RetryPolicy, Tls};
+use crust_kubernetes::leader::LeaderElector;
+use crust_types::{Context, FailureRegistry, GatewayInfoCache, IdentifyRegistry, OperatorConfig, ProcessorStatusRegistry, RemoteClientRegistry, ReshardRegistry, ShardCluster, ShardStatusRegistry, SigningKeyRegistry, StartupRegistry, WorkerRegistry};
+use k8s_openapi::api::apps::v1::{Deployment, StatefulSet};
+use k8s_openapi::api::core::v1::Secret;
 use futures::StreamExt;
 use kube::{
     api::Api,
-    runtime::{controller::Controller, watcher::Config},
-    Client,
+    runtime::{
+        controller::{self, Controller},
+        events::{Recorder, Reporter},
+        reflector::ObjectRef,
+        watcher::Config,
+    },
+    Client, ResourceExt,
 };
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tracing::{debug, info, warn, Level};
 use tracing_subscriber::EnvFilter;
 
+mod config;
+mod webhook;
+
+const LEASE_DURATION: Duration = Duration::from_secs(15);
+const LEASE_RENEW_PERIOD: Duration = Duration::from_secs(5);
+const LEASE_RETRY_PERIOD: Duration = Duration::from_secs(2);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let subscriber = EnvFilter::from_default_env()
@@ -23,20 +40,142 @@ async fn main() -> Result<()> {
     info!("Starting Crust Kubernetes Operator");
 
     let client = Client::try_default().await?;
+
+    // The environment only gives the starting point, the ConfigMap watch below
+    // replaces it as soon as the ConfigMap is read.
+    let config = OperatorConfig::from_env().context("Invalid operator config")?;
+
+    let webhook_enabled: bool = std::env::var("WEBHOOK_ENABLED")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()
+        .context("Invalid WEBHOOK_ENABLED")?;
+
+    // Every replica answers admission requests, not just the leader.
+    let webhook_task = if webhook_enabled {
+        let addr: std::net::SocketAddr = std::env::var("WEBHOOK_ADDR")
+            .unwrap_or_else(|_| "0.0.0.0:8443".to_string())
+            .parse()
+            .context("Invalid WEBHOOK_ADDR")?;
+        let cert_path = std::env::var("WEBHOOK_TLS_CERT")
+            .unwrap_or_else(|_| "/etc/crust/webhook/tls.crt".to_string());
+        let key_path = std::env::var("WEBHOOK_TLS_KEY")
+            .unwrap_or_else(|_| "/etc/crust/webhook/tls.key".to_string());
+        let webhook_client = client.clone();
+        let subject_root = config.subject_root.clone();
+
+        Some(tokio::spawn(async move {
+            webhook::serve(addr, &cert_path, &key_path, webhook_client, subject_root).await
+        }))
+    } else {
+        None
+    };
+
+    let leader_election_enabled: bool = std::env::var("LEADER_ELECTION_ENABLED")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()
+        .context("Invalid LEADER_ELECTION_ENABLED")?;
+
+    let leader_elector = if leader_election_enabled {
+        let namespace = std::env::var("LEADER_ELECTION_NAMESPACE")
+            .unwrap_or_else(|_| "default".to_string());
+        let lease_name = std::env::var("LEADER_ELECTION_LEASE_NAME")
+            .unwrap_or_else(|_| "crust-operator-leader".to_string());
+        let identity = std::env::var("POD_NAME")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .context("POD_NAME or HOSTNAME must be set for leader election")?;
+
+        let elector = LeaderElector::new(&client, &namespace, &lease_name, &identity, LEASE_DURATION);
+        info!(lease = %lease_name, namespace = %namespace, identity = %identity, "Waiting for leadership");
+        elector.acquire(LEASE_RETRY_PERIOD).await;
+        Some(elector)
+    } else {
+        None
+    };
     
     let nats_url = std::env::var("NATS_URL")
         .unwrap_or_else(|_| "nats://localhost:4222".to_string());
     
-    let nats_client = crust_nats::connect(&nats_url).await?;
+    // The operator shares one connection between all clusters, so it uses its
+    // own authentication rather than any cluster's NATS secrets.
+    let nats_connection = ConnectionBuilder::new(nats_url)
+        .auth(Auth::from_env().context("Invalid NATS authentication")?)
+        .tls(Tls::from_env().context("Invalid NATS TLS settings")?)
+        .retry(RetryPolicy::retries(3))
+        .connect()
+        .await?;
+    let nats_events = nats_connection.events().clone();
+    let nats_client = nats_connection.into_client();
     
+    // Controllers and watches are built once, so these keep their value from
+    // the environment when the ConfigMap changes.
+    let controller_config = controller::Config::default().concurrency(config.reconcile_concurrency);
+    let watch_config = Config::default().page_size(config.watch_page_size);
+    let gateway = GatewayInfoCache::new(config.gateway_info_ttl, config.gateway_info_calls_per_minute);
+    let config_namespace = std::env::var("OPERATOR_NAMESPACE")
+        .unwrap_or_else(|_| "default".to_string());
+    let config_map = std::env::var("OPERATOR_CONFIG_MAP")
+        .unwrap_or_else(|_| "crust-operator-config".to_string());
+
+    let reporter = Reporter {
+        controller: "crust-operator".to_string(),
+        instance: std::env::var("POD_NAME").ok(),
+    };
+
     let context = Context {
+        recorder: Recorder::new(client.clone(), reporter),
         client: client.clone(),
         nats_client,
+        workers: WorkerRegistry::default(),
+        shard_statuses: ShardStatusRegistry::default(),
+        processor_statuses: ProcessorStatusRegistry::default(),
+        reshards: ReshardRegistry::default(),
+        startups: StartupRegistry::default(),
+        identify: IdentifyRegistry::default(),
+        signing_keys: SigningKeyRegistry::default(),
+        gateway: Arc::new(RwLock::new(gateway)),
+        failures: FailureRegistry::default(),
+        remote_clients: RemoteClientRegistry::default(),
+        config: Arc::new(RwLock::new(config)),
     };
 
     let shard_clusters: Api<ShardCluster> = Api::all(client.clone());
+    let deployments: Api<Deployment> = Api::all(client.clone());
+    let statefulsets: Api<StatefulSet> = Api::all(client.clone());
+    let secrets: Api<Secret> = Api::all(client.clone());
     
-    let controller = Controller::new(shard_clusters.clone(), Config::default())
+    let controller = Controller::new(shard_clusters.clone(), watch_config.clone()).with_config(controller_config);
+
+    // A rotated token only reaches the pods through a new pod template, and
+    // remote clusters only see copies of their secrets, so reconcile every
+    // cluster that references a changed secret.
+    let clusters = controller.store();
+    let controller = controller
+        .owns(deployments, watch_config.clone().labels("managed-by=crust-operator,app=stratum"))
+        .owns(statefulsets, watch_config.clone().labels("managed-by=crust-operator,app=stratum"))
+        .watches(secrets, watch_config, move |secret: Secret| {
+            clusters
+                .state()
+                .into_iter()
+                .filter(|cluster| {
+                    let name = secret.name_any();
+                    let copied = cluster.spec.remote.is_some()
+                        && (cluster.spec.coordination_signing_secret.as_ref() == Some(&name)
+                            || cluster.spec.nats_credentials_secret.as_ref() == Some(&name)
+                            || cluster.spec.nats_auth_secret.as_ref() == Some(&name)
+                            || cluster.spec.nats_tls.iter().any(|tls| tls.secrets().any(|secret| secret == &name)));
+                    // Issued credentials are put back when deleted.
+                    let issued = cluster.spec.nats_account_signing_secret.is_some()
+                        && (cluster.spec.nats_account_signing_secret.as_ref() == Some(&name)
+                            || name == format!("{}-nats-credentials", cluster.name_any()));
+                    cluster.namespace() == secret.namespace()
+                        && (cluster.spec.discord_token_secret == name
+                            || cluster.spec.remote.as_ref().is_some_and(|remote| remote.kubeconfig_secret == name)
+                            || copied
+                            || issued)
+                })
+                .map(|cluster| ObjectRef::from_obj(&*cluster))
+                .collect::<Vec<_>>()
+        })
         .run(crust_controller::reconcile, crust_controller::error_policy, Arc::new(context.clone()))
         .for_each(|res| async move {
             match res {
@@ -45,17 +184,162 @@ async fn main() -> Result<()> {
             }
         });
 
-    let reshard_context = context.clone();
-    let reshard_task = tokio::spawn(async move {
-        crust_scheduler::reshard_scheduler(reshard_context).await;
+    let heartbeat_context = context.clone();
+    let heartbeat_reconnects = nats_events.reconnected();
+    let heartbeat_task = tokio::spawn(async move {
+        resubscribing(heartbeat_reconnects, "Worker heartbeat tracking", || {
+            crust_nats::track_worker_heartbeats(
+                &heartbeat_context.nats_client,
+                heartbeat_context.workers.clone(),
+                heartbeat_context.config.clone(),
+            )
+        })
+        .await;
+    });
+
+    let shard_status_context = context.clone();
+    let shard_status_reconnects = nats_events.reconnected();
+    let shard_status_task = tokio::spawn(async move {
+        resubscribing(shard_status_reconnects, "Shard status tracking", || {
+            crust_nats::track_shard_statuses(
+                &shard_status_context.nats_client,
+                shard_status_context.shard_statuses.clone(),
+                shard_status_context.config.clone(),
+            )
+        })
+        .await;
+    });
+
+    let processor_status_context = context.clone();
+    let processor_status_reconnects = nats_events.reconnected();
+    let processor_status_task = tokio::spawn(async move {
+        resubscribing(processor_status_reconnects, "Processor status tracking", || {
+            crust_nats::track_processor_statuses(
+                &processor_status_context.nats_client,
+                processor_status_context.processor_statuses.clone(),
+                processor_status_context.config.clone(),
+            )
+        })
+        .await;
+    });
+
+    let progress_context = context.clone();
+    let progress_reconnects = nats_events.reconnected();
+    let progress_task = tokio::spawn(async move {
+        resubscribing(progress_reconnects, "Reshard progress tracking", || {
+            crust_nats::track_reshard_progress(
+                &progress_context.nats_client,
+                progress_context.reshards.clone(),
+                progress_context.config.clone(),
+            )
+        })
+        .await;
     });
 
+    let startup_context = context.clone();
+    let startup_reconnects = nats_events.reconnected();
+    let startup_task = tokio::spawn(async move {
+        resubscribing(startup_reconnects, "Startup completion tracking", || {
+            crust_nats::track_startup_complete(
+                &startup_context.nats_client,
+                startup_context.startups.clone(),
+                startup_context.config.clone(),
+            )
+        })
+        .await;
+    });
+
+    let broker_context = context.clone();
+    let broker_reconnects = nats_events.reconnected();
+    let broker_task = tokio::spawn(async move {
+        resubscribing(broker_reconnects, "Identify broker", || {
+            crust_nats::serve_identify_broker(
+                &broker_context.nats_client,
+                broker_context.identify.clone(),
+                broker_context.signing_keys.clone(),
+                broker_context.config.clone(),
+            )
+        })
+        .await;
+    });
+
+    let config_context = context.clone();
+    let config_task = tokio::spawn(async move {
+        config::watch(config_context, config_namespace, config_map).await;
+    });
+
+    let monitor_context = context.clone();
+    let monitor_task = tokio::spawn(async move {
+        crust_scheduler::worker_monitor(monitor_context).await;
+    });
+
+    let leadership = async {
+        match &leader_elector {
+            Some(elector) => elector.hold(LEASE_RENEW_PERIOD).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let webhook = async {
+        match webhook_task {
+            Some(task) => match task.await {
+                Ok(Err(e)) => warn!(error = %e, "Admission webhook failed"),
+                _ => warn!("Admission webhook ended"),
+            },
+            None => std::future::pending().await,
+        }
+    };
+
+    let mut lost_leadership = false;
+
     tokio::select! {
+        _ = leadership => lost_leadership = true,
         _ = controller => warn!("Controller stream ended"),
-        _ = reshard_task => warn!("Reshard scheduler ended"),
+        _ = webhook => {}
+        _ = heartbeat_task => warn!("Worker heartbeat tracking ended"),
+        _ = shard_status_task => warn!("Shard status tracking ended"),
+        _ = processor_status_task => warn!("Processor status tracking ended"),
+        _ = progress_task => warn!("Reshard progress tracking ended"),
+        _ = startup_task => warn!("Startup completion tracking ended"),
+        _ = broker_task => warn!("Identify broker ended"),
+        _ = config_task => warn!("Operator config watch ended"),
+        _ = monitor_task => warn!("Worker monitor ended"),
         _ = tokio::signal::ctrl_c() => info!("Received shutdown signal"),
     }
 
+    if lost_leadership {
+        // Another replica may already be reconciling, so stop right away and
+        // let the pod restart and queue up for the lease again.
+        anyhow::bail!("Lost leadership");
+    }
+
     info!("Shutting down operator");
+    if let Some(elector) = &leader_elector {
+        elector
+            .release()
+            .await
+            .unwrap_or_else(|e| warn!(error = %e, "Failed to release leadership"));
+    }
     Ok(())
 }
+
+/// Runs the NATS `listener` until it ends, starting it again whenever the
+/// connection reconnects so its core subscription is made again in case the
+/// server lost it.
+async fn resubscribing<F, Fut>(mut reconnects: tokio::sync::watch::Receiver<u64>, name: &str, listener: F)
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = crust_types::Result<()>>,
+{
+    loop {
+        tokio::select! {
+            result = listener() => {
+                if let Err(e) = result {
+                    warn!("{} failed: {}", name, e);
+                }
+                return;
+            }
+            Ok(()) = reconnects.changed() => info!(listener = name, "Resubscribing after NATS reconnect"),
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use anyhow::{Context as _, Result};
+use crust_types::{v2, MirrorMode, ShardCluster, WorkloadKind};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kube::{
+    api::Api,
+    core::admission::{AdmissionRequest, AdmissionResponse, AdmissionReview, Operation},
+    core::conversion::{ConversionRequest, ConversionResponse, ConversionReview},
+    core::Status,
+    Client,
+};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{self, pki_types::PrivateKeyDer};
+use tokio_rustls::TlsAcceptor;
+use tracing::{info, warn};
+
+/// Serves the ShardCluster admission and conversion webhooks over TLS until
+/// the listener fails. Stream names are checked under `subject_root`, the one
+/// the operator started with.
+pub async fn serve(addr: SocketAddr, cert_path: &str, key_path: &str, client: Client, subject_root: String) -> Result<()> {
+    let subject_root: Arc<str> = subject_root.into();
+    let acceptor = tls_acceptor(cert_path, key_path)?;
+    let listener = TcpListener::bind(addr).await?;
+
+    info!(addr = %addr, "Admission webhook listening");
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let client = client.clone();
+        let subject_root = subject_root.clone();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!(peer = %peer, error = %e, "Webhook TLS handshake failed");
+                    return;
+                }
+            };
+
+            let service = service_fn(move |request| handle(client.clone(), subject_root.clone(), request));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                warn!(peer = %peer, error = %e, "Webhook connection failed");
+            }
+        });
+    }
+}
+
+fn tls_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor> {
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(
+        std::fs::File::open(cert_path).with_context(|| format!("Failed to open {}", cert_path))?,
+    ))
+    .collect::<std::result::Result<Vec<_>, _>>()
+    .context("Invalid webhook certificate")?;
+
+    let key: PrivateKeyDer = rustls_pemfile::private_key(&mut std::io::BufReader::new(
+        std::fs::File::open(key_path).with_context(|| format!("Failed to open {}", key_path))?,
+    ))
+    .context("Invalid webhook private key")?
+    .context("No private key found in webhook key file")?;
+
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+async fn handle(client: Client, subject_root: Arc<str>, request: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    let path = request.uri().path().to_string();
+    if request.method() != Method::POST || !["/validate", "/convert"].contains(&path.as_str()) {
+        return Ok(respond(StatusCode::NOT_FOUND, Bytes::from_static(b"not found")));
+    }
+
+    let body = match request.into_body().collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(e) => return Ok(respond(StatusCode::BAD_REQUEST, Bytes::from(e.to_string()))),
+    };
+    if path == "/convert" {
+        return Ok(convert(&body));
+    }
+
+    let review: AdmissionReview<ShardCluster> = match serde_json::from_slice(&body) {
+        Ok(review) => review,
+        Err(e) => return Ok(respond(StatusCode::BAD_REQUEST, Bytes::from(e.to_string()))),
+    };
+
+    let request: AdmissionRequest<ShardCluster> = match review.try_into() {
+        Ok(request) => request,
+        Err(e) => return Ok(respond(StatusCode::BAD_REQUEST, Bytes::from(e.to_string()))),
+    };
+
+    let response = validate(&client, &subject_root, &request).await.into_review();
+    let body = serde_json::to_vec(&response).expect("admission review serializes");
+    Ok(respond(StatusCode::OK, Bytes::from(body)))
+}
+
+/// Answers a ConversionReview, converting ShardClusters between v1 and v2.
+fn convert(body: &[u8]) -> Response<Full<Bytes>> {
+    let review: ConversionReview = match serde_json::from_slice(body) {
+        Ok(review) => review,
+        Err(e) => return respond(StatusCode::BAD_REQUEST, Bytes::from(e.to_string())),
+    };
+    let request = match ConversionRequest::from_review(review) {
+        Ok(request) => request,
+        Err(e) => return respond(StatusCode::BAD_REQUEST, Bytes::from(e.to_string())),
+    };
+
+    let desired_api_version = request.desired_api_version.clone();
+    let converted: std::result::Result<Vec<_>, String> = request
+        .objects
+        .iter()
+        .map(|object| v2::convert(object.clone(), &desired_api_version))
+        .collect();
+    let response = match converted {
+        Ok(objects) => ConversionResponse::for_request(request).success(objects),
+        Err(e) => {
+            warn!(desired_api_version = %desired_api_version, error = %e, "Failed to convert ShardClusters");
+            ConversionResponse::for_request(request).failure(Status::failure(&e, "ConversionFailed"))
+        }
+    };
+
+    let body = serde_json::to_vec(&response.into_review()).expect("conversion review serializes");
+    respond(StatusCode::OK, Bytes::from(body))
+}
+
+async fn validate(client: &Client, subject_root: &str, request: &AdmissionRequest<ShardCluster>) -> AdmissionResponse {
+    let response = AdmissionResponse::from(request);
+
+    let Some(cluster) = &request.object else {
+        return response;
+    };
+    if !matches!(request.operation, Operation::Create | Operation::Update) {
+        return response;
+    }
+
+    let namespace = request.namespace.clone().unwrap_or_else(|| "default".to_string());
+    let spec = &cluster.spec;
+    let mut problems = Vec::new();
+
+    if spec.shards_per_replica == 0 {
+        problems.push("shards_per_replica must be at least 1".to_string());
+    }
+    if spec.replicas_per_shard_group < 1 {
+        problems.push("replicas_per_shard_group must be at least 1".to_string());
+    }
+    if spec.workload_kind == Some(WorkloadKind::StatefulSet) && spec.replicas_per_shard_group != 1 {
+        problems.push("replicas_per_shard_group must be 1 with workload_kind StatefulSet".to_string());
+    }
+    if spec.shard_count_override == Some(0) {
+        problems.push("shard_count_override must be at least 1".to_string());
+    }
+    if spec.reshard_interval_hours == 0 {
+        problems.push("reshard_interval_hours must be at least 1".to_string());
+    }
+    if spec.event_stream.as_ref().and_then(|stream| stream.subjects.as_ref()).is_some_and(|subjects| subjects.is_empty()) {
+        problems.push("event_stream subjects must name at least one subject when set".to_string());
+    }
+    if let Some(stream) = &spec.event_stream {
+        let max_age = stream.max_age_secs.filter(|age| *age > 0);
+        if stream.duplicate_window_secs.zip(max_age).is_some_and(|(window, max_age)| window > max_age) {
+            problems.push("event_stream duplicate_window_secs must not exceed max_age_secs".to_string());
+        }
+        if stream.duplicate_window_secs == Some(0) {
+            problems.push("event_stream duplicate_window_secs must be at least 1".to_string());
+        }
+        if stream.partitions == Some(0) {
+            problems.push("event_stream partitions must be at least 1".to_string());
+        }
+        if stream.partitions.is_some_and(|partitions| partitions > 1) && stream.subjects.is_some() {
+            problems.push("event_stream subjects cannot be set on a partitioned stream".to_string());
+        }
+        let stream_names = cluster.event_stream_names(subject_root);
+        let mut mirror_names = std::collections::HashSet::new();
+        for mirror in stream.mirrors.iter().flatten() {
+            if !mirror_names.insert((&mirror.domain, &mirror.name)) || (mirror.domain.is_none() && stream_names.contains(&mirror.name)) {
+                problems.push(format!("event_stream mirror name '{}' is already used", mirror.name));
+            }
+            if mirror.mode == Some(MirrorMode::Mirror) && stream_names.len() > 1 {
+                problems.push(format!("event_stream mirror '{}' must use mode Source for a partitioned stream", mirror.name));
+            }
+            if let Some(domain) = mirror.domain.as_ref().filter(|domain| !valid_domain(domain)) {
+                problems.push(format!("event_stream mirror '{}' domain must be a single NATS subject token, got '{}'", mirror.name, domain));
+            }
+            if mirror.domain.is_some() && spec.nats_jetstream_domain.is_none() {
+                problems.push(format!("event_stream mirror '{}' in another domain needs nats_jetstream_domain", mirror.name));
+            }
+        }
+    }
+    if let Err(e) = bedrock_nats::check_url(&spec.nats_url, spec.nats_tls.is_some()) {
+        problems.push(format!("nats_url {}", e));
+    }
+    if let Some(url) = spec.gateway_proxy_url.as_ref().filter(|url| !["ws://", "wss://"].iter().any(|scheme| url.starts_with(scheme))) {
+        problems.push(format!("gateway_proxy_url must be a ws:// or wss:// URL, got '{}'", url));
+    }
+    if let Some(url) = spec.http_proxy_url.as_ref().filter(|url| !["http://", "https://"].iter().any(|scheme| url.starts_with(scheme))) {
+        problems.push(format!("http_proxy_url must be an http:// or https:// URL, got '{}'", url));
+    }
+    if spec.nats_credentials_secret.is_some() && spec.nats_account_signing_secret.is_some() {
+        problems.push("nats_credentials_secret and nats_account_signing_secret are mutually exclusive".to_string());
+    }
+    if spec.nats_auth_secret.is_some() && (spec.nats_credentials_secret.is_some() || spec.nats_account_signing_secret.is_some()) {
+        problems.push("nats_auth_secret cannot be combined with nats_credentials_secret or nats_account_signing_secret".to_string());
+    }
+    if let Some(domain) = spec.nats_jetstream_domain.as_ref().filter(|domain| !valid_domain(domain)) {
+        problems.push(format!("nats_jetstream_domain must be a single NATS subject token, got '{}'", domain));
+    }
+    if let Some(rolling_update) = &spec.rolling_update {
+        let zero = |value: &Option<IntOrString>| match value {
+            Some(IntOrString::Int(value)) => *value == 0,
+            Some(IntOrString::String(value)) => value.trim_end_matches('%') == "0",
+            None => false,
+        };
+        if zero(&rolling_update.max_unavailable) && zero(&rolling_update.max_surge) {
+            problems.push("rolling_update max_unavailable and max_surge must not both be 0".to_string());
+        }
+    }
+    if spec.restart_silent_groups_after_secs.is_some_and(|secs| secs < 30) {
+        problems.push("restart_silent_groups_after_secs must be at least 30".to_string());
+    }
+    if spec.intents.as_ref().is_some_and(|intents| intents.iter().all(|intent| intent.trim().is_empty())) {
+        problems.push("intents must name at least one intent when set".to_string());
+    }
+    if let Some(scaling) = &spec.event_processor_scaling {
+        if scaling.min_replicas.unwrap_or(1) > scaling.max_replicas.unwrap_or(10) {
+            problems.push("event_processor_scaling min_replicas must not exceed max_replicas".to_string());
+        }
+        if scaling.lag_threshold == Some(0) {
+            problems.push("event_processor_scaling lag_threshold must be at least 1".to_string());
+        }
+    }
+    if let Some(Err(e)) = spec.reshard_window.as_ref().map(|window| window.contains(chrono::Utc::now())) {
+        problems.push(format!("reshard_window is invalid: {}", e));
+    }
+
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), &namespace);
+    let referenced_secrets = std::iter::once(&spec.discord_token_secret)
+        .chain(spec.coordination_signing_secret.as_ref())
+        .chain(spec.nats_credentials_secret.as_ref())
+        .chain(spec.nats_account_signing_secret.as_ref())
+        .chain(spec.nats_auth_secret.as_ref())
+        .chain(spec.nats_tls.iter().flat_map(|tls| tls.secrets()))
+        .chain(spec.remote.as_ref().map(|remote| &remote.kubeconfig_secret));
+    for secret in referenced_secrets {
+        match secrets.get_opt(secret).await {
+            Ok(Some(_)) => {}
+            Ok(None) => problems.push(format!("secret '{}' does not exist in namespace '{}'", secret, namespace)),
+            Err(e) => warn!(secret = %secret, error = %e, "Could not look up secret during admission"),
+        }
+    }
+
+    if problems.is_empty() {
+        return response;
+    }
+
+    let message = problems.join("; ");
+    info!(cluster = %request.name, namespace = %namespace, reason = %message, "Rejected ShardCluster");
+    response.deny(message)
+}
+
+/// Whether `domain` is usable as a JetStream domain, a single subject token.
+fn valid_domain(domain: &str) -> bool {
+    !domain.is_empty() && !domain.contains(['.', '*', '>']) && !domain.contains(char::is_whitespace)
+}
+
+fn respond(status: StatusCode, body: Bytes) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Full::new(body))
+        .expect("static response parts are valid")
+}
@@ -0,0 +1,102 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use bedrock_nats::{coordination, kv};
+use chrono::Utc;
+use crust_types::{CrustError, Result, ShardCluster};
+use kube::ResourceExt;
+use nkeys::KeyPair;
+use sha2::{Digest, Sha256};
+
+/// Subjects a worker of `cluster` may publish to and subscribe on: everything
+/// under its subject prefix, the JetStream API of its own streams and KV
+/// buckets, and inboxes for replies.
+pub fn worker_permissions(cluster: &ShardCluster, subject_root: &str) -> (Vec<String>, Vec<String>) {
+    let subject_prefix = cluster.subject_prefix(subject_root);
+    let buckets: Vec<String> =
+        kv::BUCKETS.iter().map(|bucket| bedrock_nats::scoped_name(bucket, &subject_prefix)).collect();
+    let streams = cluster
+        .event_stream_names(subject_root)
+        .into_iter()
+        .chain([
+            coordination::signal_stream_name(&subject_prefix),
+            bedrock_nats::scoped_name(coordination::COORDINATION_STREAM, &subject_prefix),
+        ])
+        .chain(buckets.iter().map(|bucket| format!("KV_{}", bucket)));
+
+    let api = cluster.jetstream_api_prefix();
+
+    let mut publish = vec![format!("{}.>", subject_prefix), format!("{}.INFO", api)];
+    for stream in streams {
+        publish.extend([
+            format!("{}.STREAM.*.{}", api, stream),
+            format!("{}.STREAM.MSG.GET.{}", api, stream),
+            format!("{}.DIRECT.GET.{}", api, stream),
+            format!("{}.DIRECT.GET.{}.>", api, stream),
+            format!("{}.CONSUMER.*.{}", api, stream),
+            format!("{}.CONSUMER.*.{}.>", api, stream),
+            format!("{}.CONSUMER.DURABLE.CREATE.{}.>", api, stream),
+            format!("{}.CONSUMER.MSG.NEXT.{}.>", api, stream),
+            format!("$JS.ACK.{}.>", stream),
+            format!("$JS.FC.{}.>", stream),
+        ]);
+    }
+    publish.extend(buckets.iter().map(|bucket| format!("$KV.{}.>", bucket)));
+
+    let subscribe = vec![format!("{}.>", subject_prefix), "_INBOX.>".to_string()];
+    (publish, subscribe)
+}
+
+/// Issues a `.creds` file for a new NATS user limited to the subjects of
+/// `cluster`, signed with the account signing key `account_seed`. `account`
+/// is the account's public key when the signing key is not the account's own.
+pub fn issue_user_credentials(
+    account_seed: &str,
+    account: Option<&str>,
+    cluster: &ShardCluster,
+    subject_root: &str,
+) -> Result<String> {
+    let invalid = |e: nkeys::error::Error| CrustError::Validation(format!("Invalid NATS account signing key: {}", e));
+    let signer = KeyPair::from_seed(account_seed.trim()).map_err(invalid)?;
+    let user = KeyPair::new_user();
+    let (publish, subscribe) = worker_permissions(cluster, subject_root);
+
+    let mut nats = serde_json::json!({
+        "pub": { "allow": publish },
+        "sub": { "allow": subscribe },
+        "subs": -1,
+        "data": -1,
+        "payload": -1,
+        "type": "user",
+        "version": 2,
+    });
+    if let Some(account) = account {
+        nats["issuer_account"] = serde_json::json!(account.trim());
+    }
+    let mut claims = serde_json::json!({
+        "jti": "",
+        "iat": Utc::now().timestamp(),
+        "iss": signer.public_key(),
+        "name": format!("{}/{}", cluster.namespace().unwrap_or_else(|| "default".to_string()), cluster.name_any()),
+        "sub": user.public_key(),
+        "nats": nats,
+    });
+    // NATS identifies a JWT by the hash of its claims.
+    let digest = Sha256::digest(serde_json::to_vec(&claims)?);
+    claims["jti"] = serde_json::json!(data_encoding::BASE32_NOPAD.encode(&digest));
+
+    let header = URL_SAFE_NO_PAD.encode(br#"{"typ":"JWT","alg":"ed25519-nkey"}"#);
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?);
+    let signing_input = format!("{}.{}", header, payload);
+    let signature = signer.sign(signing_input.as_bytes()).map_err(invalid)?;
+    let jwt = format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature));
+    let seed = user.seed().map_err(invalid)?;
+
+    Ok(format!(
+        "-----BEGIN NATS USER JWT-----\n{}\n------END NATS USER JWT------\n\n\
+         ************************* IMPORTANT *************************\n\
+         NKEY Seed printed below can be used to sign and prove identity.\n\
+         NKEYs are sensitive and should be treated as secrets.\n\n\
+         -----BEGIN USER NKEY SEED-----\n{}\n------END USER NKEY SEED------\n\n\
+         *************************************************************\n",
+        jwt, seed
+    ))
+}
@@ -1,53 +1,297 @@
-use crust_types::{CrustError, Result, ShardGroup};
+pub mod accounts;
+
+use crust_types::{
+    CrustError, IdentifyRegistry, MirrorMode, OperatorConfigHandle, ProcessorStatusRegistry, ProcessorStatusReport, ReshardProgress, ReshardRegistry, Result, SessionStartLimit, ShardCluster, ShardGroup,
+    ShardStatusRegistry, ShardStatusReport, SigningKeyRegistry, StartupComplete, StreamDiscard, StreamMirror, StreamRetention, StreamStorage, StartupRegistry, StartupRequest, WorkerHeartbeat, WorkerRegistry,
+};
 use async_nats;
 use backon::{ExponentialBuilder, Retryable};
+use bedrock_nats::coordination::{self, CoordinationStreamSettings};
+use bedrock_nats::{dedup, partitions, rpc, signing, streams, JetStreamApi, RetryPolicy};
 use chrono::Utc;
-use tracing::{error, info};
+use std::collections::BTreeMap;
+use futures::StreamExt;
+use kube::ResourceExt;
+use tracing::{debug, error, info, warn};
 
-pub async fn connect(url: &str) -> Result<async_nats::Client> {
-    let operation = || async {
-        info!(url = %url, "Connecting to NATS");
-        async_nats::connect(url).await.map_err(|e| {
-            error!(error = %e, "Failed to connect to NATS, retrying...");
-            e
-        })
+// Coordination subjects, relative to the cluster's subject prefix.
+const RESHARD_SUBJECT: &str = "operator.reshard";
+const STARTUP_SUBJECT: &str = "operator.startup";
+const ASSIGNMENT_SUBJECT: &str = "operator.assignment";
+
+// Worker subjects are subscribed to for every cluster at once, which relies
+// on the `<root>.<namespace>.<name>` shape of ShardCluster::subject_prefix.
+// The root is matched by a wildcard so a reloaded SUBJECT_ROOT needs no new
+// subscriptions, and messages under other roots are dropped.
+const HEARTBEAT_SUBJECTS: &str = "*.*.*.workers.heartbeat";
+const STARTUP_COMPLETE_SUBJECTS: &str = "*.*.*.startup.complete";
+const RESHARD_STATUS_SUBJECTS: &str = "*.*.*.operator.reshard.status";
+const STARTUP_REQUEST_SUBJECTS: &str = "*.*.*.startup.request";
+const SHARD_STATUS_SUBJECTS: &str = "*.*.*.shards.*.status";
+/// Event processors report under `bedrock.` followed by the subject prefix
+/// of their cluster, which crust hands them as BEDROCK_CLUSTER.
+const PROCESSOR_STATUS_ROOT: &str = "bedrock.";
+const PROCESSOR_STATUS_SUBJECTS: &str = "bedrock.*.*.*.processors.*.status";
+
+/// Time on top of a worker's drain timeout for it to stop its shards and
+/// report back.
+const DRAIN_REPORT_MARGIN: std::time::Duration = std::time::Duration::from_secs(10);
+/// How long a worker has to acknowledge a drain request.
+const DRAIN_ACK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+fn under_subject_root(config: &OperatorConfigHandle, subject: &str) -> bool {
+    let config = config.read().expect("operator config poisoned");
+    subject.split('.').next() == Some(config.subject_root.as_str())
+}
+
+/// Creates the JetStream stream holding the latest coordination message of
+/// each subject under `subject_prefix`, named like the one stratum creates.
+pub async fn ensure_coordination_stream(
+    jetstream: &async_nats::jetstream::Context,
+    subject_prefix: &str,
+) -> Result<()> {
+    let stream = coordination::signal_stream_name(subject_prefix);
+
+    // The reconcile is requeued on failure, so it is not retried here.
+    let config = async_nats::jetstream::stream::Config {
+        name: stream.clone(),
+        subjects: [RESHARD_SUBJECT, STARTUP_SUBJECT, ASSIGNMENT_SUBJECT]
+            .iter()
+            .map(|subject| format!("{}.{}", subject_prefix, subject))
+            .collect(),
+        max_messages_per_subject: 1,
+        ..Default::default()
     };
+    streams::ensure_stream(jetstream, &config, RetryPolicy::retries(0))
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to create coordination stream: {}", e)))?;
 
-    match operation.retry(&ExponentialBuilder::default()).await {
-        Ok(client) => {
-            info!("Connected to NATS successfully");
-            Ok(client)
+    debug!(stream = %stream, "Ensured coordination stream exists");
+    Ok(())
+}
+
+/// Creates the cluster's coordination stream, or updates it to `settings`.
+/// Like the events stream, it is left in place when the cluster is deleted.
+pub async fn reconcile_coordination_stream(
+    nats_client: &async_nats::Client,
+    cluster: &ShardCluster,
+    subject_root: &str,
+    settings: CoordinationStreamSettings,
+) -> Result<()> {
+    let jetstream = cluster.jetstream(nats_client);
+    let subject_prefix = cluster.subject_prefix(subject_root);
+    let partitioned = match &cluster.spec.event_stream {
+        Some(_) => cluster.event_stream_partitions() > 1,
+        // Pods creating their own events stream may partition it, in which
+        // case they gave the coordination stream the shard subjects to keep.
+        None => match jetstream.get_stream(bedrock_nats::scoped_name(coordination::COORDINATION_STREAM, &subject_prefix)).await {
+            Ok(stream) => {
+                let subjects = &stream.cached_info().config.subjects;
+                coordination::SHARD_SUBJECTS
+                    .iter()
+                    .all(|subject| subjects.contains(&format!("{}.{}", subject_prefix, subject)))
+            }
+            Err(_) => false,
+        },
+    };
+    let config = coordination::stream_config(&subject_prefix, partitioned, &settings);
+
+    let stream = streams::ensure_stream(&jetstream, &config, RetryPolicy::retries(0))
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to create coordination stream {}: {}", config.name, e)))?;
+    streams::update_stream(&jetstream, &stream.cached_info().config, &config)
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to update coordination stream {}: {}", config.name, e)))?;
+
+    debug!(stream = %config.name, "Reconciled coordination stream");
+    Ok(())
+}
+
+/// Creates or updates the events stream `spec.event_stream` declares, one
+/// stream per partition when it is partitioned. Clusters
+/// without one keep the stream their stratum pods create. The stream is left
+/// in place when the cluster is deleted, since it may hold unprocessed events.
+pub async fn reconcile_event_stream(nats_client: &async_nats::Client, cluster: &ShardCluster, subject_root: &str) -> Result<()> {
+    use async_nats::jetstream::stream::{Config, DiscardPolicy, RetentionPolicy, StorageType};
+
+    let Some(event_stream) = &cluster.spec.event_stream else {
+        return Ok(());
+    };
+
+    let jetstream = cluster.jetstream(nats_client);
+    let subject_prefix = cluster.subject_prefix(subject_root);
+    let base = Config {
+        retention: match event_stream.retention.unwrap_or_default() {
+            StreamRetention::Limits => RetentionPolicy::Limits,
+            StreamRetention::Interest => RetentionPolicy::Interest,
+            StreamRetention::WorkQueue => RetentionPolicy::WorkQueue,
+        },
+        storage: match event_stream.storage.unwrap_or_default() {
+            StreamStorage::File => StorageType::File,
+            StreamStorage::Memory => StorageType::Memory,
+        },
+        max_age: event_stream.max_age_secs.map(std::time::Duration::from_secs).unwrap_or_default(),
+        max_bytes: event_stream.max_bytes.unwrap_or(-1),
+        max_messages: event_stream.max_messages.unwrap_or(10000),
+        discard: match event_stream.discard.unwrap_or_default() {
+            StreamDiscard::Old => DiscardPolicy::Old,
+            StreamDiscard::New => DiscardPolicy::New,
+        },
+        num_replicas: event_stream.replicas.unwrap_or(1),
+        // Like stratum, the default window is cut to the max age, which NATS
+        // requires it not to exceed.
+        duplicate_window: std::time::Duration::from_secs(
+            match (event_stream.duplicate_window_secs, event_stream.max_age_secs.unwrap_or(0)) {
+                (Some(window), _) => window,
+                (None, 0) => 120,
+                (None, max_age_secs) => max_age_secs.min(120),
+            },
+        ),
+        ..Default::default()
+    };
+
+    let partitioned = cluster.event_stream_partitions() > 1;
+    for (partition, name) in (0..).zip(cluster.event_stream_names(subject_root)) {
+        let subjects = match &event_stream.subjects {
+            _ if partitioned => vec![partitions::stream_subject(&subject_prefix, partition)],
+            Some(subjects) => subjects.clone(),
+            None => vec![format!("{}.shards.>", subject_prefix)],
+        };
+        let config = Config { name: name.clone(), subjects, ..base.clone() };
+
+        let stream = streams::ensure_stream(&jetstream, &config, RetryPolicy::retries(0))
+            .await
+            .map_err(|e| CrustError::Other(format!("Failed to create events stream {}: {}", name, e)))?;
+        streams::update_stream(&jetstream, &stream.cached_info().config, &config)
+            .await
+            .map_err(|e| CrustError::Other(format!("Failed to update events stream {}: {}", name, e)))?;
+
+        debug!(stream = %name, cluster = %cluster.name_any(), "Reconciled events stream");
+    }
+
+    for mirror in event_stream.mirrors.iter().flatten() {
+        reconcile_stream_mirror(nats_client, cluster, subject_root, mirror).await?;
+    }
+    Ok(())
+}
+
+/// Creates or updates a copy of the cluster's events stream in the mirror's
+/// domain, reading the events stream through the cluster's JetStream API when
+/// the copy lives in another domain.
+async fn reconcile_stream_mirror(
+    nats_client: &async_nats::Client,
+    cluster: &ShardCluster,
+    subject_root: &str,
+    mirror: &StreamMirror,
+) -> Result<()> {
+    use async_nats::jetstream::stream::{Config, StorageType};
+
+    let (jetstream, origin_api) = match &mirror.domain {
+        Some(domain) if Some(domain) != cluster.spec.nats_jetstream_domain.as_ref() => {
+            (JetStreamApi::Domain(domain.clone()).context(nats_client), cluster.jetstream_api())
         }
-        Err(e) => {
-            error!(error = %e, "Failed to connect to NATS after multiple retries");
-            Err(CrustError::Other(format!("Failed to connect to NATS: {}", e)))
+        _ => (cluster.jetstream(nats_client), JetStreamApi::Default),
+    };
+    let origins = cluster.event_stream_names(subject_root);
+    let mode = mirror
+        .mode
+        .unwrap_or(if origins.len() > 1 { MirrorMode::Source } else { MirrorMode::Mirror });
+    // A mirror follows a single stream, so it would copy one partition only.
+    if mode == MirrorMode::Mirror && origins.len() > 1 {
+        return Err(CrustError::Validation(format!(
+            "event_stream mirror '{}' must use mode Source for a partitioned stream",
+            mirror.name
+        )));
+    }
+
+    let mut config = Config {
+        name: mirror.name.clone(),
+        max_age: mirror.max_age_secs.map(std::time::Duration::from_secs).unwrap_or_default(),
+        max_bytes: mirror.max_bytes.unwrap_or(-1),
+        num_replicas: mirror.replicas.unwrap_or(1),
+        storage: match mirror.storage.unwrap_or_default() {
+            StreamStorage::File => StorageType::File,
+            StreamStorage::Memory => StorageType::Memory,
+        },
+        ..Default::default()
+    };
+    match mode {
+        MirrorMode::Mirror => config.mirror = Some(streams::source(&origins[0], &origin_api)),
+        MirrorMode::Source => {
+            config.sources = Some(origins.iter().map(|origin| streams::source(origin, &origin_api)).collect())
         }
     }
+
+    let stream = streams::ensure_stream(&jetstream, &config, RetryPolicy::retries(0))
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to create stream mirror {}: {}", mirror.name, e)))?;
+    streams::update_stream(&jetstream, &stream.cached_info().config, &config)
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to update stream mirror {}: {}", mirror.name, e)))?;
+
+    debug!(stream = %mirror.name, domain = ?mirror.domain, mode = ?mode, cluster = %cluster.name_any(), "Reconciled stream mirror");
+    Ok(())
+}
+
+/// Publishes under `message_id`, which retries of the message share so the
+/// stream keeps one copy of it.
+async fn publish_coordination(
+    jetstream: &async_nats::jetstream::Context,
+    subject: String,
+    payload: String,
+    message_id: &str,
+    signing_key: Option<&[u8]>,
+) -> std::result::Result<(), async_nats::jetstream::context::PublishError> {
+    let headers = signing_key.map(|key| signing::sign(key, &subject, payload.as_bytes()));
+    let ack = jetstream
+        .publish_with_headers(subject, dedup::with_message_id(headers, message_id), payload.into())
+        .await?;
+
+    ack.await?;
+    Ok(())
 }
 
 pub async fn send_reshard_signal(
     nats_client: &async_nats::Client,
+    cluster: &ShardCluster,
+    subject_root: &str,
     new_shard_count: u32,
+    signing_key: Option<&[u8]>,
 ) -> Result<()> {
+    let cluster_name = cluster.name_any();
+    let subject_prefix = cluster.subject_prefix(subject_root);
+    let jetstream = cluster.jetstream(nats_client);
+    ensure_coordination_stream(&jetstream, &subject_prefix).await?;
+
+    // Workers drop signals whose prefix is not their own, in case subjects
+    // are ever shared between clusters.
     let message = serde_json::json!({
         "event": "reshard",
+        "cluster": cluster_name,
+        "subject_prefix": subject_prefix,
         "new_shard_count": new_shard_count,
         "timestamp": Utc::now().to_rfc3339()
     });
 
+    let message_id = dedup::unique_id();
     let operation = || async {
-        nats_client
-            .publish("discord.operator.reshard", message.to_string().into())
-            .await
-            .map_err(|e| {
-                error!(error = %e, "Failed to send reshard signal, retrying...");
-                e
-            })
+        publish_coordination(
+            &jetstream,
+            format!("{}.{}", subject_prefix, RESHARD_SUBJECT),
+            message.to_string(),
+            &message_id,
+            signing_key,
+        )
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to send reshard signal, retrying...");
+            e
+        })
     };
 
     match operation.retry(&ExponentialBuilder::default()).await {
         Ok(_) => {
-            info!(new_shard_count, "Sent reshard signal via NATS");
+            info!(cluster = %cluster_name, new_shard_count, "Sent reshard signal via NATS");
             Ok(())
         }
         Err(e) => {
@@ -57,30 +301,47 @@ pub async fn send_reshard_signal(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn publish_startup_coordination(
     nats_client: &async_nats::Client, 
-    cluster_name: &str,
+    cluster: &ShardCluster,
+    subject_root: &str,
     max_concurrency: u32,
     total_shards: u32,
-    shard_groups: &[ShardGroup]
+    shard_groups: &[ShardGroup],
+    session_start_limit: &SessionStartLimit,
+    signing_key: Option<&[u8]>,
 ) -> Result<()> {
+    let cluster_name = cluster.name_any();
+    let subject_prefix = cluster.subject_prefix(subject_root);
+    let jetstream = cluster.jetstream(nats_client);
+    ensure_coordination_stream(&jetstream, &subject_prefix).await?;
+
     let message = serde_json::json!({
         "event": "startup_coordination",
         "cluster": cluster_name,
+        "subject_prefix": subject_prefix,
         "max_concurrency": max_concurrency,
         "total_shards": total_shards,
         "shard_groups": shard_groups,
+        "session_start_limit": session_start_limit,
         "timestamp": Utc::now().to_rfc3339()
     });
 
+    let message_id = dedup::unique_id();
     let operation = || async {
-        nats_client
-            .publish("discord.operator.startup", message.to_string().into())
-            .await
-            .map_err(|e| {
-                error!(error = %e, "Failed to send startup coordination, retrying...");
-                e
-            })
+        publish_coordination(
+            &jetstream,
+            format!("{}.{}", subject_prefix, STARTUP_SUBJECT),
+            message.to_string(),
+            &message_id,
+            signing_key,
+        )
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to send startup coordination, retrying...");
+            e
+        })
     };
 
     match operation.retry(&ExponentialBuilder::default()).await {
@@ -99,3 +360,386 @@ pub async fn publish_startup_coordination(
         }
     }
 }
+
+pub async fn track_worker_heartbeats(
+    nats_client: &async_nats::Client,
+    workers: WorkerRegistry,
+    config: OperatorConfigHandle,
+) -> Result<()> {
+    let mut subscriber = nats_client
+        .subscribe(HEARTBEAT_SUBJECTS)
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to subscribe to worker heartbeats: {}", e)))?;
+
+    info!("Tracking worker heartbeats");
+
+    while let Some(message) = subscriber.next().await {
+        if !under_subject_root(&config, &message.subject) {
+            continue;
+        }
+        match serde_json::from_slice::<WorkerHeartbeat>(&message.payload) {
+            Ok(heartbeat) => {
+                debug!(worker_id = %heartbeat.worker_id, shards = ?heartbeat.shards, "Received worker heartbeat");
+                workers
+                    .write()
+                    .expect("worker registry poisoned")
+                    .insert(heartbeat.worker_id.clone(), heartbeat);
+            }
+            Err(e) => warn!(error = %e, "Ignoring malformed worker heartbeat"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the status every shard publishes with its worker's heartbeat and
+/// when it stops or fails, keyed by the subject prefix of its cluster.
+pub async fn track_shard_statuses(
+    nats_client: &async_nats::Client,
+    shard_statuses: ShardStatusRegistry,
+    config: OperatorConfigHandle,
+) -> Result<()> {
+    let mut subscriber = nats_client
+        .subscribe(SHARD_STATUS_SUBJECTS)
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to subscribe to shard statuses: {}", e)))?;
+
+    info!("Tracking shard statuses");
+
+    while let Some(message) = subscriber.next().await {
+        if !under_subject_root(&config, &message.subject) {
+            continue;
+        }
+        let Some((subject_prefix, _)) = message.subject.split_once(".shards.") else {
+            continue;
+        };
+        match serde_json::from_slice::<ShardStatusReport>(&message.payload) {
+            Ok(report) => {
+                debug!(subject_prefix = %subject_prefix, shard_id = report.shard_id, status = %report.status, "Received shard status");
+                shard_statuses
+                    .write()
+                    .expect("shard status registry poisoned")
+                    .entry(subject_prefix.to_string())
+                    .or_default()
+                    .insert(report.shard_id, report);
+            }
+            Err(e) => warn!(error = %e, "Ignoring malformed shard status"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the health reports of every cluster's event processors, keyed by
+/// the subject prefix of the cluster.
+pub async fn track_processor_statuses(
+    nats_client: &async_nats::Client,
+    processor_statuses: ProcessorStatusRegistry,
+    config: OperatorConfigHandle,
+) -> Result<()> {
+    let mut subscriber = nats_client
+        .subscribe(PROCESSOR_STATUS_SUBJECTS)
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to subscribe to processor statuses: {}", e)))?;
+
+    info!("Tracking event processor statuses");
+
+    while let Some(message) = subscriber.next().await {
+        let Some(subject) = message.subject.strip_prefix(PROCESSOR_STATUS_ROOT) else {
+            continue;
+        };
+        if !under_subject_root(&config, subject) {
+            continue;
+        }
+        let Some((subject_prefix, _)) = subject.split_once(".processors.") else {
+            continue;
+        };
+        match serde_json::from_slice::<ProcessorStatusReport>(&message.payload) {
+            Ok(report) => {
+                debug!(subject_prefix = %subject_prefix, processor_id = %report.processor_id, status = %report.status, "Received processor status");
+                processor_statuses
+                    .write()
+                    .expect("processor status registry poisoned")
+                    .entry(subject_prefix.to_string())
+                    .or_default()
+                    .insert(report.processor_id.clone(), report);
+            }
+            Err(e) => warn!(error = %e, "Ignoring malformed processor status"),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn track_startup_complete(
+    nats_client: &async_nats::Client,
+    startups: StartupRegistry,
+    config: OperatorConfigHandle,
+) -> Result<()> {
+    let mut subscriber = nats_client
+        .subscribe(STARTUP_COMPLETE_SUBJECTS)
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to subscribe to startup completions: {}", e)))?;
+
+    info!("Tracking shard startup completions");
+
+    while let Some(message) = subscriber.next().await {
+        if !under_subject_root(&config, &message.subject) {
+            continue;
+        }
+        match serde_json::from_slice::<StartupComplete>(&message.payload) {
+            Ok(startup) => {
+                debug!(worker_id = %startup.worker_id, shard_id = startup.shard_id, "Received startup completion");
+                startups
+                    .write()
+                    .expect("startup registry poisoned")
+                    .entry(startup.worker_id)
+                    .or_default()
+                    .insert(startup.shard_id);
+            }
+            Err(e) => warn!(error = %e, "Ignoring malformed startup completion"),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn track_reshard_progress(
+    nats_client: &async_nats::Client,
+    reshards: ReshardRegistry,
+    config: OperatorConfigHandle,
+) -> Result<()> {
+    let mut subscriber = nats_client
+        .subscribe(RESHARD_STATUS_SUBJECTS)
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to subscribe to reshard progress: {}", e)))?;
+
+    info!("Tracking reshard progress");
+
+    while let Some(message) = subscriber.next().await {
+        if !under_subject_root(&config, &message.subject) {
+            continue;
+        }
+        match serde_json::from_slice::<ReshardProgress>(&message.payload) {
+            Ok(progress) => {
+                info!(
+                    worker_id = %progress.worker_id,
+                    stage = %progress.stage,
+                    total_shards = progress.total_shards,
+                    "Received reshard progress"
+                );
+                reshards
+                    .write()
+                    .expect("reshard registry poisoned")
+                    .insert(progress.worker_id.clone(), progress);
+            }
+            Err(e) => warn!(error = %e, "Ignoring malformed reshard progress"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Answers workers' identify requests from the budget of their bot token, so
+/// max_concurrency and the session start limit hold across all workers of
+/// every cluster sharing the token.
+/// Every decision is also published on `<prefix>.startup.grant`. Both are
+/// signed for their subject when the cluster has a coordination signing key.
+pub async fn serve_identify_broker(
+    nats_client: &async_nats::Client,
+    identify: IdentifyRegistry,
+    signing_keys: SigningKeyRegistry,
+    config: OperatorConfigHandle,
+) -> Result<()> {
+    let mut subscriber = nats_client
+        .subscribe(STARTUP_REQUEST_SUBJECTS)
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to subscribe to startup requests: {}", e)))?;
+
+    info!("Brokering identify requests");
+
+    while let Some(message) = subscriber.next().await {
+        if !under_subject_root(&config, &message.subject) {
+            continue;
+        }
+        let Some(reply) = message.reply else {
+            continue;
+        };
+        let Some(subject_prefix) = message.subject.strip_suffix(".startup.request") else {
+            continue;
+        };
+        let request = match serde_json::from_slice::<StartupRequest>(&message.payload) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!(error = %e, "Ignoring malformed startup request");
+                continue;
+            }
+        };
+
+        let decision = identify
+            .write()
+            .expect("identify registry poisoned")
+            .grant(subject_prefix, &request.worker_id, request.shard_id, Utc::now());
+
+        info!(
+            subject_prefix = %subject_prefix,
+            worker_id = %decision.worker_id,
+            shard_id = decision.shard_id,
+            granted = decision.granted,
+            delay_ms = decision.delay_ms,
+            reason = ?decision.reason,
+            "Answered startup request"
+        );
+
+        let payload = match serde_json::to_vec(&decision) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!(error = %e, "Failed to serialize identify grant");
+                continue;
+            }
+        };
+        let signing_key = signing_keys
+            .read()
+            .expect("signing key registry poisoned")
+            .get(subject_prefix)
+            .cloned();
+        let grant_subject = format!("{}.startup.grant", subject_prefix);
+        let (reply_headers, grant_headers) = match &signing_key {
+            Some(key) => (signing::sign(key, &reply, &payload), signing::sign(key, &grant_subject, &payload)),
+            None => Default::default(),
+        };
+        if let Err(e) = nats_client.publish_with_headers(reply, reply_headers, payload.clone().into()).await {
+            warn!(worker_id = %decision.worker_id, error = %e, "Failed to reply to startup request");
+        }
+        if let Err(e) = nats_client.publish_with_headers(grant_subject, grant_headers, payload.into()).await {
+            debug!(error = %e, "Failed to publish identify grant");
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn publish_shard_assignment(
+    nats_client: &async_nats::Client,
+    cluster: &ShardCluster,
+    subject_root: &str,
+    total_shards: u32,
+    assignments: &BTreeMap<String, Vec<u32>>,
+    signing_key: Option<&[u8]>,
+) -> Result<()> {
+    let cluster_name = cluster.name_any();
+    let subject_prefix = cluster.subject_prefix(subject_root);
+    let jetstream = cluster.jetstream(nats_client);
+    ensure_coordination_stream(&jetstream, &subject_prefix).await?;
+
+    let message = serde_json::json!({
+        "event": "shard_assignment",
+        "cluster": cluster_name,
+        "subject_prefix": subject_prefix,
+        "total_shards": total_shards,
+        "assignments": assignments,
+        "timestamp": Utc::now().to_rfc3339()
+    });
+
+    let message_id = dedup::unique_id();
+    let operation = || async {
+        publish_coordination(
+            &jetstream,
+            format!("{}.{}", subject_prefix, ASSIGNMENT_SUBJECT),
+            message.to_string(),
+            &message_id,
+            signing_key,
+        )
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to send shard assignment, retrying...");
+            e
+        })
+    };
+
+    match operation.retry(&ExponentialBuilder::default()).await {
+        Ok(_) => {
+            info!(cluster = %cluster_name, total_shards, workers = assignments.len(), "Sent shard assignment via NATS");
+            Ok(())
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to send shard assignment after retries");
+            Err(CrustError::Other(format!("Failed to send shard assignment: {}", e)))
+        }
+    }
+}
+
+/// Asks each worker in `worker_ids` to drain its shards and waits for them to
+/// report they stopped, for up to the cluster's drain timeout plus
+/// DRAIN_REPORT_MARGIN. Returns the workers that did not report in time.
+pub async fn drain_workers(
+    nats_client: &async_nats::Client,
+    cluster: &ShardCluster,
+    subject_root: &str,
+    worker_ids: &[String],
+    signing_key: Option<&[u8]>,
+) -> Result<Vec<String>> {
+    let timeout = std::time::Duration::from_secs(cluster.spec.drain_timeout_seconds.unwrap_or(25) as u64)
+        + DRAIN_REPORT_MARGIN;
+    let subject_prefix = cluster.subject_prefix(subject_root);
+    let mut pending: std::collections::HashSet<String> = worker_ids.iter().cloned().collect();
+    if pending.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Subscribed before the requests go out so no report is missed.
+    let mut subscriber = nats_client
+        .subscribe(format!("{}.workers.*.drained", subject_prefix))
+        .await
+        .map_err(|e| CrustError::Other(format!("Failed to subscribe to drain reports: {}", e)))?;
+
+    // Each worker acknowledges the request right away, before it drains.
+    let acks = worker_ids.iter().map(|worker_id| {
+        let subject = format!("{}.workers.{}.drain", subject_prefix, worker_id);
+        let request = rpc::Request::new(DRAIN_ACK_TIMEOUT);
+        let request = match signing_key {
+            Some(key) => {
+                let (key, subject) = (key.to_vec(), subject.clone());
+                request.headers(move |payload| signing::sign(&key, &subject, payload))
+            }
+            None => request,
+        };
+        async move {
+            let message = serde_json::json!({ "worker_id": worker_id });
+            (worker_id, request.send::<_, serde_json::Value>(nats_client, subject, &message).await)
+        }
+    });
+    for (worker_id, ack) in futures::future::join_all(acks).await {
+        match ack {
+            Ok(_) => debug!(worker_id = %worker_id, "Worker acknowledged drain request"),
+            Err(e) if e.is_unanswered() => warn!(worker_id = %worker_id, error = %e, "Worker did not acknowledge drain request"),
+            Err(e) => return Err(CrustError::Other(format!("Failed to send drain request to {}: {}", worker_id, e))),
+        }
+    }
+    info!(cluster = %cluster.name_any(), workers = worker_ids.len(), timeout = ?timeout, "Sent drain requests");
+
+    let _ = tokio::time::timeout(timeout, async {
+        while let Some(message) = subscriber.next().await {
+            let Ok(report) = serde_json::from_slice::<serde_json::Value>(&message.payload) else {
+                continue;
+            };
+            let Some(worker_id) = report["worker_id"].as_str() else {
+                continue;
+            };
+            if pending.remove(worker_id) {
+                debug!(worker_id = %worker_id, drained = report["drained"].as_bool().unwrap_or(false), "Worker finished draining");
+            }
+            if pending.is_empty() {
+                break;
+            }
+        }
+    })
+    .await;
+
+    let mut timed_out: Vec<String> = pending.into_iter().collect();
+    timed_out.sort();
+    if !timed_out.is_empty() {
+        warn!(cluster = %cluster.name_any(), workers = ?timed_out, "Workers did not report draining in time");
+    }
+    Ok(timed_out)
+}
@@ -1,66 +1,740 @@
-use crust_types::{Context, ShardCluster};
-use chrono::Utc;
+use crust_types::{
+    finish_reshard_record, set_condition, Condition, Context, ProcessorHealth, ReshardStatus, RolloutStatus, ShardCluster,
+    ShardClusterStatus, ShardGroup, ShardHealth,
+    CONDITION_DEGRADED, CONDITION_PROGRESSING, CONDITION_READY, CONDITION_RESHARDING,
+    ROLLOUT_CANARY, ROLLOUT_COMPLETED, ROLLOUT_ROLLED_BACK,
+};
+use chrono::{DateTime, Utc};
 use kube::{
-    api::{Api, ListParams, Patch, PatchParams},
+    api::{Api, ListParams},
+    runtime::events::EventType,
     ResourceExt,
 };
+use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 use tokio::time::interval;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+mod sizing;
+
+const WORKER_MONITOR_INTERVAL: Duration = Duration::from_secs(30);
+const WORKER_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(90);
+/// How long a shard's last status counts before the shard is counted as stale.
+const SHARD_STATUS_TIMEOUT: Duration = Duration::from_secs(90);
+/// How long an event processor's last report counts before the processor is
+/// left out of the cluster's processor health.
+const PROCESSOR_STATUS_TIMEOUT: Duration = Duration::from_secs(90);
+/// How long a reshard may stay in progress before it is failed, so a stuck
+/// one stops holding a slot of MAX_CONCURRENT_RESHARDS.
+const RESHARD_TIMEOUT: Duration = Duration::from_secs(60 * 60);
 
-pub async fn reshard_scheduler(ctx: Context) {
-    let mut interval = interval(Duration::from_secs(3600));
+pub async fn worker_monitor(ctx: Context) {
+    let mut interval = interval(WORKER_MONITOR_INTERVAL);
+    let mut last_restarts: HashMap<String, DateTime<Utc>> = HashMap::new();
+    let mut last_assignments: HashMap<String, (u32, Vec<String>)> = HashMap::new();
 
     loop {
         interval.tick().await;
-        
-        info!("Checking for clusters that need resharding");
-        
+        // Everything below restarts workloads or writes status.
+        if ctx.config().dry_run {
+            continue;
+        }
+
         let shard_clusters: Api<ShardCluster> = Api::all(ctx.client.clone());
-        
-        match shard_clusters.list(&ListParams::default()).await {
-            Ok(clusters) => {
-                for cluster in clusters.items {
-                    if should_reshard(&cluster) {
-                        info!(cluster = %cluster.name_any(), "Triggering reshard");
-                        
-                        let patch = serde_json::json!({
-                            "metadata": {
-                                "annotations": {
-                                    "crust.bedrock.dev/reshard-trigger": Utc::now().to_rfc3339()
-                                }
-                            }
-                        });
-                        
-                        if let Err(e) = shard_clusters
-                            .patch(
-                                &cluster.name_any(),
-                                &PatchParams::default(),
-                                &Patch::Merge(&patch),
-                            )
-                            .await
-                        {
-                            error!(cluster = %cluster.name_any(), error = %e, "Failed to trigger reshard");
+
+        let clusters = match shard_clusters.list(&ListParams::default()).await {
+            Ok(clusters) => clusters,
+            Err(e) => {
+                error!(error = %e, "Failed to list ShardClusters");
+                continue;
+            }
+        };
+
+        let config = ctx.config();
+        for cluster in clusters.items {
+            let cluster = config.resolve(&cluster);
+            let Some(status) = &cluster.status else {
+                continue;
+            };
+            if cluster.spec.suspend.unwrap_or(false) {
+                continue;
+            }
+
+            let stale_workers = find_stale_workers(&ctx, &cluster);
+
+            if !stale_workers.is_empty() {
+                warn!(cluster = %cluster.name_any(), workers = ?stale_workers, "Workers stopped sending heartbeats");
+            }
+
+            let namespace = cluster.namespace().unwrap_or_else(|| "default".to_string());
+
+            let reshard = match &status.pending_shard_groups {
+                Some(pending) => blue_green_progress(&ctx, &cluster, pending),
+                None => reshard_progress(&ctx, &cluster),
+            };
+            let cutover = match (&status.pending_shard_groups, reshard.as_ref().or(status.reshard.as_ref())) {
+                (Some(pending), Some(latest)) if latest.phase == "Completed" => {
+                    match finish_blue_green(&ctx, &cluster, pending).await {
+                        Ok(()) => Some((pending.clone(), latest.target_shards)),
+                        Err(e) => {
+                            error!(cluster = %cluster.name_any(), error = %e, "Failed to delete the old shard groups");
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+
+            let unready_groups: Vec<String> = status
+                .shard_groups
+                .iter()
+                .filter(|group| !group.is_ready())
+                .map(|group| group.deployment_name.clone())
+                .collect();
+
+            let rollout = advance_rollout(&ctx, &cluster, &stale_workers).await;
+            let recommended_sizing = sizing::recommend(&ctx, &cluster).await;
+
+            let (health_groups, shard_health) = observe_shard_health(&ctx, &cluster);
+            let health_changed = status.shard_health != Some(shard_health)
+                || health_groups.iter().zip(&status.shard_groups).any(|(observed, group)| observed.health != group.health);
+            let processor_health = observe_processor_health(&ctx, &cluster);
+            let processor_health_changed = status.processor_health != processor_health;
+            // Shards are expected to be down while groups start or reshard.
+            let settled = unready_groups.is_empty()
+                && status.pending_shard_groups.is_none()
+                && reshard.as_ref().or(status.reshard.as_ref()).is_none_or(|reshard| reshard.phase != "InProgress");
+
+            let mut reshard_history = status.reshard_history.clone();
+            let history_changed = match reshard.as_ref().map(|reshard| reshard.phase.as_str()) {
+                Some(phase @ ("Completed" | "Failed")) => finish_reshard_record(&mut reshard_history, phase),
+                _ => false,
+            };
+
+            let mut conditions = status.conditions.clone();
+            let conditions_changed = update_conditions(
+                &mut conditions,
+                reshard.as_ref().or(status.reshard.as_ref()),
+                &stale_workers,
+                &unready_groups,
+                settled.then_some(&shard_health),
+                status.current_shards.is_some(),
+            );
+
+            if reshard.is_some()
+                || cutover.is_some()
+                || rollout.is_some()
+                || recommended_sizing.is_some()
+                || health_changed
+                || processor_health_changed
+                || history_changed
+                || conditions_changed
+            {
+                let cluster_api: Api<ShardCluster> = Api::namespaced(ctx.client.clone(), &namespace);
+                let mut patch = serde_json::json!({ "status": { "conditions": conditions } });
+                if let Some(reshard) = &reshard {
+                    patch["status"]["reshard"] = serde_json::json!(reshard);
+                }
+                if let Some((shard_groups, total_shards)) = &cutover {
+                    patch["status"]["shard_groups"] = serde_json::json!(shard_groups);
+                    patch["status"]["current_shards"] = serde_json::json!(total_shards);
+                    patch["status"]["pending_shard_groups"] = serde_json::Value::Null;
+                } else if health_changed {
+                    patch["status"]["shard_groups"] = serde_json::json!(health_groups);
+                    patch["status"]["shard_health"] = serde_json::json!(shard_health);
+                }
+                if processor_health_changed {
+                    patch["status"]["processor_health"] = serde_json::json!(processor_health);
+                }
+                if let Some(rollout) = &rollout {
+                    patch["status"]["rollout"] = serde_json::json!(rollout);
+                    if rollout.phase == ROLLOUT_COMPLETED {
+                        patch["status"]["image"] = serde_json::json!(rollout.image);
+                    }
+                }
+                if let Some(sizing) = &recommended_sizing {
+                    patch["status"]["sizing"] = serde_json::json!(sizing);
+                }
+                if history_changed {
+                    patch["status"]["reshard_history"] = serde_json::json!(reshard_history);
+                }
+                if let Err(e) = crust_kubernetes::apply_status(&cluster_api, &cluster.name_any(), &patch).await {
+                    error!(cluster = %cluster.name_any(), error = %e, "Failed to update cluster status");
+                }
+            }
+
+            if cluster.spec.dynamic_rebalancing.unwrap_or(false) && !reshard_in_progress(status) {
+                let key = format!("{}/{}", namespace, cluster.name_any());
+                let total_shards = status.current_shards.unwrap_or(0);
+                let worker_set = (total_shards, stale_workers.clone());
+
+                if total_shards > 0 && last_assignments.get(&key) != Some(&worker_set) {
+                    let assignments = rebalance(&status.shard_groups, &stale_workers);
+                    info!(cluster = %cluster.name_any(), stale = ?stale_workers, "Worker set changed, rebalancing shards");
+
+                    let signing_key = match &cluster.spec.coordination_signing_secret {
+                        Some(secret) => crust_kubernetes::get_signing_key(&ctx.client, &namespace, secret).await.ok(),
+                        None => None,
+                    };
+
+                    match crust_nats::publish_shard_assignment(
+                        &ctx.nats_client,
+                        &cluster,
+                        &config.subject_root,
+                        total_shards,
+                        &assignments,
+                        signing_key.as_deref(),
+                    ).await {
+                        Ok(()) => {
+                            last_assignments.insert(key, worker_set);
                         }
+                        Err(e) => error!(cluster = %cluster.name_any(), error = %e, "Failed to publish shard assignment"),
                     }
                 }
             }
-            Err(e) => {
-                error!(error = %e, "Failed to list ShardClusters");
+
+            if let Some(period) = cluster.spec.restart_silent_groups_after_secs.map(Duration::from_secs).filter(|_| settled) {
+                for group in find_silent_groups(&ctx, &cluster, period) {
+                    let key = format!("{}/{}", namespace, group);
+                    let recently_restarted = last_restarts
+                        .get(&key)
+                        .is_some_and(|at| (Utc::now() - *at).to_std().unwrap_or(Duration::ZERO) < period);
+                    if recently_restarted {
+                        continue;
+                    }
+
+                    warn!(cluster = %cluster.name_any(), group = %group, "Shard group is ready but its shards went silent");
+                    let restarted = match crust_kubernetes::remote::workload_target(&ctx.client, &ctx.remote_clients, &cluster).await {
+                        Ok((workloads, workload_namespace)) => {
+                            crust_kubernetes::restart_worker(&workloads, &workload_namespace, &cluster, &group).await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    match restarted {
+                        Ok(()) => {
+                            last_restarts.insert(key, Utc::now());
+                            crust_kubernetes::record_event(
+                                &ctx.recorder,
+                                &cluster,
+                                EventType::Warning,
+                                "RestartedSilentGroup",
+                                "RestartWorker",
+                                format!(
+                                    "Restarted {} after its shards sent no status for {}s while its pods were ready",
+                                    group,
+                                    period.as_secs()
+                                ),
+                            ).await;
+                        }
+                        Err(e) => error!(group = %group, error = %e, "Failed to restart silent shard group"),
+                    }
+                }
+            }
+
+            if !cluster.spec.restart_stale_workers.unwrap_or(false) || !settled {
+                continue;
+            }
+
+            for worker in stale_workers {
+                let key = format!("{}/{}", namespace, worker);
+                let recently_restarted = last_restarts
+                    .get(&key)
+                    .map(|at| (Utc::now() - *at).to_std().unwrap_or(Duration::ZERO) < WORKER_HEARTBEAT_TIMEOUT)
+                    .unwrap_or(false);
+
+                if recently_restarted {
+                    continue;
+                }
+
+                let restarted = match crust_kubernetes::remote::workload_target(&ctx.client, &ctx.remote_clients, &cluster).await {
+                    Ok((workloads, workload_namespace)) => {
+                        crust_kubernetes::restart_worker(&workloads, &workload_namespace, &cluster, &worker).await
+                    }
+                    Err(e) => Err(e),
+                };
+                match restarted {
+                    Ok(()) => {
+                        last_restarts.insert(key, Utc::now());
+                        crust_kubernetes::record_event(
+                            &ctx.recorder,
+                            &cluster,
+                            EventType::Warning,
+                            "RestartedStaleWorker",
+                            "RestartWorker",
+                            format!("Restarted worker {} after its heartbeats stopped", worker),
+                        ).await;
+                    }
+                    Err(e) => error!(worker = %worker, error = %e, "Failed to restart stale worker"),
+                }
+            }
+        }
+    }
+}
+
+/// Derives the cluster conditions from worker heartbeats and the latest
+/// reshard. Returns whether any condition changed.
+fn update_conditions(
+    conditions: &mut Vec<Condition>,
+    reshard: Option<&ReshardStatus>,
+    stale_workers: &[String],
+    unready_groups: &[String],
+    shard_health: Option<&ShardHealth>,
+    provisioned: bool,
+) -> bool {
+    let mut changed = false;
+    let reshard_phase = reshard.map(|reshard| reshard.phase.as_str());
+
+    changed |= match reshard {
+        Some(reshard) if reshard.phase == "InProgress" => {
+            let message = format!(
+                "{} of {} workers re-identified for {} shards",
+                reshard.workers_completed, reshard.workers_total, reshard.target_shards
+            );
+            set_condition(conditions, CONDITION_RESHARDING, true, "ReshardInProgress", message.clone())
+                | set_condition(conditions, CONDITION_PROGRESSING, true, "ReshardInProgress", message)
+        }
+        Some(reshard) if reshard.phase == "Deferred" => {
+            let message = reshard.message.clone().unwrap_or_default();
+            set_condition(conditions, CONDITION_RESHARDING, false, "ReshardDeferred", message.clone())
+                | set_condition(conditions, CONDITION_PROGRESSING, false, "ReshardDeferred", message)
+        }
+        Some(reshard) if reshard.phase == "Failed" => {
+            let message = reshard.message.clone().unwrap_or_default();
+            set_condition(conditions, CONDITION_RESHARDING, false, "ReshardFailed", message.clone())
+                | set_condition(conditions, CONDITION_PROGRESSING, false, "ReshardFailed", message)
+        }
+        _ if !unready_groups.is_empty() => {
+            let message = format!("Waiting for {} to become ready", unready_groups.join(", "));
+            set_condition(conditions, CONDITION_RESHARDING, false, "ReshardCompleted", "")
+                | set_condition(conditions, CONDITION_PROGRESSING, true, "DeploymentsRollingOut", message)
+        }
+        _ => {
+            set_condition(conditions, CONDITION_RESHARDING, false, "ReshardCompleted", "")
+                | set_condition(conditions, CONDITION_PROGRESSING, false, "ReshardCompleted", "")
+        }
+    };
+
+    let (degraded, reason, message) = if !stale_workers.is_empty() {
+        (true, "WorkersStale", format!("No heartbeat from {}", stale_workers.join(", ")))
+    } else if let Some(health) = shard_health.filter(|health| !health.healthy()) {
+        (
+            true,
+            "ShardsDisconnected",
+            format!(
+                "{} of {} shards connected, {} disconnected, {} without a recent status",
+                health.connected,
+                health.connected + health.disconnected + health.stale,
+                health.disconnected,
+                health.stale
+            ),
+        )
+    } else if reshard_phase == Some("Failed") {
+        (
+            true,
+            "ReshardFailed",
+            reshard.and_then(|reshard| reshard.message.clone()).unwrap_or_default(),
+        )
+    } else {
+        (false, "WorkersHealthy", String::new())
+    };
+
+    changed |= set_condition(conditions, CONDITION_DEGRADED, degraded, reason, message.clone());
+    changed |= if !provisioned {
+        set_condition(conditions, CONDITION_READY, false, "Provisioning", "")
+    } else if !degraded && !unready_groups.is_empty() {
+        let message = format!("Waiting for {} to become ready", unready_groups.join(", "));
+        set_condition(conditions, CONDITION_READY, false, "DeploymentsNotReady", message)
+    } else {
+        set_condition(conditions, CONDITION_READY, !degraded, reason, message)
+    };
+    changed
+}
+
+/// Counts the shards of every group by the status they last reported.
+/// Returns the groups with their counts and the counts over all groups.
+fn observe_shard_health(ctx: &Context, cluster: &ShardCluster) -> (Vec<ShardGroup>, ShardHealth) {
+    let Some(status) = &cluster.status else {
+        return (Vec::new(), ShardHealth::default());
+    };
+
+    let now = Utc::now();
+    let shard_statuses = ctx.shard_statuses.read().expect("shard status registry poisoned");
+    let reports = shard_statuses.get(&cluster.subject_prefix(&ctx.config().subject_root));
+
+    let mut total = ShardHealth::default();
+    let groups = status
+        .shard_groups
+        .iter()
+        .map(|group| {
+            let mut health = ShardHealth::default();
+            for shard_id in group.shard_start..=group.shard_end {
+                let report = reports.and_then(|reports| reports.get(&shard_id)).filter(|report| {
+                    (now - report.received_at).to_std().unwrap_or(Duration::ZERO) <= SHARD_STATUS_TIMEOUT
+                });
+                match report {
+                    Some(report) if report.status == "connected" => health.connected += 1,
+                    Some(_) => health.disconnected += 1,
+                    None => health.stale += 1,
+                }
             }
+            total.connected += health.connected;
+            total.disconnected += health.disconnected;
+            total.stale += health.stale;
+            ShardGroup { health: Some(health), ..group.clone() }
+        })
+        .collect();
+
+    (groups, total)
+}
+
+/// Counts the cluster's event processors by the status they last reported,
+/// forgetting processors that stopped reporting. `None` when no processor
+/// reports for the cluster.
+fn observe_processor_health(ctx: &Context, cluster: &ShardCluster) -> Option<ProcessorHealth> {
+    let now = Utc::now();
+    let mut processor_statuses = ctx.processor_statuses.write().expect("processor status registry poisoned");
+    let reports = processor_statuses.get_mut(&cluster.subject_prefix(&ctx.config().subject_root))?;
+    reports.retain(|_, report| (now - report.received_at).to_std().unwrap_or(Duration::ZERO) <= PROCESSOR_STATUS_TIMEOUT);
+
+    let latest = reports.values().max_by_key(|report| report.received_at)?;
+    let mut health = ProcessorHealth { lag: latest.lag, ..ProcessorHealth::default() };
+    for report in reports.values() {
+        match report.status.as_str() {
+            "healthy" => health.healthy += 1,
+            _ => health.degraded += 1,
         }
     }
+    Some(health)
 }
 
-fn should_reshard(cluster: &ShardCluster) -> bool {
+/// Ready shard groups none of whose shards reported a status for `period`.
+/// Clusters that never reported a shard status are left alone, since their
+/// stratum image may predate shard status heartbeats.
+fn find_silent_groups(ctx: &Context, cluster: &ShardCluster, period: Duration) -> Vec<String> {
+    let Some(status) = &cluster.status else {
+        return Vec::new();
+    };
+
+    let now = Utc::now();
+    let shard_statuses = ctx.shard_statuses.read().expect("shard status registry poisoned");
+    let Some(reports) = shard_statuses.get(&cluster.subject_prefix(&ctx.config().subject_root)) else {
+        return Vec::new();
+    };
+
+    status
+        .shard_groups
+        .iter()
+        .filter(|group| group.is_ready())
+        .filter(|group| {
+            (group.shard_start..=group.shard_end).all(|shard_id| {
+                let last_seen = reports.get(&shard_id).map(|report| report.received_at).or(status.last_reshard);
+                last_seen.is_some_and(|at| (now - at).to_std().unwrap_or(Duration::ZERO) > period)
+            })
+        })
+        .map(|group| group.deployment_name.clone())
+        .collect()
+}
+
+fn find_stale_workers(ctx: &Context, cluster: &ShardCluster) -> Vec<String> {
+    let Some(status) = &cluster.status else {
+        return Vec::new();
+    };
+    // Workers stop and start again while they reshard, so none counts as
+    // stale until the reshard is over.
+    if reshard_in_progress(status) {
+        return Vec::new();
+    }
+
+    let now = Utc::now();
+    let workers = ctx.workers.read().expect("worker registry poisoned");
+
+    status
+        .shard_groups
+        .iter()
+        .filter(|group| {
+            let last_seen = workers
+                .get(&group.deployment_name)
+                .map(|heartbeat| heartbeat.received_at)
+                .or(status.last_reshard);
+
+            match last_seen {
+                Some(at) => (now - at).to_std().unwrap_or(Duration::ZERO) > WORKER_HEARTBEAT_TIMEOUT,
+                None => false,
+            }
+        })
+        .map(|group| group.deployment_name.clone())
+        .collect()
+}
+
+fn reshard_in_progress(status: &ShardClusterStatus) -> bool {
+    status.pending_shard_groups.is_some()
+        || status.reshard.as_ref().is_some_and(|reshard| reshard.phase == "InProgress")
+}
+
+/// Moves a canary rollout on: back to the previous image when a canary group
+/// stopped sending heartbeats, on to every group once the soak period passed.
+/// Returns the rollout when its phase changed.
+async fn advance_rollout(ctx: &Context, cluster: &ShardCluster, stale_workers: &[String]) -> Option<RolloutStatus> {
+    let rollout = cluster.status.as_ref()?.rollout.as_ref()?;
+
+    if rollout.phase != ROLLOUT_CANARY || rollout.image != cluster.spec.image {
+        return None;
+    }
+
+    let soak = Duration::from_secs(
+        cluster
+            .spec
+            .update_strategy
+            .as_ref()
+            .map(|strategy| strategy.soak_seconds)
+            .unwrap_or(600),
+    );
+    let soaked = rollout
+        .started_at
+        .is_some_and(|started_at| (Utc::now() - started_at).to_std().unwrap_or(Duration::ZERO) >= soak);
+    let failed: Vec<&String> = rollout
+        .canary_groups
+        .iter()
+        .filter(|group| stale_workers.contains(group))
+        .collect();
+
+    let mut next = rollout.clone();
+    let (reason, note) = if !failed.is_empty() {
+        next.phase = ROLLOUT_ROLLED_BACK.to_string();
+        next.message = Some(format!("Canary groups stopped sending heartbeats: {:?}", failed));
+        ("CanaryRolledBack", format!("Rolled back to {}, canaries {:?} stopped sending heartbeats", rollout.previous_image, failed))
+    } else if soaked {
+        next.phase = ROLLOUT_COMPLETED.to_string();
+        ("CanaryPromoted", format!("Canaries stayed healthy, rolling {} out to every shard group", rollout.image))
+    } else {
+        return None;
+    };
+
+    info!(cluster = %cluster.name_any(), image = %rollout.image, phase = %next.phase, "Canary rollout finished");
+    crust_kubernetes::record_event(
+        &ctx.recorder,
+        cluster,
+        if next.phase == ROLLOUT_ROLLED_BACK { EventType::Warning } else { EventType::Normal },
+        reason,
+        "Rollout",
+        note,
+    ).await;
+
+    Some(next)
+}
+
+/// Tracks a blue/green reshard by the startup completions of the pending
+/// shard groups. A group is done once every one of its shards started.
+fn blue_green_progress(ctx: &Context, cluster: &ShardCluster, pending: &[ShardGroup]) -> Option<ReshardStatus> {
+    let current = cluster.status.as_ref()?.reshard.as_ref()?;
+
+    if current.phase != "InProgress" {
+        return None;
+    }
+
+    let startups = ctx.startups.read().expect("startup registry poisoned");
+
+    let mut next = current.clone();
+    next.workers_completed = 0;
+    next.shards_reidentified = 0;
+
+    for group in pending {
+        let started = startups
+            .get(&group.deployment_name)
+            .map(|shards| (group.shard_start..=group.shard_end).filter(|shard| shards.contains(shard)).count() as u32)
+            .unwrap_or(0);
+
+        next.shards_reidentified += started;
+        if started == group.shard_end - group.shard_start + 1 {
+            next.workers_completed += 1;
+        }
+    }
+
+    if next.workers_completed >= next.workers_total {
+        next.phase = "Completed".to_string();
+        next.completed_at = Some(Utc::now());
+    } else {
+        expire_reshard(&mut next);
+    }
+
+    if next.phase == current.phase
+        && next.workers_completed == current.workers_completed
+        && next.shards_reidentified == current.shards_reidentified
+    {
+        return None;
+    }
+
+    info!(
+        cluster = %cluster.name_any(),
+        phase = %next.phase,
+        started_shards = next.shards_reidentified,
+        completed = next.workers_completed,
+        total = next.workers_total,
+        "Blue/green reshard progress updated"
+    );
+
+    Some(next)
+}
+
+/// Drains and deletes the shard groups a finished blue/green reshard replaced.
+async fn finish_blue_green(ctx: &Context, cluster: &ShardCluster, pending: &[ShardGroup]) -> crust_types::Result<()> {
+    let (workloads, workload_namespace) =
+        crust_kubernetes::remote::workload_target(&ctx.client, &ctx.remote_clients, cluster).await?;
+
+    let removed = crust_kubernetes::removed_deployments(&workloads, &workload_namespace, cluster, pending).await?;
+    if !removed.is_empty() {
+        let namespace = cluster.namespace().unwrap_or_else(|| "default".to_string());
+        let signing_key = match &cluster.spec.coordination_signing_secret {
+            Some(secret) => Some(crust_kubernetes::get_signing_key(&ctx.client, &namespace, secret).await?),
+            None => None,
+        };
+        let timed_out =
+            crust_nats::drain_workers(&ctx.nats_client, cluster, &ctx.config().subject_root, &removed, signing_key.as_deref())
+                .await?;
+        if !timed_out.is_empty() {
+            crust_kubernetes::record_event(
+                &ctx.recorder,
+                cluster,
+                EventType::Warning,
+                "DrainTimedOut",
+                "Reshard",
+                format!("Deleting {} without a drain report", timed_out.join(", ")),
+            ).await;
+        }
+    }
+    crust_kubernetes::prune_deployments(&workloads, &ctx.recorder, &workload_namespace, cluster, pending).await?;
+
     if let Some(status) = &cluster.status {
-        if let Some(last_reshard) = status.last_reshard {
-            let reshard_interval = Duration::from_secs(cluster.spec.reshard_interval_hours * 3600);
-            let time_since_reshard = Utc::now() - last_reshard;
-            
-            return time_since_reshard.to_std().unwrap_or(Duration::ZERO) >= reshard_interval;
+        let mut workers = ctx.workers.write().expect("worker registry poisoned");
+        let mut startups = ctx.startups.write().expect("startup registry poisoned");
+        for group in &status.shard_groups {
+            workers.remove(&group.deployment_name);
+            startups.remove(&group.deployment_name);
         }
     }
-    
-    true
+
+    info!(cluster = %cluster.name_any(), groups = pending.len(), "Blue/green reshard complete, switched to the new shard groups");
+    crust_kubernetes::record_event(
+        &ctx.recorder,
+        cluster,
+        EventType::Normal,
+        "ReshardCompleted",
+        "Reshard",
+        format!("All {} new shard groups started, deleted the old ones", pending.len()),
+    ).await;
+
+    Ok(())
+}
+
+fn reshard_progress(ctx: &Context, cluster: &ShardCluster) -> Option<ReshardStatus> {
+    let status = cluster.status.as_ref()?;
+    let current = status.reshard.as_ref()?;
+
+    if current.phase != "InProgress" {
+        return None;
+    }
+
+    let reshards = ctx.reshards.read().expect("reshard registry poisoned");
+
+    let mut next = current.clone();
+    next.workers_completed = 0;
+    next.workers_failed = 0;
+    next.shards_reidentified = 0;
+    next.message = None;
+
+    for group in &status.shard_groups {
+        let Some(progress) = reshards.get(&group.deployment_name) else {
+            continue;
+        };
+
+        if progress.total_shards != current.target_shards {
+            continue;
+        }
+
+        if current.started_at.is_some_and(|started_at| progress.received_at < started_at) {
+            continue;
+        }
+
+        next.shards_reidentified += progress.shards_reidentified;
+
+        match progress.stage.as_str() {
+            "completed" => next.workers_completed += 1,
+            "failed" => {
+                next.workers_failed += 1;
+                next.message = progress
+                    .message
+                    .clone()
+                    .map(|message| format!("{}: {}", progress.worker_id, message));
+            }
+            _ => {}
+        }
+    }
+
+    if next.workers_failed > 0 {
+        next.phase = "Failed".to_string();
+        next.completed_at = Some(Utc::now());
+    } else if next.workers_completed >= next.workers_total {
+        next.phase = "Completed".to_string();
+        next.completed_at = Some(Utc::now());
+    } else {
+        expire_reshard(&mut next);
+    }
+
+    if next.phase == current.phase
+        && next.workers_completed == current.workers_completed
+        && next.shards_reidentified == current.shards_reidentified
+    {
+        return None;
+    }
+
+    info!(
+        cluster = %cluster.name_any(),
+        phase = %next.phase,
+        completed = next.workers_completed,
+        total = next.workers_total,
+        "Reshard progress updated"
+    );
+
+    Some(next)
+}
+
+/// Fails a reshard that has been in progress for longer than RESHARD_TIMEOUT.
+fn expire_reshard(reshard: &mut ReshardStatus) {
+    let expired = reshard
+        .started_at
+        .is_some_and(|started_at| (Utc::now() - started_at).to_std().unwrap_or(Duration::ZERO) >= RESHARD_TIMEOUT);
+    if !expired {
+        return;
+    }
+
+    reshard.phase = "Failed".to_string();
+    reshard.completed_at = Some(Utc::now());
+    reshard.message = Some(format!("Reshard did not finish within {:?}", RESHARD_TIMEOUT));
+}
+
+fn rebalance(groups: &[ShardGroup], stale_workers: &[String]) -> BTreeMap<String, Vec<u32>> {
+    let mut assignments: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+    let mut orphaned = Vec::new();
+
+    for group in groups {
+        let shards = group.shard_start..=group.shard_end;
+        if stale_workers.contains(&group.deployment_name) {
+            orphaned.extend(shards);
+        } else {
+            assignments.insert(group.deployment_name.clone(), shards.collect());
+        }
+    }
+
+    if assignments.is_empty() {
+        return assignments;
+    }
+
+    let live: Vec<String> = assignments.keys().cloned().collect();
+    for (index, shard_id) in orphaned.into_iter().enumerate() {
+        if let Some(shards) = assignments.get_mut(&live[index % live.len()]) {
+            shards.push(shard_id);
+        }
+    }
+
+    assignments
 }
@@ -0,0 +1,234 @@
+use crust_types::{Context, ShardCluster, SizingRecommendation};
+use chrono::Utc;
+use kube::{
+    api::{Api, ApiResource, DynamicObject, GroupVersionKind, ListParams},
+    ResourceExt,
+};
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Events per second a replica is sized for when the spec sets no target.
+const DEFAULT_REPLICA_EVENTS_PER_SEC: u32 = 500;
+/// How far the ideal shards_per_replica may drift from the current one before
+/// another is recommended, so small swings in load do not relayout the cluster.
+const SHARDS_PER_REPLICA_TOLERANCE: f64 = 0.25;
+/// How long a recommended shards_per_replica stands before it may change again.
+const MIN_CHANGE_INTERVAL: Duration = Duration::from_secs(3600);
+/// How often an unchanged recommendation is written back with fresh numbers.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(600);
+/// Room left on top of the busiest pod's usage in recommended requests.
+const REQUEST_HEADROOM: f64 = 1.3;
+/// How far usage may move from the recommended requests before new ones are
+/// recommended, since auto_tune rolls the pods for every change.
+const REQUEST_TOLERANCE: f64 = 0.25;
+
+/// Recommends shards_per_replica from the shard event rates in the workers'
+/// heartbeats and resource requests from the pod usage the metrics API
+/// reports. Returns the recommendation when the status should be updated.
+pub async fn recommend(ctx: &Context, cluster: &ShardCluster) -> Option<SizingRecommendation> {
+    let status = cluster.status.as_ref()?;
+    let total_shards = status.current_shards?;
+    // Rates are skewed while shards move between workers.
+    if status.pending_shard_groups.is_some() || status.reshard.as_ref().is_some_and(|reshard| reshard.phase != "Completed") {
+        return None;
+    }
+
+    let rates: Vec<f64> = {
+        let workers = ctx.workers.read().expect("worker registry poisoned");
+        status
+            .shard_groups
+            .iter()
+            .filter_map(|group| workers.get(&group.deployment_name))
+            .flat_map(|heartbeat| heartbeat.events_per_sec.values().copied())
+            .collect()
+    };
+    if rates.is_empty() || rates.len() * 2 < total_shards as usize {
+        return None;
+    }
+
+    let shard_events_per_sec = rates.iter().sum::<f64>() / rates.len() as f64;
+    let target = cluster.spec.target_replica_events_per_sec.unwrap_or(DEFAULT_REPLICA_EVENTS_PER_SEC) as f64;
+    let current = cluster.spec.shards_per_replica;
+    let ideal = if shard_events_per_sec > 0.0 {
+        (target / shard_events_per_sec).clamp(1.0, total_shards.max(1) as f64)
+    } else {
+        total_shards.max(1) as f64
+    };
+
+    let now = Utc::now();
+    let previous = status.sizing.as_ref();
+    let settled = previous.is_some_and(|previous| {
+        previous
+            .changed_at
+            .is_some_and(|at| (now - at).to_std().unwrap_or(Duration::ZERO) < MIN_CHANGE_INTERVAL)
+    });
+
+    let (shards_per_replica, reason) = if (ideal - current as f64).abs() <= current as f64 * SHARDS_PER_REPLICA_TOLERANCE {
+        (
+            current,
+            format!(
+                "Shards average {:.1} events/s, {} shards per replica keep replicas near the target of {} events/s",
+                shard_events_per_sec, current, target
+            ),
+        )
+    } else if settled {
+        let previous = previous.expect("settled implies a previous recommendation");
+        (previous.shards_per_replica, previous.reason.clone())
+    } else {
+        let recommended = (ideal.floor() as u32).max(1);
+        (
+            recommended,
+            format!(
+                "Shards average {:.1} events/s, {} shards per replica (now {}) put replicas near the target of {} events/s",
+                shard_events_per_sec, recommended, current, target
+            ),
+        )
+    };
+
+    let (cpu_request, memory_request) = match busiest_pod_usage(ctx, cluster).await {
+        Some((cpu, memory)) => {
+            // Usage grows with the shards a pod runs.
+            let scale = shards_per_replica as f64 / current.max(1) as f64 * REQUEST_HEADROOM;
+            let limits = cluster.spec.resources.as_ref().and_then(|resources| resources.limits.as_ref());
+            let cpu_limit = limits.and_then(|limits| limits.get("cpu")).and_then(|q| parse_cpu(&q.0));
+            let memory_limit = limits.and_then(|limits| limits.get("memory")).and_then(|q| parse_memory(&q.0));
+
+            let cpu = (cpu * scale).min(cpu_limit.unwrap_or(f64::MAX));
+            let memory = (memory * scale).min(memory_limit.unwrap_or(f64::MAX));
+            let previous_cpu = previous.and_then(|previous| previous.cpu_request.as_deref());
+            let previous_memory = previous.and_then(|previous| previous.memory_request.as_deref());
+            (
+                Some(keep_request(previous_cpu, cpu, parse_cpu, settled, |cpu| {
+                    format!("{}m", (cpu * 1000.0).ceil().max(1.0) as u64)
+                })),
+                Some(keep_request(previous_memory, memory, parse_memory, settled, |memory| {
+                    format!("{}Mi", (memory / (1024.0 * 1024.0)).ceil().max(1.0) as u64)
+                })),
+            )
+        }
+        None => (
+            previous.and_then(|previous| previous.cpu_request.clone()),
+            previous.and_then(|previous| previous.memory_request.clone()),
+        ),
+    };
+
+    let changed = previous.is_none_or(|previous| {
+        previous.shards_per_replica != shards_per_replica
+            || previous.cpu_request != cpu_request
+            || previous.memory_request != memory_request
+    });
+    if changed {
+        info!(
+            cluster = %cluster.name_any(),
+            shards_per_replica,
+            shard_events_per_sec,
+            auto_tune = cluster.spec.auto_tune.unwrap_or(false),
+            cpu_request = ?cpu_request,
+            memory_request = ?memory_request,
+            "Recommending a new shard group size"
+        );
+    }
+    let recommendation = SizingRecommendation {
+        shards_per_replica,
+        cpu_request,
+        memory_request,
+        events_per_sec: shard_events_per_sec * total_shards as f64,
+        reason,
+        applied: cluster.spec.auto_tune.unwrap_or(false),
+        changed_at: if changed { Some(now) } else { previous.and_then(|previous| previous.changed_at) },
+        observed_at: Some(now),
+    };
+
+    let stale = changed
+        || previous.is_none_or(|previous| {
+            previous.applied != recommendation.applied
+                || previous
+                    .observed_at
+                    .is_none_or(|at| (now - at).to_std().unwrap_or(Duration::ZERO) >= REFRESH_INTERVAL)
+        });
+
+    stale.then_some(recommendation)
+}
+
+/// The previous request unless `wanted` moved too far from it and the last
+/// change is not too recent.
+fn keep_request(
+    previous: Option<&str>,
+    wanted: f64,
+    parse: fn(&str) -> Option<f64>,
+    settled: bool,
+    format: impl Fn(f64) -> String,
+) -> String {
+    match previous.and_then(|request| parse(request).map(|value| (request, value))) {
+        Some((request, value)) if settled || (wanted - value).abs() <= value * REQUEST_TOLERANCE => request.to_string(),
+        _ => format(wanted),
+    }
+}
+
+/// CPU in cores and memory in bytes of the stratum pod using the most of
+/// each, from the metrics API. None when metrics-server is not installed.
+async fn busiest_pod_usage(ctx: &Context, cluster: &ShardCluster) -> Option<(f64, f64)> {
+    let (workloads, namespace) = crust_kubernetes::remote::workload_target(&ctx.client, &ctx.remote_clients, cluster)
+        .await
+        .ok()?;
+    let gvk = GroupVersionKind::gvk("metrics.k8s.io", "v1beta1", "PodMetrics");
+    let resource = ApiResource::from_gvk_with_plural(&gvk, "pods");
+    let pod_metrics: Api<DynamicObject> = Api::namespaced_with(workloads, &namespace, &resource);
+
+    let list_params = ListParams::default().labels(&format!(
+        "managed-by=crust-operator,app=stratum,cluster={}",
+        cluster.name_any()
+    ));
+    let pods = match pod_metrics.list(&list_params).await {
+        Ok(pods) => pods,
+        Err(e) => {
+            debug!(cluster = %cluster.name_any(), error = %e, "Pod metrics unavailable");
+            return None;
+        }
+    };
+
+    pods.items
+        .iter()
+        .map(|pod| {
+            let containers = pod.data["containers"].as_array().cloned().unwrap_or_default();
+            containers.iter().fold((0.0, 0.0), |(cpu, memory), container| {
+                let usage = &container["usage"];
+                (
+                    cpu + usage["cpu"].as_str().and_then(parse_cpu).unwrap_or(0.0),
+                    memory + usage["memory"].as_str().and_then(parse_memory).unwrap_or(0.0),
+                )
+            })
+        })
+        .reduce(|(cpu, memory), (pod_cpu, pod_memory)| (cpu.max(pod_cpu), memory.max(pod_memory)))
+}
+
+/// Cores in a CPU quantity such as `250m` or `12345n`.
+fn parse_cpu(quantity: &str) -> Option<f64> {
+    let (number, scale) = match quantity.char_indices().last()? {
+        (i, 'n') => (&quantity[..i], 1e-9),
+        (i, 'u') => (&quantity[..i], 1e-6),
+        (i, 'm') => (&quantity[..i], 1e-3),
+        _ => (quantity, 1.0),
+    };
+    number.parse::<f64>().ok().map(|number| number * scale)
+}
+
+/// Bytes in a memory quantity such as `128Mi` or `1G`.
+fn parse_memory(quantity: &str) -> Option<f64> {
+    const SUFFIXES: [(&str, f64); 8] = [
+        ("Ki", 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Gi", 1024.0 * 1024.0 * 1024.0),
+        ("Ti", 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+    ];
+
+    let (number, scale) = SUFFIXES
+        .iter()
+        .find_map(|(suffix, scale)| quantity.strip_suffix(suffix).map(|number| (number, *scale)))
+        .unwrap_or((quantity, 1.0));
+    number.parse::<f64>().ok().map(|number| number * scale)
+}
@@ -1,19 +1,38 @@
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum CrustError {
     #[error("Kube error: {0}")]
-    Kube(#[from] kube::Error),
+    Kube(kube::Error),
     #[error("NATS error: {0}")]
     Nats(#[from] async_nats::Error),
     #[error("Discord error: {0}")]
     Discord(#[from] twilight_http::Error),
     #[error("Serialization error: {0}")]
     Serde(#[from] serde_json::Error),
+    #[error("Rate limited by Discord, retry after {retry_after:?}")]
+    RateLimited { retry_after: Duration },
+    #[error("Discord unavailable: {0}")]
+    DiscordUnavailable(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Invalid ShardCluster: {0}")]
+    Validation(String),
     #[error("General error: {0}")]
     Other(String),
 }
 
+impl From<kube::Error> for CrustError {
+    fn from(err: kube::Error) -> Self {
+        match &err {
+            kube::Error::Api(response) if response.code == 409 => CrustError::Conflict(response.message.clone()),
+            kube::Error::Api(response) if response.code == 422 => CrustError::Validation(response.message.clone()),
+            _ => CrustError::Kube(err),
+        }
+    }
+}
+
 impl From<anyhow::Error> for CrustError {
     fn from(err: anyhow::Error) -> Self {
         CrustError::Other(err.to_string())
@@ -1,5 +1,20 @@
 pub mod error;
 pub mod types;
+pub mod v2;
 
 pub use error::{CrustError, Result};
-pub use types::{Context, ShardCluster, ShardClusterSpec, ShardClusterStatus, ShardGroup};
+pub use types::{
+    finish_reshard_record, push_reshard_record, set_condition, token_key, Condition, Context,
+    DryRunPlan, EventProcessorScaling, EventStream, FailureRegistry, GatewayCache, GatewayInfo,
+    GatewayInfoCache, IdentifyBudget, IdentifyBudgets, IdentifyGrant, IdentifyRegistry, MirrorMode,
+    NatsTlsSecrets, OperatorConfig, OperatorConfigHandle, PodTemplateOverlay, ProcessorHealth,
+    ProcessorStatusRegistry, ProcessorStatusReport, RemoteClientRegistry, RemoteTarget,
+    ReshardProgress, ReshardRecord, ReshardRegistry, ReshardStatus, ReshardStrategy, ReshardWindow,
+    RolloutStatus, SessionStartLimit, ShardCluster, ShardClusterSpec, ShardClusterStatus,
+    ShardGroup, ShardHealth, ShardStatusRegistry, ShardStatusReport, SigningKeyRegistry,
+    SizingRecommendation, StartupComplete, StartupRegistry, StartupRequest, StreamDiscard,
+    StreamMirror, StreamRetention, StreamStorage, UpdateStrategy, WorkerHeartbeat, WorkerRegistry,
+    WorkloadKind, CONDITION_DEGRADED, CONDITION_PROGRESSING, CONDITION_READY, CONDITION_RESHARDING,
+    DRY_RUN_PLAN_ANNOTATION, RESHARD_HISTORY_LIMIT, RESHARD_TRIGGER_ANNOTATION, ROLLOUT_CANARY,
+    ROLLOUT_COMPLETED, ROLLOUT_ROLLED_BACK,
+};
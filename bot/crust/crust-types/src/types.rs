@@ -1,20 +1,527 @@
-use chrono::{DateTime, Utc};
-use kube::CustomResource;
+use bedrock_nats::JetStreamApi;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveTime, Utc, Weekday};
+use k8s_openapi::api::core::v1::{
+    Affinity, Container, EnvVar, ResourceRequirements, Toleration, TopologySpreadConstraint, Volume,
+    VolumeMount,
+};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kube::{runtime::events::Recorder, CustomResource, ResourceExt};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, RwLock};
 
 #[derive(CustomResource, Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[kube(group = "bedrock.dev", version = "v1", kind = "ShardCluster")]
 #[kube(status = "ShardClusterStatus")]
 #[kube(shortname = "sc")]
+#[kube(category = "bedrock")]
 #[kube(namespaced)]
+#[kube(printcolumn = r#"{"name": "Shards", "type": "integer", "jsonPath": ".status.current_shards"}"#)]
+#[kube(printcolumn = r#"{"name": "Ready", "type": "string", "jsonPath": ".status.conditions[?(@.type==\"Ready\")].status"}"#)]
+#[kube(printcolumn = r#"{"name": "Connected", "type": "integer", "jsonPath": ".status.shard_health.connected"}"#)]
+#[kube(printcolumn = r#"{"name": "Stale", "type": "integer", "jsonPath": ".status.shard_health.stale"}"#)]
+#[kube(printcolumn = r#"{"name": "LastReshard", "type": "date", "jsonPath": ".status.last_reshard"}"#)]
+#[kube(printcolumn = r#"{"name": "Age", "type": "date", "jsonPath": ".metadata.creationTimestamp"}"#)]
+// Cheap invariants the API server checks itself, so they hold without the webhook.
+#[kube(validation = Rule::new("self.spec.shards_per_replica >= 1").message("shards_per_replica must be at least 1"))]
+#[kube(validation = Rule::new("self.spec.replicas_per_shard_group >= 1").message("replicas_per_shard_group must be at least 1"))]
+#[kube(validation = Rule::new("self.spec.reshard_interval_hours >= 1").message("reshard_interval_hours must be at least 1"))]
+#[kube(validation = Rule::new(
+    "!has(self.spec.workload_kind) || self.spec.workload_kind != 'StatefulSet' || self.spec.replicas_per_shard_group == 1"
+).message("replicas_per_shard_group must be 1 with workload_kind StatefulSet"))]
 pub struct ShardClusterSpec {
+    /// Stop reconciling, resharding and touching the deployments of this
+    /// cluster until it is set back to false
+    #[serde(default)]
+    pub suspend: Option<bool>,
+    /// Name of the Kubernetes secret containing the Discord bot token
     pub discord_token_secret: String,
+    /// URL for the NATS server: nats://, tls://, or ws:// and wss:// for NATS
+    /// over WebSocket
+    #[serde(default = "default_nats_url")]
     pub nats_url: String,
+    /// Name of a secret whose 'creds' entry is the NATS credentials file the
+    /// stratum pods authenticate with
+    #[serde(default)]
+    pub nats_credentials_secret: Option<String>,
+    /// Name of a secret whose 'seed' entry is a NATS account signing key, and
+    /// whose optional 'account' entry is the account's public key. The
+    /// operator issues the stratum pods credentials limited to the cluster's
+    /// subjects with it, stored in `<name>-nats-credentials`
+    #[serde(default)]
+    pub nats_account_signing_secret: Option<String>,
+    /// Name of a secret with a 'token', an 'nkey' seed, or a 'user' and
+    /// 'password' entry the stratum pods authenticate to NATS with, for
+    /// servers not using credentials files
+    #[serde(default)]
+    pub nats_auth_secret: Option<String>,
+    /// Secrets with the CA and client certificate the stratum pods verify the
+    /// NATS server and authenticate with over TLS
+    #[serde(default)]
+    pub nats_tls: Option<NatsTlsSecrets>,
+    /// JetStream domain the cluster's streams and KV buckets live in, for
+    /// NATS servers whose JetStream is reached through a leaf node
+    #[serde(default)]
+    pub nats_jetstream_domain: Option<String>,
+    /// Docker image for the stratum bot instances, empty for the operator's
+    /// DEFAULT_IMAGE
+    #[serde(default)]
     pub image: String,
+    /// Number of replicas per shard group. Beyond the first, replicas stand
+    /// by and take over the group's shards when the active one goes away
+    #[serde(default = "default_replicas_per_shard_group")]
+    #[schemars(range(min = 1))]
     pub replicas_per_shard_group: i32,
+    /// Number of shards per replica
+    #[serde(default = "default_shards_per_replica")]
+    #[schemars(range(min = 1))]
     pub shards_per_replica: u32,
+    /// Interval in hours between automatic reshards
+    #[serde(default = "default_reshard_interval_hours")]
+    #[schemars(range(min = 1))]
     pub reshard_interval_hours: u64,
+    /// Gateway intents the shards identify with, as presets (`default`,
+    /// `all_unprivileged`, `all`) or intent names such as `GUILD_MEMBERS`
+    #[serde(default)]
+    pub intents: Option<Vec<String>>,
+    /// Allow privileged intents, which have to be enabled for the application
+    /// in the developer portal first
+    #[serde(default)]
+    pub allow_privileged_intents: Option<bool>,
+    /// Dispatch event types (such as `TYPING_START`) that are not published
+    #[serde(default)]
+    pub event_filter: Option<Vec<String>>,
+    /// Gateway proxy the shards connect through instead of Discord's gateway
+    #[serde(default)]
+    pub gateway_proxy_url: Option<String>,
+    /// HTTP proxy for Discord API requests, instead of the
+    /// twilight-gateway-proxy service in the bedrock namespace
+    #[serde(default)]
+    pub http_proxy_url: Option<String>,
+    /// JetStream stream of the cluster's events, created and updated by the
+    /// operator. Without it every stratum pod creates the stream itself
+    #[serde(default)]
+    pub event_stream: Option<EventStream>,
+    /// Restart shard group deployments whose workers stop sending heartbeats
+    #[serde(default)]
+    pub restart_stale_workers: Option<bool>,
+    /// Restart shard groups whose pods are ready but whose shards have sent no
+    /// status for this many seconds, which catches hung gateway connections
+    #[serde(default)]
+    pub restart_silent_groups_after_secs: Option<u64>,
+    /// Name of a secret whose 'key' entry is used to HMAC-sign coordination messages
+    #[serde(default)]
+    pub coordination_signing_secret: Option<String>,
+    /// Reassign shards of workers that stop sending heartbeats to the remaining workers
+    #[serde(default)]
+    pub dynamic_rebalancing: Option<bool>,
+    /// Seconds a worker may spend handing off shards on SIGTERM before it exits
+    #[serde(default)]
+    #[schemars(range(min = 1))]
+    pub drain_timeout_seconds: Option<u32>,
+    /// CPU and memory requests and limits for the stratum containers
+    #[serde(default)]
+    pub resources: Option<ResourceRequirements>,
+    /// Run with the shards_per_replica and resource requests recommended from
+    /// the observed load in status.sizing instead of the ones set here
+    #[serde(default)]
+    pub auto_tune: Option<bool>,
+    /// Events per second a replica is sized for when recommending
+    /// shards_per_replica (default 500)
+    #[serde(default)]
+    #[schemars(range(min = 1))]
+    pub target_replica_events_per_sec: Option<u32>,
+    /// Labels a node must carry for stratum pods to be scheduled on it
+    #[serde(default)]
+    pub node_selector: Option<BTreeMap<String, String>>,
+    /// Tolerations added to the stratum pods
+    #[serde(default)]
+    pub tolerations: Option<Vec<Toleration>>,
+    /// Node and pod affinity or anti-affinity for the stratum pods
+    #[serde(default)]
+    pub affinity: Option<Affinity>,
+    /// Constraints for spreading shard group pods across zones or nodes
+    #[serde(default)]
+    pub topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+    /// Priority class of the stratum pods
+    #[serde(default)]
+    pub priority_class_name: Option<String>,
+    /// Create a NetworkPolicy that only lets stratum pods reach DNS, Discord and NATS
+    #[serde(default)]
+    pub network_policy: Option<bool>,
+    /// Create a metrics Service and a Prometheus Operator ServiceMonitor for
+    /// the stratum pods, which then serve the admin endpoints on port 8080
+    /// (the image has to be built with the `admin` feature)
+    #[serde(default)]
+    pub service_monitor: Option<bool>,
+    /// Scale an event processor deployment such as mantle on the backlog of
+    /// its JetStream consumer with a KEDA ScaledObject
+    #[serde(default)]
+    pub event_processor_scaling: Option<EventProcessorScaling>,
+    /// Only reshard when Discord's recommended shard count differs from the
+    /// current one by more than this percentage
+    #[serde(default)]
+    pub reshard_threshold_percent: Option<u32>,
+    /// Fixed shard count to run instead of Discord's recommendation. Must be a
+    /// multiple of the bot's max_concurrency
+    #[serde(default)]
+    #[schemars(range(min = 1))]
+    pub shard_count_override: Option<u32>,
+    /// Daily window reshards are limited to, outside of it they wait
+    #[serde(default)]
+    pub reshard_window: Option<ReshardWindow>,
+    /// How the cluster moves to a new shard count, InPlace (default) or BlueGreen
+    #[serde(default)]
+    pub reshard_strategy: Option<ReshardStrategy>,
+    /// How image changes are rolled out to the shard groups
+    #[serde(default)]
+    pub update_strategy: Option<UpdateStrategy>,
+    /// Rolling update settings of the generated deployments
+    #[serde(default)]
+    pub rolling_update: Option<RollingUpdateSettings>,
+    /// Workload the shard groups run as, Deployment (default) or StatefulSet
+    #[serde(default)]
+    pub workload_kind: Option<WorkloadKind>,
+    /// Run the shard groups in another Kubernetes cluster instead of this one
+    #[serde(default)]
+    pub remote: Option<RemoteTarget>,
+    /// Extra settings merged into the generated pod template
+    #[serde(default)]
+    pub pod_template: Option<PodTemplateOverlay>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct ReshardWindow {
+    /// Start of the window as HH:MM
+    pub start: String,
+    /// End of the window as HH:MM, before `start` for windows past midnight
+    pub end: String,
+    /// Offset of the times from UTC such as +02:00, UTC when unset
+    #[serde(default)]
+    pub utc_offset: Option<String>,
+    /// Weekdays (Mon, Tue, ...) the window opens on, every day when unset
+    #[serde(default)]
+    pub days: Option<Vec<String>>,
+}
+
+impl ReshardWindow {
+    /// Whether `now` falls inside the window. Errors describe an invalid window.
+    pub fn contains(&self, now: DateTime<Utc>) -> std::result::Result<bool, String> {
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| format!("'{}' is not a HH:MM time", time))
+        };
+        let start = parse_time(&self.start)?;
+        let end = parse_time(&self.end)?;
+        let offset = match &self.utc_offset {
+            Some(offset) => offset
+                .parse::<FixedOffset>()
+                .map_err(|_| format!("'{}' is not a UTC offset such as +02:00", offset))?,
+            None => FixedOffset::east_opt(0).expect("zero offset is valid"),
+        };
+
+        let local = now.with_timezone(&offset);
+        let time = local.time();
+        // For windows past midnight the part after midnight belongs to the
+        // day the window opened on.
+        let (in_window, opened_on) = if start <= end {
+            (time >= start && time < end, local.weekday())
+        } else if time >= start {
+            (true, local.weekday())
+        } else {
+            (time < end, local.weekday().pred())
+        };
+
+        let day_allowed = match &self.days {
+            Some(days) => {
+                let mut allowed = false;
+                for day in days {
+                    let day = day.parse::<Weekday>().map_err(|_| format!("'{}' is not a weekday", day))?;
+                    allowed |= day == opened_on;
+                }
+                allowed
+            }
+            None => true,
+        };
+
+        Ok(in_window && day_allowed)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+pub enum ReshardStrategy {
+    /// Tell the running workers to re-identify with the new shard count.
+    #[default]
+    InPlace,
+    /// Start a complete second set of shard group deployments with the new
+    /// shard count and delete the old set once every new shard reported that
+    /// it started. Both sets publish events while they overlap.
+    BlueGreen,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+pub enum WorkloadKind {
+    /// One deployment per shard group, told its shard range through the
+    /// environment.
+    #[default]
+    Deployment,
+    /// One StatefulSet with a pod per shard group. Pods work out their shards
+    /// from their ordinal, so `replicas_per_shard_group` has to be 1.
+    StatefulSet,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct NatsTlsSecrets {
+    /// Name of a secret whose 'ca.crt' entry is the CA bundle the NATS
+    /// server's certificate is verified against
+    #[serde(default)]
+    pub ca_secret: Option<String>,
+    /// Name of a secret whose 'tls.crt' and 'tls.key' entries are the client
+    /// certificate presented for mutual TLS
+    #[serde(default)]
+    pub client_certificate_secret: Option<String>,
+}
+
+impl NatsTlsSecrets {
+    /// Names of the secrets the stratum pods mount.
+    pub fn secrets(&self) -> impl Iterator<Item = &String> {
+        self.ca_secret.iter().chain(self.client_certificate_secret.iter())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct EventStream {
+    /// Stream name, `<subject prefix with dashes>-events` by default
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Subjects the stream captures, every shard subject of the cluster by default
+    #[serde(default)]
+    pub subjects: Option<Vec<String>>,
+    #[serde(default)]
+    pub retention: Option<StreamRetention>,
+    /// Age after which messages are dropped, unlimited by default
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+    /// Size the stream is kept under, unlimited by default
+    #[serde(default)]
+    pub max_bytes: Option<i64>,
+    /// Messages the stream is kept under, 10000 by default like the stream
+    /// stratum creates
+    #[serde(default)]
+    pub max_messages: Option<i64>,
+    /// What happens once a limit is reached, dropping the oldest messages by
+    /// default
+    #[serde(default)]
+    pub discard: Option<StreamDiscard>,
+    /// Copies of the stream kept in a NATS cluster (default 1)
+    #[serde(default)]
+    #[schemars(range(min = 1, max = 5))]
+    pub replicas: Option<usize>,
+    #[serde(default)]
+    pub storage: Option<StreamStorage>,
+    /// How long Nats-Msg-Ids are remembered to drop republished events, 120
+    /// seconds or the max age if that is shorter by default
+    #[serde(default)]
+    #[schemars(range(min = 1))]
+    pub duplicate_window_secs: Option<u64>,
+    /// Streams the events are split over by shard id modulo this count, each
+    /// named `<name>-<partition>` and capturing
+    /// `<subject prefix>.partitions.<partition>.>` (default 1, a single
+    /// stream). Cannot be combined with `subjects`.
+    #[serde(default)]
+    #[schemars(range(min = 1))]
+    pub partitions: Option<u32>,
+    /// Read-only copies of the events stream, for example in another
+    /// region's JetStream domain so analytics consumers stay off the
+    /// ingestion cluster. Like the events stream they are left in place when
+    /// removed here or when the cluster is deleted.
+    #[serde(default)]
+    pub mirrors: Option<Vec<StreamMirror>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct StreamMirror {
+    /// Stream name, unique within the mirror's domain
+    pub name: String,
+    /// JetStream domain the copy is created in, the cluster's own by default.
+    /// A copy in another domain needs `nats_jetstream_domain` set, so the
+    /// events stream can be reached from there.
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// How the events are copied, Mirror for a single stream and Source for a
+    /// partitioned one by default
+    #[serde(default)]
+    pub mode: Option<MirrorMode>,
+    /// Age after which copied messages are dropped, unlimited by default
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+    /// Size the copy is kept under, unlimited by default
+    #[serde(default)]
+    pub max_bytes: Option<i64>,
+    /// Copies of the stream kept in a NATS cluster (default 1)
+    #[serde(default)]
+    #[schemars(range(min = 1, max = 5))]
+    pub replicas: Option<usize>,
+    #[serde(default)]
+    pub storage: Option<StreamStorage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub enum MirrorMode {
+    /// An exact copy of one stream, with its sequence numbers, so not
+    /// available for a partitioned stream.
+    Mirror,
+    /// One stream gathering the messages of every partition.
+    Source,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+pub enum StreamRetention {
+    /// Keep messages until a limit is reached.
+    #[default]
+    Limits,
+    /// Keep messages until every consumer acknowledged them.
+    Interest,
+    /// Keep messages until one consumer acknowledged them.
+    WorkQueue,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+pub enum StreamDiscard {
+    /// Drop the oldest messages to make room.
+    #[default]
+    Old,
+    /// Refuse new messages, so publishes fail instead of losing backlog.
+    New,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+pub enum StreamStorage {
+    #[default]
+    File,
+    Memory,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct EventProcessorScaling {
+    /// Deployment of the processors, in the namespace of the shard groups
+    pub deployment: String,
+    /// NATS monitoring endpoint KEDA reads the consumer lag from, as host:port
+    pub monitoring_endpoint: String,
+    /// Stream the processors consume, every partition of the cluster's events
+    /// stream by default
+    #[serde(default)]
+    pub stream: Option<String>,
+    /// Durable consumer the processors share, mantle-processors by default
+    #[serde(default)]
+    pub consumer: Option<String>,
+    /// NATS account of the stream, $G by default
+    #[serde(default)]
+    pub account: Option<String>,
+    /// Pending messages per processor replica before another one is added,
+    /// 1000 by default
+    #[serde(default)]
+    pub lag_threshold: Option<u64>,
+    /// Fewest processor replicas, 0 lets KEDA scale to zero when idle (default 1)
+    #[serde(default)]
+    pub min_replicas: Option<i32>,
+    /// Most processor replicas (default 10)
+    #[serde(default)]
+    pub max_replicas: Option<i32>,
+}
+
+/// Kubernetes cluster the workloads of a ShardCluster run in. The operator
+/// keeps the shard math, identify budget and status here and copies the
+/// secrets the pods reference over to the remote namespace.
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct RemoteTarget {
+    /// Name of a secret whose 'kubeconfig' entry gives access to the cluster
+    pub kubeconfig_secret: String,
+    /// Namespace in the remote cluster, the ShardCluster's own by default
+    #[serde(default)]
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct UpdateStrategy {
+    /// Shard groups that get a new image first, 0 updates every group at once
+    #[serde(default)]
+    pub canary_groups: u32,
+    /// Seconds the canary groups have to keep sending heartbeats before the
+    /// remaining groups get the new image
+    #[serde(default = "default_canary_soak_seconds")]
+    pub soak_seconds: u64,
+}
+
+fn default_canary_soak_seconds() -> u64 {
+    600
+}
+
+/// How the generated workloads replace their pods. Unset fields keep the
+/// Kubernetes defaults.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct RollingUpdateSettings {
+    /// Pods of a shard group that may be unavailable during an update, as a
+    /// number or percentage. Deployments only
+    #[serde(default)]
+    pub max_unavailable: Option<IntOrString>,
+    /// Pods of a shard group created above the desired count during an
+    /// update, as a number or percentage. Deployments only
+    #[serde(default)]
+    pub max_surge: Option<IntOrString>,
+    /// Seconds a new pod must be ready before it counts as available
+    #[serde(default)]
+    #[schemars(range(min = 0))]
+    pub min_ready_seconds: Option<i32>,
+    /// Old revisions kept for rollback
+    #[serde(default)]
+    #[schemars(range(min = 0))]
+    pub revision_history_limit: Option<i32>,
+}
+
+/// User supplied additions to the pods crust generates, for sidecars, extra
+/// credentials and the like.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct PodTemplateOverlay {
+    /// Environment variables for the stratum container, replacing generated
+    /// ones with the same name
+    #[serde(default)]
+    pub env: Option<Vec<EnvVar>>,
+    /// Volumes added to the pod
+    #[serde(default)]
+    pub volumes: Option<Vec<Volume>>,
+    /// Volume mounts added to the stratum container
+    #[serde(default)]
+    pub volume_mounts: Option<Vec<VolumeMount>>,
+    /// Containers run next to stratum in every pod
+    #[serde(default)]
+    pub sidecars: Option<Vec<Container>>,
+    /// Annotations added to the pod template
+    #[serde(default)]
+    pub annotations: Option<BTreeMap<String, String>>,
+    /// Service account the pods run as
+    #[serde(default)]
+    pub service_account_name: Option<String>,
+}
+
+// Defaults for the optional spec fields, so a ShardCluster that only names the
+// token secret is enough to get a working deployment.
+pub(crate) fn default_nats_url() -> String {
+    "nats://nats-cluster.nats-system.svc.cluster.local:4222".to_string()
+}
+
+pub(crate) fn default_replicas_per_shard_group() -> i32 {
+    1
+}
+
+pub(crate) fn default_shards_per_replica() -> u32 {
+    16
+}
+
+pub(crate) fn default_reshard_interval_hours() -> u64 {
+    24
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
@@ -23,7 +530,302 @@ pub struct ShardClusterStatus {
     #[schemars(with = "Option<String>")]
     pub last_reshard: Option<DateTime<Utc>>,
     pub shard_groups: Vec<ShardGroup>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reshard: Option<ReshardStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<u32>,
+    /// Shard groups of a blue/green reshard that are starting next to the
+    /// current ones
+    #[serde(default)]
+    pub pending_shard_groups: Option<Vec<ShardGroup>>,
+    /// Image every shard group has been rolled out with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Canary rollout of a new image
+    #[serde(default)]
+    pub rollout: Option<RolloutStatus>,
+    /// Shard group sizing recommended from the observed load
+    #[serde(default)]
+    pub sizing: Option<SizingRecommendation>,
+    /// Connection state of all shards from their status heartbeats
+    #[serde(default)]
+    pub shard_health: Option<ShardHealth>,
+    /// Event processors counted by the status they last reported
+    #[serde(default)]
+    pub processor_health: Option<ProcessorHealth>,
+    /// Latest reshards, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reshard_history: Vec<ReshardRecord>,
+}
+
+/// Shards counted by the state their last status heartbeat reported.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+pub struct ShardHealth {
+    pub connected: u32,
+    /// Shards whose runner reports them connecting, stopped or failed
+    pub disconnected: u32,
+    /// Shards that have not reported a status recently
+    pub stale: u32,
+}
+
+impl ShardHealth {
+    pub fn healthy(&self) -> bool {
+        self.disconnected == 0 && self.stale == 0
+    }
+}
+
+/// Event processors counted by the status their last health report gave.
+/// Processors that stopped reporting are left out, since they are usually
+/// ones the autoscaler removed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+pub struct ProcessorHealth {
+    pub healthy: u32,
+    /// Processors whose handlers panicked or failed too often
+    pub degraded: u32,
+    /// Pending events of the processors' consumer, as last reported
+    #[serde(default)]
+    pub lag: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SizingRecommendation {
+    pub shards_per_replica: u32,
+    /// CPU request per stratum container, from the busiest pod's usage
+    #[serde(default)]
+    pub cpu_request: Option<String>,
+    /// Memory request per stratum container, from the busiest pod's usage
+    #[serde(default)]
+    pub memory_request: Option<String>,
+    /// Events per second over all shards
+    pub events_per_sec: f64,
+    pub reason: String,
+    /// Whether auto_tune runs the cluster with this sizing
+    pub applied: bool,
+    #[schemars(with = "Option<String>")]
+    pub changed_at: Option<DateTime<Utc>>,
+    #[schemars(with = "Option<String>")]
+    pub observed_at: Option<DateTime<Utc>>,
+}
+
+pub const ROLLOUT_CANARY: &str = "Canary";
+pub const ROLLOUT_COMPLETED: &str = "Completed";
+pub const ROLLOUT_ROLLED_BACK: &str = "RolledBack";
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct RolloutStatus {
+    pub image: String,
+    pub previous_image: String,
+    /// Canary, Completed or RolledBack
     pub phase: String,
+    pub canary_groups: Vec<String>,
+    #[schemars(with = "Option<String>")]
+    pub started_at: Option<DateTime<Utc>>,
+    pub message: Option<String>,
+}
+
+const DEFAULT_SUBJECT_ROOT: &str = "discord";
+
+impl ShardCluster {
+    /// NATS subject prefix the cluster's workers and coordination messages use,
+    /// `<subject_root>.<namespace>.<name>`, so clusters sharing a NATS server
+    /// stay apart. `subject_root` is the operator's SUBJECT_ROOT.
+    pub fn subject_prefix(&self, subject_root: &str) -> String {
+        let namespace = self.namespace().unwrap_or_else(|| "default".to_string());
+        format!("{}.{}.{}", subject_root, namespace, self.name_any().replace('.', "-"))
+    }
+
+    /// JetStream context for the cluster's streams, in its JetStream domain.
+    pub fn jetstream(&self, nats_client: &async_nats::Client) -> async_nats::jetstream::Context {
+        self.jetstream_api().context(nats_client)
+    }
+
+    /// Subject prefix of the JetStream API the cluster's workers call.
+    pub fn jetstream_api_prefix(&self) -> String {
+        match &self.spec.nats_jetstream_domain {
+            Some(domain) => format!("$JS.{}.API", domain),
+            None => "$JS.API".to_string(),
+        }
+    }
+
+    /// Name of the JetStream stream holding the cluster's events.
+    pub fn event_stream_name(&self, subject_root: &str) -> String {
+        self.spec
+            .event_stream
+            .as_ref()
+            .and_then(|stream| stream.name.clone())
+            .unwrap_or_else(|| format!("{}-events", self.subject_prefix(subject_root).replace('.', "-")))
+    }
+
+    /// JetStream API the cluster's streams are reached through.
+    pub fn jetstream_api(&self) -> JetStreamApi {
+        match &self.spec.nats_jetstream_domain {
+            Some(domain) => JetStreamApi::Domain(domain.clone()),
+            None => JetStreamApi::Default,
+        }
+    }
+
+    /// Partitions of the events stream, 1 when it is a single stream.
+    pub fn event_stream_partitions(&self) -> u32 {
+        self.spec.event_stream.as_ref().and_then(|stream| stream.partitions).unwrap_or(1).max(1)
+    }
+
+    /// Names of the streams holding the cluster's events, one per partition.
+    pub fn event_stream_names(&self, subject_root: &str) -> Vec<String> {
+        bedrock_nats::partitions::stream_names(&self.event_stream_name(subject_root), self.event_stream_partitions())
+    }
+
+    /// Image the deployment of `group` should run, taking a canary rollout of
+    /// the spec image into account.
+    pub fn image_for(&self, group: &ShardGroup) -> &str {
+        let rollout = self
+            .status
+            .as_ref()
+            .and_then(|status| status.rollout.as_ref())
+            .filter(|rollout| rollout.image == self.spec.image);
+
+        match rollout {
+            Some(rollout) if rollout.phase == ROLLOUT_CANARY && !rollout.canary_groups.contains(&group.deployment_name) => {
+                &rollout.previous_image
+            }
+            Some(rollout) if rollout.phase == ROLLOUT_ROLLED_BACK => &rollout.previous_image,
+            _ => &self.spec.image,
+        }
+    }
+}
+
+/// Annotation holding the time a reshard was last requested, set by
+/// `crustctl reshard`. The controller reshards when it is newer than
+/// `status.last_reshard`.
+pub const RESHARD_TRIGGER_ANNOTATION: &str = "crust.bedrock.dev/reshard-trigger";
+
+/// Annotation the operator writes its DryRunPlan for a cluster to while
+/// running with DRY_RUN.
+pub const DRY_RUN_PLAN_ANNOTATION: &str = "crust.bedrock.dev/dry-run-plan";
+
+/// What the operator would change for a cluster if it were not in dry-run
+/// mode.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DryRunPlan {
+    /// Shard count the cluster would run.
+    pub shards: u32,
+    /// Shard groups the cluster would run.
+    pub shard_groups: Vec<ShardGroup>,
+    /// Workload changes and NATS signals, in the order they would happen.
+    pub actions: Vec<String>,
+}
+
+pub const CONDITION_READY: &str = "Ready";
+pub const CONDITION_PROGRESSING: &str = "Progressing";
+pub const CONDITION_DEGRADED: &str = "Degraded";
+pub const CONDITION_RESHARDING: &str = "Resharding";
+
+/// Kubernetes style status condition, serialized with the usual camelCase
+/// keys so `kubectl wait --for=condition=Ready` works against the CRD.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Condition {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub status: String,
+    pub reason: String,
+    pub message: String,
+    #[schemars(with = "Option<String>")]
+    pub last_transition_time: Option<DateTime<Utc>>,
+}
+
+/// Sets condition `type_`, keeping its transition time when the status does
+/// not change. Returns whether anything about the condition changed.
+pub fn set_condition(
+    conditions: &mut Vec<Condition>,
+    type_: &str,
+    status: bool,
+    reason: &str,
+    message: impl Into<String>,
+) -> bool {
+    let status = if status { "True" } else { "False" }.to_string();
+    let message = message.into();
+
+    match conditions.iter_mut().find(|condition| condition.type_ == type_) {
+        Some(condition) => {
+            if condition.status == status && condition.reason == reason && condition.message == message {
+                return false;
+            }
+            if condition.status != status {
+                condition.last_transition_time = Some(Utc::now());
+            }
+            condition.status = status;
+            condition.reason = reason.to_string();
+            condition.message = message;
+        }
+        None => conditions.push(Condition {
+            type_: type_.to_string(),
+            status,
+            reason: reason.to_string(),
+            message,
+            last_transition_time: Some(Utc::now()),
+        }),
+    }
+
+    true
+}
+
+/// Reshards status.reshard_history keeps, oldest dropped first.
+pub const RESHARD_HISTORY_LIMIT: usize = 20;
+
+/// A reshard the operator started, as kept in status.reshard_history.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ReshardRecord {
+    #[schemars(with = "String")]
+    pub started_at: DateTime<Utc>,
+    /// Shard count before the reshard, unset for the first deployment
+    #[serde(default)]
+    pub from_shards: Option<u32>,
+    pub to_shards: u32,
+    pub reason: String,
+    /// InProgress until the reshard ends as Completed, Failed or Superseded
+    pub outcome: String,
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Appends `record` to the reshard history, marking a reshard still in
+/// progress as superseded and keeping the last RESHARD_HISTORY_LIMIT entries.
+pub fn push_reshard_record(history: &mut Vec<ReshardRecord>, record: ReshardRecord) {
+    finish_reshard_record(history, "Superseded");
+    history.push(record);
+    let excess = history.len().saturating_sub(RESHARD_HISTORY_LIMIT);
+    history.drain(..excess);
+}
+
+/// Sets the outcome of the reshard in progress, if any. Returns whether the
+/// history changed.
+pub fn finish_reshard_record(history: &mut [ReshardRecord], outcome: &str) -> bool {
+    match history.last_mut() {
+        Some(record) if record.outcome == "InProgress" => {
+            record.outcome = outcome.to_string();
+            record.finished_at = Some(Utc::now());
+            true
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct ReshardStatus {
+    pub target_shards: u32,
+    pub phase: String,
+    pub workers_total: u32,
+    pub workers_completed: u32,
+    pub workers_failed: u32,
+    pub shards_reidentified: u32,
+    #[schemars(with = "Option<String>")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[schemars(with = "Option<String>")]
+    pub completed_at: Option<DateTime<Utc>>,
+    pub message: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
@@ -32,10 +834,624 @@ pub struct ShardGroup {
     pub shard_start: u32,
     pub shard_end: u32,
     pub replicas: i32,
+    /// Ready replicas of the group's deployment when it was last observed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready_replicas: Option<i32>,
+    /// Connection state of the group's shards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<ShardHealth>,
+}
+
+impl ShardGroup {
+    pub fn is_ready(&self) -> bool {
+        self.ready_replicas.is_some_and(|ready| ready >= self.replicas)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct SessionStartLimit {
+    pub total: u32,
+    pub remaining: u32,
+    pub reset_after_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GatewayInfo {
+    pub recommended_shards: u32,
+    pub max_concurrency: u32,
+    pub session_start_limit: SessionStartLimit,
+}
+
+/// Gateway info per bot token, so reconciles of many clusters and frequent
+/// requeues do not each call Get Gateway Bot.
+#[derive(Debug)]
+pub struct GatewayInfoCache {
+    ttl: std::time::Duration,
+    max_calls_per_minute: usize,
+    entries: HashMap<String, CachedGatewayInfo>,
 }
 
+#[derive(Debug, Default)]
+struct CachedGatewayInfo {
+    info: Option<(GatewayInfo, std::time::Instant)>,
+    calls: VecDeque<std::time::Instant>,
+}
+
+impl GatewayInfoCache {
+    pub fn new(ttl: std::time::Duration, max_calls_per_minute: usize) -> Self {
+        Self {
+            ttl,
+            max_calls_per_minute,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Applies a reloaded TTL and call budget, keeping what is cached.
+    pub fn set_limits(&mut self, ttl: std::time::Duration, max_calls_per_minute: usize) {
+        self.ttl = ttl;
+        self.max_calls_per_minute = max_calls_per_minute;
+    }
+
+    /// The cached info of `token` if it is younger than the TTL.
+    pub fn fresh(&self, token: &str, now: std::time::Instant) -> Option<GatewayInfo> {
+        self.entries
+            .get(token)
+            .and_then(|entry| entry.info)
+            .filter(|(_, fetched_at)| now.duration_since(*fetched_at) < self.ttl)
+            .map(|(info, fetched_at)| aged_gateway_info(info, now.duration_since(fetched_at)))
+    }
+
+    /// The cached info of `token` however old it is.
+    pub fn stale(&self, token: &str, now: std::time::Instant) -> Option<GatewayInfo> {
+        self.entries
+            .get(token)
+            .and_then(|entry| entry.info)
+            .map(|(info, fetched_at)| aged_gateway_info(info, now.duration_since(fetched_at)))
+    }
+
+    /// Counts a call to Discord for `token`, or returns false when the last
+    /// minute already used up its budget.
+    pub fn try_spend(&mut self, token: &str, now: std::time::Instant) -> bool {
+        let entry = self.entries.entry(token.to_string()).or_default();
+        while entry
+            .calls
+            .front()
+            .is_some_and(|call| now.duration_since(*call) >= std::time::Duration::from_secs(60))
+        {
+            entry.calls.pop_front();
+        }
+
+        if entry.calls.len() >= self.max_calls_per_minute {
+            return false;
+        }
+        entry.calls.push_back(now);
+        true
+    }
+
+    pub fn insert(&mut self, token: &str, info: GatewayInfo, now: std::time::Instant) {
+        self.entries.entry(token.to_string()).or_default().info = Some((info, now));
+    }
+}
+
+/// Counts the session start reset down by the time since the info was fetched.
+fn aged_gateway_info(mut info: GatewayInfo, age: std::time::Duration) -> GatewayInfo {
+    let limit = &mut info.session_start_limit;
+    let age_ms = age.as_millis() as u64;
+    if age_ms >= limit.reset_after_ms {
+        limit.remaining = limit.total;
+        limit.reset_after_ms = 0;
+    } else {
+        limit.reset_after_ms -= age_ms;
+    }
+    info
+}
+
+pub type GatewayCache = Arc<RwLock<GatewayInfoCache>>;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WorkerHeartbeat {
+    pub worker_id: String,
+    pub shards: Vec<u32>,
+    /// Events each shard published per second since the worker's previous
+    /// heartbeat
+    #[serde(default)]
+    pub events_per_sec: BTreeMap<u32, f64>,
+    pub uptime_secs: u64,
+    #[serde(skip, default = "Utc::now")]
+    pub received_at: DateTime<Utc>,
+}
+
+pub type WorkerRegistry = Arc<RwLock<HashMap<String, WorkerHeartbeat>>>;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShardStatusReport {
+    pub shard_id: u32,
+    pub status: String,
+    #[serde(skip, default = "Utc::now")]
+    pub received_at: DateTime<Utc>,
+}
+
+/// Last status each shard reported, keyed by the subject prefix of its cluster
+/// and then by shard id.
+pub type ShardStatusRegistry = Arc<RwLock<HashMap<String, HashMap<u32, ShardStatusReport>>>>;
+
+/// Health report an event processor publishes on
+/// `bedrock.<subject prefix>.processors.<id>.status`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProcessorStatusReport {
+    pub processor_id: String,
+    pub status: String,
+    #[serde(default)]
+    pub lag: Option<u64>,
+    #[serde(default)]
+    pub error_rate: f64,
+    #[serde(skip, default = "Utc::now")]
+    pub received_at: DateTime<Utc>,
+}
+
+/// Last status each event processor reported, keyed by the subject prefix of
+/// its cluster and then by processor id.
+pub type ProcessorStatusRegistry = Arc<RwLock<HashMap<String, HashMap<String, ProcessorStatusReport>>>>;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReshardProgress {
+    pub worker_id: String,
+    pub total_shards: u32,
+    pub stage: String,
+    pub shards_stopped: u32,
+    pub shards_reidentified: u32,
+    pub shards_total: u32,
+    pub message: Option<String>,
+    #[serde(skip, default = "Utc::now")]
+    pub received_at: DateTime<Utc>,
+}
+
+pub type ReshardRegistry = Arc<RwLock<HashMap<String, ReshardProgress>>>;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StartupComplete {
+    pub worker_id: String,
+    pub shard_id: u32,
+}
+
+/// A worker asking the operator for an identify slot.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StartupRequest {
+    pub worker_id: String,
+    pub shard_id: u32,
+}
+
+/// Consecutive failed reconciles of each cluster, keyed by `namespace/name`.
+pub type FailureRegistry = Arc<RwLock<HashMap<String, u32>>>;
+
+/// Clients of remote clusters keyed by `<namespace>/<kubeconfig secret>`,
+/// with a hash of the kubeconfig they were built from.
+pub type RemoteClientRegistry = Arc<RwLock<HashMap<String, (String, kube::Client)>>>;
+
+/// Shards each worker reported as started, keyed by worker id.
+pub type StartupRegistry = Arc<RwLock<HashMap<String, HashSet<u32>>>>;
+
+/// Spacing Discord enforces between identifies in one max_concurrency bucket.
+const IDENTIFY_INTERVAL_MS: i64 = 5_000;
+/// How long to hold off workers once the session start budget is spent and
+/// Discord has not told us when it resets.
+const SESSION_LIMIT_RETRY_MS: i64 = 60_000;
+/// Decisions kept per bot token in `IdentifyBudget::history`.
+const IDENTIFY_HISTORY_LEN: usize = 256;
+
+/// The broker's answer to a worker's `startup.request`, sent back as the reply.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdentifyGrant {
+    pub worker_id: String,
+    pub shard_id: u32,
+    pub granted: bool,
+    /// Wait before identifying when granted, or before asking again when not.
+    pub delay_ms: u64,
+    pub reason: Option<String>,
+    pub decided_at: DateTime<Utc>,
+}
+
+/// Identify slots of one bot token, shared by the workers of every cluster
+/// identifying with it.
+#[derive(Debug, Clone)]
+pub struct IdentifyBudget {
+    pub max_concurrency: u32,
+    pub session_start_limit: Option<SessionStartLimit>,
+    limit_resets_at: Option<DateTime<Utc>>,
+    bucket_free_at: HashMap<u32, DateTime<Utc>>,
+    pub history: VecDeque<IdentifyGrant>,
+}
+
+impl Default for IdentifyBudget {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 1,
+            session_start_limit: None,
+            limit_resets_at: None,
+            bucket_free_at: HashMap::new(),
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl IdentifyBudget {
+    /// Takes the latest limits from Discord. `None` keeps the known session
+    /// start limit, for when only `max_concurrency` is available.
+    pub fn update_limits(&mut self, max_concurrency: u32, session_start_limit: Option<SessionStartLimit>, now: DateTime<Utc>) {
+        let max_concurrency = max_concurrency.max(1);
+        if max_concurrency != self.max_concurrency {
+            self.bucket_free_at.clear();
+        }
+        self.max_concurrency = max_concurrency;
+        if let Some(mut limit) = session_start_limit {
+            // Cached gateway info misses the identifies granted since it was
+            // fetched, so within the known reset window the lower count holds.
+            let known = self.session_start_limit.zip(self.limit_resets_at).filter(|(_, resets_at)| now < *resets_at);
+            if let Some((known, _)) = known {
+                limit.remaining = limit.remaining.min(known.remaining);
+            }
+            self.limit_resets_at = Some(now + chrono::Duration::milliseconds(limit.reset_after_ms as i64));
+            self.session_start_limit = Some(limit);
+        }
+    }
+
+    /// Decides whether `shard_id` may identify and records the decision.
+    pub fn grant(&mut self, worker_id: &str, shard_id: u32, now: DateTime<Utc>) -> IdentifyGrant {
+        if self.limit_resets_at.is_some_and(|reset| now >= reset) {
+            if let Some(limit) = &mut self.session_start_limit {
+                limit.remaining = limit.total;
+            }
+            self.limit_resets_at = None;
+        }
+
+        let exhausted = self.session_start_limit.is_some_and(|limit| limit.remaining == 0);
+        let (granted, delay, reason) = if exhausted {
+            let retry = self
+                .limit_resets_at
+                .map_or(chrono::Duration::milliseconds(SESSION_LIMIT_RETRY_MS), |reset| reset - now);
+            (false, retry, Some("session start limit exhausted".to_string()))
+        } else {
+            let bucket = shard_id % self.max_concurrency;
+            let free_at = self
+                .bucket_free_at
+                .get(&bucket)
+                .copied()
+                .filter(|free_at| *free_at > now)
+                .unwrap_or(now);
+            self.bucket_free_at
+                .insert(bucket, free_at + chrono::Duration::milliseconds(IDENTIFY_INTERVAL_MS));
+            if let Some(limit) = &mut self.session_start_limit {
+                limit.remaining -= 1;
+            }
+            (true, free_at - now, None)
+        };
+
+        let decision = IdentifyGrant {
+            worker_id: worker_id.to_string(),
+            shard_id,
+            granted,
+            delay_ms: delay.num_milliseconds().max(0) as u64,
+            reason,
+            decided_at: now,
+        };
+        if self.history.len() == IDENTIFY_HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(decision.clone());
+        decision
+    }
+}
+
+/// Key standing for a bot token in the gateway info cache and the identify
+/// budgets, so the token itself is not kept in them.
+pub fn token_key(token: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    token.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Identify budgets keyed by [`token_key`], since Discord limits
+/// identifies per bot token rather than per cluster, with the token each
+/// cluster subject prefix identifies with.
+#[derive(Debug, Default)]
+pub struct IdentifyBudgets {
+    budgets: HashMap<String, IdentifyBudget>,
+    tokens: HashMap<String, String>,
+}
+
+impl IdentifyBudgets {
+    /// Records that the cluster under `subject_prefix` identifies with
+    /// `token_key` and passes the latest limits to that token's budget.
+    pub fn update_limits(
+        &mut self,
+        subject_prefix: &str,
+        token_key: &str,
+        max_concurrency: u32,
+        session_start_limit: Option<SessionStartLimit>,
+        now: DateTime<Utc>,
+    ) {
+        let previous = self.tokens.insert(subject_prefix.to_string(), token_key.to_string());
+        if let Some(previous) = previous.filter(|previous| previous != token_key) {
+            self.forget_unused(&previous);
+        }
+        self.budgets
+            .entry(token_key.to_string())
+            .or_default()
+            .update_limits(max_concurrency, session_start_limit, now);
+    }
+
+    /// Decides whether `shard_id` of the cluster under `subject_prefix` may
+    /// identify. A cluster not reconciled yet gets a budget of its own until
+    /// its token is known.
+    pub fn grant(&mut self, subject_prefix: &str, worker_id: &str, shard_id: u32, now: DateTime<Utc>) -> IdentifyGrant {
+        let token_key = self
+            .tokens
+            .entry(subject_prefix.to_string())
+            .or_insert_with(|| subject_prefix.to_string());
+        self.budgets
+            .entry(token_key.clone())
+            .or_default()
+            .grant(worker_id, shard_id, now)
+    }
+
+    /// The budget of `token_key`, if a cluster identifies with it.
+    pub fn budget(&self, token_key: &str) -> Option<&IdentifyBudget> {
+        self.budgets.get(token_key)
+    }
+
+    /// Forgets the cluster under `subject_prefix`, and its token's budget
+    /// once no other cluster identifies with it.
+    pub fn remove(&mut self, subject_prefix: &str) {
+        if let Some(token_key) = self.tokens.remove(subject_prefix) {
+            self.forget_unused(&token_key);
+        }
+    }
+
+    fn forget_unused(&mut self, token_key: &str) {
+        if !self.tokens.values().any(|key| key == token_key) {
+            self.budgets.remove(token_key);
+        }
+    }
+}
+
+pub type IdentifyRegistry = Arc<RwLock<IdentifyBudgets>>;
+
+/// Coordination signing key of every cluster that has one, keyed by its
+/// subject prefix, for the replies the operator sends outside a reconcile.
+pub type SigningKeyRegistry = Arc<RwLock<HashMap<String, Vec<u8>>>>;
+
+/// Operator-wide settings. Each one is read from the environment variable of
+/// the same name and can be overridden by a key of the operator's ConfigMap,
+/// which is watched so edits apply without a restart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperatorConfig {
+    /// Image of clusters whose spec leaves it empty.
+    pub default_image: String,
+    /// How often deployments are put back to the recorded layout between reshards.
+    pub resync_interval: std::time::Duration,
+    /// How long a cluster waits after checking Discord's recommended shard count.
+    pub reshard_check_interval: std::time::Duration,
+    /// How long a due reshard waits before checking its window again.
+    pub reshard_window_recheck: std::time::Duration,
+    /// Scheduled reshards are spread over this percentage of each cluster's
+    /// reshard interval.
+    pub reshard_jitter_percent: u64,
+    /// How many clusters may reshard at the same time, 0 for no limit.
+    pub max_concurrent_reshards: usize,
+    /// First token of every cluster's subject prefix.
+    pub subject_root: String,
+    pub gateway_info_ttl: std::time::Duration,
+    pub gateway_info_calls_per_minute: usize,
+    /// How many clusters are reconciled at the same time, 0 for no limit.
+    /// Read at startup only.
+    pub reconcile_concurrency: u16,
+    /// Objects fetched per page when the watches list. Read at startup only.
+    pub watch_page_size: u32,
+    /// Requeue after a cluster's first failed reconcile, doubled for every
+    /// failure after it.
+    pub error_requeue: std::time::Duration,
+    /// Longest requeue after failed reconciles.
+    pub max_error_requeue: std::time::Duration,
+    /// Only work out and report what would change, without changing it.
+    pub dry_run: bool,
+    /// Retention and replicas of every cluster's coordination stream.
+    pub coordination_stream: bedrock_nats::coordination::CoordinationStreamSettings,
+}
+
+impl OperatorConfig {
+    pub fn from_env() -> crate::Result<Self> {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Settings from the ConfigMap's `data`, falling back to the environment
+    /// for keys it does not set.
+    pub fn from_config_map(data: &BTreeMap<String, String>) -> crate::Result<Self> {
+        Self::from_lookup(|key| data.get(key).cloned().or_else(|| std::env::var(key).ok()))
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> crate::Result<Self> {
+        let secs = |key: &str, default: u64| setting(&lookup, key, default).map(std::time::Duration::from_secs);
+
+        let subject_root: String = setting(&lookup, "SUBJECT_ROOT", DEFAULT_SUBJECT_ROOT.to_string())?;
+        if subject_root.is_empty() || subject_root.contains(['.', '*', '>']) || subject_root.contains(char::is_whitespace) {
+            return Err(crate::CrustError::Validation(format!(
+                "SUBJECT_ROOT must be a single NATS subject token, got {:?}",
+                subject_root
+            )));
+        }
+
+        let watch_page_size = setting(&lookup, "WATCH_PAGE_SIZE", 500)?;
+        if watch_page_size == 0 {
+            return Err(crate::CrustError::Validation("WATCH_PAGE_SIZE must be at least 1".to_string()));
+        }
+
+        let coordination_replicas = setting(&lookup, "COORDINATION_STREAM_REPLICAS", 3)?;
+        if !(1..=5).contains(&coordination_replicas) {
+            return Err(crate::CrustError::Validation("COORDINATION_STREAM_REPLICAS must be between 1 and 5".to_string()));
+        }
+
+        Ok(Self {
+            default_image: setting(&lookup, "DEFAULT_IMAGE", "ghcr.io/vt-d/bedrock/stratum:latest".to_string())?,
+            resync_interval: secs("RESYNC_INTERVAL_SECS", 600)?,
+            reshard_check_interval: secs("RESHARD_CHECK_INTERVAL_SECS", 1800)?,
+            reshard_window_recheck: secs("RESHARD_WINDOW_RECHECK_SECS", 600)?,
+            reshard_jitter_percent: setting(&lookup, "RESHARD_JITTER_PERCENT", 10)?,
+            max_concurrent_reshards: setting(&lookup, "MAX_CONCURRENT_RESHARDS", 0)?,
+            subject_root,
+            gateway_info_ttl: secs("GATEWAY_INFO_TTL_SECS", 60)?,
+            gateway_info_calls_per_minute: setting(&lookup, "GATEWAY_INFO_CALLS_PER_MINUTE", 10)?,
+            reconcile_concurrency: setting(&lookup, "RECONCILE_CONCURRENCY", 0)?,
+            watch_page_size,
+            error_requeue: secs("ERROR_REQUEUE_SECS", 15)?,
+            max_error_requeue: secs("MAX_ERROR_REQUEUE_SECS", 600)?,
+            dry_run: setting(&lookup, "DRY_RUN", false)?,
+            coordination_stream: bedrock_nats::coordination::CoordinationStreamSettings {
+                max_age: secs("COORDINATION_STREAM_MAX_AGE_SECS", 7 * 24 * 60 * 60)?,
+                max_bytes: setting(&lookup, "COORDINATION_STREAM_MAX_BYTES", -1)?,
+                replicas: coordination_replicas,
+            },
+        })
+    }
+
+    /// The cluster the way the operator runs it: the operator defaults filled
+    /// into its spec, the secret of issued NATS credentials named and, with
+    /// auto_tune, the recommended sizing applied.
+    pub fn resolve(&self, cluster: &ShardCluster) -> ShardCluster {
+        let mut cluster = cluster.clone();
+        if cluster.spec.image.trim().is_empty() {
+            cluster.spec.image = self.default_image.clone();
+        }
+        if cluster.spec.nats_account_signing_secret.is_some() && cluster.spec.nats_credentials_secret.is_none() {
+            cluster.spec.nats_credentials_secret = Some(format!("{}-nats-credentials", cluster.name_any()));
+        }
+
+        let sizing = cluster.status.as_ref().and_then(|status| status.sizing.clone());
+        if let Some(sizing) = sizing.filter(|_| cluster.spec.auto_tune.unwrap_or(false)) {
+            cluster.spec.shards_per_replica = sizing.shards_per_replica;
+            let requests = cluster
+                .spec
+                .resources
+                .get_or_insert_with(Default::default)
+                .requests
+                .get_or_insert_with(Default::default);
+            if let Some(cpu) = sizing.cpu_request {
+                requests.insert("cpu".to_string(), Quantity(cpu));
+            }
+            if let Some(memory) = sizing.memory_request {
+                requests.insert("memory".to_string(), Quantity(memory));
+            }
+        }
+        cluster
+    }
+}
+
+fn setting<T>(lookup: impl Fn(&str) -> Option<String>, key: &str, default: T) -> crate::Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match lookup(key) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|e| crate::CrustError::Validation(format!("Invalid {}: {}", key, e))),
+        None => Ok(default),
+    }
+}
+
+pub type OperatorConfigHandle = Arc<RwLock<OperatorConfig>>;
+
 #[derive(Clone)]
 pub struct Context {
     pub client: kube::Client,
     pub nats_client: async_nats::Client,
+    pub workers: WorkerRegistry,
+    pub shard_statuses: ShardStatusRegistry,
+    pub processor_statuses: ProcessorStatusRegistry,
+    pub reshards: ReshardRegistry,
+    pub startups: StartupRegistry,
+    pub identify: IdentifyRegistry,
+    pub signing_keys: SigningKeyRegistry,
+    pub gateway: GatewayCache,
+    pub failures: FailureRegistry,
+    pub remote_clients: RemoteClientRegistry,
+    pub config: OperatorConfigHandle,
+    pub recorder: Recorder,
+}
+
+impl Context {
+    /// A snapshot of the operator config, which may be reloaded at any time.
+    pub fn config(&self) -> OperatorConfig {
+        self.config.read().expect("operator config poisoned").clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn grant_spaces_identifies_within_a_bucket() {
+        let mut budget = IdentifyBudget::default();
+        budget.update_limits(2, None, at(0));
+
+        let first = budget.grant("worker-0", 0, at(0));
+        let other_bucket = budget.grant("worker-0", 1, at(0));
+        let same_bucket = budget.grant("worker-1", 2, at(0));
+
+        assert!(first.granted && other_bucket.granted && same_bucket.granted);
+        assert_eq!(first.delay_ms, 0);
+        assert_eq!(other_bucket.delay_ms, 0);
+        assert_eq!(same_bucket.delay_ms, IDENTIFY_INTERVAL_MS as u64);
+        assert_eq!(budget.history.len(), 3);
+    }
+
+    #[test]
+    fn grant_holds_off_until_the_session_start_limit_resets() {
+        let mut budget = IdentifyBudget::default();
+        let limit = SessionStartLimit { total: 1000, remaining: 1, reset_after_ms: 60_000 };
+        budget.update_limits(1, Some(limit), at(0));
+
+        assert!(budget.grant("worker-0", 0, at(0)).granted);
+        let refused = budget.grant("worker-0", 1, at(10));
+        assert!(!refused.granted);
+        assert_eq!(refused.delay_ms, 50_000);
+        assert_eq!(refused.reason.as_deref(), Some("session start limit exhausted"));
+
+        assert!(budget.grant("worker-0", 1, at(60)).granted);
+        assert_eq!(budget.session_start_limit.unwrap().remaining, 999);
+    }
+
+    #[test]
+    fn cached_limits_do_not_restore_granted_identifies() {
+        let mut budget = IdentifyBudget::default();
+        let limit = SessionStartLimit { total: 1000, remaining: 10, reset_after_ms: 60_000 };
+        budget.update_limits(1, Some(limit), at(0));
+        assert!(budget.grant("worker-0", 0, at(0)).granted);
+
+        budget.update_limits(1, Some(limit), at(10));
+        assert_eq!(budget.session_start_limit.unwrap().remaining, 9);
+
+        budget.update_limits(1, Some(limit), at(70));
+        assert_eq!(budget.session_start_limit.unwrap().remaining, 10);
+    }
+
+    #[test]
+    fn clusters_sharing_a_token_share_its_budget() {
+        let mut budgets = IdentifyBudgets::default();
+        budgets.update_limits("discord.default.a", "token", 1, None, at(0));
+        budgets.update_limits("discord.default.b", "token", 1, None, at(0));
+
+        assert_eq!(budgets.grant("discord.default.a", "worker-a", 0, at(0)).delay_ms, 0);
+        assert_eq!(budgets.grant("discord.default.b", "worker-b", 0, at(0)).delay_ms, IDENTIFY_INTERVAL_MS as u64);
+
+        budgets.remove("discord.default.a");
+        assert!(budgets.budget("token").is_some());
+        budgets.remove("discord.default.b");
+        assert!(budgets.budget("token").is_none());
+    }
 }
@@ -0,0 +1,558 @@
+//! `bedrock.dev/v2` ShardCluster, which groups the spec fields of v1 by what
+//! they configure. Both versions hold the same settings, so objects convert
+//! between them without loss. v1 stays the storage version and the one the
+//! operator reads, the conversion webhook translates v2 objects on the way.
+
+use crate::types::{
+    self, default_nats_url, default_replicas_per_shard_group, default_reshard_interval_hours,
+    default_shards_per_replica, EventProcessorScaling, EventStream, NatsTlsSecrets, PodTemplateOverlay,
+    RemoteTarget, ReshardStrategy, ReshardWindow, RollingUpdateSettings, ShardClusterStatus, UpdateStrategy, WorkloadKind,
+};
+use k8s_openapi::api::core::v1::{Affinity, ResourceRequirements, Toleration, TopologySpreadConstraint};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(CustomResource, Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[kube(group = "bedrock.dev", version = "v2", kind = "ShardCluster")]
+#[kube(status = "ShardClusterStatus")]
+#[kube(shortname = "sc")]
+#[kube(category = "bedrock")]
+#[kube(namespaced)]
+#[kube(printcolumn = r#"{"name": "Shards", "type": "integer", "jsonPath": ".status.current_shards"}"#)]
+#[kube(printcolumn = r#"{"name": "Ready", "type": "string", "jsonPath": ".status.conditions[?(@.type==\"Ready\")].status"}"#)]
+#[kube(printcolumn = r#"{"name": "Connected", "type": "integer", "jsonPath": ".status.shard_health.connected"}"#)]
+#[kube(printcolumn = r#"{"name": "Stale", "type": "integer", "jsonPath": ".status.shard_health.stale"}"#)]
+#[kube(printcolumn = r#"{"name": "LastReshard", "type": "date", "jsonPath": ".status.last_reshard"}"#)]
+#[kube(printcolumn = r#"{"name": "Age", "type": "date", "jsonPath": ".metadata.creationTimestamp"}"#)]
+#[kube(validation = Rule::new(
+    "!has(self.spec.workload) || !has(self.spec.workload.kind) || self.spec.workload.kind != 'StatefulSet' || !has(self.spec.sharding) || self.spec.sharding.replicas_per_shard_group == 1"
+).message("sharding.replicas_per_shard_group must be 1 with workload.kind StatefulSet"))]
+pub struct ShardClusterSpec {
+    /// Stop reconciling, resharding and touching the deployments of this
+    /// cluster until it is set back to false
+    #[serde(default)]
+    pub suspend: Option<bool>,
+    /// Name of the Kubernetes secret containing the Discord bot token
+    pub discord_token_secret: String,
+    /// Docker image for the stratum bot instances, empty for the operator's
+    /// DEFAULT_IMAGE
+    #[serde(default)]
+    pub image: String,
+    /// How the shards are split into shard groups
+    #[serde(default)]
+    pub sharding: Sharding,
+    /// When and how the cluster moves to a new shard count
+    #[serde(default)]
+    pub resharding: Resharding,
+    /// What the shards identify with and publish
+    #[serde(default)]
+    pub gateway: Gateway,
+    /// NATS server, credentials and streams of the cluster
+    #[serde(default)]
+    pub nats: Nats,
+    /// How unhealthy workers are dealt with
+    #[serde(default)]
+    pub health: Health,
+    /// The workloads running the shard groups
+    #[serde(default)]
+    pub workload: Workload,
+    /// Scale an event processor deployment such as mantle on the backlog of
+    /// its JetStream consumer with a KEDA ScaledObject
+    #[serde(default)]
+    pub event_processor_scaling: Option<EventProcessorScaling>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Sharding {
+    /// Number of shards per replica
+    #[serde(default = "default_shards_per_replica")]
+    #[schemars(range(min = 1))]
+    pub shards_per_replica: u32,
+    /// Number of replicas per shard group. Beyond the first, replicas stand
+    /// by and take over the group's shards when the active one goes away
+    #[serde(default = "default_replicas_per_shard_group")]
+    #[schemars(range(min = 1))]
+    pub replicas_per_shard_group: i32,
+    /// Fixed shard count to run instead of Discord's recommendation. Must be a
+    /// multiple of the bot's max_concurrency
+    #[serde(default)]
+    #[schemars(range(min = 1))]
+    pub shard_count_override: Option<u32>,
+    /// Run with the shards_per_replica and resource requests recommended from
+    /// the observed load in status.sizing instead of the ones set here
+    #[serde(default)]
+    pub auto_tune: Option<bool>,
+    /// Events per second a replica is sized for when recommending
+    /// shards_per_replica (default 500)
+    #[serde(default)]
+    #[schemars(range(min = 1))]
+    pub target_replica_events_per_sec: Option<u32>,
+}
+
+impl Default for Sharding {
+    fn default() -> Self {
+        Self {
+            shards_per_replica: default_shards_per_replica(),
+            replicas_per_shard_group: default_replicas_per_shard_group(),
+            shard_count_override: None,
+            auto_tune: None,
+            target_replica_events_per_sec: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Resharding {
+    /// Interval in hours between automatic reshards
+    #[serde(default = "default_reshard_interval_hours")]
+    #[schemars(range(min = 1))]
+    pub interval_hours: u64,
+    /// Only reshard when Discord's recommended shard count differs from the
+    /// current one by more than this percentage
+    #[serde(default)]
+    pub threshold_percent: Option<u32>,
+    /// Daily window reshards are limited to, outside of it they wait
+    #[serde(default)]
+    pub window: Option<ReshardWindow>,
+    /// How the cluster moves to a new shard count, InPlace (default) or BlueGreen
+    #[serde(default)]
+    pub strategy: Option<ReshardStrategy>,
+}
+
+impl Default for Resharding {
+    fn default() -> Self {
+        Self {
+            interval_hours: default_reshard_interval_hours(),
+            threshold_percent: None,
+            window: None,
+            strategy: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct Gateway {
+    /// Gateway intents the shards identify with, as presets (`default`,
+    /// `all_unprivileged`, `all`) or intent names such as `GUILD_MEMBERS`
+    #[serde(default)]
+    pub intents: Option<Vec<String>>,
+    /// Allow privileged intents, which have to be enabled for the application
+    /// in the developer portal first
+    #[serde(default)]
+    pub allow_privileged_intents: Option<bool>,
+    /// Dispatch event types (such as `TYPING_START`) that are not published
+    #[serde(default)]
+    pub event_filter: Option<Vec<String>>,
+    /// Gateway proxy the shards connect through instead of Discord's gateway
+    #[serde(default)]
+    pub proxy_url: Option<String>,
+    /// HTTP proxy for Discord API requests, instead of the
+    /// twilight-gateway-proxy service in the bedrock namespace
+    #[serde(default)]
+    pub http_proxy_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Nats {
+    /// URL for the NATS server: nats://, tls://, or ws:// and wss:// for NATS
+    /// over WebSocket
+    #[serde(default = "default_nats_url")]
+    pub url: String,
+    /// Name of a secret whose 'creds' entry is the NATS credentials file the
+    /// stratum pods authenticate with
+    #[serde(default)]
+    pub credentials_secret: Option<String>,
+    /// Name of a secret whose 'seed' entry is a NATS account signing key, and
+    /// whose optional 'account' entry is the account's public key
+    #[serde(default)]
+    pub account_signing_secret: Option<String>,
+    /// Name of a secret with a 'token', an 'nkey' seed, or a 'user' and
+    /// 'password' entry the stratum pods authenticate with
+    #[serde(default)]
+    pub auth_secret: Option<String>,
+    /// Secrets with the CA and client certificate the stratum pods use for
+    /// TLS
+    #[serde(default)]
+    pub tls: Option<NatsTlsSecrets>,
+    /// JetStream domain the cluster's streams and KV buckets live in
+    #[serde(default)]
+    pub jetstream_domain: Option<String>,
+    /// Name of a secret whose 'key' entry is used to HMAC-sign coordination messages
+    #[serde(default)]
+    pub coordination_signing_secret: Option<String>,
+    /// JetStream stream of the cluster's events, created and updated by the
+    /// operator
+    #[serde(default)]
+    pub event_stream: Option<EventStream>,
+}
+
+impl Default for Nats {
+    fn default() -> Self {
+        Self {
+            url: default_nats_url(),
+            credentials_secret: None,
+            account_signing_secret: None,
+            auth_secret: None,
+            tls: None,
+            jetstream_domain: None,
+            coordination_signing_secret: None,
+            event_stream: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct Health {
+    /// Restart shard group deployments whose workers stop sending heartbeats
+    #[serde(default)]
+    pub restart_stale_workers: Option<bool>,
+    /// Restart shard groups whose pods are ready but whose shards have sent no
+    /// status for this many seconds
+    #[serde(default)]
+    pub restart_silent_groups_after_secs: Option<u64>,
+    /// Reassign shards of workers that stop sending heartbeats to the remaining workers
+    #[serde(default)]
+    pub dynamic_rebalancing: Option<bool>,
+    /// Seconds a worker may spend handing off shards on SIGTERM before it exits
+    #[serde(default)]
+    #[schemars(range(min = 1))]
+    pub drain_timeout_seconds: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct Workload {
+    /// Workload the shard groups run as, Deployment (default) or StatefulSet
+    #[serde(default)]
+    pub kind: Option<WorkloadKind>,
+    /// How image changes are rolled out to the shard groups
+    #[serde(default)]
+    pub update_strategy: Option<UpdateStrategy>,
+    /// Rolling update settings of the generated deployments
+    #[serde(default)]
+    pub rolling_update: Option<RollingUpdateSettings>,
+    /// CPU and memory requests and limits for the stratum containers
+    #[serde(default)]
+    pub resources: Option<ResourceRequirements>,
+    /// Labels a node must carry for stratum pods to be scheduled on it
+    #[serde(default)]
+    pub node_selector: Option<BTreeMap<String, String>>,
+    /// Tolerations added to the stratum pods
+    #[serde(default)]
+    pub tolerations: Option<Vec<Toleration>>,
+    /// Node and pod affinity or anti-affinity for the stratum pods
+    #[serde(default)]
+    pub affinity: Option<Affinity>,
+    /// Constraints for spreading shard group pods across zones or nodes
+    #[serde(default)]
+    pub topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+    /// Priority class of the stratum pods
+    #[serde(default)]
+    pub priority_class_name: Option<String>,
+    /// Extra settings merged into the generated pod template
+    #[serde(default)]
+    pub pod_template: Option<PodTemplateOverlay>,
+    /// Create a NetworkPolicy that only lets stratum pods reach DNS, Discord and NATS
+    #[serde(default)]
+    pub network_policy: Option<bool>,
+    /// Create a metrics Service and a Prometheus Operator ServiceMonitor for
+    /// the stratum pods (the image has to be built with the `admin` feature)
+    #[serde(default)]
+    pub service_monitor: Option<bool>,
+    /// Run the shard groups in another Kubernetes cluster instead of this one
+    #[serde(default)]
+    pub remote: Option<RemoteTarget>,
+}
+
+impl From<types::ShardClusterSpec> for ShardClusterSpec {
+    fn from(spec: types::ShardClusterSpec) -> Self {
+        Self {
+            suspend: spec.suspend,
+            discord_token_secret: spec.discord_token_secret,
+            image: spec.image,
+            sharding: Sharding {
+                shards_per_replica: spec.shards_per_replica,
+                replicas_per_shard_group: spec.replicas_per_shard_group,
+                shard_count_override: spec.shard_count_override,
+                auto_tune: spec.auto_tune,
+                target_replica_events_per_sec: spec.target_replica_events_per_sec,
+            },
+            resharding: Resharding {
+                interval_hours: spec.reshard_interval_hours,
+                threshold_percent: spec.reshard_threshold_percent,
+                window: spec.reshard_window,
+                strategy: spec.reshard_strategy,
+            },
+            gateway: Gateway {
+                intents: spec.intents,
+                allow_privileged_intents: spec.allow_privileged_intents,
+                event_filter: spec.event_filter,
+                proxy_url: spec.gateway_proxy_url,
+                http_proxy_url: spec.http_proxy_url,
+            },
+            nats: Nats {
+                url: spec.nats_url,
+                credentials_secret: spec.nats_credentials_secret,
+                account_signing_secret: spec.nats_account_signing_secret,
+                auth_secret: spec.nats_auth_secret,
+                tls: spec.nats_tls,
+                jetstream_domain: spec.nats_jetstream_domain,
+                coordination_signing_secret: spec.coordination_signing_secret,
+                event_stream: spec.event_stream,
+            },
+            health: Health {
+                restart_stale_workers: spec.restart_stale_workers,
+                restart_silent_groups_after_secs: spec.restart_silent_groups_after_secs,
+                dynamic_rebalancing: spec.dynamic_rebalancing,
+                drain_timeout_seconds: spec.drain_timeout_seconds,
+            },
+            workload: Workload {
+                kind: spec.workload_kind,
+                update_strategy: spec.update_strategy,
+                rolling_update: spec.rolling_update,
+                resources: spec.resources,
+                node_selector: spec.node_selector,
+                tolerations: spec.tolerations,
+                affinity: spec.affinity,
+                topology_spread_constraints: spec.topology_spread_constraints,
+                priority_class_name: spec.priority_class_name,
+                pod_template: spec.pod_template,
+                network_policy: spec.network_policy,
+                service_monitor: spec.service_monitor,
+                remote: spec.remote,
+            },
+            event_processor_scaling: spec.event_processor_scaling,
+        }
+    }
+}
+
+impl From<ShardClusterSpec> for types::ShardClusterSpec {
+    fn from(spec: ShardClusterSpec) -> Self {
+        let ShardClusterSpec { suspend, discord_token_secret, image, sharding, resharding, gateway, nats, health, workload, event_processor_scaling } = spec;
+        Self {
+            suspend,
+            discord_token_secret,
+            nats_url: nats.url,
+            nats_credentials_secret: nats.credentials_secret,
+            nats_account_signing_secret: nats.account_signing_secret,
+            nats_auth_secret: nats.auth_secret,
+            nats_tls: nats.tls,
+            nats_jetstream_domain: nats.jetstream_domain,
+            image,
+            replicas_per_shard_group: sharding.replicas_per_shard_group,
+            shards_per_replica: sharding.shards_per_replica,
+            reshard_interval_hours: resharding.interval_hours,
+            intents: gateway.intents,
+            allow_privileged_intents: gateway.allow_privileged_intents,
+            event_filter: gateway.event_filter,
+            gateway_proxy_url: gateway.proxy_url,
+            http_proxy_url: gateway.http_proxy_url,
+            event_stream: nats.event_stream,
+            restart_stale_workers: health.restart_stale_workers,
+            restart_silent_groups_after_secs: health.restart_silent_groups_after_secs,
+            coordination_signing_secret: nats.coordination_signing_secret,
+            dynamic_rebalancing: health.dynamic_rebalancing,
+            drain_timeout_seconds: health.drain_timeout_seconds,
+            resources: workload.resources,
+            auto_tune: sharding.auto_tune,
+            target_replica_events_per_sec: sharding.target_replica_events_per_sec,
+            node_selector: workload.node_selector,
+            tolerations: workload.tolerations,
+            affinity: workload.affinity,
+            topology_spread_constraints: workload.topology_spread_constraints,
+            priority_class_name: workload.priority_class_name,
+            network_policy: workload.network_policy,
+            service_monitor: workload.service_monitor,
+            event_processor_scaling,
+            reshard_threshold_percent: resharding.threshold_percent,
+            shard_count_override: sharding.shard_count_override,
+            reshard_window: resharding.window,
+            reshard_strategy: resharding.strategy,
+            update_strategy: workload.update_strategy,
+            rolling_update: workload.rolling_update,
+            workload_kind: workload.kind,
+            remote: workload.remote,
+            pod_template: workload.pod_template,
+        }
+    }
+}
+
+/// Converts a ShardCluster object of either version to `desired_api_version`,
+/// keeping its metadata and status.
+pub fn convert(object: serde_json::Value, desired_api_version: &str) -> Result<serde_json::Value, String> {
+    let api_version = object["apiVersion"].as_str().unwrap_or_default().to_string();
+    let mut converted = object.clone();
+    let spec = object.get("spec").cloned().unwrap_or_default();
+
+    let spec = match (api_version.as_str(), desired_api_version) {
+        (from, to) if from == to => return Ok(object),
+        ("bedrock.dev/v1", "bedrock.dev/v2") => {
+            let spec: types::ShardClusterSpec = serde_json::from_value(spec).map_err(|e| format!("Invalid v1 spec: {}", e))?;
+            serde_json::to_value(ShardClusterSpec::from(spec))
+        }
+        ("bedrock.dev/v2", "bedrock.dev/v1") => {
+            let spec: ShardClusterSpec = serde_json::from_value(spec).map_err(|e| format!("Invalid v2 spec: {}", e))?;
+            serde_json::to_value(types::ShardClusterSpec::from(spec))
+        }
+        (from, to) => return Err(format!("Cannot convert ShardCluster from {} to {}", from, to)),
+    }
+    .map_err(|e| e.to_string())?;
+
+    converted["apiVersion"] = desired_api_version.into();
+    converted["spec"] = spec;
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// A v1 spec with every field set, so a field either version drops shows
+    /// up in the round trip.
+    fn full_v1_spec() -> Value {
+        let event_stream = json!({
+            "name": "events",
+            "subjects": ["discord.default.bot.shards.>"],
+            "retention": "Interest",
+            "max_age_secs": 3600,
+            "max_bytes": 1048576,
+            "max_messages": 50000,
+            "discard": "New",
+            "replicas": 3,
+            "storage": "Memory",
+            "duplicate_window_secs": 60,
+            "partitions": 1,
+            "mirrors": [{
+                "name": "events-copy",
+                "domain": "edge",
+                "mode": "Mirror",
+                "max_age_secs": 600,
+                "max_bytes": 4096,
+                "replicas": 1,
+                "storage": "File"
+            }]
+        });
+        let event_processor_scaling = json!({
+            "deployment": "mantle",
+            "monitoring_endpoint": "nats:8222",
+            "stream": "events",
+            "consumer": "mantle",
+            "account": "bots",
+            "lag_threshold": 100,
+            "min_replicas": 1,
+            "max_replicas": 10
+        });
+        let pod_template = json!({
+            "env": [{ "name": "RUST_LOG", "value": "debug" }],
+            "volumes": [{ "name": "scratch", "emptyDir": {} }],
+            "volume_mounts": [{ "name": "scratch", "mountPath": "/scratch" }],
+            "sidecars": [{ "name": "proxy", "image": "envoyproxy/envoy:v1.30" }],
+            "annotations": { "team": "bots" },
+            "service_account_name": "stratum"
+        });
+
+        let mut spec = json!({
+            "suspend": true,
+            "discord_token_secret": "discord-token",
+            "nats_url": "tls://nats.example:4222",
+            "nats_credentials_secret": "nats-creds",
+            "nats_account_signing_secret": "nats-account",
+            "nats_auth_secret": "nats-auth",
+            "nats_tls": { "ca_secret": "nats-ca", "client_certificate_secret": "nats-client" },
+            "nats_jetstream_domain": "hub",
+            "image": "ghcr.io/vt-d/stratum:1.2.3",
+            "replicas_per_shard_group": 2,
+            "shards_per_replica": 8,
+            "reshard_interval_hours": 12,
+            "intents": ["GUILDS", "GUILD_MESSAGES"],
+            "allow_privileged_intents": true,
+            "event_filter": ["TYPING_START"],
+            "gateway_proxy_url": "http://gateway-proxy:7878",
+            "http_proxy_url": "http://http-proxy:3000",
+            "event_stream": event_stream
+        });
+        // Split in two, the whole spec is too deep for one json! call.
+        let workload = json!({
+            "restart_stale_workers": true,
+            "restart_silent_groups_after_secs": 300,
+            "coordination_signing_secret": "signing-key",
+            "dynamic_rebalancing": true,
+            "drain_timeout_seconds": 45,
+            "resources": { "limits": { "cpu": "1", "memory": "512Mi" } },
+            "auto_tune": true,
+            "target_replica_events_per_sec": 500,
+            "node_selector": { "pool": "gateway" },
+            "tolerations": [{ "key": "dedicated", "operator": "Exists" }],
+            "affinity": { "podAntiAffinity": {} },
+            "topology_spread_constraints": [{
+                "maxSkew": 1,
+                "topologyKey": "topology.kubernetes.io/zone",
+                "whenUnsatisfiable": "ScheduleAnyway"
+            }],
+            "priority_class_name": "gateway",
+            "network_policy": true,
+            "service_monitor": true,
+            "event_processor_scaling": event_processor_scaling,
+            "reshard_threshold_percent": 20,
+            "shard_count_override": 16,
+            "reshard_window": {
+                "start": "02:00",
+                "end": "04:00",
+                "utc_offset": "+02:00",
+                "days": ["Sat", "Sun"]
+            },
+            "reshard_strategy": "BlueGreen",
+            "update_strategy": { "canary_groups": 1, "soak_seconds": 120 },
+            "rolling_update": {
+                "max_unavailable": 1,
+                "max_surge": "25%",
+                "min_ready_seconds": 10,
+                "revision_history_limit": 3
+            },
+            "workload_kind": "StatefulSet",
+            "remote": { "kubeconfig_secret": "region-b", "namespace": "gateway" },
+            "pod_template": pod_template
+        });
+        spec.as_object_mut().unwrap().extend(workload.as_object().unwrap().clone());
+        spec
+    }
+
+    fn assert_no_nulls(value: &Value, path: &str) {
+        match value {
+            Value::Null => panic!("{} is not set", path),
+            Value::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    assert_no_nulls(item, &format!("{}[{}]", path, index));
+                }
+            }
+            Value::Object(fields) => {
+                for (name, field) in fields {
+                    assert_no_nulls(field, &format!("{}.{}", path, name));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn v1_spec_survives_a_round_trip_through_v2() {
+        let spec: types::ShardClusterSpec = serde_json::from_value(full_v1_spec()).unwrap();
+        let spec = serde_json::to_value(spec).unwrap();
+        assert_no_nulls(&spec, "spec");
+
+        let object = json!({
+            "apiVersion": "bedrock.dev/v1",
+            "kind": "ShardCluster",
+            "metadata": { "name": "bot", "namespace": "default" },
+            "spec": spec,
+            "status": { "current_shards": 16 }
+        });
+        let v2 = convert(object.clone(), "bedrock.dev/v2").unwrap();
+        assert_eq!(v2["apiVersion"], "bedrock.dev/v2");
+        let v1 = convert(v2, "bedrock.dev/v1").unwrap();
+
+        assert_eq!(v1, object);
+    }
+}
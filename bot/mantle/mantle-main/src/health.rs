@@ -0,0 +1,145 @@
+use bedrock_nats::events::ConnectionEvents;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_REPORT_INTERVAL_SECS: u64 = 15;
+const DEGRADED_ERROR_RATE: f64 = 0.05;
+
+#[derive(Default)]
+pub struct ProcessorHealth {
+    processed: AtomicU64,
+    failed: AtomicU64,
+    panics: AtomicU64,
+}
+
+impl ProcessorHealth {
+    pub fn record_success(&self) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_failure(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_panic(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        self.panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> (u64, u64, u64) {
+        (
+            self.processed.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+            self.panics.load(Ordering::Relaxed),
+        )
+    }
+}
+
+pub struct HealthReporter {
+    nats: async_nats::Client,
+    consumer: async_nats::jetstream::consumer::PullConsumer,
+    health: Arc<ProcessorHealth>,
+    nats_events: Arc<ConnectionEvents>,
+    subject: String,
+    cluster: String,
+    processor_id: String,
+    interval: Duration,
+}
+
+impl HealthReporter {
+    pub fn from_env(
+        nats: async_nats::Client,
+        consumer: async_nats::jetstream::consumer::PullConsumer,
+        health: Arc<ProcessorHealth>,
+        nats_events: Arc<ConnectionEvents>,
+    ) -> Self {
+        let cluster = std::env::var("BEDROCK_CLUSTER").unwrap_or_else(|_| "default".to_string());
+        let processor_id = std::env::var("PROCESSOR_ID")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| "mantle".to_string());
+        let interval = std::env::var("HEALTH_REPORT_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_REPORT_INTERVAL_SECS);
+
+        Self {
+            nats,
+            consumer,
+            health,
+            nats_events,
+            subject: format!("bedrock.{}.processors.{}.status", cluster, processor_id),
+            cluster,
+            processor_id,
+            interval: Duration::from_secs(interval),
+        }
+    }
+
+    pub async fn run(mut self) {
+        let started = Instant::now();
+        let mut ticker = tokio::time::interval(self.interval);
+        let mut previous = self.health.snapshot();
+
+        loop {
+            ticker.tick().await;
+
+            let current = self.health.snapshot();
+            let processed = current.0 - previous.0;
+            let failed = current.1 - previous.1;
+            let panics = current.2 - previous.2;
+            previous = current;
+
+            let error_rate = if processed + failed == 0 {
+                0.0
+            } else {
+                failed as f64 / (processed + failed) as f64
+            };
+
+            let (lag, ack_pending) = match self.consumer.info().await {
+                Ok(info) => (Some(info.num_pending), Some(info.num_ack_pending)),
+                Err(e) => {
+                    eprintln!("Failed to fetch consumer info for health report: {}", e);
+                    (None, None)
+                }
+            };
+
+            let status = if panics > 0 || error_rate > DEGRADED_ERROR_RATE {
+                "degraded"
+            } else {
+                "healthy"
+            };
+
+            let report = serde_json::json!({
+                "event": "processor_status",
+                "cluster": self.cluster,
+                "processor_id": self.processor_id,
+                "status": status,
+                "lag": lag,
+                "ack_pending": ack_pending,
+                "processed": processed,
+                "failed": failed,
+                "error_rate": error_rate,
+                "handler_panics": panics,
+                "handler_panics_total": current.2,
+                "nats_disconnects_total": self.nats_events.disconnects(),
+                "nats_reconnects_total": self.nats_events.reconnects(),
+                "nats_slow_consumers_total": self.nats_events.slow_consumers(),
+                "nats_errors_total": self.nats_events.errors(),
+                "interval_secs": self.interval.as_secs(),
+                "uptime_secs": started.elapsed().as_secs(),
+                "timestamp": SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            });
+
+            if let Err(e) = self
+                .nats
+                .publish(self.subject.clone(), report.to_string().into())
+                .await
+            {
+                eprintln!("Failed to publish health report: {}", e);
+            }
+        }
+    }
+}
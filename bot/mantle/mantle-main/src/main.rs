@@ -1,13 +1,30 @@
-use futures::StreamExt;
+mod health;
+mod metrics;
+
+use futures::{FutureExt, StreamExt};
+use bedrock_nats::lag::LagMonitor;
+use health::{HealthReporter, ProcessorHealth};
 use serde::de::DeserializeSeed;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
 use twilight_model::gateway::event::GatewayEventDeserializer;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let nats = async_nats::connect("nats://localhost:4222").await?;
-    let jetstream = async_nats::jetstream::new(nats);
-    
-    let consumer = jetstream
+    // Authentication, TLS and the JetStream API come from the same
+    // environment variables stratum reads.
+    let connection = bedrock_nats::ConnectionBuilder::from_env()?.connect().await?;
+
+    // A processor consumes one partition of a partitioned events stream,
+    // named by EVENT_PARTITION.
+    let event_stream = std::env::var("EVENT_STREAM").unwrap_or_else(|_| "discord-events".to_string());
+    let event_stream = match std::env::var("EVENT_PARTITION") {
+        Ok(partition) if !partition.is_empty() => bedrock_nats::partitions::stream_name(&event_stream, partition.parse()?),
+        _ => event_stream,
+    };
+
+    let consumer = connection
+        .jetstream()
         .create_consumer_on_stream(
             async_nats::jetstream::consumer::pull::Config {
                 durable_name: Some("mantle-processors".to_string()),
@@ -16,25 +33,64 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 max_deliver: 3,
                 ..Default::default()
             },
-            "discord-events",
+            event_stream.as_str(),
         )
         .await?;
 
+    // The processors' backlog, and that of any other consumers in
+    // LAG_CONSUMERS, is published on bedrock.<cluster>.consumers.lag and
+    // served on METRICS_ADDR when it is set.
+    let cluster = std::env::var("BEDROCK_CLUSTER").unwrap_or_else(|_| "default".to_string());
+    let lag = LagMonitor::from_env(connection.jetstream().clone())?
+        .consumer(event_stream.as_str(), "mantle-processors")
+        .publish_to(connection.client().clone(), format!("bedrock.{}.consumers.lag", cluster));
+    tokio::spawn(lag.clone().run());
+    if let Ok(addr) = std::env::var("METRICS_ADDR") {
+        let addr = addr.parse()?;
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(addr, lag).await {
+                eprintln!("Metrics server failed: {}", e);
+            }
+        });
+    }
+
+    let health = Arc::new(ProcessorHealth::default());
+    let nats_events = connection.events().clone();
+    tokio::spawn(HealthReporter::from_env(connection.into_client(), consumer.clone(), health.clone(), nats_events).run());
+
     println!("Mantle processor started, waiting for events...");
 
     let mut messages = consumer.messages().await?;
     while let Some(message) = messages.next().await {
         match message {
             Ok(msg) => {
-                if let Err(e) = process_discord_event(&msg.payload).await {
-                    eprintln!("Failed to process event: {}", e);
+                let result = AssertUnwindSafe(process_discord_event(&msg.payload))
+                    .catch_unwind()
+                    .await;
+
+                let failed = match result {
+                    Ok(Ok(())) => {
+                        health.record_success();
+                        false
+                    }
+                    Ok(Err(e)) => {
+                        eprintln!("Failed to process event: {}", e);
+                        health.record_failure();
+                        true
+                    }
+                    Err(_) => {
+                        eprintln!("Event handler panicked");
+                        health.record_panic();
+                        true
+                    }
+                };
+
+                if failed {
                     if let Err(ack_err) = msg.ack_with(async_nats::jetstream::AckKind::Nak(None)).await {
                         eprintln!("Failed to NAK message: {}", ack_err);
                     }
-                } else {
-                    if let Err(ack_err) = msg.ack().await {
-                        eprintln!("Failed to ACK message: {}", ack_err);
-                    }
+                } else if let Err(ack_err) = msg.ack().await {
+                    eprintln!("Failed to ACK message: {}", ack_err);
                 }
             }
             Err(e) => {
@@ -0,0 +1,30 @@
+use bedrock_nats::lag::LagMonitor;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Answers every request on `addr` with the consumer lag metrics, for
+/// Prometheus to scrape.
+pub async fn serve(addr: SocketAddr, lag: LagMonitor) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    println!("Metrics listening on {}", addr);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let body = lag.render_metrics();
+
+        tokio::spawn(async move {
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).await;
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: text/plain; version=0.0.4\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            if let Err(e) = stream.write_all(response.as_bytes()).await {
+                eprintln!("Failed to write metrics response: {}", e);
+            }
+        });
+    }
+}
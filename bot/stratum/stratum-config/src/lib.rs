@@ -1,58 +1,677 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use bedrock_nats::coordination::CoordinationStreamSettings;
+use bedrock_nats::kv::BucketSettings;
+use bedrock_nats::{Auth, ConnectionBuilder, JetStreamApi, Tls};
+use std::str::FromStr;
+use std::time::Duration;
 use tracing::info;
+use twilight_model::gateway::Intents;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    ExitWorker,
+    MarkFailed,
+}
+
+impl FromStr for FailureAction {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "exit" => Ok(Self::ExitWorker),
+            "mark-failed" => Ok(Self::MarkFailed),
+            other => bail!("unknown failure action '{}', expected 'exit' or 'mark-failed'", other),
+        }
+    }
+}
+
+/// What a runner does with an event when the publish rate limit is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Queue the event until a token is free, and stop reading from the
+    /// gateway while the queue is full.
+    Block,
+    /// Drop the event and count it.
+    Drop,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "block" => Ok(Self::Block),
+            "drop" => Ok(Self::Drop),
+            other => bail!("unknown overflow policy '{}', expected 'block' or 'drop'", other),
+        }
+    }
+}
+
+/// When the events stream lets go of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamRetention {
+    /// Keep messages until a limit is reached.
+    Limits,
+    /// Keep messages until every consumer acknowledged them.
+    Interest,
+    /// Keep messages until one consumer acknowledged them.
+    WorkQueue,
+}
+
+impl FromStr for StreamRetention {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "limits" => Ok(Self::Limits),
+            "interest" => Ok(Self::Interest),
+            "workqueue" => Ok(Self::WorkQueue),
+            other => bail!("unknown retention '{}', expected 'limits', 'interest' or 'workqueue'", other),
+        }
+    }
+}
+
+/// What the events stream does once a limit is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamDiscard {
+    /// Drop the oldest messages to make room.
+    Old,
+    /// Refuse new messages, so publishes fail instead of losing backlog.
+    New,
+}
+
+impl FromStr for StreamDiscard {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "old" => Ok(Self::Old),
+            "new" => Ok(Self::New),
+            other => bail!("unknown discard policy '{}', expected 'old' or 'new'", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamStorage {
+    File,
+    Memory,
+}
+
+impl FromStr for StreamStorage {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "file" => Ok(Self::File),
+            "memory" => Ok(Self::Memory),
+            other => bail!("unknown storage type '{}', expected 'file' or 'memory'", other),
+        }
+    }
+}
+
+/// Settings of the events stream a worker creates when the operator does not
+/// provision it. Limits of 0 or below mean unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamSettings {
+    pub retention: StreamRetention,
+    pub max_age_secs: u64,
+    pub max_bytes: i64,
+    pub max_messages: i64,
+    pub discard: StreamDiscard,
+    pub storage: StreamStorage,
+    pub replicas: usize,
+    /// How long the stream remembers Nats-Msg-Ids to drop republished events
+    pub duplicate_window_secs: u64,
+    /// Streams the events are split over by shard id, 1 for a single stream
+    pub partitions: u32,
+}
+
+impl StreamSettings {
+    fn from_env() -> Result<Self> {
+        Ok(Self {
+            retention: std::env::var("STREAM_RETENTION")
+                .unwrap_or_else(|_| "limits".to_string())
+                .parse()
+                .context("STREAM_RETENTION is invalid")?,
+            max_age_secs: std::env::var("STREAM_MAX_AGE_SECS")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .context("STREAM_MAX_AGE_SECS must be a non-negative integer")?,
+            max_bytes: std::env::var("STREAM_MAX_BYTES")
+                .unwrap_or_else(|_| "-1".to_string())
+                .parse()
+                .context("STREAM_MAX_BYTES must be an integer")?,
+            max_messages: std::env::var("STREAM_MAX_MESSAGES")
+                .unwrap_or_else(|_| "10000".to_string())
+                .parse()
+                .context("STREAM_MAX_MESSAGES must be an integer")?,
+            discard: std::env::var("STREAM_DISCARD")
+                .unwrap_or_else(|_| "old".to_string())
+                .parse()
+                .context("STREAM_DISCARD is invalid")?,
+            storage: std::env::var("STREAM_STORAGE")
+                .unwrap_or_else(|_| "file".to_string())
+                .parse()
+                .context("STREAM_STORAGE is invalid")?,
+            replicas: std::env::var("STREAM_REPLICAS")
+                .unwrap_or_else(|_| "1".to_string())
+                .parse()
+                .context("STREAM_REPLICAS must be a positive integer")?,
+            duplicate_window_secs: std::env::var("STREAM_DUPLICATE_WINDOW_SECS")
+                .unwrap_or_else(|_| "120".to_string())
+                .parse()
+                .context("STREAM_DUPLICATE_WINDOW_SECS must be a positive integer")?,
+            partitions: std::env::var("STREAM_PARTITIONS")
+                .unwrap_or_else(|_| "1".to_string())
+                .parse()
+                .context("STREAM_PARTITIONS must be a positive integer")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardAssignment {
+    Static,
+    Ordinal { ordinal: u32, shards_per_replica: u32 },
+}
+
+/// Intents that have to be enabled for the application in the developer
+/// portal before Discord accepts them.
+pub const PRIVILEGED_INTENTS: Intents = Intents::GUILD_MEMBERS
+    .union(Intents::GUILD_PRESENCES)
+    .union(Intents::MESSAGE_CONTENT);
+
+/// Parses INTENTS: a comma separated list of presets (`default`,
+/// `all_unprivileged`, `all`) and intent names such as `GUILD_MEMBERS`.
+pub fn parse_intents(value: &str) -> Result<Intents> {
+    let mut intents = Intents::empty();
+
+    for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        intents |= match name {
+            "default" => Intents::GUILD_MESSAGES,
+            "all_unprivileged" => Intents::all().difference(PRIVILEGED_INTENTS),
+            "all" => Intents::all(),
+            flag => Intents::from_name(&flag.to_ascii_uppercase())
+                .with_context(|| format!("unknown intent '{}'", flag))?,
+        };
+    }
+
+    if intents.is_empty() {
+        bail!("no intents selected");
+    }
+
+    Ok(intents)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartupDelay {
+    /// Start immediately without asking the operator for startup permission.
+    None,
+    /// Wait `ordinal * interval` before starting so replicas identify in turn.
+    Ordinal { ordinal: u32, interval: Duration },
+    /// Start immediately and pace identifies through operator startup grants.
+    Operator,
+}
+
+impl StartupDelay {
+    pub fn delay(&self) -> Duration {
+        match self {
+            Self::Ordinal { ordinal, interval } => *interval * *ordinal,
+            Self::None | Self::Operator => Duration::ZERO,
+        }
+    }
+
+    pub fn requests_permission(&self) -> bool {
+        !matches!(self, Self::None)
+    }
+}
 
 #[derive(Clone)]
 pub struct Config {
     pub nats_url: String,
+    pub nats_auth: Auth,
+    pub nats_tls: Tls,
+    pub jetstream_api: JetStreamApi,
+    pub subject_prefix: String,
     pub discord_token: String,
     pub shard_id_start: u32,
     pub shard_id_end: u32,
     pub total_shards: u32,
+    pub shard_assignment: ShardAssignment,
+    pub intents: Intents,
+    pub allow_privileged_intents: bool,
+    pub event_filter: Vec<String>,
+    pub startup_delay: StartupDelay,
+    pub standalone: bool,
+    pub auto_total_shards: bool,
     pub worker_id: String,
     pub max_concurrency: u32,
+    pub startup_permission_timeout_secs: u64,
+    pub heartbeat_interval_secs: u64,
+    pub instance_id: String,
+    pub handoff_timeout_secs: u64,
+    pub coordination_signing_key: Option<String>,
+    pub restart_max_attempts: u32,
+    pub restart_backoff_base_secs: u64,
+    pub restart_backoff_max_secs: u64,
+    pub restart_failure_action: FailureAction,
+    pub coordination_resubscribe_attempts: u32,
+    pub admin_addr: std::net::SocketAddr,
+    pub drain_timeout_secs: u64,
+    pub publish_rate_limit: u32,
+    pub publish_burst: u32,
+    pub publish_overflow: OverflowPolicy,
+    /// Only the replica holding the group lease connects shards, the others
+    /// wait to take over.
+    pub active_standby: bool,
+    pub lease_ttl_secs: u64,
+    /// Gateway proxy shards connect through instead of Discord's gateway
+    pub gateway_url: Option<String>,
+    /// Events stream the operator provisions, created by the worker when unset
+    pub event_stream: Option<String>,
+    /// Settings the worker creates and updates its own events stream with
+    pub stream_settings: StreamSettings,
+    /// Retention and replicas of the coordination stream the worker creates
+    /// when the operator does not
+    pub coordination_stream: CoordinationStreamSettings,
+    /// History and TTL of the KV buckets of resumable sessions, shard states
+    /// and shard overrides
+    pub session_bucket: BucketSettings,
+    pub shard_status_bucket: BucketSettings,
+    pub override_bucket: BucketSettings,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self> {
         let nats_url =
             std::env::var("NATS_URL").unwrap_or_else(|_| "nats://localhost:4222".to_string());
-        let discord_token = std::env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN must be set");
-        let shard_id_start: u32 = std::env::var("SHARD_ID_START")
-            .expect("SHARD_ID_START must be set")
-            .parse()?;
-        let shard_id_end: u32 = std::env::var("SHARD_ID_END")
-            .expect("SHARD_ID_END must be set")
-            .parse()?;
-        let total_shards: u32 = std::env::var("TOTAL_SHARDS")
-            .expect("TOTAL_SHARDS must be set")
-            .parse()?;
-        let worker_id = std::env::var("WORKER_ID")
-            .unwrap_or_else(|_| "unknown".to_string());
+        let nats_auth = Auth::from_env()?;
+        let nats_tls = Tls::from_env()?;
+        let jetstream_api = JetStreamApi::from_env()?;
+        let subject_prefix =
+            std::env::var("SUBJECT_PREFIX").unwrap_or_else(|_| bedrock_nats::DEFAULT_SUBJECT_PREFIX.to_string());
+        let discord_token = std::env::var("DISCORD_TOKEN").context("DISCORD_TOKEN must be set")?;
+        let total_shards_env = std::env::var("TOTAL_SHARDS").ok();
+        let standalone = std::env::var("SHARD_ASSIGNMENT").map_or(true, |mode| mode == "static")
+            && total_shards_env.as_deref().is_none_or(|total| total == "auto")
+            && ["SHARD_ID_START", "SHARD_ID_END"]
+                .iter()
+                .all(|name| std::env::var(name).is_err());
+        let auto_total_shards = standalone || total_shards_env.as_deref() == Some("auto");
+        let total_shards: u32 = if auto_total_shards {
+            0
+        } else {
+            total_shards_env
+                .context("TOTAL_SHARDS must be set")?
+                .parse()
+                .context("TOTAL_SHARDS must be a non-negative integer or 'auto'")?
+        };
+        let shard_assignment = match std::env::var("SHARD_ASSIGNMENT")
+            .unwrap_or_else(|_| "static".to_string())
+            .as_str()
+        {
+            "static" => ShardAssignment::Static,
+            "ordinal" => {
+                let hostname = std::env::var("HOSTNAME")
+                    .context("HOSTNAME must be set for ordinal shard assignment")?;
+                let ordinal = pod_ordinal(&hostname)?;
+                let shards_per_replica: u32 = std::env::var("SHARDS_PER_REPLICA")
+                    .context("SHARDS_PER_REPLICA must be set for ordinal shard assignment")?
+                    .parse()
+                    .context("SHARDS_PER_REPLICA must be a non-negative integer")?;
+                if shards_per_replica == 0 {
+                    bail!("SHARDS_PER_REPLICA must be at least 1");
+                }
+                ShardAssignment::Ordinal { ordinal, shards_per_replica }
+            }
+            other => bail!("SHARD_ASSIGNMENT must be 'static' or 'ordinal', got '{}'", other),
+        };
+        let (shard_id_start, shard_id_end) = match shard_assignment {
+            ShardAssignment::Static if standalone => (0, 0),
+            ShardAssignment::Static => {
+                let shard_id_start: u32 = std::env::var("SHARD_ID_START")
+                    .context("SHARD_ID_START must be set")?
+                    .parse()
+                    .context("SHARD_ID_START must be a non-negative integer")?;
+                let shard_id_end: u32 = std::env::var("SHARD_ID_END")
+                    .context("SHARD_ID_END must be set")?
+                    .parse()
+                    .context("SHARD_ID_END must be a non-negative integer")?;
+                (shard_id_start, shard_id_end)
+            }
+            ShardAssignment::Ordinal { ordinal, shards_per_replica } => {
+                let shard_id_start = ordinal.saturating_mul(shards_per_replica);
+                let mut shard_id_end = shard_id_start.saturating_add(shards_per_replica - 1);
+                if !auto_total_shards {
+                    shard_id_end = shard_id_end.min(total_shards.saturating_sub(1));
+                }
+                (shard_id_start, shard_id_end)
+            }
+        };
+        let worker_id = std::env::var("WORKER_ID").unwrap_or_else(|_| match shard_assignment {
+            ShardAssignment::Static if standalone => "standalone".to_string(),
+            ShardAssignment::Static => "unknown".to_string(),
+            ShardAssignment::Ordinal { .. } => std::env::var("HOSTNAME").unwrap_or_default(),
+        });
+        let intents = parse_intents(&std::env::var("INTENTS").unwrap_or_else(|_| "default".to_string()))
+            .context("INTENTS is invalid")?;
+        let allow_privileged_intents: bool = std::env::var("ALLOW_PRIVILEGED_INTENTS")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .context("ALLOW_PRIVILEGED_INTENTS must be true or false")?;
+        let event_filter: Vec<String> = std::env::var("EVENT_FILTER")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_ascii_uppercase)
+            .collect();
+        let startup_delay_interval_secs: u64 = std::env::var("STARTUP_DELAY_INTERVAL_SECS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse()
+            .context("STARTUP_DELAY_INTERVAL_SECS must be a non-negative integer")?;
+        let startup_delay = match std::env::var("STARTUP_DELAY").ok().as_deref() {
+            Some("none") => StartupDelay::None,
+            Some("operator") => StartupDelay::Operator,
+            Some("ordinal") => StartupDelay::Ordinal {
+                ordinal: match shard_assignment {
+                    ShardAssignment::Ordinal { ordinal, .. } => ordinal,
+                    ShardAssignment::Static => pod_ordinal(&worker_id)
+                        .context("STARTUP_DELAY=ordinal needs a worker id ending in -<ordinal>")?,
+                },
+                interval: Duration::from_secs(startup_delay_interval_secs),
+            },
+            Some(other) => bail!(
+                "STARTUP_DELAY must be 'none', 'ordinal' or 'operator', got '{}'",
+                other
+            ),
+            None if standalone => StartupDelay::None,
+            None => match shard_assignment {
+                ShardAssignment::Ordinal { ordinal, .. } => StartupDelay::Ordinal {
+                    ordinal,
+                    interval: Duration::from_secs(startup_delay_interval_secs),
+                },
+                ShardAssignment::Static => pod_ordinal(&worker_id).map_or(StartupDelay::Operator, |ordinal| {
+                    StartupDelay::Ordinal {
+                        ordinal,
+                        interval: Duration::from_secs(startup_delay_interval_secs),
+                    }
+                }),
+            },
+        };
         let max_concurrency: u32 = std::env::var("MAX_CONCURRENCY")
             .unwrap_or_else(|_| "1".to_string())
-            .parse()?;
+            .parse()
+            .context("MAX_CONCURRENCY must be a non-negative integer")?;
+        let startup_permission_timeout_secs: u64 = std::env::var("STARTUP_PERMISSION_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse()
+            .context("STARTUP_PERMISSION_TIMEOUT_SECS must be a non-negative integer")?;
+        let heartbeat_interval_secs: u64 = std::env::var("HEARTBEAT_INTERVAL_SECS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse()
+            .context("HEARTBEAT_INTERVAL_SECS must be a non-negative integer")?;
+        let instance_id = std::env::var("HOSTNAME")
+            .unwrap_or_else(|_| format!("{}-{}", worker_id, std::process::id()));
+        let handoff_timeout_secs: u64 = std::env::var("HANDOFF_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse()
+            .context("HANDOFF_TIMEOUT_SECS must be a non-negative integer")?;
+        let coordination_signing_key = std::env::var("COORDINATION_SIGNING_KEY")
+            .ok()
+            .filter(|key| !key.is_empty());
+        let restart_max_attempts: u32 = std::env::var("RESTART_MAX_ATTEMPTS")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("RESTART_MAX_ATTEMPTS must be a non-negative integer")?;
+        let restart_backoff_base_secs: u64 = std::env::var("RESTART_BACKOFF_BASE_SECS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse()
+            .context("RESTART_BACKOFF_BASE_SECS must be a non-negative integer")?;
+        let restart_backoff_max_secs: u64 = std::env::var("RESTART_BACKOFF_MAX_SECS")
+            .unwrap_or_else(|_| "300".to_string())
+            .parse()
+            .context("RESTART_BACKOFF_MAX_SECS must be a non-negative integer")?;
+        let restart_failure_action: FailureAction = std::env::var("RESTART_FAILURE_ACTION")
+            .unwrap_or_else(|_| "mark-failed".to_string())
+            .parse()
+            .context("RESTART_FAILURE_ACTION is invalid")?;
+        let coordination_resubscribe_attempts: u32 = std::env::var("COORDINATION_RESUBSCRIBE_ATTEMPTS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse()
+            .context("COORDINATION_RESUBSCRIBE_ATTEMPTS must be a non-negative integer")?;
+        let admin_addr: std::net::SocketAddr = std::env::var("ADMIN_ADDR")
+            .unwrap_or_else(|_| "127.0.0.1:8081".to_string())
+            .parse()
+            .context("ADMIN_ADDR must be a socket address such as 127.0.0.1:8081")?;
+        let drain_timeout_secs: u64 = std::env::var("DRAIN_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "25".to_string())
+            .parse()
+            .context("DRAIN_TIMEOUT_SECS must be a non-negative integer")?;
+        let publish_rate_limit: u32 = std::env::var("PUBLISH_RATE_LIMIT")
+            .unwrap_or_else(|_| "0".to_string())
+            .parse()
+            .context("PUBLISH_RATE_LIMIT must be a non-negative integer")?;
+        let publish_burst: u32 = match std::env::var("PUBLISH_BURST") {
+            Ok(burst) => burst.parse().context("PUBLISH_BURST must be a non-negative integer")?,
+            Err(_) => publish_rate_limit,
+        };
+        let publish_overflow: OverflowPolicy = std::env::var("PUBLISH_OVERFLOW")
+            .unwrap_or_else(|_| "block".to_string())
+            .parse()
+            .context("PUBLISH_OVERFLOW is invalid")?;
+        let active_standby: bool = std::env::var("ACTIVE_STANDBY")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .context("ACTIVE_STANDBY must be true or false")?;
+        let lease_ttl_secs: u64 = std::env::var("LEASE_TTL_SECS")
+            .unwrap_or_else(|_| "15".to_string())
+            .parse()
+            .context("LEASE_TTL_SECS must be a non-negative integer")?;
+        let gateway_url = std::env::var("GATEWAY_URL").ok().filter(|url| !url.is_empty());
+        let event_stream = std::env::var("EVENT_STREAM").ok().filter(|stream| !stream.is_empty());
+        let stream_settings = StreamSettings::from_env()?;
+        let coordination_stream = CoordinationStreamSettings::default().from_env()?;
+        let session_bucket = BucketSettings::new(1, Duration::from_secs(300)).from_env("SESSION_BUCKET")?;
+        let shard_status_bucket = BucketSettings::new(1, Duration::from_secs(120)).from_env("SHARD_STATUS_BUCKET")?;
+        let override_bucket = BucketSettings::new(1, Duration::ZERO).from_env("OVERRIDE_BUCKET")?;
 
         info!(
             shard_id_start,
             shard_id_end, 
             total_shards, 
+            shard_assignment = ?shard_assignment,
+            intents = ?intents,
+            event_filter = ?event_filter,
+            startup_delay = ?startup_delay,
+            standalone,
+            auto_total_shards,
             worker_id = %worker_id,
+            instance_id = %instance_id,
             max_concurrency,
+            signed_coordination = coordination_signing_key.is_some(),
+            nats_auth = nats_auth.method(),
+            nats_tls = nats_tls.enabled(),
+            jetstream_api = ?jetstream_api,
+            subject_prefix = %subject_prefix,
+            restart_max_attempts,
+            restart_failure_action = ?restart_failure_action,
+            publish_rate_limit,
+            active_standby,
+            gateway_url = ?gateway_url,
+            event_stream = ?event_stream,
+            stream_settings = ?stream_settings,
+            coordination_stream = ?coordination_stream,
+            session_bucket = ?session_bucket,
+            shard_status_bucket = ?shard_status_bucket,
+            override_bucket = ?override_bucket,
             "Loaded cluster configuration"
         );
 
         Ok(Self {
             nats_url,
+            nats_auth,
+            nats_tls,
+            jetstream_api,
+            subject_prefix,
             discord_token,
             shard_id_start,
             shard_id_end,
             total_shards,
+            shard_assignment,
+            intents,
+            allow_privileged_intents,
+            event_filter,
+            startup_delay,
+            standalone,
+            auto_total_shards,
             worker_id,
             max_concurrency,
+            startup_permission_timeout_secs,
+            heartbeat_interval_secs,
+            instance_id,
+            handoff_timeout_secs,
+            coordination_signing_key,
+            restart_max_attempts,
+            restart_backoff_base_secs,
+            restart_backoff_max_secs,
+            restart_failure_action,
+            coordination_resubscribe_attempts,
+            admin_addr,
+            drain_timeout_secs,
+            publish_rate_limit,
+            publish_burst,
+            publish_overflow,
+            active_standby,
+            lease_ttl_secs,
+            gateway_url,
+            event_stream,
+            stream_settings,
+            coordination_stream,
+            session_bucket,
+            shard_status_bucket,
+            override_bucket,
         })
     }
 
+    /// Connection to the configured NATS server under the subject prefix.
+    pub fn nats_connection(&self) -> ConnectionBuilder {
+        ConnectionBuilder::new(&self.nats_url)
+            .auth(self.nats_auth.clone())
+            .tls(self.nats_tls.clone())
+            .jetstream_api(self.jetstream_api.clone())
+            .subject_prefix(&self.subject_prefix)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.discord_token.trim().is_empty() {
+            bail!("DISCORD_TOKEN is empty");
+        }
+        if self.subject_prefix.split('.').any(|token| {
+            token.is_empty() || token.contains(|c: char| c.is_whitespace() || c == '*' || c == '>')
+        }) {
+            bail!(
+                "SUBJECT_PREFIX ({:?}) must be dot separated NATS subject tokens without wildcards",
+                self.subject_prefix
+            );
+        }
+        // TOTAL_SHARDS=auto (and standalone) workers learn their shard count
+        // from Discord once the token is known, so an unresolved config is
+        // still valid here.
+        let unresolved = self.auto_total_shards && self.total_shards == 0;
+        if self.total_shards == 0 && !unresolved {
+            bail!("TOTAL_SHARDS must be at least 1");
+        }
+        if let ShardAssignment::Ordinal { ordinal, shards_per_replica } = self.shard_assignment {
+            if !unresolved && self.shard_id_start >= self.total_shards {
+                bail!(
+                    "Pod ordinal {} has no shards: {} shards per replica only covers {} replicas of TOTAL_SHARDS ({})",
+                    ordinal,
+                    shards_per_replica,
+                    self.total_shards.div_ceil(shards_per_replica),
+                    self.total_shards
+                );
+            }
+        }
+        if self.active_standby && self.lease_ttl_secs < 3 {
+            bail!("LEASE_TTL_SECS must be at least 3 with ACTIVE_STANDBY");
+        }
+        if self.shard_id_start > self.shard_id_end {
+            bail!(
+                "SHARD_ID_START ({}) is greater than SHARD_ID_END ({})",
+                self.shard_id_start,
+                self.shard_id_end
+            );
+        }
+        if !unresolved && self.shard_id_end >= self.total_shards {
+            bail!(
+                "SHARD_ID_END ({}) must be less than TOTAL_SHARDS ({})",
+                self.shard_id_end,
+                self.total_shards
+            );
+        }
+        let privileged = self.intents.intersection(PRIVILEGED_INTENTS);
+        if !privileged.is_empty() && !self.allow_privileged_intents {
+            bail!(
+                "INTENTS requests privileged intents ({:?}), set ALLOW_PRIVILEGED_INTENTS=true once they are enabled for the application",
+                privileged
+            );
+        }
+        if self.publish_rate_limit != 0 && self.publish_burst == 0 {
+            bail!("PUBLISH_BURST must be at least 1 when PUBLISH_RATE_LIMIT is set");
+        }
+        if !(1..=5).contains(&self.stream_settings.replicas) {
+            bail!("STREAM_REPLICAS must be between 1 and 5");
+        }
+        if self.stream_settings.duplicate_window_secs == 0 {
+            bail!("STREAM_DUPLICATE_WINDOW_SECS must be at least 1");
+        }
+        if self.stream_settings.partitions == 0 {
+            bail!("STREAM_PARTITIONS must be at least 1");
+        }
+        if self.max_concurrency == 0 {
+            bail!("MAX_CONCURRENCY must be at least 1");
+        }
+        if self.heartbeat_interval_secs == 0 {
+            bail!("HEARTBEAT_INTERVAL_SECS must be at least 1");
+        }
+        if self.restart_backoff_max_secs < self.restart_backoff_base_secs {
+            bail!(
+                "RESTART_BACKOFF_MAX_SECS ({}) must not be less than RESTART_BACKOFF_BASE_SECS ({})",
+                self.restart_backoff_max_secs,
+                self.restart_backoff_base_secs
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn apply_gateway_info(&mut self, recommended_shards: u32, max_concurrency: u32) {
+        self.total_shards = recommended_shards;
+        if self.standalone {
+            self.shard_id_start = 0;
+            self.shard_id_end = recommended_shards.saturating_sub(1);
+        } else if let ShardAssignment::Ordinal { .. } = self.shard_assignment {
+            self.shard_id_end = self.shard_id_end.min(recommended_shards.saturating_sub(1));
+        }
+        self.max_concurrency = max_concurrency;
+
+        info!(
+            total_shards = self.total_shards,
+            max_concurrency = self.max_concurrency,
+            "Applied shard configuration from Discord gateway"
+        );
+    }
+
     pub fn worker_id(&self) -> &str {
         &self.worker_id
     }
 }
+
+fn pod_ordinal(hostname: &str) -> Result<u32> {
+    hostname
+        .rsplit_once('-')
+        .and_then(|(_, ordinal)| ordinal.parse().ok())
+        .with_context(|| format!("Cannot derive a pod ordinal from hostname '{}'", hostname))
+}
@@ -1,76 +1,721 @@
+use async_nats::jetstream::{self, consumer::pull, consumer::AckPolicy, consumer::DeliverPolicy};
 use async_nats::Client as NatsClient;
+use bedrock_nats::{dedup, rpc, signing};
 use futures_util::StreamExt;
-use tracing::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
 
+const DEFAULT_DENY_RETRY: Duration = Duration::from_secs(5);
+const RESUBSCRIBE_BACKOFF_BASE: Duration = Duration::from_secs(1);
+const RESUBSCRIBE_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+// Coordination subjects, relative to the cluster's subject prefix.
+const RESHARD_SUBJECT: &str = "operator.reshard";
+const STARTUP_SUBJECT: &str = "operator.startup";
+const ASSIGNMENT_SUBJECT: &str = "operator.assignment";
+
+const CONSUMER_INACTIVE_THRESHOLD: Duration = Duration::from_secs(3600);
+
+/// Events each shard published since the previous heartbeat, per second,
+/// worked out from the counters of a worker status snapshot. Shards seen for
+/// the first time only have their counter recorded.
+fn shard_event_rates(
+    snapshot: &serde_json::Value,
+    last_counts: &mut HashMap<u32, (u64, std::time::Instant)>,
+) -> HashMap<u32, f64> {
+    #[derive(Deserialize)]
+    struct ShardCounter {
+        shard_id: u32,
+        events_published: u64,
+    }
+
+    let counters: Vec<ShardCounter> = snapshot
+        .get("shards")
+        .and_then(|shards| serde_json::from_value(shards.clone()).ok())
+        .unwrap_or_default();
+
+    let now = std::time::Instant::now();
+    let mut rates = HashMap::new();
+    for counter in &counters {
+        if let Some((previous, at)) = last_counts.get(&counter.shard_id) {
+            let elapsed = now.duration_since(*at).as_secs_f64();
+            // A restarted shard starts counting from zero again.
+            if elapsed > 0.0 && counter.events_published >= *previous {
+                rates.insert(counter.shard_id, (counter.events_published - previous) as f64 / elapsed);
+            }
+        }
+    }
+
+    last_counts.clear();
+    last_counts.extend(counters.iter().map(|counter| (counter.shard_id, (counter.events_published, now))));
+    rates
+}
+
+#[derive(Clone)]
 pub struct CoordinationHandler {
     nats_client: NatsClient,
+    jetstream: jetstream::Context,
+    subject_prefix: String,
+    signing_key: Option<Vec<u8>>,
+    consumer_name: Option<String>,
+    resubscribe_attempts: u32,
+    status_bucket: Option<jetstream::kv::Store>,
+    reconnects: Option<watch::Receiver<u64>>,
+}
+
+/// The operator's answer to a startup request.
+#[derive(Debug, Deserialize)]
+struct StartupReply {
+    #[serde(default)]
+    granted: bool,
+    delay_ms: Option<u64>,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartupPermission {
+    Granted { delay: Duration },
+    Denied { retry_after: Duration, reason: Option<String> },
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReshardStage {
+    Started,
+    ShardsStopped,
+    BucketReidentified,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReshardProgress {
+    pub worker_id: String,
+    pub total_shards: u32,
+    pub stage: ReshardStage,
+    pub shards_stopped: u32,
+    pub shards_reidentified: u32,
+    pub shards_total: u32,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShardGroupAssignment {
+    pub deployment_name: String,
+    pub shard_start: u32,
+    pub shard_end: u32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct SessionStartLimit {
+    pub total: u32,
+    pub remaining: u32,
+    pub reset_after_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReshardSignal {
+    pub event: String,
+    #[serde(default)]
+    pub cluster: Option<String>,
+    #[serde(default)]
+    pub subject_prefix: Option<String>,
+    pub new_shard_count: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StartupCoordination {
+    pub event: String,
+    pub cluster: Option<String>,
+    #[serde(default)]
+    pub subject_prefix: Option<String>,
+    pub max_concurrency: u32,
+    pub total_shards: u32,
+    pub shard_groups: Vec<ShardGroupAssignment>,
+    #[serde(default)]
+    pub session_start_limit: Option<SessionStartLimit>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShardAssignmentUpdate {
+    pub event: String,
+    pub cluster: Option<String>,
+    #[serde(default)]
+    pub subject_prefix: Option<String>,
+    pub total_shards: u32,
+    pub assignments: std::collections::HashMap<String, Vec<u32>>,
 }
 
 pub trait ShardManagerInterface {
     fn worker_id(&self) -> &str;
-    fn update_shards(&mut self, new_shard_count: u32) -> impl std::future::Future<Output = anyhow::Result<()>> + Send;
+    fn update_shards(&self, new_shard_count: u32) -> impl std::future::Future<Output = anyhow::Result<()>> + Send;
+    fn running_shards(&self) -> impl std::future::Future<Output = anyhow::Result<Vec<u32>>> + Send;
+    fn apply_startup_coordination(&self, coordination: StartupCoordination) -> impl std::future::Future<Output = anyhow::Result<()>> + Send;
+    fn status_snapshot(&self) -> impl std::future::Future<Output = anyhow::Result<serde_json::Value>> + Send;
+    fn assign_shards(&self, total_shards: u32, shard_ids: Vec<u32>) -> impl std::future::Future<Output = anyhow::Result<()>> + Send;
 }
 
 impl CoordinationHandler {
-    pub fn new(nats_client: NatsClient) -> Self {
-        Self { nats_client }
+    pub fn new(nats_client: NatsClient, subject_prefix: impl Into<String>) -> Self {
+        Self {
+            jetstream: jetstream::new(nats_client.clone()),
+            nats_client,
+            subject_prefix: subject_prefix.into(),
+            signing_key: None,
+            consumer_name: None,
+            resubscribe_attempts: 0,
+            status_bucket: None,
+            reconnects: None,
+        }
+    }
+
+    /// Uses `jetstream` for the coordination stream instead of the local
+    /// account's JetStream API.
+    pub fn with_jetstream(mut self, jetstream: jetstream::Context) -> Self {
+        self.jetstream = jetstream;
+        self
+    }
+
+    /// Also keeps the latest state of each shard under `shard.<id>` in
+    /// `bucket`, for readers that were not subscribed when it was published.
+    pub fn with_status_bucket(mut self, bucket: jetstream::kv::Store) -> Self {
+        self.status_bucket = Some(bucket);
+        self
+    }
+
+    /// Makes the core subscriptions again whenever `reconnects`, the
+    /// connection's reconnect count, changes, in case the server lost them
+    /// while the connection was down.
+    pub fn with_reconnects(mut self, reconnects: watch::Receiver<u64>) -> Self {
+        self.reconnects = Some(reconnects);
+        self
+    }
+
+    pub fn with_signing_key(mut self, signing_key: Option<Vec<u8>>) -> Self {
+        self.signing_key = signing_key;
+        self
+    }
+
+    pub fn with_resubscribe_attempts(mut self, resubscribe_attempts: u32) -> Self {
+        self.resubscribe_attempts = resubscribe_attempts;
+        self
+    }
+
+    pub fn with_consumer_name(mut self, consumer_name: impl Into<String>) -> Self {
+        self.consumer_name = Some(consumer_name.into());
+        self
+    }
+
+    fn subject(&self, suffix: &str) -> String {
+        format!("{}.{}", self.subject_prefix, suffix)
+    }
+
+    /// Publishes under a Nats-Msg-Id of its own, so a stream capturing the
+    /// subject stores a message that repeats an earlier one word for word.
+    async fn publish(&self, subject: String, payload: String) -> Result<(), async_nats::PublishError> {
+        let headers = dedup::with_message_id(None, &dedup::unique_id());
+        self.nats_client.publish_with_headers(subject, headers, payload.into()).await
+    }
+
+    /// Whether a coordination message names this worker's cluster. Messages
+    /// from operators that do not address them yet are accepted.
+    fn is_addressed_to_us(&self, cluster: Option<&str>, subject_prefix: Option<&str>) -> bool {
+        match subject_prefix {
+            Some(prefix) if prefix != self.subject_prefix => {
+                warn!(
+                    cluster = ?cluster,
+                    subject_prefix = %prefix,
+                    own_subject_prefix = %self.subject_prefix,
+                    "Ignoring coordination message addressed to another cluster"
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    async fn wait_for_retry(&self, subject: &str, failures: u32) -> Result<(), Box<dyn std::error::Error>> {
+        if self.resubscribe_attempts != 0 && failures > self.resubscribe_attempts {
+            return Err(format!("giving up on {} after {} failed subscriptions", subject, failures).into());
+        }
+
+        if failures > 0 {
+            let factor = 2u32.saturating_pow(failures - 1);
+            let backoff = RESUBSCRIBE_BACKOFF_BASE.saturating_mul(factor).min(RESUBSCRIBE_BACKOFF_MAX);
+            warn!(subject, failures, backoff = ?backoff, "Resubscribing to coordination subject");
+            tokio::time::sleep(backoff).await;
+        }
+
+        Ok(())
+    }
+
+    async fn subscribe(
+        &self,
+        subject: &str,
+        failures: &mut u32,
+    ) -> Result<async_nats::Subscriber, Box<dyn std::error::Error>> {
+        loop {
+            self.wait_for_retry(subject, *failures).await?;
+
+            let error = match self.nats_client.subscribe(subject.to_string()).await {
+                Ok(subscriber) => return Ok(subscriber),
+                Err(e) => e.to_string(),
+            };
+
+            *failures += 1;
+            error!(subject, error = %error, "Failed to subscribe to coordination subject");
+        }
+    }
+
+    fn reconnect_count(&self) -> u64 {
+        self.reconnects.as_ref().map_or(0, |reconnects| *reconnects.borrow())
+    }
+
+    /// The next message of a subscription made at `reconnect_count`, or None
+    /// once it ends or the connection has reconnected since.
+    async fn next_message(
+        &self,
+        subscriber: &mut async_nats::Subscriber,
+        reconnect_count: u64,
+    ) -> Option<async_nats::Message> {
+        let Some(reconnects) = &self.reconnects else {
+            return subscriber.next().await;
+        };
+        let mut reconnects = reconnects.clone();
+
+        tokio::select! {
+            message = subscriber.next() => message,
+            _ = reconnects.wait_for(|count| *count != reconnect_count) => None,
+        }
+    }
+
+    async fn consume(
+        &self,
+        subject: &str,
+        failures: &mut u32,
+    ) -> Result<pull::Stream, Box<dyn std::error::Error>> {
+        loop {
+            self.wait_for_retry(subject, *failures).await?;
+
+            let error = match self.create_consumer(subject).await {
+                Ok(messages) => return Ok(messages),
+                Err(e) => e,
+            };
+
+            *failures += 1;
+            error!(subject, error = %error, "Failed to create coordination consumer");
+        }
+    }
+
+    async fn create_consumer(&self, subject: &str) -> Result<pull::Stream, String> {
+        // Failures are retried by the caller, which counts them.
+        let stream = bedrock_nats::streams::ensure_stream(
+            &self.jetstream,
+            &jetstream::stream::Config {
+                name: bedrock_nats::coordination::signal_stream_name(&self.subject_prefix),
+                subjects: vec![
+                    self.subject(RESHARD_SUBJECT),
+                    self.subject(STARTUP_SUBJECT),
+                    self.subject(ASSIGNMENT_SUBJECT),
+                ],
+                max_messages_per_subject: 1,
+                ..Default::default()
+            },
+            bedrock_nats::RetryPolicy::retries(0),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let durable_name = self
+            .consumer_name
+            .as_ref()
+            .map(|name| format!("{}-{}", name, subject).replace('.', "-"));
+
+        let config = pull::Config {
+            durable_name: durable_name.clone(),
+            filter_subject: subject.to_string(),
+            deliver_policy: DeliverPolicy::LastPerSubject,
+            ack_policy: AckPolicy::Explicit,
+            inactive_threshold: CONSUMER_INACTIVE_THRESHOLD,
+            ..Default::default()
+        };
+
+        let consumer = match &durable_name {
+            Some(name) => stream.get_or_create_consumer(name, config).await,
+            None => stream.create_consumer(config).await,
+        }
+        .map_err(|e| e.to_string())?;
+
+        info!(subject, consumer = ?durable_name, "Consuming coordination subject from JetStream");
+        consumer.messages().await.map_err(|e| e.to_string())
+    }
+
+    fn is_authentic(&self, message: &jetstream::Message) -> bool {
+        if self.signing_key.is_none() {
+            return true;
+        }
+
+        let received_at = message
+            .info()
+            .map(|info| info.published.unix_timestamp() as u64)
+            .unwrap_or_else(|_| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            });
+
+        self.verify_signature(message, received_at)
+    }
+
+    fn verify_signature(&self, message: &async_nats::Message, received_at: u64) -> bool {
+        let Some(key) = &self.signing_key else {
+            return true;
+        };
+
+        match signing::verify_at(key, &message.subject, message.headers.as_ref(), &message.payload, received_at) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(subject = %message.subject, error = %e, "Rejected coordination message with bad signature");
+                false
+            }
+        }
     }
 
     pub async fn listen_for_reshard_signals<T: ShardManagerInterface + Send + Sync>(
         &self,
-        shard_manager: std::sync::Arc<tokio::sync::RwLock<T>>,
+        shard_manager: T,
     ) -> Result<(), Box<dyn std::error::Error>> {
         info!("Starting reshard signal listener");
-        
-        let mut subscriber = self.nats_client.subscribe("discord.operator.reshard").await?;
-        
-        while let Some(message) = subscriber.next().await {
-            info!(payload = %String::from_utf8_lossy(&message.payload), "Received reshard signal");
-            
-            if let Ok(reshard_data) = serde_json::from_slice::<serde_json::Value>(&message.payload) {
-                if let Some(event) = reshard_data.get("event").and_then(|v| v.as_str()) {
-                    if event == "reshard" {
-                        if let Some(new_shard_count) = reshard_data.get("new_shard_count").and_then(|v| v.as_u64()) {
-                            let manager = shard_manager.read().await;
-                            let worker_id = manager.worker_id();
-                            info!(new_shard_count, worker_id = %worker_id, "Processing reshard signal");
-                            drop(manager);
-                            
-                            let mut manager = shard_manager.write().await;
-                            if let Err(e) = manager.update_shards(new_shard_count as u32).await {
-                                error!(error = ?e, worker_id = %manager.worker_id(), "Failed to update shards");
-                            }
-                        }
+
+        let subject = self.subject(RESHARD_SUBJECT);
+        let mut failures = 0;
+
+        loop {
+            let mut messages = self.consume(&subject, &mut failures).await?;
+
+            while let Some(message) = messages.next().await {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!(subject = %subject, error = %e, "Reshard consumer failed");
+                        break;
                     }
+                };
+
+                failures = 0;
+                // Acked before it is applied, which for a reshard takes longer
+                // than the ack wait and would have the signal redelivered.
+                let accepted = self.accept_reshard_signal(&message, shard_manager.worker_id());
+                if let Err(e) = message.ack().await {
+                    warn!(subject = %subject, error = %e, "Failed to acknowledge coordination message");
+                }
+                if let Some(accepted) = accepted {
+                    Self::apply_reshard_signal(accepted, &shard_manager).await;
                 }
             }
+
+            failures += 1;
+            warn!(subject = %subject, "Reshard subscription ended");
+        }
+    }
+
+    /// The reshard signal in `message` if it is authentic and addressed to
+    /// this worker's cluster.
+    fn accept_reshard_signal(&self, message: &jetstream::Message, worker_id: &str) -> Option<ReshardSignal> {
+        info!(payload = %String::from_utf8_lossy(&message.payload), "Received reshard signal");
+
+        if !self.is_authentic(message) {
+            return None;
+        }
+
+        let signal = match serde_json::from_slice::<ReshardSignal>(&message.payload) {
+            Ok(signal) if signal.event == "reshard" => signal,
+            Ok(_) => return None,
+            Err(e) => {
+                warn!(error = %e, "Ignoring malformed reshard signal");
+                return None;
+            }
+        };
+
+        if !self.is_addressed_to_us(signal.cluster.as_deref(), signal.subject_prefix.as_deref()) {
+            return None;
+        }
+
+        info!(
+            new_shard_count = signal.new_shard_count,
+            cluster = ?signal.cluster,
+            worker_id = %worker_id,
+            "Processing reshard signal"
+        );
+        Some(signal)
+    }
+
+    async fn apply_reshard_signal<T: ShardManagerInterface + Send + Sync>(signal: ReshardSignal, shard_manager: &T) {
+        if let Err(e) = shard_manager.update_shards(signal.new_shard_count).await {
+            error!(error = ?e, worker_id = %shard_manager.worker_id(), "Failed to update shards");
         }
-        
-        Ok(())
     }
 
     pub async fn listen_for_startup_coordination<T: ShardManagerInterface + Send + Sync>(
         &self,
-        shard_manager: std::sync::Arc<tokio::sync::RwLock<T>>,
+        shard_manager: T,
     ) -> Result<(), Box<dyn std::error::Error>> {
         info!("Starting startup coordination listener");
-        
-        let mut subscriber = self.nats_client.subscribe("discord.operator.startup").await?;
-        
-        while let Some(message) = subscriber.next().await {
-            info!(payload = %String::from_utf8_lossy(&message.payload), "Received startup coordination");
-            
-            if let Ok(startup_data) = serde_json::from_slice::<serde_json::Value>(&message.payload) {
-                if let Some(event) = startup_data.get("event").and_then(|v| v.as_str()) {
-                    if event == "startup_coordination" {
-                        let manager = shard_manager.read().await;
-                        let worker_id = manager.worker_id();
-                        info!(worker_id = %worker_id, "Processing startup coordination signal");
+
+        let subject = self.subject(STARTUP_SUBJECT);
+        let mut failures = 0;
+
+        loop {
+            let mut messages = self.consume(&subject, &mut failures).await?;
+
+            while let Some(message) = messages.next().await {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!(subject = %subject, error = %e, "Startup coordination consumer failed");
+                        break;
                     }
+                };
+
+                failures = 0;
+                // Acked before it is applied, like reshard signals.
+                let accepted = self.accept_startup_coordination(&message, shard_manager.worker_id());
+                if let Err(e) = message.ack().await {
+                    warn!(subject = %subject, error = %e, "Failed to acknowledge coordination message");
+                }
+                if let Some(accepted) = accepted {
+                    Self::apply_startup_coordination(accepted, &shard_manager).await;
                 }
             }
+
+            failures += 1;
+            warn!(subject = %subject, "Startup coordination subscription ended");
         }
-        
+    }
+
+    /// The startup coordination in `message` if it is authentic and addressed
+    /// to this worker's cluster.
+    fn accept_startup_coordination(&self, message: &jetstream::Message, worker_id: &str) -> Option<StartupCoordination> {
+        info!(payload = %String::from_utf8_lossy(&message.payload), "Received startup coordination");
+
+        if !self.is_authentic(message) {
+            return None;
+        }
+
+        let coordination = match serde_json::from_slice::<StartupCoordination>(&message.payload) {
+            Ok(coordination) if coordination.event == "startup_coordination" => coordination,
+            Ok(_) => return None,
+            Err(e) => {
+                warn!(error = %e, "Ignoring malformed startup coordination");
+                return None;
+            }
+        };
+
+        if !self.is_addressed_to_us(coordination.cluster.as_deref(), coordination.subject_prefix.as_deref()) {
+            return None;
+        }
+
+        info!(
+            worker_id = %worker_id,
+            cluster = ?coordination.cluster,
+            max_concurrency = coordination.max_concurrency,
+            total_shards = coordination.total_shards,
+            "Processing startup coordination signal"
+        );
+        Some(coordination)
+    }
+
+    async fn apply_startup_coordination<T: ShardManagerInterface + Send + Sync>(
+        coordination: StartupCoordination,
+        shard_manager: &T,
+    ) {
+        if let Err(e) = shard_manager.apply_startup_coordination(coordination).await {
+            error!(error = ?e, worker_id = %shard_manager.worker_id(), "Failed to apply startup coordination");
+        }
+    }
+
+    pub async fn listen_for_shard_assignments<T: ShardManagerInterface + Send + Sync>(
+        &self,
+        shard_manager: T,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        info!("Starting shard assignment listener");
+
+        let subject = self.subject(ASSIGNMENT_SUBJECT);
+        let mut failures = 0;
+
+        loop {
+            let mut messages = self.consume(&subject, &mut failures).await?;
+
+            while let Some(message) = messages.next().await {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!(subject = %subject, error = %e, "Shard assignment consumer failed");
+                        break;
+                    }
+                };
+
+                failures = 0;
+                // Acked before it is applied, like reshard signals.
+                let accepted = self.accept_shard_assignment(&message, shard_manager.worker_id());
+                if let Err(e) = message.ack().await {
+                    warn!(subject = %subject, error = %e, "Failed to acknowledge coordination message");
+                }
+                if let Some(accepted) = accepted {
+                    Self::apply_shard_assignment(accepted, &shard_manager).await;
+                }
+            }
+
+            failures += 1;
+            warn!(subject = %subject, "Shard assignment subscription ended");
+        }
+    }
+
+    /// The total and the shards `message` assigns this worker, if it is
+    /// authentic and addressed to this worker's cluster.
+    fn accept_shard_assignment(&self, message: &jetstream::Message, worker_id: &str) -> Option<(u32, Vec<u32>)> {
+        if !self.is_authentic(message) {
+            return None;
+        }
+
+        let mut update = match serde_json::from_slice::<ShardAssignmentUpdate>(&message.payload) {
+            Ok(update) if update.event == "shard_assignment" => update,
+            Ok(_) => return None,
+            Err(e) => {
+                warn!(error = %e, "Ignoring malformed shard assignment");
+                return None;
+            }
+        };
+
+        if !self.is_addressed_to_us(update.cluster.as_deref(), update.subject_prefix.as_deref()) {
+            return None;
+        }
+
+        let Some(shard_ids) = update.assignments.remove(worker_id) else {
+            warn!(worker_id = %worker_id, cluster = ?update.cluster, "Shard assignment has no entry for this worker");
+            return None;
+        };
+
+        info!(worker_id = %worker_id, shards = ?shard_ids, total_shards = update.total_shards, "Received shard assignment");
+        Some((update.total_shards, shard_ids))
+    }
+
+    async fn apply_shard_assignment<T: ShardManagerInterface + Send + Sync>(
+        (total_shards, shard_ids): (u32, Vec<u32>),
+        shard_manager: &T,
+    ) {
+        if let Err(e) = shard_manager.assign_shards(total_shards, shard_ids).await {
+            error!(error = ?e, worker_id = %shard_manager.worker_id(), "Failed to apply shard assignment");
+        }
+    }
+
+    pub async fn serve_status_requests<T: ShardManagerInterface + Send + Sync>(
+        &self,
+        shard_manager: T,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let subject = self.subject(&format!("workers.{}.status", shard_manager.worker_id()));
+        info!(subject = %subject, "Starting worker status responder");
+
+        let mut failures = 0;
+
+        loop {
+            let reconnect_count = self.reconnect_count();
+            let mut subscriber = self.subscribe(&subject, &mut failures).await?;
+
+            while let Some(message) = self.next_message(&mut subscriber, reconnect_count).await {
+                failures = 0;
+
+                let Some(reply) = message.reply else {
+                    continue;
+                };
+
+                let payload = match shard_manager.status_snapshot().await {
+                    Ok(snapshot) => snapshot,
+                    Err(e) => serde_json::json!({ "error": e.to_string() }),
+                };
+
+                if let Err(e) = self.nats_client.publish(reply, payload.to_string().into()).await {
+                    warn!(error = %e, "Failed to reply to worker status request");
+                }
+            }
+
+            if self.reconnect_count() != reconnect_count {
+                info!(subject = %subject, "Resubscribing after NATS reconnect");
+                continue;
+            }
+
+            failures += 1;
+            warn!(subject = %subject, "Worker status subscription ended");
+        }
+    }
+
+    pub async fn listen_for_drain_requests(
+        &self,
+        worker_id: &str,
+        drain: std::sync::Arc<tokio::sync::Notify>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let subject = self.subject(&format!("workers.{}.drain", worker_id));
+        info!(subject = %subject, "Starting drain request listener");
+
+        let mut failures = 0;
+
+        loop {
+            let reconnect_count = self.reconnect_count();
+            let mut subscriber = self.subscribe(&subject, &mut failures).await?;
+
+            while let Some(message) = self.next_message(&mut subscriber, reconnect_count).await {
+                failures = 0;
+
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                if !self.verify_signature(&message, now) {
+                    continue;
+                }
+
+                let addressed_to = serde_json::from_slice::<serde_json::Value>(&message.payload)
+                    .ok()
+                    .and_then(|request| request.get("worker_id")?.as_str().map(str::to_string));
+                if addressed_to.as_deref() != Some(worker_id) {
+                    warn!(worker_id = %worker_id, addressed_to = ?addressed_to, "Ignoring drain request for another worker");
+                    continue;
+                }
+
+                info!(worker_id = %worker_id, "Received drain request");
+                drain.notify_one();
+
+                if let Some(reply) = message.reply {
+                    let payload = serde_json::json!({ "worker_id": worker_id, "draining": true });
+                    if let Err(e) = self.nats_client.publish(reply, payload.to_string().into()).await {
+                        warn!(error = %e, "Failed to reply to drain request");
+                    }
+                }
+            }
+
+            if self.reconnect_count() != reconnect_count {
+                info!(subject = %subject, "Resubscribing after NATS reconnect");
+                continue;
+            }
+
+            failures += 1;
+            warn!(subject = %subject, "Drain subscription ended");
+        }
+    }
+
+    /// Tells the operator this worker stopped its shards after a drain, so it
+    /// can delete the worker's deployment without cutting sessions short.
+    pub async fn report_drained(&self, worker_id: &str, drained: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let payload = serde_json::json!({ "worker_id": worker_id, "drained": drained });
+        self.publish(self.subject(&format!("workers.{}.drained", worker_id)), payload.to_string()).await?;
+        self.nats_client.flush().await?;
+
+        info!(worker_id = %worker_id, drained, "Reported drain complete");
         Ok(())
     }
 
@@ -78,7 +723,8 @@ impl CoordinationHandler {
         &self,
         worker_id: &str,
         shard_id: u32,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        timeout: Duration,
+    ) -> Result<StartupPermission, Box<dyn std::error::Error>> {
         let request = serde_json::json!({
             "action": "request_startup",
             "worker_id": worker_id,
@@ -89,11 +735,136 @@ impl CoordinationHandler {
                 .as_secs()
         });
 
-        self.nats_client
-            .publish("discord.startup.request", request.to_string().into())
-            .await?;
-        
-        info!(worker_id = %worker_id, shard_id, "Requested startup permission");
+        // A signed cluster's operator signs its grants for the reply inbox.
+        let mut rpc_request = rpc::Request::new(timeout);
+        if let Some(key) = self.signing_key.clone() {
+            rpc_request = rpc_request.verify(move |reply| {
+                signing::verify(&key, &reply.subject, reply.headers.as_ref(), &reply.payload).map_err(|e| e.to_string())
+            });
+        }
+
+        let reply: StartupReply = match rpc_request.send(&self.nats_client, self.subject("startup.request"), &request).await {
+            Ok(reply) => reply,
+            Err(e) if e.is_unanswered() => {
+                warn!(worker_id = %worker_id, shard_id, error = %e, "Startup permission unavailable, proceeding without grant");
+                return Ok(StartupPermission::Unavailable);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let delay = reply.delay_ms.map(Duration::from_millis);
+        let permission = if reply.granted {
+            StartupPermission::Granted {
+                delay: delay.unwrap_or(Duration::ZERO),
+            }
+        } else {
+            StartupPermission::Denied {
+                retry_after: delay.unwrap_or(DEFAULT_DENY_RETRY),
+                reason: reply.reason,
+            }
+        };
+
+        info!(worker_id = %worker_id, shard_id, permission = ?permission, "Received startup permission reply");
+        Ok(permission)
+    }
+
+    pub async fn run_heartbeat<T: ShardManagerInterface + Send + Sync>(
+        &self,
+        shard_manager: T,
+        interval: Duration,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        info!(interval_secs = interval.as_secs(), "Starting worker heartbeat");
+
+        let started = std::time::Instant::now();
+        let mut ticker = tokio::time::interval(interval);
+        let mut last_counts: HashMap<u32, (u64, std::time::Instant)> = HashMap::new();
+
+        loop {
+            ticker.tick().await;
+
+            let shards = match shard_manager.running_shards().await {
+                Ok(shards) => shards,
+                Err(e) => {
+                    error!(error = ?e, "Failed to read running shards for heartbeat");
+                    continue;
+                }
+            };
+
+            let events_per_sec = match shard_manager.status_snapshot().await {
+                Ok(snapshot) => {
+                    self.publish_shard_states(&snapshot).await;
+                    shard_event_rates(&snapshot, &mut last_counts)
+                }
+                Err(e) => {
+                    warn!(error = ?e, "Failed to read shard event counts for heartbeat");
+                    HashMap::new()
+                }
+            };
+
+            let heartbeat = serde_json::json!({
+                "event": "heartbeat",
+                "worker_id": shard_manager.worker_id(),
+                "shards": shards,
+                "events_per_sec": events_per_sec,
+                "uptime_secs": started.elapsed().as_secs(),
+                "timestamp": std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            });
+
+            if let Err(e) = self.publish(self.subject("workers.heartbeat"), heartbeat.to_string()).await {
+                warn!(error = %e, "Failed to publish worker heartbeat");
+            }
+        }
+    }
+
+    /// Publishes the connection state of every shard in `snapshot` on its
+    /// `shards.<id>.status` subject, so the operator can tell connected shards
+    /// from ones whose runner is up but not connected.
+    async fn publish_shard_states(&self, snapshot: &serde_json::Value) {
+        #[derive(Deserialize)]
+        struct ShardState {
+            shard_id: u32,
+            state: String,
+        }
+
+        let shards: Vec<ShardState> = snapshot
+            .get("shards")
+            .and_then(|shards| serde_json::from_value(shards.clone()).ok())
+            .unwrap_or_default();
+
+        for shard in shards {
+            let status = serde_json::json!({ "shard_id": shard.shard_id, "status": shard.state });
+            if let Err(e) = self
+                .publish(self.subject(&format!("shards.{}.status", shard.shard_id)), status.to_string())
+                .await
+            {
+                warn!(shard_id = shard.shard_id, error = %e, "Failed to publish shard status");
+            }
+            if let Some(bucket) = &self.status_bucket {
+                if let Err(e) = bucket.put(format!("shard.{}", shard.shard_id), status.to_string().into()).await {
+                    warn!(shard_id = shard.shard_id, error = %e, "Failed to store shard status");
+                }
+            }
+        }
+    }
+
+    pub async fn report_reshard_progress(
+        &self,
+        progress: &ReshardProgress,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut payload = serde_json::to_value(progress)?;
+        payload["event"] = "reshard_progress".into();
+        payload["timestamp"] = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .into();
+
+        self.publish(self.subject("operator.reshard.status"), payload.to_string()).await?;
+
+        info!(worker_id = %progress.worker_id, stage = ?progress.stage, "Reported reshard progress");
         Ok(())
     }
 
@@ -112,9 +883,7 @@ impl CoordinationHandler {
                 .as_secs()
         });
 
-        self.nats_client
-            .publish("discord.startup.complete", notification.to_string().into())
-            .await?;
+        self.publish(self.subject("startup.complete"), notification.to_string()).await?;
         
         info!(worker_id = %worker_id, shard_id, "Notified startup complete");
         Ok(())
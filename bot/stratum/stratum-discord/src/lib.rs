@@ -1,8 +1,8 @@
 use stratum_config::Config;
 use anyhow::Result;
 use std::sync::Arc;
+use tracing::info;
 use twilight_gateway::{Config as GatewayConfig, ConfigBuilder as GatewayConfigBuilder};
-use twilight_model::gateway::Intents;
 
 pub struct ShardManagerConfig {
     pub gateway_config: Arc<GatewayConfig>,
@@ -10,9 +10,11 @@ pub struct ShardManagerConfig {
 }
 
 pub fn new_shard_manager_config(config: &Config) -> Result<ShardManagerConfig> {
-    let gateway_config = Arc::new(
-        GatewayConfigBuilder::new(config.discord_token.clone(), Intents::GUILD_MESSAGES).build(),
-    );
+    let mut gateway_config = GatewayConfigBuilder::new(config.discord_token.clone(), config.intents);
+    if let Some(gateway_url) = &config.gateway_url {
+        gateway_config = gateway_config.proxy_url(gateway_url.clone());
+    }
+    let gateway_config = Arc::new(gateway_config.build());
 
     let shard_ids = config.shard_id_start..config.shard_id_end + 1;
 
@@ -21,3 +23,22 @@ pub fn new_shard_manager_config(config: &Config) -> Result<ShardManagerConfig> {
         shard_ids,
     })
 }
+
+pub async fn get_gateway_info(client: &twilight_http::Client) -> Result<(u32, u32)> {
+    let info = client.gateway().authed().await?.model().await?;
+
+    info!(
+        shards = info.shards,
+        max_concurrency = info.session_start_limit.max_concurrency,
+        "Retrieved Discord gateway information"
+    );
+
+    Ok((info.shards, info.session_start_limit.max_concurrency as u32))
+}
+
+pub async fn resolve_total_shards(config: &mut Config) -> Result<()> {
+    let (recommended_shards, max_concurrency) = get_gateway_info(&util::CLIENT).await?;
+
+    config.apply_gateway_info(recommended_shards, max_concurrency);
+    Ok(())
+}
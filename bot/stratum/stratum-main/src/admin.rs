@@ -0,0 +1,149 @@
+use bedrock_nats::events::ConnectionEvents;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use stratum_coordination::ShardManagerInterface;
+use stratum_shard_manager::ShardManagerHandle;
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+#[derive(Clone)]
+struct AdminState {
+    shard_manager: ShardManagerHandle,
+    drain: Arc<Notify>,
+    nats_events: Arc<ConnectionEvents>,
+}
+
+pub async fn serve(
+    addr: SocketAddr,
+    shard_manager: ShardManagerHandle,
+    drain: Arc<Notify>,
+    nats_events: Arc<ConnectionEvents>,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    let state = AdminState { shard_manager, drain, nats_events };
+
+    info!(addr = %addr, "Admin server listening");
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            let service = service_fn(move |request| handle(state.clone(), request));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                warn!(peer = %peer, error = %e, "Admin connection failed");
+            }
+        });
+    }
+}
+
+async fn handle(
+    state: AdminState,
+    request: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let method = request.method().clone();
+    let segments: Vec<&str> = request
+        .uri()
+        .path()
+        .trim_matches('/')
+        .split('/')
+        .collect();
+
+    info!(method = %method, path = %request.uri().path(), "Admin request");
+
+    let response = match (method, segments.as_slice()) {
+        (Method::GET, ["shards"]) => match state.shard_manager.snapshot().await {
+            Ok(snapshot) => json(StatusCode::OK, serde_json::json!(snapshot)),
+            Err(e) => error(StatusCode::SERVICE_UNAVAILABLE, e),
+        },
+        (Method::GET, ["metrics"]) => {
+            let body = render_metrics(&state.shard_manager, &state.nats_events);
+            Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", "text/plain; version=0.0.4")
+                .body(Full::new(Bytes::from(body)))
+                .expect("static response parts are valid")
+        }
+        (Method::GET, ["config"]) => match state.shard_manager.snapshot().await {
+            Ok(snapshot) => json(StatusCode::OK, serde_json::json!(snapshot.config)),
+            Err(e) => error(StatusCode::SERVICE_UNAVAILABLE, e),
+        },
+        (Method::POST, ["shards", shard_id, "restart"]) => match shard_id.parse::<u32>() {
+            Ok(shard_id) => match restart_shard(&state.shard_manager, shard_id).await {
+                Ok(true) => json(StatusCode::ACCEPTED, serde_json::json!({ "restarting": shard_id })),
+                Ok(false) => json(StatusCode::NOT_FOUND, serde_json::json!({ "error": "shard is not owned by this worker" })),
+                Err(e) => error(StatusCode::SERVICE_UNAVAILABLE, e),
+            },
+            Err(_) => json(StatusCode::BAD_REQUEST, serde_json::json!({ "error": "invalid shard id" })),
+        },
+        (Method::POST, ["drain"]) => {
+            state.drain.notify_one();
+            json(StatusCode::ACCEPTED, serde_json::json!({ "draining": true }))
+        }
+        _ => json(StatusCode::NOT_FOUND, serde_json::json!({ "error": "not found" })),
+    };
+
+    Ok(response)
+}
+
+async fn restart_shard(shard_manager: &ShardManagerHandle, shard_id: u32) -> anyhow::Result<bool> {
+    let status = shard_manager.status().await?;
+    if !status.running_shards.contains(&shard_id) && !status.failed_shards.contains(&shard_id) {
+        return Ok(false);
+    }
+
+    shard_manager.stop_shard(shard_id).await?;
+    shard_manager.start_shard(shard_id).await?;
+    Ok(true)
+}
+
+fn render_metrics(shard_manager: &ShardManagerHandle, nats_events: &ConnectionEvents) -> String {
+    let metrics = shard_manager.metrics();
+    let worker_id = shard_manager.worker_id();
+    let gauges = [
+        ("stratum_shards_owned", "gauge", metrics.shards_owned.load(Ordering::Relaxed) as f64),
+        ("stratum_shards_ready", "gauge", metrics.shards_ready.load(Ordering::Relaxed) as f64),
+        ("stratum_permits_in_use", "gauge", metrics.permits_in_use.load(Ordering::Relaxed) as f64),
+        ("stratum_identifies_in_window", "gauge", metrics.identifies_in_window.load(Ordering::Relaxed) as f64),
+        ("stratum_identify_budget_remaining", "gauge", metrics.identify_budget_remaining.load(Ordering::Relaxed) as f64),
+        ("stratum_restarts_total", "counter", metrics.restarts_total.load(Ordering::Relaxed) as f64),
+        ("stratum_events_published_total", "counter", metrics.events_published_total.load(Ordering::Relaxed) as f64),
+        ("stratum_events_dropped_total", "counter", metrics.events_dropped_total.load(Ordering::Relaxed) as f64),
+        ("stratum_nats_disconnects_total", "counter", nats_events.disconnects() as f64),
+        ("stratum_nats_reconnects_total", "counter", nats_events.reconnects() as f64),
+        ("stratum_nats_slow_consumers_total", "counter", nats_events.slow_consumers() as f64),
+        ("stratum_nats_errors_total", "counter", nats_events.errors() as f64),
+    ];
+
+    let mut body = String::new();
+    for (name, kind, value) in gauges {
+        let _ = writeln!(body, "# TYPE {} {}", name, kind);
+        let _ = writeln!(body, "{}{{worker_id=\"{}\"}} {}", name, worker_id, value);
+    }
+    body
+}
+
+fn json(status: StatusCode, body: serde_json::Value) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .expect("static response parts are valid")
+}
+
+fn error(status: StatusCode, error: anyhow::Error) -> Response<Full<Bytes>> {
+    json(status, serde_json::json!({ "error": error.to_string() }))
+}
@@ -0,0 +1,177 @@
+use clap::{Parser, Subcommand};
+use bedrock_nats::Auth;
+use stratum_config::Config;
+use stratum_shard_manager::{IDENTIFY_INTERVAL, SHARD_START_INTERVAL};
+
+#[derive(Parser)]
+#[command(name = "stratum", about = "Discord gateway ingestion worker for Bedrock", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone, Copy)]
+pub enum Command {
+    /// Connect to Discord and NATS and run the configured shards (default)
+    Run,
+    /// Load and validate configuration from the environment, then exit
+    CheckConfig,
+    /// Print the shards this worker would own without connecting to Discord
+    PrintShardAssignment,
+    /// Print the shards, identify schedule and NATS subjects this worker would use
+    PrintPlan,
+}
+
+pub fn check_config() -> anyhow::Result<()> {
+    let config = Config::from_env()?;
+    config.validate()?;
+
+    println!("Configuration is valid");
+    println!("  worker_id:       {}", config.worker_id);
+    println!("  nats_url:        {}", config.nats_url);
+    match &config.nats_auth {
+        Auth::None => {}
+        Auth::CredentialsFile(path) => println!("  nats auth:       credentials {}", path),
+        Auth::UserPassword { user, .. } => println!("  nats auth:       user {}", user),
+        auth => println!("  nats auth:       {}", auth.method()),
+    }
+    if let Some(ca_file) = &config.nats_tls.ca_file {
+        println!("  nats tls ca:     {}", ca_file);
+    }
+    if let Some((cert_file, _)) = &config.nats_tls.client_certificate {
+        println!("  nats tls cert:   {}", cert_file);
+    }
+    if config.standalone {
+        println!("  shard range:     all recommended shards (standalone)");
+    } else {
+        println!("  shard range:     {}..={}", config.shard_id_start, config.shard_id_end);
+        println!("  assignment:      {:?}", config.shard_assignment);
+        if config.auto_total_shards {
+            println!("  total_shards:    auto");
+        } else {
+            println!("  total_shards:    {}", config.total_shards);
+        }
+    }
+    println!("  max_concurrency: {}", config.max_concurrency);
+    println!("  intents:         {:?}", config.intents);
+    if !config.event_filter.is_empty() {
+        println!("  event filter:    {}", config.event_filter.join(", "));
+    }
+    if config.stream_settings.partitions > 1 {
+        println!("  event streams:   {} partitions", config.stream_settings.partitions);
+    }
+    println!("  startup delay:   {:?} ({:?})", config.startup_delay, config.startup_delay.delay());
+    println!(
+        "  restart policy:  {} attempts, {}s..{}s backoff, {:?} on failure",
+        match config.restart_max_attempts {
+            0 => "unlimited".to_string(),
+            attempts => attempts.to_string(),
+        },
+        config.restart_backoff_base_secs,
+        config.restart_backoff_max_secs,
+        config.restart_failure_action
+    );
+    match config.publish_rate_limit {
+        0 => println!("  publish limit:   unlimited"),
+        rate => println!(
+            "  publish limit:   {}/s, burst {}, {:?} on overflow",
+            rate, config.publish_burst, config.publish_overflow
+        ),
+    }
+
+    Ok(())
+}
+
+pub fn print_shard_assignment() -> anyhow::Result<()> {
+    let config = Config::from_env()?;
+    config.validate()?;
+    if config.auto_total_shards {
+        anyhow::bail!("TOTAL_SHARDS is resolved from Discord at startup, set it explicitly to print an assignment");
+    }
+
+    let shard_ids = stratum_discord::new_shard_manager_config(&config)?.shard_ids;
+
+    println!(
+        "Worker {} owns {} of {} shards",
+        config.worker_id,
+        shard_ids.len(),
+        config.total_shards
+    );
+    for shard_id in shard_ids {
+        println!(
+            "  shard [{}, {}] identify bucket {}",
+            shard_id,
+            config.total_shards,
+            shard_id % config.max_concurrency
+        );
+    }
+
+    Ok(())
+}
+
+pub fn print_plan() -> anyhow::Result<()> {
+    let config = Config::from_env()?;
+    config.validate()?;
+    if config.auto_total_shards {
+        anyhow::bail!("TOTAL_SHARDS is resolved from Discord at startup, set it explicitly to print a plan");
+    }
+
+    let shard_ids: Vec<u32> = stratum_discord::new_shard_manager_config(&config)?.shard_ids.collect();
+    let startup_delay = config.startup_delay.delay();
+
+    println!(
+        "Worker {} owns {} of {} shards ({:?} assignment)",
+        config.worker_id,
+        shard_ids.len(),
+        config.total_shards,
+        config.shard_assignment
+    );
+
+    println!();
+    println!(
+        "Identify schedule (max_concurrency {}, {:?} startup delay{}):",
+        config.max_concurrency,
+        startup_delay,
+        if config.startup_delay.requests_permission() {
+            ", paced further by operator grants"
+        } else {
+            ""
+        }
+    );
+    let mut bucket_free_at = std::collections::HashMap::new();
+    for (index, shard_id) in shard_ids.iter().enumerate() {
+        let bucket = shard_id % config.max_concurrency;
+        let started = startup_delay + SHARD_START_INTERVAL * index as u32;
+        let identify_at = bucket_free_at
+            .get(&bucket)
+            .map_or(started, |free_at: &std::time::Duration| started.max(*free_at));
+        bucket_free_at.insert(bucket, identify_at + IDENTIFY_INTERVAL);
+
+        println!(
+            "  t+{:>4}s  shard [{}, {}] bucket {}",
+            identify_at.as_secs(),
+            shard_id,
+            config.total_shards,
+            bucket
+        );
+    }
+
+    println!();
+    let prefix = &config.subject_prefix;
+    println!("Publishes to:");
+    for shard_id in &shard_ids {
+        println!(
+            "  {0}.shards.{1}.events, {0}.shards.{1}.startup, {0}.shards.{1}.status",
+            prefix, shard_id
+        );
+    }
+    println!("  {0}.workers.heartbeat, {0}.startup.request, {0}.startup.complete", prefix);
+    println!("  {}.operator.reshard.status", prefix);
+
+    println!();
+    println!("Listens on:");
+    println!("  {0}.workers.{1}.status, {0}.workers.{1}.drain", prefix, config.worker_id);
+    println!("  {0}.operator.reshard, {0}.operator.startup, {0}.operator.assignment", prefix);
+
+    Ok(())
+}
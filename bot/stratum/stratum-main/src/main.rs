@@ -2,24 +2,62 @@
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-use std::sync::Arc;
-use stratum_shard_manager::ShardManager;
-use stratum_coordination::ShardManagerInterface;
-use tokio::sync::RwLock;
-use tracing::{error, info, span, Level};
+#[cfg(feature = "admin")]
+mod admin;
+mod cli;
+
+use bedrock_nats::{kv, Connection, RetryPolicy};
+use clap::Parser;
+use cli::{Cli, Command};
+use stratum_coordination::{CoordinationHandler, ShardManagerInterface};
+use stratum_shard_manager::{ShardManager, ShardManagerHandle};
+use tracing::{error, info, span, warn, Level};
 use tracing_subscriber::{EnvFilter, fmt::format::FmtSpan};
 
+const LISTENER_MAX_FAILURES: u32 = 5;
+const LISTENER_BACKOFF_BASE: std::time::Duration = std::time::Duration::from_secs(1);
+const LISTENER_BACKOFF_MAX: std::time::Duration = std::time::Duration::from_secs(30);
+const LISTENER_HEALTHY_AFTER: std::time::Duration = std::time::Duration::from_secs(300);
+const NATS_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
     init_logging()?;
-    
-    let config = stratum_config::Config::from_env()?;
+
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => run().await,
+        Command::CheckConfig => cli::check_config(),
+        Command::PrintShardAssignment => cli::print_shard_assignment(),
+        Command::PrintPlan => cli::print_plan(),
+    }
+}
+
+async fn run() -> anyhow::Result<()> {
+    let mut config = stratum_config::Config::from_env()?;
+    if config.standalone {
+        info!("No shard range configured, running standalone with all recommended shards");
+    }
+    if config.auto_total_shards {
+        stratum_discord::resolve_total_shards(&mut config).await?;
+    }
+    config.validate()?;
     info!("Worker ID: {}", config.worker_id);
 
-    let nats_client = connect_to_nats(&config.nats_url).await?;
-    
-    setup_jetstream(&nats_client).await?;
-    run_application(config, nats_client).await
+    // Connecting and stream setup retry until they succeed, since a worker
+    // can do nothing without NATS.
+    let connection = config.nats_connection().retry(RetryPolicy::forever()).connect().await?;
+
+    stratum_nats::setup_jetstream(
+        &connection,
+        config.event_stream.as_deref(),
+        &config.stream_settings,
+        &config.coordination_stream,
+    )
+    .await?;
+    info!("JetStream setup complete");
+    run_application(config, connection).await
 }
 
 fn init_logging() -> anyhow::Result<()> {
@@ -35,100 +73,392 @@ fn init_logging() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn connect_to_nats(nats_url: &str) -> anyhow::Result<async_nats::Client> {
-    loop {
-        match stratum_nats::connect(nats_url).await {
-            Ok(client) => {
-                info!("Connected to NATS");
-                return Ok(client);
-            }
-            Err(e) => {
-                error!(error = ?e, "Failed to connect to NATS, retrying in 5 seconds");
-                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+async fn run_application(config: stratum_config::Config, connection: Connection) -> anyhow::Result<()> {
+    let main_span = span!(Level::INFO, "main");
+    let _enter = main_span.enter();
+
+    info!("Starting application");
+
+    let heartbeat_interval = std::time::Duration::from_secs(config.heartbeat_interval_secs);
+    #[cfg(feature = "admin")]
+    let admin_addr = config.admin_addr;
+    let signing_key = config.coordination_signing_key.clone().map(String::into_bytes);
+    let resubscribe_attempts = config.coordination_resubscribe_attempts;
+    let consumer_name = config.instance_id.clone();
+    let drain_timeout = std::time::Duration::from_secs(config.drain_timeout_secs);
+    let standalone = config.standalone;
+    let active_standby = config.active_standby;
+    let lease_ttl = std::time::Duration::from_secs(config.lease_ttl_secs);
+    let worker_id = config.worker_id.clone();
+    let nats_client = connection.client().clone();
+    let shard_status = connection
+        .open_bucket(kv::SHARD_STATUS_BUCKET, "Latest connection state of each shard", config.shard_status_bucket)
+        .await?;
+    let coordination = CoordinationHandler::new(nats_client.clone(), &config.subject_prefix)
+        .with_jetstream(connection.jetstream().clone())
+        .with_status_bucket(shard_status)
+        .with_reconnects(connection.events().reconnected());
+    let sessions = stratum_nats::sessions::SessionStore::open(&connection, config.session_bucket).await?;
+    let overrides = stratum_nats::overrides::OverrideStore::open(&connection, config.override_bucket).await?;
+    let leases = if active_standby {
+        Some(stratum_nats::leases::LeaseStore::open(&connection, lease_ttl).await?)
+    } else {
+        None
+    };
+    let (shard_manager, mut manager_task) = ShardManager::new(config, nats_client.clone(), sessions)?
+        .with_overrides(overrides)
+        .start();
+    let drain = std::sync::Arc::new(tokio::sync::Notify::new());
+    // One listener for the whole run, so a signal during the lease wait or
+    // while shards start is not lost between the two selects below.
+    let shutdown_requested = shutdown_signal(&drain);
+    tokio::pin!(shutdown_requested);
+    #[cfg(feature = "admin")]
+    let admin_handle = start_admin_server(admin_addr, &shard_manager, drain.clone(), connection.events().clone());
+
+    // A standby replica stays connected to NATS with its shard manager
+    // started, and only connects shards and reports as the worker once the
+    // active replica's lease lapses.
+    let lease_handle = match &leases {
+        Some(leases) => {
+            info!(worker_id = %worker_id, "Standing by until this replica holds the group lease");
+            let revision = tokio::select! {
+                revision = leases.acquire(&worker_id, &consumer_name) => revision?,
+                _ = &mut shutdown_requested => {
+                    manager_task.abort();
+                    drain_nats(&nats_client).await;
+                    return Ok(());
+                }
+            };
+            Some(start_lease_holder(leases, &worker_id, &consumer_name, revision))
+        }
+        None => None,
+    };
+
+    let heartbeat_handle = start_heartbeat(&shard_manager, &coordination, heartbeat_interval);
+    let status_handle = start_status_responder(&shard_manager, &coordination);
+    let drain_handle = start_drain_listener(&shard_manager, &coordination, signing_key.clone(), drain.clone());
+
+    info!("Starting shard manager for worker: {}", shard_manager.worker_id());
+    shard_manager.start_shards().await?;
+
+    let (reshard_handle, startup_handle, assignment_handle) = if standalone {
+        info!("Standalone mode, not listening for operator coordination");
+        (None, None, None)
+    } else {
+        let (reshard, startup, assignment) = start_coordination_listeners(
+            &shard_manager,
+            &coordination,
+            signing_key,
+            resubscribe_attempts,
+            &consumer_name,
+        );
+        (Some(reshard), Some(startup), Some(assignment))
+    };
+
+    info!("System ready");
+
+    let mut drained = true;
+    let mut draining = false;
+    let mut failed_listener = None;
+    let mut lost_lease = false;
+
+    tokio::select! {
+        result = &mut manager_task => {
+            heartbeat_handle.abort();
+            status_handle.abort();
+            if let Err(e) = result {
+                error!(error = ?e, "Shard manager task failed");
             }
+            drain_nats(&nats_client).await;
+            anyhow::bail!("Shard manager stopped unexpectedly");
+        }
+        _ = &mut shutdown_requested => {
+            drained = drain_worker(&shard_manager, drain_timeout).await;
+            draining = true;
+        }
+        _ = listener(reshard_handle) => {
+            failed_listener = Some("reshard");
+        }
+        _ = listener(startup_handle) => {
+            failed_listener = Some("startup");
+        }
+        _ = listener(assignment_handle) => {
+            failed_listener = Some("assignment");
+        }
+        _ = listener(lease_handle) => {
+            lost_lease = true;
         }
     }
-}
 
-async fn setup_jetstream(nats_client: &async_nats::Client) -> anyhow::Result<()> {
-    loop {
-        match stratum_nats::setup_jetstream(nats_client).await {
-            Ok(_) => {
-                info!("JetStream setup complete");
-                return Ok(());
-            }
-            Err(e) => {
-                error!(error = ?e, "Failed to setup JetStream, retrying in 5 seconds");
-                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-            }
+    heartbeat_handle.abort();
+    status_handle.abort();
+    drain_handle.abort();
+    #[cfg(feature = "admin")]
+    admin_handle.abort();
+
+    if drained {
+        shutdown(&shard_manager).await;
+    } else {
+        manager_task.abort();
+    }
+
+    if let Some(leases) = leases.filter(|_| !lost_lease) {
+        if let Err(e) = leases.release(&worker_id, &consumer_name).await {
+            warn!(error = ?e, "Failed to release group lease");
         }
     }
-}
 
-async fn run_application(config: stratum_config::Config, nats_client: async_nats::Client) -> anyhow::Result<()> {
-    let main_span = span!(Level::INFO, "main");
-    let _enter = main_span.enter();
+    match manager_task.await {
+        Err(e) if !e.is_cancelled() => error!(error = ?e, "Shard manager task failed"),
+        _ => {}
+    }
 
-    info!("Starting application");
+    if draining {
+        if let Err(e) = coordination.report_drained(&worker_id, drained).await {
+            warn!(error = %e, "Failed to report drain complete");
+        }
+    }
 
-    let shard_manager = Arc::new(RwLock::new(
-        ShardManager::new(config, nats_client)?
-    ));
+    drain_nats(&nats_client).await;
 
-    {
-        let mut manager = shard_manager.write().await;
-        info!("Starting shard manager for worker: {}", manager.worker_id());
-        manager.start_shards().await?;
+    if lost_lease {
+        anyhow::bail!("Lost the group lease to another replica");
+    }
+    if let Some(name) = failed_listener {
+        anyhow::bail!("Coordination listener '{}' failed repeatedly", name);
     }
 
-    let (reshard_handle, startup_handle) = start_coordination_listeners(&shard_manager).await;
+    Ok(())
+}
 
-    info!("System ready");
+fn start_coordination_listeners(
+    shard_manager: &ShardManagerHandle,
+    coordination: &CoordinationHandler,
+    signing_key: Option<Vec<u8>>,
+    resubscribe_attempts: u32,
+    consumer_name: &str,
+) -> (tokio::task::JoinHandle<()>, tokio::task::JoinHandle<()>, tokio::task::JoinHandle<()>) {
+    if signing_key.is_none() {
+        warn!("COORDINATION_SIGNING_KEY is not set, accepting unsigned coordination messages");
+    }
 
-    tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            info!("Received shutdown signal");
+    let coordination = coordination
+        .clone()
+        .with_signing_key(signing_key)
+        .with_resubscribe_attempts(resubscribe_attempts)
+        .with_consumer_name(consumer_name);
+
+    let (handler, shard_manager_clone) = (coordination.clone(), shard_manager.clone());
+    let reshard_handle = supervise_listener("reshard", move || {
+        let (coordination, shard_manager) = (handler.clone(), shard_manager_clone.clone());
+        async move {
+            if let Err(e) = coordination.listen_for_reshard_signals(shard_manager).await {
+                error!(error = ?e, "Reshard listener failed");
+            }
+        }
+    });
+
+    let (handler, shard_manager_clone) = (coordination.clone(), shard_manager.clone());
+    let startup_handle = supervise_listener("startup", move || {
+        let (coordination, shard_manager) = (handler.clone(), shard_manager_clone.clone());
+        async move {
+            if let Err(e) = coordination.listen_for_startup_coordination(shard_manager).await {
+                error!(error = ?e, "Startup coordination listener failed");
+            }
         }
-        _ = reshard_handle => {
-            info!("Reshard listener ended");
+    });
+
+    let (handler, shard_manager_clone) = (coordination, shard_manager.clone());
+    let assignment_handle = supervise_listener("assignment", move || {
+        let (coordination, shard_manager) = (handler.clone(), shard_manager_clone.clone());
+        async move {
+            if let Err(e) = coordination.listen_for_shard_assignments(shard_manager).await {
+                error!(error = ?e, "Shard assignment listener failed");
+            }
+        }
+    });
+
+    (reshard_handle, startup_handle, assignment_handle)
+}
+
+/// Runs a coordination listener, restarting it with backoff whenever it
+/// returns or panics. The returned task only finishes once the listener has
+/// failed `LISTENER_MAX_FAILURES` times in a row, which escalates to a worker
+/// shutdown in `run_application`.
+fn supervise_listener<F, Fut>(name: &'static str, listener: F) -> tokio::task::JoinHandle<()>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut failures = 0;
+
+        loop {
+            let started = std::time::Instant::now();
+            let result = tokio::spawn(listener()).await;
+
+            if started.elapsed() >= LISTENER_HEALTHY_AFTER {
+                failures = 0;
+            }
+            failures += 1;
+
+            match result {
+                Err(e) if e.is_panic() => error!(listener = name, failures, "Coordination listener panicked"),
+                _ => warn!(listener = name, failures, "Coordination listener stopped"),
+            }
+
+            if failures >= LISTENER_MAX_FAILURES {
+                error!(listener = name, failures, "Coordination listener keeps failing, giving up");
+                return;
+            }
+
+            let backoff = LISTENER_BACKOFF_BASE
+                .saturating_mul(1 << (failures - 1))
+                .min(LISTENER_BACKOFF_MAX);
+            info!(listener = name, backoff = ?backoff, "Restarting coordination listener");
+            tokio::time::sleep(backoff).await;
         }
-        _ = startup_handle => {
-            info!("Startup coordination listener ended");
+    })
+}
+
+async fn listener(handle: Option<tokio::task::JoinHandle<()>>) {
+    match handle {
+        Some(handle) => {
+            let _ = handle.await;
         }
+        None => std::future::pending().await,
     }
+}
 
-    shutdown(shard_manager).await;
+fn start_lease_holder(
+    leases: &stratum_nats::leases::LeaseStore,
+    worker_id: &str,
+    instance_id: &str,
+    revision: u64,
+) -> tokio::task::JoinHandle<()> {
+    let (leases, worker_id, instance_id) = (leases.clone(), worker_id.to_string(), instance_id.to_string());
+    tokio::spawn(async move {
+        let e = leases.hold(&worker_id, &instance_id, revision).await;
+        error!(error = ?e, "Failed to renew group lease, stopping shards");
+    })
+}
 
-    Ok(())
+fn start_heartbeat(
+    shard_manager: &ShardManagerHandle,
+    coordination: &CoordinationHandler,
+    interval: std::time::Duration,
+) -> tokio::task::JoinHandle<()> {
+    let coordination = coordination.clone();
+    let shard_manager_clone = shard_manager.clone();
+    tokio::spawn(async move {
+        if let Err(e) = coordination.run_heartbeat(shard_manager_clone, interval).await {
+            error!(error = ?e, "Worker heartbeat failed");
+        }
+    })
 }
 
-async fn start_coordination_listeners(
-    shard_manager: &Arc<RwLock<ShardManager>>,
-) -> (tokio::task::JoinHandle<()>, tokio::task::JoinHandle<()>) {
+fn start_status_responder(
+    shard_manager: &ShardManagerHandle,
+    coordination: &CoordinationHandler,
+) -> tokio::task::JoinHandle<()> {
+    let coordination = coordination.clone();
     let shard_manager_clone = shard_manager.clone();
-    let reshard_handle = tokio::spawn(async move {
-        let manager = shard_manager_clone.read().await;
-        let coordination = manager.coordination();
-        if let Err(e) = coordination.listen_for_reshard_signals(shard_manager_clone.clone()).await {
-            error!(error = ?e, "Reshard listener failed");
+    tokio::spawn(async move {
+        if let Err(e) = coordination.serve_status_requests(shard_manager_clone).await {
+            error!(error = ?e, "Worker status responder failed");
         }
-    });
+    })
+}
 
-    let shard_manager_clone2 = shard_manager.clone();
-    let startup_handle = tokio::spawn(async move {
-        let manager = shard_manager_clone2.read().await;
-        let coordination = manager.coordination();
-        if let Err(e) = coordination.listen_for_startup_coordination(shard_manager_clone2.clone()).await {
-            error!(error = ?e, "Startup coordination listener failed");
+#[cfg(feature = "admin")]
+fn start_admin_server(
+    addr: std::net::SocketAddr,
+    shard_manager: &ShardManagerHandle,
+    drain: std::sync::Arc<tokio::sync::Notify>,
+    nats_events: std::sync::Arc<bedrock_nats::events::ConnectionEvents>,
+) -> tokio::task::JoinHandle<()> {
+    let shard_manager_clone = shard_manager.clone();
+    tokio::spawn(async move {
+        if let Err(e) = admin::serve(addr, shard_manager_clone, drain, nats_events).await {
+            error!(error = ?e, "Admin server failed");
         }
-    });
+    })
+}
+
+fn start_drain_listener(
+    shard_manager: &ShardManagerHandle,
+    coordination: &CoordinationHandler,
+    signing_key: Option<Vec<u8>>,
+    drain: std::sync::Arc<tokio::sync::Notify>,
+) -> tokio::task::JoinHandle<()> {
+    let coordination = coordination.clone().with_signing_key(signing_key);
+    let worker_id = shard_manager.worker_id().to_string();
+    tokio::spawn(async move {
+        if let Err(e) = coordination.listen_for_drain_requests(&worker_id, drain).await {
+            error!(error = ?e, "Drain request listener failed");
+        }
+    })
+}
+
+/// Resolves on an interrupt, SIGTERM or drain request. The SIGTERM handler is
+/// installed on the call rather than the first poll, and the returned future
+/// keeps it, so a signal arriving while the future is not being polled is
+/// still seen the next time it is.
+fn shutdown_signal(drain: &tokio::sync::Notify) -> impl std::future::Future<Output = ()> + '_ {
+    #[cfg(unix)]
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .inspect_err(|e| error!(error = ?e, "Failed to install SIGTERM handler"))
+        .ok();
 
-    (reshard_handle, startup_handle)
+    async move {
+        #[cfg(unix)]
+        let terminate = async {
+            match terminate.as_mut() {
+                Some(signal) => {
+                    signal.recv().await;
+                }
+                None => std::future::pending::<()>().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => info!("Received interrupt, draining"),
+            _ = terminate => info!("Received SIGTERM, draining"),
+            _ = drain.notified() => info!("Drain requested, draining"),
+        }
+    }
+}
+
+async fn drain_worker(shard_manager: &ShardManagerHandle, timeout: std::time::Duration) -> bool {
+    if tokio::time::timeout(timeout, shard_manager.drain()).await.is_err() {
+        warn!(timeout = ?timeout, "Drain did not finish in time, shutting down anyway");
+        return false;
+    }
+
+    true
+}
+
+/// Flushes buffered publishes and closes the NATS connection so the last
+/// events and shard statuses reach the server before the process exits.
+async fn drain_nats(nats_client: &async_nats::Client) {
+    let drain = async {
+        nats_client.flush().await?;
+        nats_client.drain().await?;
+        anyhow::Ok(())
+    };
+
+    match tokio::time::timeout(NATS_DRAIN_TIMEOUT, drain).await {
+        Ok(Ok(())) => info!("Flushed pending NATS publishes"),
+        Ok(Err(e)) => warn!(error = %e, "Failed to flush NATS client on shutdown"),
+        Err(_) => warn!(timeout = ?NATS_DRAIN_TIMEOUT, "Timed out flushing NATS client on shutdown"),
+    }
 }
 
-async fn shutdown(shard_manager: Arc<RwLock<ShardManager>>) {
+async fn shutdown(shard_manager: &ShardManagerHandle) {
     info!("Shutting down gracefully");
     
-    let mut manager = shard_manager.write().await;
-    manager.shutdown().await;
+    shard_manager.shutdown().await;
 }
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use async_nats::jetstream::kv;
+use bedrock_nats::kv::{BucketSettings, LEASES_BUCKET};
+use bedrock_nats::Connection;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Lease that makes one replica of a shard group the active one. The entry
+/// for a group expires with the bucket's max age unless its holder renews it.
+#[derive(Clone)]
+pub struct LeaseStore {
+    kv: kv::Store,
+    ttl: Duration,
+}
+
+impl LeaseStore {
+    pub async fn open(connection: &Connection, ttl: Duration) -> Result<Self> {
+        let kv = connection
+            .open_bucket(LEASES_BUCKET, "Active replica of each shard group", BucketSettings::new(1, ttl))
+            .await?;
+        Ok(Self { kv, ttl })
+    }
+
+    /// Waits until `instance_id` holds the lease of `worker_id`, trying again
+    /// every third of the TTL. Returns the revision to renew from.
+    pub async fn acquire(&self, worker_id: &str, instance_id: &str) -> Result<u64> {
+        loop {
+            match self.kv.create(key(worker_id), instance_id.to_string().into()).await {
+                Ok(revision) => {
+                    info!(worker_id, instance_id, "Acquired group lease");
+                    return Ok(revision);
+                }
+                Err(e) if e.kind() == kv::CreateErrorKind::AlreadyExists => {
+                    debug!(worker_id, "Group lease is held by another replica, standing by");
+                }
+                Err(e) => return Err(e.into()),
+            }
+            tokio::time::sleep(self.ttl / 3).await;
+        }
+    }
+
+    /// Renews the lease every third of the TTL and returns once it cannot be
+    /// renewed, after which another replica may hold it.
+    pub async fn hold(&self, worker_id: &str, instance_id: &str, mut revision: u64) -> anyhow::Error {
+        loop {
+            tokio::time::sleep(self.ttl / 3).await;
+            match self.kv.update(key(worker_id), instance_id.to_string().into(), revision).await {
+                Ok(next) => revision = next,
+                Err(e) => return e.into(),
+            }
+        }
+    }
+
+    /// Gives the lease up so a standby replica takes over without waiting
+    /// for it to expire.
+    pub async fn release(&self, worker_id: &str, instance_id: &str) -> Result<()> {
+        let Some(entry) = self.kv.entry(key(worker_id)).await? else {
+            return Ok(());
+        };
+        if entry.operation != kv::Operation::Put || entry.value != instance_id.as_bytes() {
+            return Ok(());
+        }
+        self.kv.delete_expect_revision(key(worker_id), Some(entry.revision)).await?;
+        info!(worker_id, "Released group lease");
+        Ok(())
+    }
+}
+
+fn key(worker_id: &str) -> String {
+    format!("group.{}", worker_id)
+}
@@ -1,66 +1,87 @@
-use anyhow::Result;
-use async_nats;
-use backon::{ExponentialBuilder, Retryable};
-use tracing::{Level, error, info, span};
-
-pub async fn connect(url: &str) -> Result<async_nats::Client> {
-    let operation = || async {
-        info!(url = %url, "Connecting to NATS");
-        async_nats::connect(url).await.map_err(|e| {
-            error!(error = %e, "Failed to connect to NATS, retrying...");
-            e
-        })
-    };
+pub mod leases;
+pub mod overrides;
+pub mod sessions;
+pub mod sink;
 
-    let backoff = ExponentialBuilder::default().with_max_times(10);
-    let client = operation.retry(&backoff).await?;
-    
-    info!("Connected to NATS successfully");
-    Ok(client)
-}
+use anyhow::Result;
+use async_nats::jetstream::stream;
+use backon::Retryable;
+use bedrock_nats::coordination::{self, CoordinationStreamSettings};
+use bedrock_nats::{dedup, partitions, streams, Connection, RetryPolicy};
+use stratum_config::{StreamDiscard, StreamRetention, StreamSettings, StreamStorage};
+use tracing::{Level, error, info, span, warn};
 
-pub async fn setup_jetstream(client: &async_nats::Client) -> Result<()> {
+/// Makes sure the events stream, or each of its partitions, exists. A stream
+/// named by `event_stream` is provisioned by the operator and only waited
+/// for, otherwise the stream is created under the connection's subject
+/// prefix with `settings`, and updated when it exists with different ones.
+/// The coordination stream is then created the same way, with
+/// `coordination_settings`, unless it exists.
+pub async fn setup_jetstream(
+    connection: &Connection,
+    event_stream: Option<&str>,
+    settings: &StreamSettings,
+    coordination_settings: &CoordinationStreamSettings,
+) -> Result<()> {
     let nats_setup_span = span!(Level::INFO, "nats_setup");
     let _enter_nats = nats_setup_span.enter();
 
-    let jetstream = async_nats::jetstream::new(client.clone());
+    let jetstream = connection.jetstream();
+    let subject_prefix = connection.subject_prefix();
+    let stream_name = event_stream
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{}-events", subject_prefix.replace('.', "-")));
 
-    info!("ensuring 'discord-events' stream exists");
+    info!(
+        stream.name = %stream_name,
+        partitions = settings.partitions,
+        operator_managed = event_stream.is_some(),
+        "ensuring events stream exists"
+    );
 
     info!("Checking JetStream availability...");
 
-    let stream_op = || async {
-        jetstream
-            .get_or_create_stream(async_nats::jetstream::stream::Config {
-                name: "discord-events".to_string(),
-                subjects: vec!["discord.shards.>".to_string()],
-                max_messages: 10000,
-                ..Default::default()
-            })
-            .await
-            .map_err(|e| {
-                error!(stream.name = "discord-events", error = %e, "failed to get or create jetstream stream, retrying...");
-                e
-            })
-    };
+    for (partition, name) in (0..).zip(partitions::stream_names(&stream_name, settings.partitions)) {
+        match event_stream {
+            Some(_) => streams::wait_for_stream(jetstream, &name, connection.retry_policy()).await?,
+            None => {
+                let subject = match settings.partitions {
+                    0 | 1 => format!("{}.shards.>", subject_prefix),
+                    _ => partitions::stream_subject(subject_prefix, partition),
+                };
+                let config = stream_config(&name, subject, settings);
+                let stream = streams::ensure_stream(jetstream, &config, connection.retry_policy()).await?;
+                // Storage cannot change on an existing stream; NATS rejects that
+                // update and the stream keeps working with its old settings.
+                if let Err(e) = streams::update_stream(jetstream, &stream.cached_info().config, &config).await {
+                    warn!(stream.name = %name, error = %e, "failed to update events stream settings, keeping the existing ones");
+                }
+            }
+        }
 
-    let backoff = ExponentialBuilder::default()
-        .with_max_times(20)
-        .with_max_delay(std::time::Duration::from_secs(60));
-    
-    stream_op.retry(&backoff).await.map_err(|e| {
-        error!(stream.name = "discord-events", error = %e, "failed to get or create jetstream stream after all retries");
-        e
-    })?;
-    
-    info!(
-        stream.name = "discord-events",
-        "ensured jetstream stream exists"
-    );
+        info!(
+            stream.name = %name,
+            "ensured jetstream stream exists"
+        );
+    }
+
+    // An existing coordination stream keeps its settings, which the operator
+    // may manage. It only keeps a record, so the worker carries on without it.
+    if event_stream.is_none() {
+        let config = coordination::stream_config(subject_prefix, settings.partitions > 1, coordination_settings);
+        match streams::ensure_stream(jetstream, &config, RetryPolicy::retries(2)).await {
+            Ok(_) => info!(stream.name = %config.name, "ensured coordination stream exists"),
+            Err(e) => warn!(stream.name = %config.name, error = %e, "failed to create coordination stream, continuing without it"),
+        }
+    }
 
+    let startup_subject = connection.subject("gateway.startup");
+    let startup_message = "Bot is starting up!";
+    let headers = dedup::with_message_id(None, &dedup::unique_id());
     let publish_op = || async {
-        client
-            .publish("discord.gateway.startup", "Bot is starting up!".into())
+        connection
+            .client()
+            .publish_with_headers(startup_subject.clone(), headers.clone(), startup_message.into())
             .await
             .map_err(|e| {
                 error!(error = %e, "Failed to publish startup message, retrying...");
@@ -68,8 +89,7 @@ pub async fn setup_jetstream(client: &async_nats::Client) -> Result<()> {
             })
     };
 
-    let backoff = ExponentialBuilder::default().with_max_times(10);
-    publish_op.retry(&backoff).await.map_err(|e| {
+    publish_op.retry(backon::ExponentialBuilder::default().with_max_times(10)).await.map_err(|e| {
         error!(error = %e, "Failed to publish startup message after all retries");
         e
     })?;
@@ -77,3 +97,33 @@ pub async fn setup_jetstream(client: &async_nats::Client) -> Result<()> {
     info!("Published startup message");
     Ok(())
 }
+
+fn stream_config(name: &str, subject: String, settings: &StreamSettings) -> stream::Config {
+    stream::Config {
+        name: name.to_string(),
+        subjects: vec![subject],
+        retention: match settings.retention {
+            StreamRetention::Limits => stream::RetentionPolicy::Limits,
+            StreamRetention::Interest => stream::RetentionPolicy::Interest,
+            StreamRetention::WorkQueue => stream::RetentionPolicy::WorkQueue,
+        },
+        max_age: std::time::Duration::from_secs(settings.max_age_secs),
+        max_bytes: settings.max_bytes,
+        max_messages: settings.max_messages,
+        discard: match settings.discard {
+            StreamDiscard::Old => stream::DiscardPolicy::Old,
+            StreamDiscard::New => stream::DiscardPolicy::New,
+        },
+        storage: match settings.storage {
+            StreamStorage::File => stream::StorageType::File,
+            StreamStorage::Memory => stream::StorageType::Memory,
+        },
+        num_replicas: settings.replicas,
+        // NATS rejects a duplicate window longer than the stream's max age.
+        duplicate_window: std::time::Duration::from_secs(match settings.max_age_secs {
+            0 => settings.duplicate_window_secs,
+            max_age_secs => settings.duplicate_window_secs.min(max_age_secs),
+        }),
+        ..Default::default()
+    }
+}
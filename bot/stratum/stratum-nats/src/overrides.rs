@@ -0,0 +1,114 @@
+use anyhow::Result;
+use async_nats::jetstream::kv;
+use bedrock_nats::kv::{BucketSettings, OVERRIDES_BUCKET};
+use bedrock_nats::Connection;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Operator supplied runtime override for a single shard, stored as JSON under
+/// `shard.<id>` in the override bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShardOverride {
+    /// Keep the gateway connection but stop publishing events.
+    pub paused: bool,
+    /// Dispatch event types (such as `TYPING_START`) that are not published.
+    pub event_filter: Vec<String>,
+    /// Fraction of the remaining events to publish, between 0 and 1.
+    pub sample_rate: f64,
+}
+
+impl Default for ShardOverride {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            event_filter: Vec::new(),
+            sample_rate: 1.0,
+        }
+    }
+}
+
+pub type ShardOverrides = HashMap<u32, ShardOverride>;
+
+const WATCH_BACKOFF_BASE: Duration = Duration::from_secs(1);
+const WATCH_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+pub struct OverrideStore {
+    kv: kv::Store,
+}
+
+impl OverrideStore {
+    pub async fn open(connection: &Connection, settings: BucketSettings) -> Result<Self> {
+        let kv = connection
+            .open_bucket(OVERRIDES_BUCKET, "Per-shard runtime overrides set by operators", settings)
+            .await?;
+        Ok(Self { kv })
+    }
+
+    /// Mirrors the bucket into `overrides` for as long as the future runs,
+    /// watching it again with backoff whenever the watch ends or fails.
+    pub async fn watch(&self, overrides: watch::Sender<ShardOverrides>) {
+        let mut backoff = WATCH_BACKOFF_BASE;
+        loop {
+            let started = Instant::now();
+            let result = self.watch_once(&overrides).await;
+            // A watch that held for a while ended on its own, not because
+            // the bucket is unreachable.
+            if started.elapsed() > WATCH_BACKOFF_MAX {
+                backoff = WATCH_BACKOFF_BASE;
+            }
+            match result {
+                Ok(()) => warn!(retry_in = ?backoff, "Shard override watch ended"),
+                Err(e) => warn!(error = %e, retry_in = ?backoff, "Shard override watch failed"),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(WATCH_BACKOFF_MAX);
+        }
+    }
+
+    /// Applies the current values of the bucket and then every change to
+    /// `overrides`, until the watch ends.
+    async fn watch_once(&self, overrides: &watch::Sender<ShardOverrides>) -> Result<()> {
+        let mut entries = self.kv.watch_with_history("shard.*").await?;
+
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            let Some(shard_id) = entry
+                .key
+                .strip_prefix("shard.")
+                .and_then(|id| id.parse::<u32>().ok())
+            else {
+                warn!(key = %entry.key, "Ignoring override with an invalid key");
+                continue;
+            };
+
+            let shard_override = match entry.operation {
+                kv::Operation::Put => match serde_json::from_slice::<ShardOverride>(&entry.value) {
+                    Ok(shard_override) => Some(shard_override),
+                    Err(e) => {
+                        warn!(shard_id, error = %e, "Ignoring malformed shard override");
+                        continue;
+                    }
+                },
+                kv::Operation::Delete | kv::Operation::Purge => None,
+            };
+
+            info!(shard_id, shard_override = ?shard_override, "Shard override changed");
+            overrides.send_modify(|overrides| match shard_override {
+                Some(shard_override) => {
+                    overrides.insert(shard_id, shard_override);
+                }
+                None => {
+                    overrides.remove(&shard_id);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
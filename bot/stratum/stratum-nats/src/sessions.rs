@@ -0,0 +1,140 @@
+use anyhow::Result;
+use async_nats::jetstream::kv;
+use bedrock_nats::kv::{BucketSettings, SESSIONS_BUCKET};
+use bedrock_nats::Connection;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    Owned,
+    Released,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardSession {
+    pub shard_id: u32,
+    pub total_shards: u32,
+    pub owner: String,
+    pub state: SessionState,
+    pub session_id: Option<String>,
+    pub sequence: Option<u64>,
+    pub resume_url: Option<String>,
+    pub updated_at: u64,
+}
+
+impl ShardSession {
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(unix_now().saturating_sub(self.updated_at))
+    }
+
+    pub fn is_resumable(&self, total_shards: u32) -> bool {
+        self.state == SessionState::Released
+            && self.total_shards == total_shards
+            && self.session_id.is_some()
+            && self.sequence.is_some()
+    }
+}
+
+#[derive(Clone)]
+pub struct SessionStore {
+    kv: kv::Store,
+}
+
+impl SessionStore {
+    pub async fn open(connection: &Connection, settings: BucketSettings) -> Result<Self> {
+        let kv = connection
+            .open_bucket(SESSIONS_BUCKET, "Resumable gateway sessions for shard handoff", settings)
+            .await?;
+        Ok(Self { kv })
+    }
+
+    pub async fn get(&self, shard_id: u32) -> Result<Option<ShardSession>> {
+        match self.kv.get(key(shard_id)).await? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes).ok()),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn put(&self, session: &ShardSession) -> Result<()> {
+        let mut session = session.clone();
+        session.updated_at = unix_now();
+        self.kv
+            .put(key(session.shard_id), serde_json::to_vec(&session)?.into())
+            .await?;
+        Ok(())
+    }
+
+    /// Waits for the holder of the shard to release its session. The wait
+    /// lasts at least `timeout`, and as long as the holder keeps refreshing
+    /// its claim, so a pod that is still draining is waited for. It ends once
+    /// the claim has not been refreshed for `stale_after`.
+    pub async fn wait_for_release(
+        &self,
+        shard_id: u32,
+        timeout: Duration,
+        stale_after: Duration,
+    ) -> Result<Option<ShardSession>> {
+        let mut watch = self.kv.watch(key(shard_id)).await?;
+        let mut deadline = Instant::now() + timeout;
+
+        if let Some(session) = self.get(shard_id).await? {
+            if session.state == SessionState::Released {
+                return Ok(Some(session));
+            }
+            deadline = deadline.max(Instant::now() + stale_after.saturating_sub(session.age()));
+        }
+
+        loop {
+            let entry = tokio::select! {
+                entry = watch.next() => entry,
+                _ = tokio::time::sleep_until(deadline) => return Ok(None),
+            };
+            let entry = match entry {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => {
+                    warn!(shard_id, error = %e, "Session watch failed");
+                    return Ok(None);
+                }
+                None => return Ok(None),
+            };
+            match serde_json::from_slice::<ShardSession>(&entry.value) {
+                Ok(session) if session.state == SessionState::Released => return Ok(Some(session)),
+                Ok(_) => deadline = deadline.max(Instant::now() + stale_after),
+                Err(_) => {}
+            }
+        }
+    }
+
+    pub async fn wait_for_claim(&self, shard_id: u32, previous_owner: &str, timeout: Duration) -> Result<bool> {
+        let mut watch = self.kv.watch(key(shard_id)).await?;
+
+        let claimed = async {
+            while let Some(Ok(entry)) = watch.next().await {
+                if let Ok(session) = serde_json::from_slice::<ShardSession>(&entry.value) {
+                    if session.state == SessionState::Owned && session.owner != previous_owner {
+                        return true;
+                    }
+                }
+            }
+            false
+        };
+
+        Ok(tokio::time::timeout(timeout, claimed).await.unwrap_or(false))
+    }
+}
+
+fn key(shard_id: u32) -> String {
+    format!("shard.{}", shard_id)
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
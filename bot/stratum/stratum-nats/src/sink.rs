@@ -0,0 +1,92 @@
+use anyhow::Result;
+use async_nats::header::NATS_MESSAGE_ID;
+use async_nats::HeaderMap;
+use bedrock_nats::dedup;
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use std::sync::{Arc, Mutex};
+
+/// Destination for gateway events and shard status updates.
+///
+/// The shard runners and manager only ever publish through this trait, so the
+/// transport can be swapped out (or replaced with an in-memory sink) without
+/// touching the gateway handling.
+pub trait EventSink: Send + Sync {
+    fn publish(&self, subject: String, headers: Option<HeaderMap>, payload: Bytes) -> BoxFuture<'_, Result<()>>;
+
+    /// Publishes with a Nats-Msg-Id of its own, so the stream stores the
+    /// message even when it repeats an earlier one.
+    fn publish_unique(&self, subject: String, payload: Bytes) -> BoxFuture<'_, Result<()>> {
+        let headers = dedup::with_message_id(None, &dedup::unique_id());
+        self.publish(subject, Some(headers), payload)
+    }
+
+    /// Waits until everything published so far has left the process.
+    fn flush(&self) -> BoxFuture<'_, Result<()>>;
+}
+
+impl EventSink for async_nats::Client {
+    fn publish(&self, subject: String, headers: Option<HeaderMap>, payload: Bytes) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            match headers {
+                Some(headers) => self.publish_with_headers(subject, headers, payload).await?,
+                None => async_nats::Client::publish(self, subject, payload).await?,
+            }
+            Ok(())
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            async_nats::Client::flush(self).await?;
+            Ok(())
+        })
+    }
+}
+
+/// A message an [`InMemorySink`] was given.
+#[derive(Debug, Clone)]
+pub struct PublishedMessage {
+    pub subject: String,
+    pub headers: Option<HeaderMap>,
+    pub payload: Bytes,
+}
+
+impl PublishedMessage {
+    /// The Nats-Msg-Id the message was published under, if any.
+    pub fn message_id(&self) -> Option<&str> {
+        self.headers.as_ref()?.get(NATS_MESSAGE_ID).map(|id| id.as_str())
+    }
+}
+
+/// Keeps every message published to it, so shards can be run and checked
+/// without a NATS server.
+#[derive(Debug, Clone, Default)]
+pub struct InMemorySink {
+    published: Arc<Mutex<Vec<PublishedMessage>>>,
+}
+
+impl InMemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything published so far, in order.
+    pub fn published(&self) -> Vec<PublishedMessage> {
+        self.published.lock().expect("in-memory sink poisoned").clone()
+    }
+}
+
+impl EventSink for InMemorySink {
+    fn publish(&self, subject: String, headers: Option<HeaderMap>, payload: Bytes) -> BoxFuture<'_, Result<()>> {
+        self.published
+            .lock()
+            .expect("in-memory sink poisoned")
+            .push(PublishedMessage { subject, headers, payload });
+        Box::pin(async { Ok(()) })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
@@ -1,27 +1,175 @@
 use anyhow::Result;
-use async_nats;
+use bytes::Bytes;
 use backon::{ExponentialBuilder, Retryable};
+use async_nats::HeaderMap;
+use bedrock_nats::{dedup, partitions};
 use futures_util::StreamExt;
-use tracing::{Level, error, info, span, trace};
-use twilight_gateway::{Message, Shard, error::ReceiveMessageErrorType};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use stratum_nats::overrides::{ShardOverride, ShardOverrides};
+use stratum_nats::sessions::{SessionState, SessionStore, ShardSession};
+use stratum_nats::sink::EventSink;
+use tokio::sync::{mpsc, watch};
+use tracing::{Level, error, info, span, trace, warn};
+use twilight_gateway::{CloseFrame, Message, Shard, error::ReceiveMessageErrorType};
+use twilight_model::gateway::event::GatewayEventDeserializer;
+use twilight_model::gateway::CloseCode;
 
-pub async fn runner(mut shard: Shard, nats_client: async_nats::Client) -> Result<()> {
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+const SESSION_REFRESH_INTERVAL: Duration = Duration::from_secs(20);
+/// Events a runner holds while its publisher waits on the publish limiter.
+const PUBLISH_QUEUE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    Stopping,
+    HandingOff,
+}
+
+/// Discord closed the gateway connection with a code that cannot be recovered
+/// by reconnecting, so restarting the runner would only loop.
+#[derive(Debug)]
+pub struct FatalClose {
+    pub code: u16,
+    pub reason: String,
+}
+
+impl std::fmt::Display for FatalClose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match CloseCode::try_from(self.code) {
+            Ok(CloseCode::DisallowedIntents) => write!(
+                f,
+                "Discord rejected the configured intents (close code 4014), enable them for the application or remove them from INTENTS"
+            ),
+            _ => write!(f, "Discord closed the gateway with non-recoverable code {}: {}", self.code, self.reason),
+        }
+    }
+}
+
+impl std::error::Error for FatalClose {}
+
+#[derive(Debug, Default)]
+pub struct WorkerMetrics {
+    pub shards_owned: AtomicI64,
+    pub shards_ready: AtomicI64,
+    pub restarts_total: AtomicU64,
+    pub permits_in_use: AtomicI64,
+    pub events_published_total: AtomicU64,
+    pub events_dropped_total: AtomicU64,
+    pub identifies_in_window: AtomicU64,
+    pub identify_budget_remaining: AtomicI64,
+}
+
+impl WorkerMetrics {
+    pub fn record_ready(&self, ready: bool) {
+        let delta = if ready { 1 } else { -1 };
+        self.shards_ready.fetch_add(delta, Ordering::Relaxed);
+    }
+}
+
+/// Token bucket shared by every runner of a worker so one busy shard group
+/// cannot flood the NATS cluster.
+#[derive(Debug)]
+pub struct PublishLimiter {
+    rate: f64,
+    burst: f64,
+    drop_overflow: bool,
+    bucket: std::sync::Mutex<(f64, Instant)>,
+}
+
+impl PublishLimiter {
+    pub fn new(rate: u32, burst: u32, drop_overflow: bool) -> Self {
+        Self {
+            rate: rate as f64,
+            burst: burst as f64,
+            drop_overflow,
+            bucket: std::sync::Mutex::new((burst as f64, Instant::now())),
+        }
+    }
+
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().expect("publish limiter poisoned");
+        let (tokens, refilled_at) = &mut *bucket;
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*refilled_at).as_secs_f64() * self.rate).min(self.burst);
+        *refilled_at = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - *tokens) / self.rate))
+        }
+    }
+
+    /// Returns whether the event may be published, waiting for a token unless
+    /// the limiter drops overflow.
+    pub async fn admit(&self) -> bool {
+        loop {
+            match self.try_acquire() {
+                Ok(()) => return true,
+                Err(_) if self.drop_overflow => return false,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ShardMetrics {
+    pub events_published: AtomicU64,
+    pub restarts: AtomicU64,
+    pub worker: Arc<WorkerMetrics>,
+}
+
+impl ShardMetrics {
+    pub fn new(worker: Arc<WorkerMetrics>) -> Self {
+        Self {
+            events_published: AtomicU64::new(0),
+            restarts: AtomicU64::new(0),
+            worker,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RunnerContext {
+    pub sink: Arc<dyn EventSink>,
+    pub overrides: watch::Receiver<ShardOverrides>,
+    pub publish_limiter: Option<Arc<PublishLimiter>>,
+    pub sessions: SessionStore,
+    pub instance_id: String,
+    pub subject_prefix: String,
+    pub event_filter: Vec<String>,
+    /// Events stream partitions, which decide the subject events go to
+    pub event_partitions: u32,
+}
+
+pub async fn runner(
+    mut shard: Shard,
+    context: RunnerContext,
+    mut shutdown: watch::Receiver<RunState>,
+    ready: &watch::Sender<bool>,
+    metrics: &ShardMetrics,
+) -> Result<()> {
     let runner_span = span!(
         Level::INFO,
         "discord_shard_runner",
         shard.id = shard.id().number()
     );
     let _enter = runner_span.enter();
+    let sink = context.sink.as_ref();
 
     info!("Starting Discord shard runner");
 
-    let subject = format!("discord.shards.{}.startup", shard.id().number());
-    let startup_message = format!("Shard {} is starting", shard.id().number());
+    let subject = format!("{}.shards.{}.startup", context.subject_prefix, shard.id().number());
+    let startup_message = Bytes::from(format!("Shard {} is starting", shard.id().number()));
+    let headers = dedup::with_message_id(None, &dedup::unique_id());
 
     let publish_op = || async {
-        nats_client
-            .publish(subject.clone(), startup_message.clone().into())
-            .await
+        sink.publish(subject.clone(), Some(headers.clone()), startup_message.clone()).await
     };
 
     let backoff = ExponentialBuilder::default().with_max_times(5);
@@ -31,28 +179,85 @@ pub async fn runner(mut shard: Shard, nats_client: async_nats::Client) -> Result
         "Published shard startup message to NATS"
     );
 
-    while let Some(event) = shard.next().await {
+    let mut session_refresh = tokio::time::interval(SESSION_REFRESH_INTERVAL);
+    let mut overrides = context.overrides.clone();
+    let mut shard_override = current_override(&mut overrides, shard.id().number());
+    let mut sampled = 0.0;
+
+    // Events are published from a queue so that waiting on the publish
+    // limiter only stops the shard from being polled, which would miss
+    // gateway heartbeats, once the queue is full and overflow blocks.
+    let (queue, queued) = mpsc::channel(PUBLISH_QUEUE);
+    let block_overflow = context.publish_limiter.as_ref().is_some_and(|limiter| !limiter.drop_overflow);
+    let publisher = publish_queued(queued, sink, context.publish_limiter.as_deref(), metrics);
+    tokio::pin!(publisher);
+
+    let stopped = loop {
+        let event = tokio::select! {
+            biased;
+            _ = shutdown.changed() => break Some(*shutdown.borrow()),
+            result = &mut publisher => return result,
+            _ = session_refresh.tick() => {
+                if let Some(session) = shard_session(&shard, &context, SessionState::Owned) {
+                    if let Err(e) = context.sessions.put(&session).await {
+                        warn!(error = %e, "Failed to refresh shard session");
+                    }
+                }
+                continue;
+            }
+            event = shard.next() => event,
+        };
+
+        let Some(event) = event else {
+            break None;
+        };
+
+        let identified = shard.state().is_identified();
+        if ready.send_if_modified(|ready| std::mem::replace(ready, identified) != identified) {
+            metrics.worker.record_ready(identified);
+        }
+
         let event_span = span!(Level::TRACE, "discord_event_handling");
         let _enter_event = event_span.enter();
         match event {
             Ok(message) => {
                 let Some(bytes) = (match message {
-                    Message::Text(text) => Some(text.into_bytes()),
+                    Message::Text(text) => Some(Bytes::from(text)),
+                    Message::Close(Some(frame))
+                        if CloseCode::try_from(frame.code).is_ok_and(|code| !code.can_reconnect()) =>
+                    {
+                        return Err(FatalClose {
+                            code: frame.code,
+                            reason: frame.reason.into_owned(),
+                        }
+                        .into());
+                    }
                     Message::Close(_) => None,
                 }) else {
                     continue;
                 };
 
-                let subject = format!("discord.shards.{}.events", shard.id().number());
-                let publish_op = || async {
-                    nats_client
-                        .publish(subject.clone(), bytes.clone().into())
-                        .await
-                };
+                if overrides.has_changed().unwrap_or(false) {
+                    shard_override = current_override(&mut overrides, shard.id().number());
+                    info!(shard_override = ?shard_override, "Applied shard override");
+                }
+                if !admit(&shard_override, &context.event_filter, &bytes, &mut sampled) {
+                    continue;
+                }
 
-                let backoff = ExponentialBuilder::default().with_max_times(5);
-                publish_op.retry(&backoff).await?;
-                trace!(subject = %subject, "Published event to NATS");
+                let subject =
+                    partitions::event_subject(&context.subject_prefix, shard.id().number(), context.event_partitions);
+                let headers = dedup::with_message_id(None, &event_message_id(&shard, &subject, &bytes));
+                if block_overflow {
+                    // Waiting for room stops the shard from being polled, so a
+                    // queue that stays full costs the session its heartbeats.
+                    tokio::select! {
+                        _ = queue.send((subject, headers, bytes)) => {}
+                        result = &mut publisher => return result,
+                    }
+                } else if queue.try_send((subject, headers, bytes)).is_err() {
+                    metrics.worker.events_dropped_total.fetch_add(1, Ordering::Relaxed);
+                }
             }
             Err(e) => {
                 error!(error = %e, "Error processing event from Discord");
@@ -64,7 +269,230 @@ pub async fn runner(mut shard: Shard, nats_client: async_nats::Client) -> Result
                 }
             }
         }
+    };
+
+    if let Some(state) = stopped {
+        close_shard(&mut shard, &context, state).await;
+    }
+
+    drop(queue);
+    match tokio::time::timeout(CLOSE_TIMEOUT, publisher).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!(error = %e, "Failed to publish queued events"),
+        Err(_) => warn!("Timed out publishing queued events"),
+    }
+
+    if let Some(state) = stopped {
+        publish_final_status(&context.subject_prefix, shard.id().number(), sink, state).await;
     }
 
     Ok(())
 }
+
+/// Publishes queued events once the limiter admits them, until the queue is
+/// closed and empty. Events the limiter drops are counted.
+async fn publish_queued(
+    mut queued: mpsc::Receiver<(String, HeaderMap, Bytes)>,
+    sink: &dyn EventSink,
+    limiter: Option<&PublishLimiter>,
+    metrics: &ShardMetrics,
+) -> Result<()> {
+    while let Some((subject, headers, bytes)) = queued.recv().await {
+        if let Some(limiter) = limiter {
+            if !limiter.admit().await {
+                metrics.worker.events_dropped_total.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        }
+
+        let publish_op = || async {
+            sink.publish(subject.clone(), Some(headers.clone()), bytes.clone()).await
+        };
+
+        let backoff = ExponentialBuilder::default().with_max_times(5);
+        publish_op.retry(&backoff).await?;
+        metrics.events_published.fetch_add(1, Ordering::Relaxed);
+        metrics.worker.events_published_total.fetch_add(1, Ordering::Relaxed);
+        trace!(subject = %subject, "Published event to NATS");
+    }
+
+    Ok(())
+}
+
+/// Nats-Msg-Id of a gateway message. Dispatches are identified by their
+/// session and sequence, which a resumed session replays unchanged, so events
+/// sent again after a resume or a publish retry are stored once. During a
+/// reshard the old and new generations see the same dispatches under their
+/// own sessions, so those are not deduplicated.
+fn event_message_id(shard: &Shard, subject: &str, payload: &[u8]) -> String {
+    let sequence = std::str::from_utf8(payload)
+        .ok()
+        .and_then(GatewayEventDeserializer::from_json)
+        .and_then(|event| event.sequence());
+
+    match (shard.session(), sequence) {
+        (Some(session), Some(sequence)) => format!("{}-{}-{}", shard.id().number(), session.id(), sequence),
+        _ => dedup::message_id(subject, payload),
+    }
+}
+
+fn current_override(overrides: &mut watch::Receiver<ShardOverrides>, shard_id: u32) -> ShardOverride {
+    overrides.borrow_and_update().get(&shard_id).cloned().unwrap_or_default()
+}
+
+/// Decides whether an event is published under the cluster's event filter and
+/// the shard's override. Sampling is deterministic: `sampled` accumulates the
+/// rate and an event goes out every time it reaches one.
+fn admit(shard_override: &ShardOverride, event_filter: &[String], payload: &[u8], sampled: &mut f64) -> bool {
+    if shard_override.paused {
+        return false;
+    }
+
+    if !event_filter.is_empty() || !shard_override.event_filter.is_empty() {
+        let filtered = std::str::from_utf8(payload)
+            .ok()
+            .and_then(GatewayEventDeserializer::from_json)
+            .is_some_and(|event| {
+                event.event_type().is_some_and(|event_type| {
+                    event_filter
+                        .iter()
+                        .chain(&shard_override.event_filter)
+                        .any(|name| name == event_type)
+                })
+            });
+        if filtered {
+            return false;
+        }
+    }
+
+    if shard_override.sample_rate < 1.0 {
+        *sampled += shard_override.sample_rate.max(0.0);
+        if *sampled < 1.0 {
+            return false;
+        }
+        *sampled -= 1.0;
+    }
+
+    true
+}
+
+fn shard_session(shard: &Shard, context: &RunnerContext, state: SessionState) -> Option<ShardSession> {
+    let session = shard.session()?;
+
+    Some(ShardSession {
+        shard_id: shard.id().number(),
+        total_shards: shard.id().total(),
+        owner: context.instance_id.clone(),
+        state,
+        session_id: Some(session.id().to_string()),
+        sequence: Some(session.sequence()),
+        resume_url: shard.resume_url().map(str::to_string),
+        updated_at: 0,
+    })
+}
+
+async fn close_shard(shard: &mut Shard, context: &RunnerContext, state: RunState) {
+    let handoff = state == RunState::HandingOff;
+    info!(handoff, "Shutdown requested, closing gateway connection");
+
+    let released = if handoff {
+        shard_session(shard, context, SessionState::Released)
+    } else {
+        None
+    };
+
+    shard.close(if handoff { CloseFrame::RESUME } else { CloseFrame::NORMAL });
+
+    let drain = async {
+        while let Some(message) = shard.next().await {
+            if let Ok(Message::Close(_)) = message {
+                break;
+            }
+        }
+    };
+
+    if tokio::time::timeout(CLOSE_TIMEOUT, drain).await.is_err() {
+        warn!("Timed out waiting for gateway close acknowledgement");
+    }
+
+    if let Some(session) = released {
+        match context.sessions.put(&session).await {
+            Ok(()) => info!(sequence = ?session.sequence, "Released resumable session for handoff"),
+            Err(e) => warn!(error = %e, "Failed to store session for handoff"),
+        }
+    }
+}
+
+async fn publish_final_status(subject_prefix: &str, shard_id: u32, sink: &dyn EventSink, state: RunState) {
+    let subject = format!("{}.shards.{}.status", subject_prefix, shard_id);
+    let status = if state == RunState::HandingOff { "handed_off" } else { "stopped" };
+    let status = format!(r#"{{"shard_id":{},"status":"{}"}}"#, shard_id, status);
+
+    if let Err(e) = sink.publish_unique(subject, status.into()).await {
+        warn!(error = %e, "Failed to publish final shard status");
+    }
+
+    if let Err(e) = sink.flush().await {
+        warn!(error = %e, "Failed to flush NATS client after shard stop");
+    }
+
+    info!("Shard runner stopped cleanly");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stratum_nats::sink::InMemorySink;
+
+    fn queue_of(events: &[&str]) -> mpsc::Receiver<(String, HeaderMap, Bytes)> {
+        let (queue, queued) = mpsc::channel(PUBLISH_QUEUE);
+        for (sequence, event) in events.iter().enumerate() {
+            let headers = dedup::with_message_id(None, &format!("0-session-{}", sequence));
+            queue
+                .try_send(("discord.shards.0.events".to_string(), headers, Bytes::from(event.to_string())))
+                .unwrap();
+        }
+        queued
+    }
+
+    #[tokio::test]
+    async fn publishes_queued_events_in_order() {
+        let sink = InMemorySink::new();
+        let metrics = ShardMetrics::new(Arc::default());
+
+        publish_queued(queue_of(&["first", "second"]), &sink, None, &metrics).await.unwrap();
+
+        let published = sink.published();
+        let payloads: Vec<_> = published.iter().map(|message| message.payload.clone()).collect();
+        assert_eq!(payloads, ["first", "second"]);
+        assert_eq!(published[1].message_id(), Some("0-session-1"));
+        assert_eq!(metrics.events_published.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.worker.events_published_total.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn counts_events_the_limiter_drops() {
+        let sink = InMemorySink::new();
+        let metrics = ShardMetrics::new(Arc::default());
+        let limiter = PublishLimiter::new(1, 1, true);
+
+        publish_queued(queue_of(&["first", "second"]), &sink, Some(&limiter), &metrics).await.unwrap();
+
+        assert_eq!(sink.published().len(), 1);
+        assert_eq!(metrics.worker.events_dropped_total.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn repeated_final_statuses_keep_their_own_ids() {
+        let sink = InMemorySink::new();
+
+        publish_final_status("discord", 3, &sink, RunState::Stopping).await;
+        publish_final_status("discord", 3, &sink, RunState::Stopping).await;
+
+        let published = sink.published();
+        assert_eq!(published.len(), 2);
+        assert_eq!(published[0].subject, "discord.shards.3.status");
+        assert_eq!(published[0].payload, published[1].payload);
+        assert_ne!(published[0].message_id(), published[1].message_id());
+    }
+}
@@ -1,175 +1,1331 @@
-use stratum_config::Config;
-use stratum_coordination::{CoordinationHandler, ShardManagerInterface};
+use stratum_config::{Config, FailureAction, OverflowPolicy, StartupDelay};
+use stratum_coordination::{
+    CoordinationHandler, ReshardProgress, ReshardStage, SessionStartLimit, ShardManagerInterface,
+    StartupCoordination, StartupPermission,
+};
 use stratum_discord;
-use stratum_runner;
+use stratum_nats::overrides::{OverrideStore, ShardOverrides};
+use stratum_nats::sessions::{SessionState, SessionStore, ShardSession};
+use stratum_nats::sink::EventSink;
+use stratum_runner::{FatalClose, PublishLimiter, RunState, RunnerContext, ShardMetrics, WorkerMetrics};
 use async_nats::Client as NatsClient;
-use std::collections::{HashMap, HashSet};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::task::JoinHandle;
-use tracing::{error, info};
+use twilight_gateway::{ConfigBuilder, Session};
+use twilight_model::gateway::ShardId;
+use tracing::{error, info, warn};
 
-pub struct ShardManager {
-    config: Config,
-    nats_client: NatsClient,
-    coordination: CoordinationHandler,
-    shard_handles: HashMap<u32, JoinHandle<()>>,
-    gateway_config: std::sync::Arc<twilight_gateway::Config>,
-    startup_semaphore: std::sync::Arc<tokio::sync::Semaphore>,
+const COMMAND_BUFFER: usize = 64;
+const SHARD_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Minimum spacing Discord enforces between identifies in one bucket.
+pub const IDENTIFY_INTERVAL: Duration = Duration::from_secs(5);
+/// Spacing between shard starts when a worker boots.
+pub const SHARD_START_INTERVAL: Duration = Duration::from_secs(2);
+/// Age of a claim past which its holder counts as gone, three of its refreshes.
+const SESSION_STALE_AFTER: Duration = Duration::from_secs(60);
+const RESHARD_READY_TIMEOUT: Duration = Duration::from_secs(300);
+const RESHARD_CATCH_UP_GRACE: Duration = Duration::from_secs(10);
+const SESSION_START_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub enum ShardCommand {
+    StartShard {
+        shard_id: u32,
+    },
+    StopShard {
+        shard_id: u32,
+    },
+    UpdateTotal {
+        total_shards: u32,
+        reply: oneshot::Sender<anyhow::Result<()>>,
+    },
+    Status {
+        reply: oneshot::Sender<ShardManagerStatus>,
+    },
+    Snapshot {
+        reply: oneshot::Sender<WorkerSnapshot>,
+    },
+    ApplyCoordination {
+        coordination: StartupCoordination,
+        reply: oneshot::Sender<anyhow::Result<()>>,
+    },
+    Assign {
+        total_shards: u32,
+        shard_ids: Vec<u32>,
+        reply: oneshot::Sender<anyhow::Result<()>>,
+    },
+    /// Starts the next bucket of a reshard's new generation and replies with
+    /// the readiness of its shards.
+    ReshardBucket {
+        shard_ids: Vec<u32>,
+        reply: oneshot::Sender<Vec<watch::Receiver<bool>>>,
+    },
+    ReshardFinished {
+        total_shards: u32,
+        shards_stopped: u32,
+    },
+    Handoff {
+        reply: oneshot::Sender<()>,
+    },
+    Drain {
+        reply: oneshot::Sender<()>,
+    },
+    Shutdown {
+        reply: oneshot::Sender<()>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ShardManagerStatus {
+    pub worker_id: String,
+    pub total_shards: u32,
+    pub assigned_shards: Vec<u32>,
+    pub running_shards: Vec<u32>,
+    pub failed_shards: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShardSnapshot {
+    pub shard_id: u32,
+    pub state: &'static str,
+    pub uptime_secs: u64,
+    pub events_published: u64,
+    pub events_per_sec: f64,
+    pub restarts: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigSnapshot {
+    pub instance_id: String,
+    pub shard_id_start: u32,
+    pub shard_id_end: u32,
+    pub max_concurrency: u32,
+    pub shard_assignment: String,
+    pub restart_max_attempts: u32,
+    pub signed_coordination: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerSnapshot {
+    pub worker_id: String,
+    pub total_shards: u32,
+    pub assigned_shards: Vec<u32>,
+    pub failed_shards: Vec<u32>,
+    pub shards: Vec<ShardSnapshot>,
+    pub config: ConfigSnapshot,
 }
 
-impl ShardManagerInterface for ShardManager {
+#[derive(Clone)]
+pub struct ShardManagerHandle {
+    sender: mpsc::Sender<ShardCommand>,
+    worker_id: String,
+    startup_delay: StartupDelay,
+    metrics: Arc<WorkerMetrics>,
+}
+
+impl ShardManagerInterface for ShardManagerHandle {
     fn worker_id(&self) -> &str {
-        &self.config.worker_id
+        &self.worker_id
+    }
+
+    async fn update_shards(&self, new_shard_count: u32) -> anyhow::Result<()> {
+        self.update_total(new_shard_count).await
+    }
+
+    async fn running_shards(&self) -> anyhow::Result<Vec<u32>> {
+        Ok(self.status().await?.running_shards)
     }
 
-    async fn update_shards(&mut self, new_total_shards: u32) -> anyhow::Result<()> {
+    async fn status_snapshot(&self) -> anyhow::Result<serde_json::Value> {
+        Ok(serde_json::to_value(self.snapshot().await?)?)
+    }
+
+    async fn apply_startup_coordination(&self, coordination: StartupCoordination) -> anyhow::Result<()> {
+        let (reply, response) = oneshot::channel();
+        self.send(ShardCommand::ApplyCoordination { coordination, reply }).await?;
+        response.await?
+    }
+
+    async fn assign_shards(&self, total_shards: u32, shard_ids: Vec<u32>) -> anyhow::Result<()> {
+        let (reply, response) = oneshot::channel();
+        self.send(ShardCommand::Assign { total_shards, shard_ids, reply }).await?;
+        response.await?
+    }
+}
+
+impl ShardManagerHandle {
+    async fn send(&self, command: ShardCommand) -> anyhow::Result<()> {
+        self.sender
+            .send(command)
+            .await
+            .map_err(|_| anyhow::anyhow!("Shard manager is not running"))
+    }
+
+    pub fn metrics(&self) -> Arc<WorkerMetrics> {
+        self.metrics.clone()
+    }
+
+    pub async fn start_shards(&self) -> anyhow::Result<()> {
+        let status = self.status().await?;
+        let startup_delay = self.startup_delay.delay();
+
         info!(
-            current_shards = self.config.total_shards,
-            new_shards = new_total_shards,
-            "Updating shard configuration,"
+            "Starting shards: {:?}, with startup delay: {:?}",
+            status.assigned_shards,
+            startup_delay
         );
 
-        self.config.total_shards = new_total_shards;
-        
-        let new_shard_manager_config = stratum_discord::new_shard_manager_config(&self.config)?;
-        let new_shard_ids: HashSet<u32> = new_shard_manager_config.shard_ids.into_iter().collect();
-        let current_shard_ids: HashSet<u32> = self.shard_handles.keys().cloned().collect();
+        if startup_delay > std::time::Duration::ZERO {
+            info!(
+                worker_id = %status.worker_id,
+                delay_seconds = startup_delay.as_secs(),
+                "Waiting before starting shards to respect global concurrency"
+            );
+            tokio::time::sleep(startup_delay).await;
+        }
 
-        for shard_id in current_shard_ids.difference(&new_shard_ids) {
-            self.stop_shard(*shard_id).await;
+        for shard_id in status.assigned_shards {
+            self.start_shard(shard_id).await?;
+            tokio::time::sleep(SHARD_START_INTERVAL).await;
         }
 
-        for shard_id in new_shard_ids.difference(&current_shard_ids) {
-            self.start_shard(*shard_id).await;
+        Ok(())
+    }
+
+    pub async fn start_shard(&self, shard_id: u32) -> anyhow::Result<()> {
+        self.send(ShardCommand::StartShard { shard_id }).await
+    }
+
+    pub async fn stop_shard(&self, shard_id: u32) -> anyhow::Result<()> {
+        self.send(ShardCommand::StopShard { shard_id }).await
+    }
+
+    pub async fn update_total(&self, total_shards: u32) -> anyhow::Result<()> {
+        let (reply, response) = oneshot::channel();
+        self.send(ShardCommand::UpdateTotal { total_shards, reply }).await?;
+        response.await?
+    }
+
+    pub async fn status(&self) -> anyhow::Result<ShardManagerStatus> {
+        let (reply, response) = oneshot::channel();
+        self.send(ShardCommand::Status { reply }).await?;
+        Ok(response.await?)
+    }
+
+    pub async fn snapshot(&self) -> anyhow::Result<WorkerSnapshot> {
+        let (reply, response) = oneshot::channel();
+        self.send(ShardCommand::Snapshot { reply }).await?;
+        Ok(response.await?)
+    }
+
+    pub async fn handoff(&self) {
+        let (reply, response) = oneshot::channel();
+        if self.send(ShardCommand::Handoff { reply }).await.is_ok() {
+            let _ = response.await;
         }
+    }
 
-        info!(
-            active_shards = ?new_shard_ids,
-            "Shard update complete"
-        );
+    pub async fn drain(&self) {
+        let (reply, response) = oneshot::channel();
+        if self.send(ShardCommand::Drain { reply }).await.is_ok() {
+            let _ = response.await;
+        }
+    }
+
+    pub async fn shutdown(&self) {
+        let (reply, response) = oneshot::channel();
+        if self.send(ShardCommand::Shutdown { reply }).await.is_ok() {
+            let _ = response.await;
+        }
+    }
+}
+
+struct ShardTask {
+    handle: JoinHandle<()>,
+    shutdown: watch::Sender<RunState>,
+    ready: watch::Receiver<bool>,
+    metrics: Arc<ShardMetrics>,
+    started_at: Instant,
+}
+
+impl ShardTask {
+    fn snapshot(&self, shard_id: u32) -> ShardSnapshot {
+        let uptime = self.started_at.elapsed();
+        let events_published = self.metrics.events_published.load(Ordering::Relaxed);
+        let state = if *self.ready.borrow() {
+            "connected"
+        } else if self.handle.is_finished() {
+            "stopped"
+        } else {
+            "connecting"
+        };
+
+        ShardSnapshot {
+            shard_id,
+            state,
+            uptime_secs: uptime.as_secs(),
+            events_published,
+            events_per_sec: events_published as f64 / uptime.as_secs_f64().max(1.0),
+            restarts: self.metrics.restarts.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn stop(mut self, shard_id: u32, state: RunState) {
+        let _ = self.shutdown.send(state);
+
+        match tokio::time::timeout(SHARD_STOP_TIMEOUT, &mut self.handle).await {
+            Ok(Ok(())) => info!(shard_id, "Shard runner exited cleanly"),
+            Ok(Err(e)) => error!(shard_id, error = ?e, "Shard runner task failed while stopping"),
+            Err(_) => {
+                warn!(shard_id, "Shard runner did not stop in time, aborting");
+                self.handle.abort();
+            }
+        }
+    }
+}
+
+/// A reshard whose new generation is still coming up in [`reshard`].
+struct Reshard {
+    task: JoinHandle<()>,
+    cancel: oneshot::Sender<()>,
+    reply: oneshot::Sender<anyhow::Result<()>>,
+    total_shards: u32,
+    shards_reidentified: u32,
+}
+
+#[derive(Clone, Copy)]
+struct RestartPolicy {
+    max_attempts: u32,
+    backoff_base: Duration,
+    backoff_max: Duration,
+}
+
+impl RestartPolicy {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            max_attempts: config.restart_max_attempts,
+            backoff_base: Duration::from_secs(config.restart_backoff_base_secs),
+            backoff_max: Duration::from_secs(config.restart_backoff_max_secs),
+        }
+    }
+
+    fn exhausted(&self, attempts: u32) -> bool {
+        self.max_attempts != 0 && attempts > self.max_attempts
+    }
+
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.backoff_base.saturating_mul(factor).min(self.backoff_max)
+    }
+}
+
+#[derive(Clone)]
+struct ShardRuntime {
+    runner: RunnerContext,
+    coordination: std::sync::Arc<CoordinationHandler>,
+    gateway_config: std::sync::Arc<twilight_gateway::Config>,
+    startup_semaphore: std::sync::Arc<tokio::sync::Semaphore>,
+    identify_budget: Arc<IdentifyBudget>,
+    worker_id: String,
+    request_permission: bool,
+    permission_timeout: Duration,
+    handoff_timeout: Duration,
+    restart: RestartPolicy,
+    failures: mpsc::UnboundedSender<ShardId>,
+}
+
+/// Identifies this worker may still perform in the current session start
+/// window, as last reported by the operator. Without a report every identify
+/// is allowed and only counted.
+#[derive(Default)]
+struct IdentifyBudget {
+    window: std::sync::Mutex<Option<IdentifyWindow>>,
+}
+
+struct IdentifyWindow {
+    total: u32,
+    remaining: u32,
+    resets_at: Instant,
+}
+
+impl IdentifyBudget {
+    fn update(&self, limit: SessionStartLimit, metrics: &WorkerMetrics) {
+        let mut window = self.window.lock().expect("identify budget poisoned");
+        *window = Some(IdentifyWindow {
+            total: limit.total,
+            remaining: limit.remaining,
+            resets_at: Instant::now() + Duration::from_millis(limit.reset_after_ms),
+        });
+        metrics.identifies_in_window.store(0, Ordering::Relaxed);
+        metrics.identify_budget_remaining.store(limit.remaining as i64, Ordering::Relaxed);
+    }
+
+    /// Takes one identify from the budget, or returns how long to wait for
+    /// the window to reset when it is exhausted.
+    fn try_identify(&self, metrics: &WorkerMetrics) -> Result<(), Duration> {
+        let mut window = self.window.lock().expect("identify budget poisoned");
+        let Some(window) = window.as_mut() else {
+            metrics.identifies_in_window.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        if now >= window.resets_at {
+            window.remaining = window.total;
+            window.resets_at = now + SESSION_START_WINDOW;
+            metrics.identifies_in_window.store(0, Ordering::Relaxed);
+        }
+
+        if window.remaining == 0 {
+            return Err(window.resets_at - now);
+        }
 
+        window.remaining -= 1;
+        metrics.identifies_in_window.fetch_add(1, Ordering::Relaxed);
+        metrics.identify_budget_remaining.store(window.remaining as i64, Ordering::Relaxed);
         Ok(())
     }
 }
 
+struct TrackedPermit<'a> {
+    _permit: tokio::sync::SemaphorePermit<'a>,
+    metrics: &'a WorkerMetrics,
+}
+
+impl<'a> TrackedPermit<'a> {
+    fn new(permit: tokio::sync::SemaphorePermit<'a>, metrics: &'a WorkerMetrics) -> Self {
+        metrics.permits_in_use.fetch_add(1, Ordering::Relaxed);
+        Self { _permit: permit, metrics }
+    }
+}
+
+impl Drop for TrackedPermit<'_> {
+    fn drop(&mut self) {
+        self.metrics.permits_in_use.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub struct ShardManager {
+    config: Config,
+    nats_client: NatsClient,
+    sink: Arc<dyn EventSink>,
+    coordination: CoordinationHandler,
+    sessions: SessionStore,
+    override_store: Option<OverrideStore>,
+    overrides: watch::Sender<ShardOverrides>,
+    shard_handles: HashMap<u32, ShardTask>,
+    assigned_shards: Option<BTreeSet<u32>>,
+    failed_shards: BTreeSet<u32>,
+    reshard: Option<Reshard>,
+    draining: bool,
+    failure_sender: mpsc::UnboundedSender<ShardId>,
+    failures: mpsc::UnboundedReceiver<ShardId>,
+    metrics: Arc<WorkerMetrics>,
+    gateway_config: std::sync::Arc<twilight_gateway::Config>,
+    startup_semaphore: std::sync::Arc<tokio::sync::Semaphore>,
+    identify_budget: Arc<IdentifyBudget>,
+    publish_limiter: Option<Arc<PublishLimiter>>,
+}
+
 impl ShardManager {
-    pub fn new(config: Config, nats_client: NatsClient) -> anyhow::Result<Self> {
+    pub fn new(config: Config, nats_client: NatsClient, sessions: SessionStore) -> anyhow::Result<Self> {
         let gateway_config = stratum_discord::new_shard_manager_config(&config)?.gateway_config;
         
         let startup_semaphore = std::sync::Arc::new(
             tokio::sync::Semaphore::new(config.max_concurrency as usize)
         );
         
-        let coordination = CoordinationHandler::new(nats_client.clone());
-        
+        let coordination = CoordinationHandler::new(nats_client.clone(), &config.subject_prefix)
+            .with_jetstream(config.jetstream_api.context(&nats_client));
+        let (failure_sender, failures) = mpsc::unbounded_channel();
+        let publish_limiter = (config.publish_rate_limit > 0).then(|| {
+            Arc::new(PublishLimiter::new(
+                config.publish_rate_limit,
+                config.publish_burst,
+                config.publish_overflow == OverflowPolicy::Drop,
+            ))
+        });
+        let metrics = Arc::new(WorkerMetrics::default());
+        // -1 until the operator reports a session start limit.
+        metrics.identify_budget_remaining.store(-1, Ordering::Relaxed);
+
         Ok(Self {
             config,
+            sink: Arc::new(nats_client.clone()),
             nats_client,
             coordination,
+            sessions,
+            override_store: None,
+            overrides: watch::channel(ShardOverrides::new()).0,
             shard_handles: HashMap::new(),
+            assigned_shards: None,
+            failed_shards: BTreeSet::new(),
+            reshard: None,
+            draining: false,
+            failure_sender,
+            failures,
+            metrics,
             gateway_config,
             startup_semaphore,
+            identify_budget: Arc::new(IdentifyBudget::default()),
+            publish_limiter,
         })
     }
 
-    fn calculate_startup_delay(&self) -> std::time::Duration {
-        let group_number = self.config.worker_id
-            .strip_prefix("stratum-group-")
-            .and_then(|s| s.parse::<u32>().ok())
-            .unwrap_or(0);
-        
-        std::time::Duration::from_secs(group_number as u64 * 10)
+    /// Publishes gateway events and shard statuses through `sink` instead of
+    /// the NATS client. Coordination and sessions still go through NATS.
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.sink = sink;
+        self
     }
 
-    pub async fn start_shards(&mut self) -> anyhow::Result<()> {
-        let shard_manager_config = stratum_discord::new_shard_manager_config(&self.config)?;
-        
-        let startup_delay = self.calculate_startup_delay();
-        
-        info!(
-            "Starting shards: {:?}, with startup delay: {:?}",
-            shard_manager_config.shard_ids,
-            startup_delay
-        );
-        
-        if startup_delay > std::time::Duration::ZERO {
+    /// Applies per-shard overrides from `store` to the runners as they change.
+    pub fn with_overrides(mut self, store: OverrideStore) -> Self {
+        self.override_store = Some(store);
+        self
+    }
+
+    pub fn spawn(config: Config, nats_client: NatsClient, sessions: SessionStore) -> anyhow::Result<(ShardManagerHandle, JoinHandle<()>)> {
+        Ok(Self::new(config, nats_client, sessions)?.start())
+    }
+
+    pub fn start(self) -> (ShardManagerHandle, JoinHandle<()>) {
+        let (sender, receiver) = mpsc::channel(COMMAND_BUFFER);
+        let handle = ShardManagerHandle {
+            sender,
+            worker_id: self.config.worker_id.clone(),
+            startup_delay: self.config.startup_delay,
+            metrics: self.metrics.clone(),
+        };
+
+        let commands = handle.sender.downgrade();
+        let task = tokio::spawn(self.run(receiver, commands));
+
+        (handle, task)
+    }
+
+    async fn run(mut self, receiver: mpsc::Receiver<ShardCommand>, commands: mpsc::WeakSender<ShardCommand>) {
+        info!(worker_id = %self.config.worker_id, "Shard manager started");
+
+        let override_watch = self.override_store.take().map(|store| {
+            let overrides = self.overrides.clone();
+            tokio::spawn(async move { store.watch(overrides).await })
+        });
+        self.serve(receiver, commands).await;
+
+        if let Some(override_watch) = override_watch {
+            override_watch.abort();
+        }
+    }
+
+    async fn serve(&mut self, mut receiver: mpsc::Receiver<ShardCommand>, commands: mpsc::WeakSender<ShardCommand>) {
+        loop {
+            let command = tokio::select! {
+                command = receiver.recv() => command,
+                Some(shard_id) = self.failures.recv() => {
+                    if self.shard_failed(shard_id).await {
+                        return;
+                    }
+                    continue;
+                }
+            };
+
+            let Some(command) = command else {
+                break;
+            };
+
+            match command {
+                ShardCommand::StartShard { shard_id } if self.draining => {
+                    warn!(shard_id, "Worker is draining, not starting shard");
+                }
+                ShardCommand::UpdateTotal { reply, .. } | ShardCommand::Assign { reply, .. } if self.draining => {
+                    let _ = reply.send(Err(anyhow::anyhow!("Worker is draining")));
+                }
+                ShardCommand::UpdateTotal { reply, .. } if self.reshard.is_some() => {
+                    let _ = reply.send(Err(anyhow::anyhow!("A reshard is already in progress")));
+                }
+                ShardCommand::StartShard { shard_id } => self.start_shard(shard_id),
+                ShardCommand::StopShard { shard_id } => self.stop_shard(shard_id).await,
+                ShardCommand::UpdateTotal { total_shards, reply } => {
+                    self.update_shards(total_shards, reply, commands.clone()).await;
+                }
+                ShardCommand::ReshardBucket { shard_ids, reply } => {
+                    if let Some(ready) = self.start_reshard_bucket(&shard_ids).await {
+                        let _ = reply.send(ready);
+                    }
+                }
+                ShardCommand::ReshardFinished { total_shards, shards_stopped } => {
+                    self.finish_reshard(total_shards, shards_stopped).await;
+                }
+                ShardCommand::Status { reply } => {
+                    let _ = reply.send(self.status());
+                }
+                ShardCommand::Snapshot { reply } => {
+                    let _ = reply.send(self.snapshot());
+                }
+                ShardCommand::ApplyCoordination { coordination, reply } => {
+                    let _ = reply.send(self.apply_coordination(coordination));
+                }
+                ShardCommand::Assign { total_shards, shard_ids, reply } => {
+                    let _ = reply.send(self.assign(total_shards, shard_ids).await);
+                }
+                ShardCommand::Handoff { reply } => {
+                    self.handoff().await;
+                    let _ = reply.send(());
+                }
+                ShardCommand::Drain { reply } => {
+                    self.drain().await;
+                    let _ = reply.send(());
+                }
+                ShardCommand::Shutdown { reply } => {
+                    self.shutdown().await;
+                    let _ = reply.send(());
+                    return;
+                }
+            }
+        }
+
+        warn!(worker_id = %self.config.worker_id, "All shard manager handles dropped, stopping");
+        self.shutdown().await;
+    }
+
+    fn record_owned(&self) {
+        self.metrics.shards_owned.store(self.shard_handles.len() as i64, Ordering::Relaxed);
+    }
+
+    fn assigned_shard_ids(&self) -> anyhow::Result<Vec<u32>> {
+        match &self.assigned_shards {
+            Some(assigned) => Ok(assigned.iter().copied().collect()),
+            None => Ok(stratum_discord::new_shard_manager_config(&self.config)?.shard_ids.collect()),
+        }
+    }
+
+    fn status(&self) -> ShardManagerStatus {
+        let assigned_shards = self.assigned_shard_ids().unwrap_or_default();
+
+        let mut running_shards: Vec<u32> = self.shard_handles.keys().copied().collect();
+        running_shards.sort_unstable();
+
+        ShardManagerStatus {
+            worker_id: self.config.worker_id.clone(),
+            total_shards: self.config.total_shards,
+            assigned_shards,
+            running_shards,
+            failed_shards: self.failed_shards.iter().copied().collect(),
+        }
+    }
+
+    fn snapshot(&self) -> WorkerSnapshot {
+        let status = self.status();
+        let mut shards: Vec<ShardSnapshot> = self
+            .shard_handles
+            .iter()
+            .map(|(shard_id, task)| task.snapshot(*shard_id))
+            .collect();
+        shards.sort_unstable_by_key(|shard| shard.shard_id);
+
+        WorkerSnapshot {
+            worker_id: status.worker_id,
+            total_shards: status.total_shards,
+            assigned_shards: status.assigned_shards,
+            failed_shards: status.failed_shards,
+            shards,
+            config: ConfigSnapshot {
+                instance_id: self.config.instance_id.clone(),
+                shard_id_start: self.config.shard_id_start,
+                shard_id_end: self.config.shard_id_end,
+                max_concurrency: self.config.max_concurrency,
+                shard_assignment: format!("{:?}", self.config.shard_assignment),
+                restart_max_attempts: self.config.restart_max_attempts,
+                signed_coordination: self.config.coordination_signing_key.is_some(),
+            },
+        }
+    }
+
+    fn apply_coordination(&mut self, coordination: StartupCoordination) -> anyhow::Result<()> {
+        if coordination.max_concurrency == 0 {
+            anyhow::bail!("Operator sent max_concurrency of 0");
+        }
+
+        if coordination.max_concurrency != self.config.max_concurrency {
             info!(
-                worker_id = %self.config.worker_id,
-                delay_seconds = startup_delay.as_secs(),
-                "Waiting before starting shards to respect global concurrency"
+                current = self.config.max_concurrency,
+                new = coordination.max_concurrency,
+                "Resizing identify semaphore from operator coordination"
             );
-            tokio::time::sleep(startup_delay).await;
+            self.resize_startup_semaphore(coordination.max_concurrency);
         }
-        
-        for shard_id_u32 in shard_manager_config.shard_ids {
-            self.start_shard(shard_id_u32).await;
-            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+        if let Some(limit) = coordination.session_start_limit {
+            info!(
+                total = limit.total,
+                remaining = limit.remaining,
+                reset_after_ms = limit.reset_after_ms,
+                "Updated identify budget from operator coordination"
+            );
+            self.identify_budget.update(limit, &self.metrics);
         }
-        
+
+        if coordination.total_shards != self.config.total_shards {
+            warn!(
+                local = self.config.total_shards,
+                operator = coordination.total_shards,
+                "Operator total_shards differs from local configuration"
+            );
+        }
+
+        let assignment = coordination
+            .shard_groups
+            .iter()
+            .find(|group| group.deployment_name == self.config.worker_id);
+
+        match assignment {
+            Some(group) if group.shard_start != self.config.shard_id_start || group.shard_end != self.config.shard_id_end => {
+                warn!(
+                    worker_id = %self.config.worker_id,
+                    local_range = ?(self.config.shard_id_start..=self.config.shard_id_end),
+                    operator_range = ?(group.shard_start..=group.shard_end),
+                    "Operator shard assignment differs from local configuration"
+                );
+            }
+            Some(_) => info!(worker_id = %self.config.worker_id, "Shard assignment matches operator coordination"),
+            None => warn!(worker_id = %self.config.worker_id, "Worker is not part of the operator's shard groups"),
+        }
+
         Ok(())
     }
 
-    async fn start_shard(&mut self, shard_id_u32: u32) {
-        if self.shard_handles.contains_key(&shard_id_u32) {
-            info!(shard_id = shard_id_u32, worker_id = %self.config.worker_id, "Shard already running, skipping");
+    /// Resizes the identify semaphore in place, since running supervisors
+    /// hold on to it. Permits in use when it shrinks are retired as they
+    /// come back.
+    fn resize_startup_semaphore(&mut self, max_concurrency: u32) {
+        let current = self.config.max_concurrency as usize;
+        let new = max_concurrency as usize;
+        self.config.max_concurrency = max_concurrency;
+
+        if new > current {
+            self.startup_semaphore.add_permits(new - current);
             return;
         }
 
-        let nats_client_clone = self.nats_client.clone();
-        let gateway_config_clone = self.gateway_config.clone();
-        let total_shards = self.config.total_shards;
-        let worker_id = self.config.worker_id.clone();
-        let startup_semaphore = self.startup_semaphore.clone();
-        let coordination = CoordinationHandler::new(nats_client_clone.clone());
-
-        let handle = tokio::spawn(async move {
-            let shard_id = twilight_model::gateway::ShardId::new(shard_id_u32, total_shards);
-            
-            loop {
-                if let Err(e) = coordination.request_startup_permission(&worker_id, shard_id_u32).await {
-                    error!(worker_id = %worker_id, shard_id = shard_id.number(), error = ?e, "Failed to request startup permission");
+        let excess = (current - new).saturating_sub(self.startup_semaphore.forget_permits(current - new));
+        if excess > 0 {
+            let semaphore = self.startup_semaphore.clone();
+            tokio::spawn(async move {
+                if let Ok(permits) = semaphore.acquire_many_owned(excess as u32).await {
+                    permits.forget();
                 }
-                
-                let _permit = startup_semaphore.acquire().await.expect("Semaphore closed");
-                
-                info!(shard_id = shard_id.number(), worker_id = %worker_id, "Acquired startup permit, starting runner");
-                
-                let shard = twilight_gateway::Shard::with_config(shard_id, (*gateway_config_clone).clone());
-                let nats_client_for_runner = nats_client_clone.clone();
+            });
+        }
+    }
 
-                let result = stratum_runner::runner(shard, nats_client_for_runner).await;
-                
-                if let Err(e) = coordination.notify_startup_complete(&worker_id, shard_id_u32).await {
-                    error!(worker_id = %worker_id, shard_id = shard_id.number(), error = ?e, "Failed to notify startup complete");
-                }
+    async fn assign(&mut self, total_shards: u32, shard_ids: Vec<u32>) -> anyhow::Result<()> {
+        if total_shards != self.config.total_shards {
+            anyhow::bail!(
+                "Assignment is for {} total shards but this worker runs {}",
+                total_shards,
+                self.config.total_shards
+            );
+        }
 
-                if let Err(e) = result {
-                    error!(shard_id = shard_id.number(), worker_id = %worker_id, error = ?e, "Runner failed, restarting");
-                    
-                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-                }
+        let assigned: BTreeSet<u32> = shard_ids.into_iter().filter(|id| *id < total_shards).collect();
+        let running: BTreeSet<u32> = self.shard_handles.keys().copied().collect();
+
+        let shed: Vec<u32> = running.difference(&assigned).copied().collect();
+        let adopt: Vec<u32> = assigned.difference(&running).copied().collect();
+
+        info!(worker_id = %self.config.worker_id, shed = ?shed, adopt = ?adopt, "Applying shard assignment");
+
+        for shard_id in shed {
+            self.stop_shard(shard_id).await;
+        }
+        for shard_id in adopt {
+            self.start_shard(shard_id);
+        }
+
+        self.failed_shards.retain(|shard_id| assigned.contains(shard_id));
+        self.assigned_shards = Some(assigned);
+
+        Ok(())
+    }
+
+    fn reshard_progress(&self, stage: ReshardStage, shards_stopped: u32, shards_reidentified: u32) -> ReshardProgress {
+        let shards_total = self.assigned_shard_ids().map(|ids| ids.len() as u32).unwrap_or(0);
+
+        ReshardProgress {
+            worker_id: self.config.worker_id.clone(),
+            total_shards: self.config.total_shards,
+            stage,
+            shards_stopped,
+            shards_reidentified,
+            shards_total,
+            message: None,
+        }
+    }
+
+    async fn report_progress(&self, progress: ReshardProgress) {
+        if let Err(e) = self.coordination.report_reshard_progress(&progress).await {
+            warn!(error = %e, stage = ?progress.stage, "Failed to report reshard progress");
+        }
+    }
+
+    /// Starts bringing up a generation of shards for `new_total_shards` in a
+    /// task of its own, which reports back through `commands` so the manager
+    /// keeps answering status queries while the new shards identify.
+    async fn update_shards(
+        &mut self,
+        new_total_shards: u32,
+        reply: oneshot::Sender<anyhow::Result<()>>,
+        commands: mpsc::WeakSender<ShardCommand>,
+    ) {
+        if new_total_shards == self.config.total_shards {
+            info!(total_shards = new_total_shards, "Shard count unchanged, nothing to re-identify");
+            self.report_progress(self.reshard_progress(ReshardStage::Completed, 0, 0)).await;
+            let _ = reply.send(Ok(()));
+            return;
+        }
+
+        info!(
+            current_shards = self.config.total_shards,
+            new_shards = new_total_shards,
+            "Updating shard configuration, re-identifying all shards"
+        );
+
+        // A count below this worker's shard range is refused rather than
+        // leaving it fewer shards than it was assigned.
+        let mut resharded = self.config.clone();
+        resharded.total_shards = new_total_shards;
+        if let Err(e) = resharded.validate() {
+            self.reshard_failed(reply, e).await;
+            return;
+        }
+
+        self.config = resharded;
+        self.report_progress(self.reshard_progress(ReshardStage::Started, 0, 0)).await;
+
+        let new_shard_manager_config = match stratum_discord::new_shard_manager_config(&self.config) {
+            Ok(config) => config,
+            Err(e) => {
+                self.reshard_failed(reply, e).await;
+                return;
             }
+        };
+        let mut new_shard_ids: Vec<u32> = new_shard_manager_config.shard_ids.collect();
+        new_shard_ids.sort_unstable();
+
+        // Make before break: the previous generation keeps serving events
+        // while the new one identifies, and is only torn down once every new
+        // shard is connected, so a reshard never leaves a gap in the stream.
+        // Until then both generations publish every guild's dispatches, and
+        // mantle receives them twice.
+        let previous: Vec<(u32, ShardTask)> = self.shard_handles.drain().collect();
+        self.failed_shards.clear();
+        self.assigned_shards = None;
+        self.record_owned();
+
+        let bucket_size = self.config.max_concurrency.max(1) as usize;
+        let buckets = new_shard_ids.chunks(bucket_size).map(<[u32]>::to_vec).collect();
+        let (cancel, cancelled) = oneshot::channel();
+
+        self.reshard = Some(Reshard {
+            task: tokio::spawn(reshard(commands, buckets, previous, new_total_shards, cancelled)),
+            cancel,
+            reply,
+            total_shards: new_total_shards,
+            shards_reidentified: 0,
         });
+    }
+
+    async fn start_reshard_bucket(&mut self, shard_ids: &[u32]) -> Option<Vec<watch::Receiver<bool>>> {
+        if self.reshard.is_none() || self.draining {
+            return None;
+        }
+
+        for shard_id in shard_ids {
+            self.start_shard(*shard_id);
+        }
+        let ready = shard_ids
+            .iter()
+            .filter_map(|shard_id| self.shard_handles.get(shard_id))
+            .map(|task| task.ready.clone())
+            .collect();
+
+        let shards_reidentified = {
+            let reshard = self.reshard.as_mut()?;
+            reshard.shards_reidentified += shard_ids.len() as u32;
+            reshard.shards_reidentified
+        };
+
+        info!(shards = ?shard_ids, "Started shard bucket for new shard count");
+        self.report_progress(self.reshard_progress(
+            ReshardStage::BucketReidentified,
+            0,
+            shards_reidentified,
+        )).await;
+
+        Some(ready)
+    }
+
+    async fn finish_reshard(&mut self, total_shards: u32, shards_stopped: u32) {
+        // A reshard cancelled just as it finished still reports in; ignore it.
+        let reshard = match self.reshard.take() {
+            Some(reshard) if reshard.total_shards == total_shards => reshard,
+            other => {
+                self.reshard = other;
+                return;
+            }
+        };
+
+        self.report_progress(self.reshard_progress(
+            ReshardStage::ShardsStopped,
+            shards_stopped,
+            reshard.shards_reidentified,
+        )).await;
+
+        info!(
+            active_shards = ?self.shard_handles.keys().collect::<BTreeSet<_>>(),
+            retired_shards = shards_stopped,
+            total_shards,
+            "Shard update complete"
+        );
+        self.report_progress(self.reshard_progress(
+            ReshardStage::Completed,
+            shards_stopped,
+            reshard.shards_reidentified,
+        )).await;
+
+        let _ = reshard.reply.send(Ok(()));
+    }
+
+    /// Stops a reshard still in progress, retiring its previous generation.
+    async fn cancel_reshard(&mut self) {
+        let Some(reshard) = self.reshard.take() else {
+            return;
+        };
+
+        let _ = reshard.cancel.send(());
+        if let Err(e) = reshard.task.await {
+            error!(error = ?e, "Reshard task failed while cancelling");
+        }
+        self.reshard_failed(reshard.reply, anyhow::anyhow!("Reshard interrupted")).await;
+    }
+
+    async fn reshard_failed(&self, reply: oneshot::Sender<anyhow::Result<()>>, error: anyhow::Error) {
+        error!(error = ?error, worker_id = %self.config.worker_id, "Failed to update shards");
+        let mut progress = self.reshard_progress(ReshardStage::Failed, 0, 0);
+        progress.message = Some(error.to_string());
+        self.report_progress(progress).await;
+        let _ = reply.send(Err(error));
+    }
+
+    fn runtime(&self) -> ShardRuntime {
+        ShardRuntime {
+            runner: RunnerContext {
+                sink: self.sink.clone(),
+                overrides: self.overrides.subscribe(),
+                publish_limiter: self.publish_limiter.clone(),
+                sessions: self.sessions.clone(),
+                instance_id: self.config.instance_id.clone(),
+                subject_prefix: self.config.subject_prefix.clone(),
+                event_filter: self.config.event_filter.clone(),
+                event_partitions: self.config.stream_settings.partitions,
+            },
+            coordination: std::sync::Arc::new(
+                CoordinationHandler::new(self.nats_client.clone(), &self.config.subject_prefix)
+                    .with_jetstream(self.config.jetstream_api.context(&self.nats_client))
+                    .with_signing_key(self.config.coordination_signing_key.clone().map(String::into_bytes)),
+            ),
+            gateway_config: self.gateway_config.clone(),
+            startup_semaphore: self.startup_semaphore.clone(),
+            identify_budget: self.identify_budget.clone(),
+            worker_id: self.config.worker_id.clone(),
+            request_permission: self.config.startup_delay.requests_permission(),
+            permission_timeout: Duration::from_secs(self.config.startup_permission_timeout_secs),
+            handoff_timeout: Duration::from_secs(self.config.handoff_timeout_secs),
+            restart: RestartPolicy::from_config(&self.config),
+            failures: self.failure_sender.clone(),
+        }
+    }
+
+    fn start_shard(&mut self, shard_id_u32: u32) {
+        if self.shard_handles.contains_key(&shard_id_u32) {
+            info!(shard_id = shard_id_u32, worker_id = %self.config.worker_id, "Shard already running, skipping");
+            return;
+        }
+
+        self.failed_shards.remove(&shard_id_u32);
 
-        self.shard_handles.insert(shard_id_u32, handle);
+        let shard_id = ShardId::new(shard_id_u32, self.config.total_shards);
+        let (shutdown_sender, shutdown) = watch::channel(RunState::Running);
+        let (ready_sender, ready) = watch::channel(false);
+        let metrics = Arc::new(ShardMetrics::new(self.metrics.clone()));
+        let handle = tokio::spawn(supervise_shard(self.runtime(), shard_id, shutdown, ready_sender, metrics.clone()));
+
+        self.shard_handles.insert(shard_id_u32, ShardTask {
+            handle,
+            shutdown: shutdown_sender,
+            ready,
+            metrics,
+            started_at: Instant::now(),
+        });
+        self.record_owned();
         info!(shard_id = shard_id_u32, worker_id = %self.config.worker_id, "Started shard runner");
     }
 
     async fn stop_shard(&mut self, shard_id_u32: u32) {
-        if let Some(handle) = self.shard_handles.remove(&shard_id_u32) {
-            handle.abort();
+        if let Some(task) = self.shard_handles.remove(&shard_id_u32) {
+            self.record_owned();
+            task.stop(shard_id_u32, RunState::Stopping).await;
             info!(shard_id = shard_id_u32, worker_id = %self.config.worker_id, "Stopped shard runner");
         }
     }
 
-    pub async fn shutdown(&mut self) {
+    async fn shard_failed(&mut self, shard_id: ShardId) -> bool {
+        if shard_id.total() != self.config.total_shards {
+            return false;
+        }
+
+        let shard_id = shard_id.number();
+        if self.shard_handles.remove(&shard_id).is_none() {
+            return false;
+        }
+        self.record_owned();
+
+        let subject = format!("{}.shards.{}.status", self.config.subject_prefix, shard_id);
+        let status = format!(r#"{{"shard_id":{},"status":"failed"}}"#, shard_id);
+        if let Err(e) = self.sink.publish_unique(subject, status.into()).await {
+            warn!(shard_id, error = %e, "Failed to publish failed shard status");
+        }
+
+        match self.config.restart_failure_action {
+            FailureAction::MarkFailed => {
+                error!(shard_id, worker_id = %self.config.worker_id, "Shard exhausted its restart attempts, marking failed");
+                self.failed_shards.insert(shard_id);
+                false
+            }
+            FailureAction::ExitWorker => {
+                error!(shard_id, worker_id = %self.config.worker_id, "Shard exhausted its restart attempts, stopping worker");
+                self.shutdown().await;
+                true
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) {
         info!("Shutting down all shard runners");
-        for (shard_id, handle) in self.shard_handles.drain() {
-            handle.abort();
-            info!(shard_id, "Stopped shard runner");
+        self.cancel_reshard().await;
+        let stops = self
+            .shard_handles
+            .drain()
+            .map(|(shard_id, task)| task.stop(shard_id, RunState::Stopping));
+        futures_util::future::join_all(stops).await;
+        self.record_owned();
+
+        if let Err(e) = self.sink.flush().await {
+            warn!(error = %e, "Failed to flush NATS client after stopping shard runners");
+        }
+
+        info!("All shard runners stopped");
+    }
+
+    async fn drain(&mut self) {
+        info!(worker_id = %self.config.worker_id, "Draining worker");
+        self.draining = true;
+        self.handoff().await;
+
+        if let Err(e) = self.sink.flush().await {
+            warn!(error = %e, "Failed to flush NATS client while draining");
+        }
+
+        info!(worker_id = %self.config.worker_id, "Worker drained");
+    }
+
+    async fn handoff(&mut self) {
+        self.cancel_reshard().await;
+
+        let mut shard_ids: Vec<u32> = self.shard_handles.keys().copied().collect();
+        shard_ids.sort_unstable();
+
+        info!(shards = ?shard_ids, "Handing off shards one at a time");
+
+        let handoff_timeout = Duration::from_secs(self.config.handoff_timeout_secs);
+
+        for shard_id in shard_ids {
+            let Some(task) = self.shard_handles.remove(&shard_id) else {
+                continue;
+            };
+            self.record_owned();
+
+            task.stop(shard_id, RunState::HandingOff).await;
+
+            if handoff_timeout.is_zero() {
+                continue;
+            }
+
+            match self.sessions.wait_for_claim(shard_id, &self.config.instance_id, handoff_timeout).await {
+                Ok(true) => info!(shard_id, "Shard picked up by successor"),
+                Ok(false) => warn!(shard_id, "No successor claimed shard before timeout"),
+                Err(e) => warn!(shard_id, error = %e, "Failed to watch for shard claim"),
+            }
+        }
+
+        info!("Shard handoff complete");
+    }
+}
+
+/// Brings up a reshard's new generation bucket by bucket through the shard
+/// manager, then retires `previous` once the new shards are connected, the
+/// wait for them times out, or the reshard is cancelled.
+async fn reshard(
+    commands: mpsc::WeakSender<ShardCommand>,
+    buckets: Vec<Vec<u32>>,
+    previous: Vec<(u32, ShardTask)>,
+    total_shards: u32,
+    mut cancelled: oneshot::Receiver<()>,
+) {
+    let generation = async {
+        let mut ready = Vec::new();
+
+        for (index, shard_ids) in buckets.into_iter().enumerate() {
+            if index > 0 {
+                tokio::time::sleep(IDENTIFY_INTERVAL).await;
+            }
+
+            let (reply, started) = oneshot::channel();
+            commands
+                .upgrade()?
+                .send(ShardCommand::ReshardBucket { shard_ids, reply })
+                .await
+                .ok()?;
+            ready.extend(started.await.ok()?);
+        }
+
+        let all_ready = async {
+            for shard in &mut ready {
+                if shard.wait_for(|ready| *ready).await.is_err() {
+                    return false;
+                }
+            }
+            true
+        };
+
+        match tokio::time::timeout(RESHARD_READY_TIMEOUT, all_ready).await {
+            Ok(true) => {
+                info!(total_shards, "New shard generation identified, letting it catch up");
+                tokio::time::sleep(RESHARD_CATCH_UP_GRACE).await;
+            }
+            Ok(false) => warn!("A new shard runner exited before identifying, retiring old shards anyway"),
+            Err(_) => warn!(timeout = ?RESHARD_READY_TIMEOUT, "New shards did not identify in time, retiring old shards anyway"),
+        }
+        Some(())
+    };
+
+    let finished = tokio::select! {
+        finished = generation => finished.is_some(),
+        _ = &mut cancelled => false,
+    };
+
+    let shards_stopped = previous.len() as u32;
+    let stops = previous
+        .into_iter()
+        .map(|(shard_id, task)| task.stop(shard_id, RunState::Stopping));
+    futures_util::future::join_all(stops).await;
+
+    if let (true, Some(commands)) = (finished, commands.upgrade()) {
+        tokio::select! {
+            _ = commands.send(ShardCommand::ReshardFinished { total_shards, shards_stopped }) => {}
+            _ = &mut cancelled => {}
         }
     }
+}
+
+async fn supervise_shard(
+    runtime: ShardRuntime,
+    shard_id: ShardId,
+    mut shutdown: watch::Receiver<RunState>,
+    ready: watch::Sender<bool>,
+    metrics: Arc<ShardMetrics>,
+) {
+    let worker_id = &runtime.worker_id;
+    let mut resume = take_over_session(&runtime, shard_id).await;
+    let mut attempts = 0;
+
+    while *shutdown.borrow() == RunState::Running {
+        let mut permit = if resume.is_some() {
+            None
+        } else {
+            let permission = if runtime.request_permission {
+                runtime
+                    .coordination
+                    .request_startup_permission(worker_id, shard_id.number(), runtime.permission_timeout)
+                    .await
+                    .map_err(|e| e.to_string())
+            } else {
+                Ok(StartupPermission::Unavailable)
+            };
+
+            let wait = match permission {
+                Ok(StartupPermission::Granted { delay }) => delay,
+                Ok(StartupPermission::Unavailable) => Duration::ZERO,
+                Ok(StartupPermission::Denied { retry_after, reason }) => {
+                    info!(worker_id = %worker_id, shard_id = shard_id.number(), reason = ?reason, retry_after = ?retry_after, "Startup permission denied, waiting");
+                    tokio::select! {
+                        _ = tokio::time::sleep(retry_after) => continue,
+                        _ = shutdown.changed() => break,
+                    }
+                }
+                Err(e) => {
+                    error!(worker_id = %worker_id, shard_id = shard_id.number(), error = %e, "Failed to request startup permission");
+                    Duration::ZERO
+                }
+            };
+
+            if wait > Duration::ZERO {
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = shutdown.changed() => break,
+                }
+            }
+
+            let permit = tokio::select! {
+                permit = runtime.startup_semaphore.acquire() => permit.expect("Semaphore closed"),
+                _ = shutdown.changed() => break,
+            };
+
+            if let Err(reset_in) = runtime.identify_budget.try_identify(&metrics.worker) {
+                warn!(shard_id = shard_id.number(), worker_id = %worker_id, reset_in = ?reset_in, "Identify budget exhausted, waiting for the session start window to reset");
+                drop(permit);
+                tokio::select! {
+                    _ = tokio::time::sleep(reset_in) => continue,
+                    _ = shutdown.changed() => break,
+                }
+            }
+
+            info!(shard_id = shard_id.number(), worker_id = %worker_id, "Acquired startup permit, starting runner");
+            Some(TrackedPermit::new(permit, &metrics.worker))
+        };
+
+        let mut gateway_config = ConfigBuilder::from((*runtime.gateway_config).clone());
+        let mut claim = ShardSession {
+            shard_id: shard_id.number(),
+            total_shards: shard_id.total(),
+            owner: runtime.runner.instance_id.clone(),
+            state: SessionState::Owned,
+            session_id: None,
+            sequence: None,
+            resume_url: None,
+            updated_at: 0,
+        };
+
+        if let Some(session) = resume.take() {
+            if let (Some(id), Some(sequence)) = (session.session_id.clone(), session.sequence) {
+                info!(shard_id = shard_id.number(), previous_owner = %session.owner, "Resuming handed-off session");
+                gateway_config = gateway_config.session(Session::new(sequence, id));
+                if let Some(resume_url) = session.resume_url.clone() {
+                    gateway_config = gateway_config.resume_url(resume_url);
+                }
+            }
+            claim.session_id = session.session_id;
+            claim.sequence = session.sequence;
+            claim.resume_url = session.resume_url;
+        }
 
-    pub fn coordination(&self) -> &CoordinationHandler {
-        &self.coordination
+        if let Err(e) = runtime.runner.sessions.put(&claim).await {
+            warn!(shard_id = shard_id.number(), error = %e, "Failed to record shard ownership");
+        }
+
+        let shard = twilight_gateway::Shard::with_config(shard_id, gateway_config.build());
+        let runner = stratum_runner::runner(shard, runtime.runner.clone(), shutdown.clone(), &ready, &metrics);
+        tokio::pin!(runner);
+
+        let permit_interval = tokio::time::sleep(IDENTIFY_INTERVAL);
+        tokio::pin!(permit_interval);
+        let mut became_ready = ready.subscribe();
+        let mut notified = false;
+
+        let result = loop {
+            tokio::select! {
+                result = &mut runner => break result,
+                // The permit only spaces out identifies, so it goes back once
+                // this one had its interval instead of staying held while
+                // connected.
+                _ = &mut permit_interval, if permit.is_some() => drop(permit.take()),
+                // The operator counts a shard as started once it is ready, so
+                // blue/green reshards can finish while it stays connected.
+                Ok(()) = async { became_ready.wait_for(|ready| *ready).await.map(drop) }, if !notified => {
+                    notified = true;
+                    if let Err(e) = runtime.coordination.notify_startup_complete(worker_id, shard_id.number()).await {
+                        error!(worker_id = %worker_id, shard_id = shard_id.number(), error = ?e, "Failed to notify startup complete");
+                    }
+                }
+            }
+        };
+        let identified = ready.send_replace(false);
+        if identified {
+            metrics.worker.record_ready(false);
+        }
+
+        if let Err(e) = result {
+            if e.downcast_ref::<FatalClose>().is_some() {
+                error!(shard_id = shard_id.number(), worker_id = %worker_id, error = %e, "Gateway closed fatally, not restarting");
+                let _ = runtime.failures.send(shard_id);
+                break;
+            }
+
+            if identified {
+                attempts = 0;
+            }
+            attempts += 1;
+
+            if runtime.restart.exhausted(attempts) {
+                error!(shard_id = shard_id.number(), worker_id = %worker_id, error = ?e, attempts, "Runner failed, giving up");
+                let _ = runtime.failures.send(shard_id);
+                break;
+            }
+
+            metrics.restarts.fetch_add(1, Ordering::Relaxed);
+            metrics.worker.restarts_total.fetch_add(1, Ordering::Relaxed);
+            let backoff = runtime.restart.backoff(attempts);
+            error!(shard_id = shard_id.number(), worker_id = %worker_id, error = ?e, attempt = attempts, backoff = ?backoff, "Runner failed, restarting");
+
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = shutdown.changed() => break,
+            }
+        }
     }
 }
+
+async fn take_over_session(runtime: &ShardRuntime, shard_id: ShardId) -> Option<ShardSession> {
+    let sessions = &runtime.runner.sessions;
+    let existing = match sessions.get(shard_id.number()).await {
+        Ok(existing) => existing?,
+        Err(e) => {
+            warn!(shard_id = shard_id.number(), error = %e, "Failed to read shard session");
+            return None;
+        }
+    };
+
+    let candidate = match existing.state {
+        SessionState::Released => Some(existing),
+        SessionState::Owned
+            if existing.owner != runtime.runner.instance_id
+                && existing.age() < SESSION_STALE_AFTER
+                && !runtime.handoff_timeout.is_zero() =>
+        {
+            info!(shard_id = shard_id.number(), owner = %existing.owner, "Shard owned by another instance, waiting for handoff");
+            sessions
+                .wait_for_release(shard_id.number(), runtime.handoff_timeout, SESSION_STALE_AFTER)
+                .await
+                .unwrap_or(None)
+        }
+        SessionState::Owned => None,
+    };
+
+    candidate.filter(|session| session.is_resumable(shard_id.total()))
+}
@@ -1,12 +1,16 @@
 use std::sync::LazyLock;
 
-pub static CLIENT: LazyLock<twilight_http::Client> = LazyLock::new(|| {
+pub static CLIENT: LazyLock<twilight_http::Client> =
+    LazyLock::new(|| client(std::env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN must be set")));
+
+/// HTTP client authenticating with `token`, through the TWILIGHT_PROXY_URL proxy.
+pub fn client(token: String) -> twilight_http::Client {
     let proxy_url = std::env::var("TWILIGHT_PROXY_URL")
         .unwrap_or_else(|_| "http://twilight-gateway-proxy.bedrock.svc.cluster.local".to_string());
     
     twilight_http::Client::builder()
-        .token(std::env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN must be set"))
+        .token(token)
         .proxy(proxy_url, false)  // Production: Use HTTP proxy
         .ratelimiter(None)
         .build()
-});
\ No newline at end of file
+}
\ No newline at end of file